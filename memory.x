@@ -1,8 +1,24 @@
 MEMORY
 {
-    FLASH : ORIGIN = 0x08000000, LENGTH = 256K
+    FLASH : ORIGIN = 0x08000000, LENGTH = 254K /* the last 2K page holds saved settings */
     CCRAM : ORIGIN = 0x10000000, LENGTH = 8K
     RAM : ORIGIN = 0x20000000, LENGTH = 40K
 }
 
 _stack_start = ORIGIN(RAM) + LENGTH(RAM);
+
+/* With the 'ccmram' feature, the CPU's working sample buffers (tagged #[link_section = ".ccmram"])
+   go in the core-coupled RAM, which the CPU reaches without contending with DMA on the bus
+   matrix, but which DMA can't reach at all, so the capture buffers must stay in RAM. The section
+   is NOLOAD: nothing is copied from flash, and main() zeroes it from _sccmram to _eccmram, so
+   only zero-initialized statics may go in it. */
+SECTIONS
+{
+    .ccmram (NOLOAD) : ALIGN(4)
+    {
+        _sccmram = .;
+        *(.ccmram .ccmram.*);
+        . = ALIGN(4);
+        _eccmram = .;
+    } > CCRAM
+}
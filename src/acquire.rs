@@ -0,0 +1,231 @@
+// stm32f3-oscilloscope - src/acquire.rs
+// acquisition modes: plain sampling, peak detection, and averaging of triggered sweeps
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// Peak detection captures more samples than there are screen columns, and draws each column from
+// the lowest to the highest of its samples, so that glitches too short to land on a column's
+// sample still show.
+//
+// Oversampling captures several samples per screen column, and averages each column's down to
+// one before anything else sees them, which takes out some of the noise (each fourfold increase
+// halves it, for another bit of effective resolution). The rest of the display, and the
+// measurements, work on the averaged samples as if they had been captured at the column rate.
+//
+// Averaging adds up the displayed window of each triggered sweep, column by column, so every
+// sweep added must be lined up on the trigger point the same way; untriggered (free-running)
+// sweeps, or a change in sample rate or channel count, start the average over.
+
+use core;
+
+use DISPLAY_WIDTH;
+
+/// How many samples Peak Detect mode captures per screen column, if the ADC is fast enough; the
+/// capture buffers have room for this many screen widths of one channel.
+pub const PEAK_DETECT_SAMPLES_PER_COLUMN: usize = 2;
+
+/// The most samples Oversample mode can capture per screen column; the capture buffers have room
+/// for this many screen widths of one channel. 16x, for two more bits, would need sixteen: 5120
+/// samples, 10kB for each of the two buffers, which the 40kB of RAM has no room left for.
+pub const OVERSAMPLE_MAX: usize = 4;
+
+/// The most channels which can be averaged at once.
+pub const AVERAGE_CHANNELS: usize = 2;
+
+/// The most samples per channel which can be averaged: a screen width.
+pub const AVERAGE_COLUMNS: usize = DISPLAY_WIDTH;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AcqMode {
+    Sample,         // display each sweep as captured
+    PeakDetect,     // display the min/max envelope of each column's samples
+    Oversample(u8), // display the average of each column's samples
+    Average(u8),    // display the average of every so many triggered sweeps
+}
+
+/// Returns the acquisition mode which captures and averages `k` samples per sample displayed:
+/// plain sampling for 1, or Oversample mode for a power of two up to OVERSAMPLE_MAX. Any other `k`
+/// is refused with `None`, rather than cut down to what the capture buffers have room for.
+pub fn oversample_mode(k: u8) -> Option<AcqMode> {
+    match k {
+        1 => Some(AcqMode::Sample),
+        k if k.is_power_of_two() && k as usize <= OVERSAMPLE_MAX => Some(AcqMode::Oversample(k)),
+        _ => None,
+    }
+}
+
+/// Returns the effective resolution, in half bits, of the ADC's 12 bits oversampled `factor`
+/// times: each doubling takes out another half bit's worth of noise, e.g. 26 (13 bits) for 4x.
+pub fn effective_half_bits(factor: usize) -> u32 {
+    let mut half_bits = 24;
+    let mut factor = factor;
+    while factor >= 2 {
+        factor /= 2;
+        half_bits += 1;
+    }
+    half_bits
+}
+
+/// Averages each run of `factor` samples of `samples` down to one, rounded to the nearest count,
+/// for `AcqMode::Oversample`, writing them to `out`, as far as it has room. Returns the number
+/// written. Any samples left over after the last whole run are dropped.
+pub fn decimate(samples: &[u16], factor: usize, out: &mut [u16]) -> usize {
+    let factor = core::cmp::max(factor, 1);
+    let n = core::cmp::min(samples.len() / factor, out.len());
+    for i in 0..n {
+        let run = &samples[i * factor..(i + 1) * factor];
+        let sum = run.iter().fold(0u32, |sum, &sample| sum + sample as u32);
+        out[i] = ((sum + factor as u32 / 2) / factor as u32) as u16;
+    }
+    n
+}
+
+/// Accumulates triggered sweeps for `AcqMode::Average`.
+pub struct Averager {
+    count: u8,       // sweeps added so far
+    channels: usize, // in each sweep added
+    columns: usize,  // samples per channel in each sweep added
+    sample_rate: u32,
+    sums: [[u32; AVERAGE_COLUMNS]; AVERAGE_CHANNELS],
+    averages: [[u16; AVERAGE_COLUMNS]; AVERAGE_CHANNELS],
+}
+
+impl Averager {
+    pub fn new() -> Averager {
+        Averager {
+            count: 0,
+            channels: 0,
+            columns: 0,
+            sample_rate: 0,
+            sums: [[0; AVERAGE_COLUMNS]; AVERAGE_CHANNELS],
+            averages: [[0; AVERAGE_COLUMNS]; AVERAGE_CHANNELS],
+        }
+    }
+
+    /// Discards the sweeps added so far, starting the average over.
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+
+    /// Adds a triggered sweep, given as the displayed window of each of its `channels`, all
+    /// lined up with the trigger point, and captured at `sample_rate`. Returns true once `count`
+    /// sweeps have been added, when their averages are ready to be displayed, and the next sweep
+    /// starts a new average. Sweeps which don't match the previous ones in sample rate, number of
+    /// channels, or window width start a new average.
+    pub fn add(&mut self, channels: &[&[u16]], sample_rate: u32, count: u8) -> bool {
+        let n = core::cmp::min(channels.len(), AVERAGE_CHANNELS);
+        let columns = channels.iter().map(|c| c.len()).min().unwrap_or(0);
+        let columns = core::cmp::min(columns, AVERAGE_COLUMNS);
+        if n != self.channels || columns != self.columns || sample_rate != self.sample_rate {
+            self.channels = n;
+            self.columns = columns;
+            self.sample_rate = sample_rate;
+            self.count = 0;
+        }
+        let fresh = self.count == 0;
+        for (sums, channel) in self.sums.iter_mut().zip(channels[..n].iter()) {
+            for (sum, &sample) in sums[..columns].iter_mut().zip(channel.iter()) {
+                *sum = if fresh { 0 } else { *sum } + sample as u32;
+            }
+        }
+        self.count += 1;
+        if self.count < count {
+            return false;
+        }
+        let count = self.count as u32;
+        for c in 0..n {
+            for x in 0..columns {
+                self.averages[c][x] = ((self.sums[c][x] + count / 2) / count) as u16;
+            }
+        }
+        self.count = 0;
+        true
+    }
+
+    /// Returns the latest average of `channel`, one sample per column of the window.
+    pub fn average(&self, channel: usize) -> &[u16] {
+        &self.averages[channel][..self.columns]
+    }
+}
+
+impl Default for Averager {
+    fn default() -> Averager {
+        Averager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testgen::{dc, noise};
+
+    #[test]
+    fn oversample_mode_offers_powers_of_two_up_to_the_max() {
+        assert!(oversample_mode(1) == Some(AcqMode::Sample));
+        assert!(oversample_mode(2) == Some(AcqMode::Oversample(2)));
+        assert!(oversample_mode(4) == Some(AcqMode::Oversample(4)));
+    }
+
+    #[test]
+    fn oversample_mode_refuses_what_the_buffers_have_no_room_for() {
+        assert!(oversample_mode(0) == None);
+        assert!(oversample_mode(3) == None);
+        assert!(oversample_mode(8) == None);
+        assert!(oversample_mode(16) == None);
+    }
+
+    #[test]
+    fn effective_half_bits_adds_a_half_bit_per_doubling() {
+        assert_eq!(effective_half_bits(1), 24);
+        assert_eq!(effective_half_bits(2), 25);
+        assert_eq!(effective_half_bits(4), 26);
+        assert_eq!(effective_half_bits(16), 28);
+    }
+
+    #[test]
+    fn decimate_rounds_each_run_to_the_nearest_count() {
+        let samples = [0, 1, 1, 1, 10, 11, 0, 0];
+        let mut out = [0u16; 4];
+        assert_eq!(decimate(&samples, 4, &mut out), 2);
+        assert_eq!(&out[..2], &[1, 5]);
+        assert_eq!(decimate(&samples, 2, &mut out), 4);
+        assert_eq!(out, [1, 1, 11, 0]);
+    }
+
+    #[test]
+    fn decimate_sums_full_scale_runs_without_overflow() {
+        let samples = dc(4095, 64);
+        let mut out = [0u16; 4];
+        assert_eq!(decimate(&samples, 16, &mut out), 4);
+        assert_eq!(out, [4095; 4]);
+    }
+
+    #[test]
+    fn decimate_stops_at_the_last_whole_run_or_the_end_of_out() {
+        let samples = noise(1, 100.0, 2000.0, 10);
+        let mut out = [0u16; 8];
+        assert_eq!(decimate(&samples, 4, &mut out), 2);
+        let mut short = [0u16; 1];
+        assert_eq!(decimate(&samples, 2, &mut short), 1);
+    }
+}
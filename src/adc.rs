@@ -0,0 +1,399 @@
+// stm32f3-oscilloscope - src/adc.rs
+// ADC1 bring-up, input channel selection, and software-triggered block reads
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// ADC1 is clocked synchronously from the AHB clock divided by 2 (ADC1_2 CCR CKMODE = 0b10), or
+// 36MHz, rather than from the PLL-based ADC12 prescaler, so that conversions triggered by a timer
+// have no clock-domain-crossing jitter. Each 12-bit conversion takes the sample time (set by
+// set_sample_time(), 7.5 ADC clocks by default) plus 12.5 ADC clocks, so at the default the
+// maximum throughput is 36MHz / 20 = 1.8Msps. Longer sample times give the sample-and-hold
+// capacitor time to charge through a high source impedance, at the cost of a lower maximum rate.
+//
+// ADC1 single-ended input channels supported by adc_select_channel() and adc_select_channels():
+// - IN1 through IN4 on PA0 through PA3 (PA0 is the Discovery's user pushbutton)
+// - IN6 through IN9 on PC0 through PC3 (IN7, on PC1, is the oscilloscope input)
+//
+// ADC1 also has internal channels, read one at a time by adc_read_internal(): IN18 is VREFINT, a
+// 1.2V bandgap reference, which gives away the analog supply voltage VDDA, the ADC's full-scale
+// reference. Its raw reading at VDDA = 3.3V was stored in system memory at the factory. IN16 is
+// the temperature sensor, whose raw readings at 30°C and 110°C (also at VDDA = 3.3V) were stored
+// there too, as TS_CAL1 and TS_CAL2.
+
+use core;
+
+use cortex_m;
+use stm32f30x::{ADC1, ADC1_2, GPIOA, GPIOC, RCC};
+
+use timing::{delay_ms, delay_us};
+
+/// The default input channel: ADC1_IN2, on PA1, which is not used by anything else on the
+/// STM32F3 Discovery board.
+pub const ADC_DEFAULT_CHANNEL: u8 = 2;
+
+/// The sample-and-hold times ADC1 can use for the input channels, in ADC clock cycles (the
+/// settings of the SMPx fields in SMPR1 and SMPR2).
+#[derive(Clone, Copy, PartialEq)]
+pub enum SampleCycles {
+    C1_5,
+    C2_5,
+    C4_5,
+    C7_5,
+    C19_5,
+    C61_5,
+    C181_5,
+    C601_5,
+}
+
+impl SampleCycles {
+    /// Returns the sample time in half ADC clock cycles, e.g. 15 for 7.5 cycles.
+    pub fn half_cycles(&self) -> u32 {
+        match *self {
+            SampleCycles::C1_5 => 3,
+            SampleCycles::C2_5 => 5,
+            SampleCycles::C4_5 => 9,
+            SampleCycles::C7_5 => 15,
+            SampleCycles::C19_5 => 39,
+            SampleCycles::C61_5 => 123,
+            SampleCycles::C181_5 => 363,
+            SampleCycles::C601_5 => 1203,
+        }
+    }
+
+    // the SMPx field setting for this sample time
+    fn smp_bits(&self) -> u32 {
+        match *self {
+            SampleCycles::C1_5 => 0b000,
+            SampleCycles::C2_5 => 0b001,
+            SampleCycles::C4_5 => 0b010,
+            SampleCycles::C7_5 => 0b011,
+            SampleCycles::C19_5 => 0b100,
+            SampleCycles::C61_5 => 0b101,
+            SampleCycles::C181_5 => 0b110,
+            SampleCycles::C601_5 => 0b111,
+        }
+    }
+}
+
+// ADC1's clock, in Hz, and the conversion time on top of the sample time, in half ADC clocks
+const ADC_CLOCK_HZ: u32 = 36_000_000;
+const ADC_CONVERSION_HALF_CYCLES: u32 = 25;
+
+// the sample time of the input channels, applied as they are selected
+static mut SAMPLE_TIME: SampleCycles = SampleCycles::C7_5;
+
+// ADC1's internal VREFINT channel, and its enable bit in ADC1_2 CCR (VREFEN)
+const ADC_VREFINT_CHANNEL: u8 = 18;
+const ADC_CCR_VREFEN: u32 = 1 << 22;
+
+// the factory calibration of VREFINT: its raw reading at VDDA = 3.3V, at 30°C
+const VREFINT_CAL: *const u16 = 0x1fff_f7ba as *const u16;
+const VREFINT_CAL_VDDA_MV: u32 = 3300;
+
+// ADC1's internal temperature sensor channel, and its enable bit in ADC1_2 CCR (TSEN)
+const ADC_TEMPERATURE_CHANNEL: u8 = 16;
+const ADC_CCR_TSEN: u32 = 1 << 23;
+
+// the factory calibration of the temperature sensor: its raw readings at VDDA = 3.3V, at
+// TS_CAL1_C and TS_CAL2_C
+const TS_CAL1: *const u16 = 0x1fff_f7b8 as *const u16;
+const TS_CAL2: *const u16 = 0x1fff_f7c2 as *const u16;
+const TS_CAL1_C: i32 = 30;
+const TS_CAL2_C: i32 = 110;
+
+// the number of conversions averaged for each internal channel reading
+const INTERNAL_READS: u32 = 8;
+
+// how long to wait for calibration, or for ADC1 to become ready, before giving up on it, in
+// microseconds (each takes only a few microseconds)
+const ADC_TIMEOUT_US: u32 = 1000;
+
+/// Powers on, calibrates, and enables ADC1 for software-triggered, single-conversion sampling of
+/// `channel`. Unsupported channel numbers select `ADC_DEFAULT_CHANNEL` instead. Returns false if
+/// calibration never finished, or ADC1 never became ready.
+pub fn adc_init(channel: u8) -> bool {
+    let mut ok = true;
+    cortex_m::interrupt::free(|cs| {
+        // enable clock to ADC1
+        let rcc = RCC.borrow(cs);
+        rcc.ahbenr.modify(|_, w| w.adc12en().enabled());
+
+        // configure ADC clock
+        // - turn off the PLL-based ADC12 clock
+        rcc.cfgr2.modify(|_, w| unsafe { w.adc12pres().bits(0b00000) }); // ADC clock is from AHB
+        // - turn on the AHB clock to ADC12, set to AHB/2
+        //   (calibration will hang if this isn't done now)
+        let adc12 = ADC1_2.borrow(cs);
+        adc12.ccr.modify(|_, w| unsafe { w.ckmode().bits(0b10) });
+
+        // ADC calibration procedure
+        // - turn on voltage regulator
+        let adc1 = ADC1.borrow(cs);
+        adc1.cr.modify(|_, w| unsafe { w.advregen().bits(0b00) }); // set to intermediate state first
+        adc1.cr.modify(|_, w| unsafe { w.advregen().bits(0b01) }); // then enable
+        // - leave critical section and wait for at least 10µs (the hardware requirement)
+    });
+    delay_ms(2); // delay at least 1ms (convenient, but longer than required)
+    // - enter critical section again
+    cortex_m::interrupt::free(|cs| {
+        // - select calibration mode
+        let adc1 = ADC1.borrow(cs);
+        adc1.cr.modify(|_, w| unsafe { w.adcaldif().bits(0) }); // single-ended
+        // - start calibration
+        adc1.cr.modify(|_, w| unsafe { w.adcal().bits(1) });
+        // - wait for calibration to finish
+        if !adc_wait(|| adc1.cr.read().adcal().bits() == 0) {
+            ok = false;
+            return;
+        }
+        // - calibration complete
+
+        // configure ADC1 for software-triggered sampling
+        let adc12 = ADC1_2.borrow(cs);
+        adc12.ccr.modify(|_, w| unsafe {
+            w.ckmode().bits(0b10) // ADC clock is AHB/2
+             .mdma().bits(0b00)   // dual DMA mode: disabled
+             .dmacfg().bits(0)    // dual DMA mode: one-shot
+             .delay().bits(0)     // no delay between phases (for interleaved mode only)
+             .mult().bits(0)      // independent mode
+        });
+        adc1.cfgr.modify(|_, w| unsafe {
+            w.jauto().bits(0)       // no auto inject group conversion
+             .cont().bits(0)        // single (non-continuous) conversion mode
+             .ovrmod().bits(1)      // keep new value on overrun
+             .exten().bits(0b00)    // software trigger
+             .align().bits(0)       // align right
+             .res().bits(0b00)      // 12 bits
+             .dmacfg().bits(0)      // DMA one-shot mode
+             .dmaen().bits(0)       // DMA disabled
+        });
+
+        // enable ADC1
+        adc1.cr.modify(|_, w| unsafe { w.aden().bits(1) });
+        // wait for ADRDY
+        ok = adc_wait(|| adc1.isr.read().adrdy().bits() != 0);
+    });
+    if ok {
+        adc_select_channel(channel);
+    }
+    ok
+}
+
+// Poll `done` until it returns true, or ADC_TIMEOUT_US passes. Returns whether it did. (This
+// counts cycles rather than milliseconds, since it is called with interrupts disabled.)
+fn adc_wait<F: Fn() -> bool>(done: F) -> bool {
+    for _ in 0..ADC_TIMEOUT_US {
+        if done() {
+            return true;
+        }
+        delay_us(1);
+    }
+    done()
+}
+
+/// Sets the sample time of the input channels, which takes effect when they are next selected by
+/// `adc_select_channel` or `adc_select_channels`.
+pub fn set_sample_time(cycles: SampleCycles) {
+    unsafe { SAMPLE_TIME = cycles; }
+}
+
+/// Returns the sample time of the input channels.
+pub fn sample_time() -> SampleCycles {
+    unsafe { SAMPLE_TIME }
+}
+
+/// Returns the maximum rate at which ADC1 can convert an input channel with the current sample
+/// time, in samples per second: a 36MHz ADC clock, and the sample time plus 12.5 ADC clocks per
+/// 12-bit conversion (1.8Msps at the default 7.5 cycles).
+pub fn adc_max_sample_rate() -> u32 {
+    ADC_CLOCK_HZ * 2 / (sample_time().half_cycles() + ADC_CONVERSION_HALF_CYCLES)
+}
+
+/// Makes `channel` the one (and only) conversion in ADC1's regular sequence, configuring its GPIO
+/// pin as an analog input and setting its sample time (see `set_sample_time`). Unsupported channel
+/// numbers select `ADC_DEFAULT_CHANNEL` instead. ADC1 must not be converting when this is called.
+pub fn adc_select_channel(channel: u8) {
+    let channel = adc_configure_channel(channel);
+    cortex_m::interrupt::free(|cs| {
+        let adc1 = ADC1.borrow(cs);
+        adc1.sqr1.modify(|_, w| unsafe {
+            w.sq1().bits(channel) // 1st conversion in sequence
+             .l3().bits(0b0000)   // 1 conversion in sequence  (typo in SVD, should be "l", not "l3")
+        });
+    });
+}
+
+/// Makes `first` and `second` the two conversions in ADC1's regular sequence, so that each
+/// trigger converts both, one after the other, taking twice as long as a single conversion.
+/// Otherwise like `adc_select_channel`.
+pub fn adc_select_channels(first: u8, second: u8) {
+    let first = adc_configure_channel(first);
+    let second = adc_configure_channel(second);
+    cortex_m::interrupt::free(|cs| {
+        let adc1 = ADC1.borrow(cs);
+        adc1.sqr1.modify(|_, w| unsafe {
+            w.sq1().bits(first)   // 1st conversion in sequence
+             .sq2().bits(second)  // 2nd conversion in sequence
+             .l3().bits(0b0001)   // 2 conversions in sequence
+        });
+    });
+}
+
+// Configure `channel`'s GPIO pin as an analog input with no pull, and set its sample time to
+// SAMPLE_TIME. Returns the channel configured, which is `ADC_DEFAULT_CHANNEL` if `channel` isn't
+// supported.
+fn adc_configure_channel(channel: u8) -> u8 {
+    let channel = if adc_channel_is_supported(channel) { channel } else { ADC_DEFAULT_CHANNEL };
+    cortex_m::interrupt::free(|cs| {
+        let rcc = RCC.borrow(cs);
+        // configure the pin as an analog input with no pull
+        if channel <= 4 {
+            let pin = channel - 1; // PA0..PA3
+            rcc.ahbenr.modify(|_, w| w.iopaen().enabled());
+            let gpioa = GPIOA.borrow(cs);
+            gpioa.moder.modify(|r, w| unsafe { w.bits(r.bits() | (0b11 << (pin * 2))) });
+            gpioa.pupdr.modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << (pin * 2))) });
+        } else {
+            let pin = channel - 6; // PC0..PC3
+            rcc.ahbenr.modify(|_, w| w.iopcen().enabled());
+            let gpioc = GPIOC.borrow(cs);
+            gpioc.moder.modify(|r, w| unsafe { w.bits(r.bits() | (0b11 << (pin * 2))) });
+            gpioc.pupdr.modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << (pin * 2))) });
+        }
+
+        let adc1 = ADC1.borrow(cs);
+        // sample time: SMPRx fields are 3 bits per channel, SMP1 starts at SMPR1 bit 3
+        let shift = 3 * channel as u32;
+        let smp = sample_time().smp_bits();
+        adc1.smpr1.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(0b111 << shift)) | (smp << shift))
+        });
+    });
+    channel
+}
+
+fn adc_channel_is_supported(channel: u8) -> bool {
+    (channel >= 1 && channel <= 4) || (channel >= 6 && channel <= 9)
+}
+
+/// Fills `buf` with up to `count` right-aligned 12-bit samples from the selected channel,
+/// converting back-to-back as fast as the ADC allows, and returns the number of samples written
+/// (`count` clamped to `buf.len()`). This busy-waits for each conversion. Any ongoing timer- or
+/// DMA-driven conversions are stopped, and ADC1's trigger and DMA configuration is restored
+/// afterward, but conversions are not restarted.
+#[allow(unused)]
+pub fn adc_read_block(buf: &mut [u16], count: usize) -> usize {
+    let n = core::cmp::min(count, buf.len());
+    let adc1 = ADC1.get();
+    unsafe {
+        adc_stop();
+        // switch to software triggering without DMA, remembering the previous configuration
+        let cfgr = (*adc1).cfgr.read().bits();
+        (*adc1).cfgr.modify(|_, w| w.cont().bits(0).exten().bits(0b00).dmaen().bits(0));
+        for sample in buf[..n].iter_mut() {
+            (*adc1).cr.modify(|_, w| w.adstart().bits(1));
+            while (*adc1).isr.read().eoc().bits() == 0 {}
+            *sample = (*adc1).dr.read().bits() as u16; // reading DR clears EOC
+        }
+        (*adc1).cfgr.write(|w| w.bits(cfgr));
+    }
+    n
+}
+
+/// Returns the analog supply voltage, VDDA, in millivolts, worked out from a reading of the
+/// internal reference VREFINT against the factory calibration of it. Like `adc_read_block`, this
+/// stops any ongoing conversions and doesn't restart them.
+pub fn read_vdda_mv() -> u16 {
+    let reading = adc_read_internal(ADC_VREFINT_CHANNEL, ADC_CCR_VREFEN) as u32;
+    let cal = unsafe { core::ptr::read_volatile(VREFINT_CAL) } as u32;
+    if reading == 0 {
+        return 0;
+    }
+    (VREFINT_CAL_VDDA_MV * cal / reading) as u16
+}
+
+/// Returns the chip's temperature, in whole degrees Celsius, worked out from a reading of the
+/// internal temperature sensor against its two factory calibration points. The reading is scaled
+/// to what it would have been at VDDA = 3.3V, as the calibration was, using a reading of VREFINT.
+/// Like `read_vdda_mv`, this stops any ongoing conversions and doesn't restart them. The sensor
+/// reads the die, not the air, and is only good to a few degrees.
+pub fn read_temp_c() -> i16 {
+    let reading = adc_read_internal(ADC_TEMPERATURE_CHANNEL, ADC_CCR_TSEN) as i32;
+    let vrefint = adc_read_internal(ADC_VREFINT_CHANNEL, ADC_CCR_VREFEN) as i32;
+    let (vrefint_cal, cal1, cal2) = unsafe {
+        (core::ptr::read_volatile(VREFINT_CAL) as i32, core::ptr::read_volatile(TS_CAL1) as i32,
+         core::ptr::read_volatile(TS_CAL2) as i32)
+    };
+    if vrefint == 0 || cal2 == cal1 {
+        return 0;
+    }
+    let at_3v3 = reading * vrefint_cal / vrefint;
+    (TS_CAL1_C + (at_3v3 - cal1) * (TS_CAL2_C - TS_CAL1_C) / (cal2 - cal1)) as i16
+}
+
+// Convert ADC1's internal `channel` several times, with its source switched on by `ccr_enable`
+// (a bit in ADC1_2 CCR) for the time being, and return the average reading. The internal channels
+// need at least 2.2µs to sample (the temperature sensor's minimum sampling time is the longest),
+// so they get the 181.5-cycle (5µs) sample time, and 10µs to start up once switched on. Any
+// ongoing conversions are stopped, and the regular sequence, sample times, trigger, and DMA
+// configuration are restored afterward, but conversions are not restarted.
+fn adc_read_internal(channel: u8, ccr_enable: u32) -> u16 {
+    let adc1 = ADC1.get();
+    let adc12 = ADC1_2.get();
+    adc_stop();
+    unsafe {
+        // switch on the source, and let it start up
+        (*adc12).ccr.modify(|r, w| w.bits(r.bits() | ccr_enable));
+        delay_us(10);
+        // select the channel alone, with software triggering and no DMA, remembering the previous
+        // configuration (SMPR2 has the sample times of channels 10 through 18)
+        let cfgr = (*adc1).cfgr.read().bits();
+        let sqr1 = (*adc1).sqr1.read().bits();
+        let smpr2 = (*adc1).smpr2.read().bits();
+        let shift = 3 * (channel as u32 - 10);
+        (*adc1).smpr2.write(|w| w.bits((smpr2 & !(0b111 << shift)) | (0b110 << shift)));
+        (*adc1).sqr1.write(|w| w.bits((channel as u32) << 6)); // SQ1, and L = 0: one conversion
+        (*adc1).cfgr.modify(|_, w| w.cont().bits(0).exten().bits(0b00).dmaen().bits(0));
+        let mut sum = 0;
+        for _ in 0..INTERNAL_READS {
+            (*adc1).cr.modify(|_, w| w.adstart().bits(1));
+            while (*adc1).isr.read().eoc().bits() == 0 {}
+            sum += (*adc1).dr.read().bits() & 0xfff; // reading DR clears EOC
+        }
+        (*adc1).cfgr.write(|w| w.bits(cfgr));
+        (*adc1).sqr1.write(|w| w.bits(sqr1));
+        (*adc1).smpr2.write(|w| w.bits(smpr2));
+        (*adc12).ccr.modify(|r, w| w.bits(r.bits() & !ccr_enable));
+        (sum / INTERNAL_READS) as u16
+    }
+}
+
+/// Stops any ongoing regular conversions, and waits until ADC1 is ready to be reconfigured.
+pub fn adc_stop() {
+    let adc1 = ADC1.get();
+    unsafe {
+        if (*adc1).cr.read().adstart().bits() != 0 {
+            (*adc1).cr.modify(|_, w| w.adstp().bits(1));
+            while (*adc1).cr.read().adstp().bits() != 0 {}
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// stm32f3-oscilloscope - src/autoset.rs
+// automatic selection of timebase and gain to suit the input signal
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Autoset tries the timebases from the fastest up, capturing a buffer at each, until it finds one
+// at which the signal is both big enough not to be noise and has a measurable frequency. (A slow
+// signal looks flat at fast timebases, so its amplitude is only trusted where it has one.) The
+// slowest timebases aren't tried, since each of their buffers takes seconds or more to capture.
+
+use capture;
+use capture::SAMPLE_COUNT;
+use measure::{mean, measure_frequency, peak_to_peak};
+use timebase::{TimeBase, timebase_apply};
+use vscale::VerticalScale;
+
+/// The slowest time per division autoset tries, in microseconds.
+const AUTOSET_SLOWEST_US_PER_DIV: u32 = 100_000;
+
+/// The smallest peak-to-peak amplitude, in ADC counts, taken to be a signal rather than noise:
+/// about 30mV.
+const AUTOSET_MIN_PEAK_TO_PEAK: u16 = 40;
+
+/// How much of the screen height the signal should fill, in percent.
+const AUTOSET_FILL_PERCENT: u32 = 80;
+
+/// The fewest periods of the signal to show across the screen.
+const AUTOSET_MIN_PERIODS: f32 = 2.0;
+
+/// The settings chosen by `autoset`.
+pub struct Autoset {
+    pub timebase: TimeBase,
+    pub vscale: VerticalScale,
+    pub trigger_level: u16, // the signal's mean
+}
+
+/// Captures buffers at increasingly slow timebases, at `pixels_per_div` pixels per division,
+/// until the input signal's peak-to-peak amplitude and frequency can be estimated, then returns
+/// the settings which show at least two (and fewer than five) periods of it across `divs`
+/// divisions, at the highest gain which keeps it within 80% of the screen height. Returns `None`
+/// if no signal is found. Either way, the sample timer is left set for some other timebase, so
+/// the caller must set it again. In two-channel capture, only channel A is considered.
+pub fn autoset(pixels_per_div: u32, divs: u32) -> Option<Autoset> {
+    let mut samples = [0u16; SAMPLE_COUNT];
+    let mut index = 0;
+    while let Some(timebase) = TimeBase::from_index(index) {
+        if timebase.microseconds_per_div() > AUTOSET_SLOWEST_US_PER_DIV {
+            break;
+        }
+        let rate = timebase_apply(&timebase, pixels_per_div);
+        let n = capture::adc_dma_fresh(&mut samples);
+        let samples = &samples[..n];
+        let pp = peak_to_peak(samples);
+        if pp >= AUTOSET_MIN_PEAK_TO_PEAK {
+            let level = mean(samples);
+            if let Some(hz) = measure_frequency(samples, rate, level, pp / 4) {
+                return Some(Autoset {
+                    timebase: timebase_for(hz, divs),
+                    vscale: vscale_for(pp),
+                    trigger_level: level,
+                });
+            }
+        }
+        index += 1;
+    }
+    None
+}
+
+// the fastest timebase showing at least AUTOSET_MIN_PERIODS periods of a `hz` signal across `divs`
+// divisions, or the slowest one if none does
+fn timebase_for(hz: f32, divs: u32) -> TimeBase {
+    let period_us = 1_000_000.0 / hz;
+    let mut timebase = TimeBase::new();
+    let mut index = 0;
+    while let Some(t) = TimeBase::from_index(index) {
+        timebase = t;
+        if (t.microseconds_per_div() * divs) as f32 >= period_us * AUTOSET_MIN_PERIODS {
+            break;
+        }
+        index += 1;
+    }
+    timebase
+}
+
+// the highest gain at which `peak_to_peak` counts fill no more than AUTOSET_FILL_PERCENT of the
+// screen height
+fn vscale_for(peak_to_peak: u16) -> VerticalScale {
+    let mut vscale = VerticalScale::new();
+    let mut index = 0;
+    while let Some(v) = VerticalScale::from_index(index) {
+        // (gains are Q16.16)
+        if peak_to_peak as u64 * v.gain_q16() as u64 * 100 <=
+           (4096 * AUTOSET_FILL_PERCENT as u64) << 16 {
+            vscale = v;
+        }
+        index += 1;
+    }
+    vscale
+}
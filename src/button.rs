@@ -0,0 +1,269 @@
+// stm32f3-oscilloscope - src/vscale.rs
+// vertical scale (display gain) settings
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The pushbuttons are on GPIOD pins, PD12 through PD15 on the breakout board, shorting to ground
+// against the pin's pull-up when pressed; a board wired differently can give its own pins, and
+// which way round each of its buttons is, to button_init() in a ButtonConfig. A button which
+// connects its pin to the supply instead, active-high, gets a pull-down. The SysTick handler polls
+// them once per millisecond, calling poll_from_isr() with the GPIOD input data register, and
+// after each change of state a button is ignored for DEBOUNCE_MS, to ride out contact bounce.
+//
+// Each press is reported as one of three events:
+// - Long, as soon as the button has been held for LONG_PRESS_MS,
+// - Double, when a second press begins within DOUBLE_PRESS_MS of the start of a short first
+//   press (the second press is not reported on its own), or
+// - Short, once a press has been released and DOUBLE_PRESS_MS has passed since it began without
+//   a second press. This means short presses are reported up to DOUBLE_PRESS_MS late.
+//
+// While SysTick is stopped, e.g. in the low-power idle mode, nothing polls the buttons, so
+// button_wake_enable() has a press raise an EXTI interrupt instead, which wakes the CPU from
+// sleep. That works for buttons on PD5 through PD15, whose EXTI lines have interrupts (EXTI9_5 and
+// EXTI15_10) no other peripheral uses; a button on PD0 through PD4 isn't noticed until the next
+// interrupt of some other kind wakes the CPU.
+
+use core::cell::RefCell;
+
+use cortex_m;
+use cortex_m::interrupt::Mutex;
+use stm32f30x::{EXTI, GPIOD, RCC, SYSCFG};
+use stm32f30x::interrupt::Interrupt;
+
+use gpio::{GPIO_PINS, configure_input_pulldown, configure_input_pullup};
+
+pub const BUTTON_COUNT: usize = 4;
+
+/// Which GPIOD pin each button is on, and which way round each is wired.
+#[derive(Clone, Copy)]
+pub struct ButtonConfig {
+    pub pins: [usize; BUTTON_COUNT],
+    pub active_low: [bool; BUTTON_COUNT], // shorting the pin to ground when pressed, not to 3V3
+}
+
+/// The breakout board's buttons, on PD12 through PD15, shorting to ground when pressed.
+pub const BUTTON_CONFIG: ButtonConfig = ButtonConfig {
+    pins: [12, 13, 14, 15],
+    active_low: [true; BUTTON_COUNT],
+};
+
+// the configuration the buttons were set up with
+static mut CONFIG: ButtonConfig = BUTTON_CONFIG;
+/// How long a button is ignored after each change of state.
+pub const DEBOUNCE_MS: u32 = 100;
+const LONG_PRESS_MS: u32 = 500;
+const DOUBLE_PRESS_MS: u32 = 300;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ButtonEvent {
+    Short,
+    Long,
+    Double,
+}
+
+pub struct Buttons {
+    state: [bool; BUTTON_COUNT],    // true while pressed
+    debounce: [u32; BUTTON_COUNT],  // milliseconds until the button is read again
+    held: [u32; BUTTON_COUNT],      // milliseconds since the latest press began
+    reported: [bool; BUTTON_COUNT], // the latest press has already been reported
+    pending: [bool; BUTTON_COUNT],  // a short press was released, and may become a double
+    event: [Option<ButtonEvent>; BUTTON_COUNT], // not yet taken by take_event()
+}
+
+impl Buttons {
+    /// Updates the button states from `idr`, the GPIOD input data register, and generates button
+    /// events. Call this once per millisecond.
+    pub fn poll_from_isr(&mut self, idr: u32) {
+        for i in 0..BUTTON_COUNT {
+            self.held[i] = self.held[i].saturating_add(1);
+            if self.debounce[i] > 0 {
+                self.debounce[i] -= 1;
+            } else {
+                let state = button_is_down(idr, i);
+                if state != self.state[i] {
+                    self.state[i] = state;
+                    self.debounce[i] = DEBOUNCE_MS;
+                    if state {
+                        self.press(i);
+                    } else {
+                        self.release(i);
+                    }
+                }
+            }
+            if self.state[i] {
+                if !self.reported[i] && self.held[i] >= LONG_PRESS_MS {
+                    self.report(i, ButtonEvent::Long);
+                }
+            } else if self.pending[i] && self.held[i] >= DOUBLE_PRESS_MS {
+                self.pending[i] = false;
+                self.event[i] = Some(ButtonEvent::Short);
+            }
+        }
+    }
+
+    fn press(&mut self, i: usize) {
+        self.held[i] = 0;
+        if self.pending[i] {
+            self.pending[i] = false;
+            self.report(i, ButtonEvent::Double);
+        } else {
+            self.reported[i] = false;
+        }
+    }
+
+    fn release(&mut self, i: usize) {
+        if !self.reported[i] {
+            self.reported[i] = true;
+            if self.held[i] < DOUBLE_PRESS_MS {
+                self.pending[i] = true; // wait to see if a second press follows
+            } else {
+                self.event[i] = Some(ButtonEvent::Short);
+            }
+        }
+    }
+
+    fn report(&mut self, i: usize, event: ButtonEvent) {
+        self.reported[i] = true;
+        self.event[i] = Some(event);
+    }
+
+    /// Returns the latest event from button `i`, if there has been one since the last call, and
+    /// forgets it.
+    pub fn take_event(&mut self, i: usize) -> Option<ButtonEvent> {
+        self.event[i].take()
+    }
+}
+
+/// The pushbuttons, shared between the SysTick handler and the main loop.
+pub static BUTTONS: Mutex<RefCell<Buttons>> = Mutex::new(RefCell::new(Buttons {
+    state: [false; BUTTON_COUNT],
+    debounce: [0; BUTTON_COUNT],
+    held: [0; BUTTON_COUNT],
+    reported: [false; BUTTON_COUNT],
+    pending: [false; BUTTON_COUNT],
+    event: [None; BUTTON_COUNT],
+}));
+
+/// Sets up the buttons' GPIOD pins as given by `config`, as inputs pulled the opposite way to
+/// what a press drives them to, and reads them that way from then on. GPIOD must already be
+/// powered on. Returns false, setting up nothing, if a pin isn't on GPIOD, or two buttons share
+/// one.
+pub fn button_init(gpiod: &GPIOD, config: ButtonConfig) -> bool {
+    let pins = &config.pins;
+    for (i, &pin) in pins.iter().enumerate() {
+        if pin >= GPIO_PINS || pins[..i].contains(&pin) {
+            return false;
+        }
+    }
+    unsafe { CONFIG = config; }
+    for i in 0..BUTTON_COUNT {
+        let pin = &pins[i..i + 1];
+        if config.active_low[i] {
+            configure_input_pullup(gpiod, pin);
+        } else {
+            configure_input_pulldown(gpiod, pin);
+        }
+    }
+    true
+}
+
+/// Returns true if button `i` is down according to `idr`, the GPIOD input data register, without
+/// any debouncing, e.g. for checking buttons at power-on, before the SysTick handler has polled
+/// them.
+pub fn button_is_down(idr: u32, i: usize) -> bool {
+    let config = unsafe { CONFIG };
+    let high = (idr & (1 << config.pins[i])) != 0;
+    high != config.active_low[i]
+}
+
+/// Returns the latest event from button `i`, if there has been one since the last call.
+pub fn button_event(i: usize) -> Option<ButtonEvent> {
+    cortex_m::interrupt::free(|cs| BUTTONS.borrow(cs).borrow_mut().take_event(i))
+}
+
+// ======== waking from sleep ========
+
+// the lowest pin whose EXTI line can wake the CPU for a press
+const WAKE_PIN_FIRST: usize = 5;
+
+/// Makes a press of any button on PD5 through PD15 raise an EXTI interrupt, waking the CPU if it
+/// is sleeping, if `enable` is true, or stops that, if false. The buttons must already be set up by
+/// `button_init()`.
+pub fn button_wake_enable(enable: bool) {
+    let config = unsafe { CONFIG };
+    let lines = wake_lines();
+    cortex_m::interrupt::free(|cs| {
+        let exti = EXTI.borrow(cs);
+        if enable {
+            let rcc = RCC.borrow(cs);
+            rcc.apb2enr.modify(|_, w| w.syscfgen().enabled());
+            let syscfg = SYSCFG.borrow(cs);
+            for i in 0..BUTTON_COUNT {
+                let pin = config.pins[i];
+                if pin < WAKE_PIN_FIRST {
+                    continue;
+                }
+                // connect the pin's EXTI line to GPIOD (each EXTICR register selects the port for
+                // four lines, in four bits each, and PD is 0b0011)
+                let shift = pin % 4 * 4;
+                let select = |bits: u32| (bits & !(0b1111 << shift)) | (0b0011 << shift);
+                match pin / 4 {
+                    1 => syscfg.exticr2.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+                    2 => syscfg.exticr3.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+                    _ => syscfg.exticr4.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+                }
+                // interrupt on the edge a press makes
+                if config.active_low[i] {
+                    exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << pin)) });
+                } else {
+                    exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << pin)) });
+                }
+            }
+            exti.pr1.write(|w| w.bits(lines)); // forget any edges from before
+            exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() | lines) });
+            // (the interrupts only wake the CPU, so they can wait for anything more pressing)
+            let nvic = cortex_m::peripheral::NVIC.borrow(cs);
+            unsafe {
+                nvic.set_priority(Interrupt::Exti95, 0x80);
+                nvic.set_priority(Interrupt::Exti1510, 0x80);
+            }
+            nvic.enable(Interrupt::Exti95);
+            nvic.enable(Interrupt::Exti1510);
+        } else {
+            exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !lines) });
+        }
+    });
+}
+
+/// Handles the EXTI9_5 and EXTI15_10 interrupts, raised by a press while `button_wake_enable()`
+/// has enabled them; called from their interrupt handlers. They are only for waking the CPU, so
+/// this just clears them: the press itself is read by polling, once SysTick is running again.
+pub fn button_wake_interrupt() {
+    unsafe { (*EXTI.get()).pr1.write(|w| w.bits(wake_lines())); }
+}
+
+// the EXTI lines of the buttons which can wake the CPU, as a bit mask
+fn wake_lines() -> u32 {
+    let config = unsafe { CONFIG };
+    let pins = config.pins.iter().filter(|&&pin| pin >= WAKE_PIN_FIRST);
+    pins.fold(0, |lines, &pin| lines | (1 << pin))
+}
@@ -0,0 +1,157 @@
+// stm32f3-oscilloscope - src/calibration.rs
+// conversion of ADC counts to millivolts, and its calibration against known inputs
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// A reading of `counts` ADC counts is taken to be (counts - offset) / counts_per_volt volts. The
+// factory calibration assumes an ideal ADC: no offset, and full scale (4096 counts) at the
+// Discovery board's 3.0V analog supply. Calibrating measures the actual offset with the input
+// grounded, then the actual scale with a known voltage applied, which corrects for the ADC's
+// offset and gain errors, and for the tolerance of the supply.
+//
+// A center-zero front end (one built around a virtual ground at mid-supply) shifts its input up
+// to mid-scale, so that 0V reads about 2048 counts and negative voltages can be measured. For one
+// of those, the zero offset is the reading taken as 0V: the display centers it, and measurements
+// are made from it, in place of the calibration's offset. Calibrating the offset with the input
+// grounded measures it too.
+//
+// Voltages are reported at the probe tip: a probe which attenuates the signal by `probe_ratio`
+// (e.g. 10 for a 10x probe) multiplies every reading by that, though calibration is always of the
+// input itself.
+//
+// The readings calibrated against are taken by `capture::calibrate_offset()` and
+// `capture::calibrate_scale()`, so that nothing here touches the ADC.
+
+/// The middle of the 12-bit ADC range, which the display centers on, and a center-zero front end
+/// reads 0V at, ideally.
+pub const MID_SCALE: u16 = 2048;
+
+#[derive(Clone, Copy)]
+pub struct Calibration {
+    pub offset: u16,          // ADC counts read with the input grounded
+    pub counts_per_volt: u16, // ADC counts per volt above `offset`
+}
+
+/// The calibration used until the input has been calibrated.
+pub const FACTORY_CALIBRATION: Calibration = Calibration { offset: 0, counts_per_volt: 1365 };
+
+static mut CALIBRATION: Calibration = FACTORY_CALIBRATION;
+static mut PROBE_RATIO: u16 = 1;
+static mut ZERO_OFFSET: Option<u16> = None;
+
+/// Returns the calibration in use.
+pub fn calibration() -> Calibration {
+    unsafe { CALIBRATION }
+}
+
+/// Replaces the calibration in use, e.g. with one restored from flash. A `counts_per_volt` of
+/// zero is not usable, so selects the factory calibration instead.
+pub fn set_calibration(calibration: Calibration) {
+    unsafe {
+        CALIBRATION = if calibration.counts_per_volt == 0 {
+            FACTORY_CALIBRATION
+        } else {
+            calibration
+        };
+    }
+}
+
+/// Sets the probe's attenuation, e.g. 10 for a 10x probe, which readings are multiplied by. A ratio
+/// of zero is not usable, so selects 1 instead.
+pub fn set_probe_ratio(ratio: u16) {
+    unsafe { PROBE_RATIO = if ratio == 0 { 1 } else { ratio }; }
+}
+
+/// Returns the probe's attenuation.
+pub fn probe_ratio() -> u16 {
+    unsafe { PROBE_RATIO }
+}
+
+/// Sets the zero offset, for a center-zero front end: `Some` reading, in ADC counts, taken as 0V,
+/// or `None` for a front end which passes the input straight through, so that the calibration's
+/// offset is 0V, and the display centers on mid-scale. Readings above 4095 aren't usable, so
+/// select mid-scale instead.
+pub fn set_zero_offset(zero: Option<u16>) {
+    unsafe { ZERO_OFFSET = zero.map(|z| if z > 4095 { MID_SCALE } else { z }); }
+}
+
+/// Returns the zero offset, if there is one.
+pub fn zero_offset() -> Option<u16> {
+    unsafe { ZERO_OFFSET }
+}
+
+/// Returns the reading drawn on the graticule's center line: the zero offset, if there is one, or
+/// mid-scale.
+pub fn center_counts() -> u16 {
+    zero_offset().unwrap_or(MID_SCALE)
+}
+
+/// Converts a level in ADC counts to millivolts at the probe tip, which are negative below the
+/// zero offset, if there is one, or the calibration's offset.
+pub fn counts_to_mv(counts: u16) -> i32 {
+    span_to_mv(counts as i32 - zero_counts() as i32)
+}
+
+/// Converts a difference between two levels, in ADC counts, e.g. a peak-to-peak amplitude, to
+/// millivolts at the probe tip. Unlike a level, a difference doesn't depend on the offset.
+pub fn span_to_mv(counts: i32) -> i32 {
+    let counts_per_volt = calibration().counts_per_volt as i32;
+    let rounding = if counts < 0 { -counts_per_volt / 2 } else { counts_per_volt / 2 };
+    (counts * 1000 * probe_ratio() as i32 + rounding) / counts_per_volt
+}
+
+/// Makes `offset`, the average reading with the input grounded, the offset of the calibration in
+/// use, keeping its scale, and the zero offset too, if there is one.
+pub fn set_measured_offset(offset: u16) {
+    unsafe {
+        CALIBRATION.offset = offset;
+        if ZERO_OFFSET.is_some() {
+            ZERO_OFFSET = Some(offset);
+        }
+    }
+}
+
+/// Sets the scale of the calibration in use from `reading`, the average reading with `known_mv`
+/// millivolts applied to the input itself (not through the probe), keeping its offset (so the
+/// offset, or the zero offset, should be calibrated first). Returns false, leaving the calibration
+/// unchanged, if the reading is not within a factor of two of what the factory calibration
+/// expects, e.g. if there's no input.
+pub fn set_measured_scale(reading: u16, known_mv: u16) -> bool {
+    if known_mv == 0 {
+        return false;
+    }
+    let counts = reading as i32 - zero_counts() as i32;
+    let counts_per_volt = (counts * 1000 + known_mv as i32 / 2) / known_mv as i32;
+    let factory = FACTORY_CALIBRATION.counts_per_volt as i32;
+    if counts_per_volt < factory / 2 || counts_per_volt > factory * 2 {
+        return false;
+    }
+    unsafe {
+        CALIBRATION.counts_per_volt = counts_per_volt as u16;
+    }
+    true
+}
+
+// the reading taken as 0V: the zero offset, if there is one, or the calibration's offset
+fn zero_counts() -> u16 {
+    zero_offset().unwrap_or(calibration().offset)
+}
@@ -22,82 +22,59 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
-// This uses ADC1 channel 7, input on GPIO PC1
+// This uses ADC1 channel 7, input on GPIO PC1, plus channel 6, on PC0, in two-channel capture
 // - ADC12 is clocked by AHB clock to minimize jitter
-// TIM15 triggers the ADC conversions
+// TIM15 triggers the ADC conversions, rather than TIM2, which paces the signal generator's DAC
 // - this is a 16-bit counter with 16-bit prescaler, clocked directly from APB2 (72MHz)
 // - TIM15 outputs TIM15_TRGO, which is ADC1's EXT14
 // DMA1 channel 1 moves converted data to RAM
+// PD1 is the external trigger input, interrupting on EXTI line 1
+
+use core;
 
 use cortex_m;
-use stm32f30x::{ADC1, ADC1_2, DMA1, GPIOC, RCC, TIM15};
+use stm32f30x::{ADC1, DMA1, EXTI, GPIOD, RCC, SYSCFG, TIM15};
 use stm32f30x::interrupt::Interrupt;
 
-use delay_ms;
+use adc::{self, SampleCycles, adc_init, adc_max_sample_rate, adc_select_channel,
+          adc_select_channels, adc_stop};
+use calibration;
+use measure::mean;
+use st7735::DISPLAY_WIDTH;
+use sysclk::{apb2_timer_divider, apb2_timer_rate};
+use trigger::Edge;
+
+/// The ADC1 input channel sampled by the capture sweep: ADC1_IN7, on PC1.
+const CAPTURE_ADC_CHANNEL: u8 = 7;
+
+/// The second input channel, converted after `CAPTURE_ADC_CHANNEL` in two-channel capture:
+/// ADC1_IN6, on PC0.
+const CAPTURE_ADC_CHANNEL_B: u8 = 6;
+
+// the number of channels in the conversion sequence, 1 or 2
+static mut CHANNEL_COUNT: usize = 1;
 
-pub static mut CAPTURE_CHANNEL_1: [u16; 160] = [0; 160];
+/// Prepares the hardware for sample capture, by configuring the ADC (via `adc_init`), timer, DMA
+/// channel, and GPIO pin. Each of those peripherals will be ready for capture, except for the DMA
+/// buffers, DMA enabling, and ADC start, which are done by `adc_dma_start`. Returns false, with
+/// nothing else set up, if the ADC couldn't be.
+pub fn setup() -> bool {
+    // power on, calibrate, and enable ADC1, with PC1 as an analog input
+    if !adc_init(CAPTURE_ADC_CHANNEL) {
+        return false;
+    }
 
-/// Prepares the hardware for sample capture, by configuring the ADC, timer, DMA channel, and
-/// GPIO pin. Each of those peripherals will be ready for a new sampling sweep, except for the
-/// ADC start and DMA enabling, which is done by `begin_sweep`.
-pub fn setup() {
     cortex_m::interrupt::free(|cs| {
-        // enable clock to ADC1, DMA1, and GPIOC
+        // enable clock to DMA1
         let rcc = RCC.borrow(cs);
-        rcc.ahbenr.modify(|_, w|
-            w.adc12en().enabled()
-             .dmaen().enabled() // should be 'dma1en'
-             .iopcen().enabled()
-        );
+        rcc.ahbenr.modify(|_, w| w.dmaen().enabled()); // should be 'dma1en'
         // enable clock to TIM15
         rcc.apb2enr.modify(|_, w| w.tim15en().enabled());
 
-        // configure PC1 and PC? as analog inputs with no pull
-        let gpioc = GPIOC.borrow(cs);
-        gpioc.moder.modify(|_, w|
-            w.moder1().analog()
-        );
-        gpioc.pupdr.modify(|_, w| unsafe {
-            w.pupdr1().bits(0b00) // no pull
-        });
-
-        // configure ADC clock
-        // -FIX- adjust sample time with sample rate
-        // - turn off the PLL-based ADC12 clock
-        rcc.cfgr2.modify(|_, w| unsafe { w.adc12pres().bits(0b00000) }); // ADC clock is from AHB
-        // - turn on the AHB clock to ADC12, set to AHB/2
-        //   (calibration will hang if this isn't done now)
-        let adc12 = ADC1_2.borrow(cs);
-        adc12.ccr.modify(|_, w| unsafe { w.ckmode().bits(0b10) });
-
-        // ADC calibration procedure
-        // - turn on voltage regulator
-        let adc1 = ADC1.borrow(cs);
-        adc1.cr.modify(|_, w| unsafe { w.advregen().bits(0b00) }); // set to intermediate state first
-        adc1.cr.modify(|_, w| unsafe { w.advregen().bits(0b01) }); // then enable
-        // - leave critical section and wait for at least 10µs (the hardware requirement)
-    });
-        delay_ms(2); // delay at least 1ms (convenient, but longer than required)
-        // - enter critical section again
-    cortex_m::interrupt::free(|cs| {
-        // - select calibration mode
-        let adc1 = ADC1.borrow(cs);
-        adc1.cr.modify(|_, w| unsafe { w.adcaldif().bits(0) }); // single-ended
-        // - start calibration
-        adc1.cr.modify(|_, w| unsafe { w.adcal().bits(1) });
-        // - wait for calibration to finish
-        while adc1.cr.read().adcal().bits() != 0 {}
-        // - calibration complete
-
         // configure ADC1 for TIM15-driven sampling
-        let adc12 = ADC1_2.borrow(cs);
-        adc12.ccr.modify(|_, w| unsafe {
-            w.ckmode().bits(0b10) // ADC clock is AHB/2
-             .mdma().bits(0b00)   // dual DMA mode: disabled
-             .dmacfg().bits(0)    // dual DMA mode: one-shot
-             .delay().bits(0)     // no delay between phases (for interleaved mode only)
-             .mult().bits(0)      // independent mode -FIX- for dual channel
-        });
+        // (ADC1 stays independent of ADC2, leaving ADC1_2 CCR's MULT at 0: two channels are
+        // captured as a two-conversion regular sequence, by adc_select_channels())
+        let adc1 = ADC1.borrow(cs);
         adc1.cfgr.modify(|_, w| unsafe {
             w.jauto().bits(0)       // no auto inject group conversion
              .cont().bits(0)        // single (non-continuous) conversion mode
@@ -109,11 +86,6 @@ pub fn setup() {
              .dmacfg().bits(0)      // DMA one-shot mode
              .dmaen().bits(1)       // DMA enabled
         });
-        adc1.sqr1.modify(|_, w| unsafe {
-            w.sq1().bits(7)     // 1st conversion in sequence: channel 7
-             .l3().bits(0b0000) // 1 conversion in sequence  (typo in SVD, should be "l", not "l3")
-        });
-        adc1.smpr1.modify(|_, w| unsafe { w.smp7().bits(0b011) }); // sample time 7.5 cycles -FIX-
 
         // configure TIM15 to trigger sampling
         let tim15 = TIM15.borrow(cs);
@@ -140,95 +112,557 @@ pub fn setup() {
              .dir().bits(0)      // transfer direction: peripheral -> memory
              .tcie().bits(1)     // trigger interrupt on transfer completion
         });
-        dma1.cndtr1.write(|w| unsafe { w.ndt().bits(160) });  // buffer size
         let adc1_dr_address: u32 = &adc1.dr as *const _ as u32;
         debug_assert_eq!(adc1_dr_address, 0x50000040);
         dma1.cpar1.write(|w| unsafe {
             w.bits(adc1_dr_address) // peripheral base address
         });
-        dma1.cmar1.write(|w| unsafe {
-            w.bits(&CAPTURE_CHANNEL_1 as *const _ as u32) // memory base address
-        });
         // - enable DMA1_Channel1 interrupt
         let nvic = cortex_m::peripheral::NVIC.borrow(cs);
         unsafe { nvic.set_priority(Interrupt::Dma1Ch1, 0); }
         nvic.enable(Interrupt::Dma1Ch1);
 
-        // enable ADC1
-        adc1.cr.modify(|_, w| unsafe { w.aden().bits(1) });
-        // wait for ADRDY
-        while adc1.isr.read().adrdy().bits() == 0 {}
-
         // enable TIM15
         tim15.cr1.modify(|_, w| unsafe { w.cen().bits(1) });
     });
+    ext_trigger_setup();
+    true
 }
 
-/// Begins a new sampling sweep by enabling DMA and starting ADC conversions.
-pub fn begin_sweep() {
-    // begin the next sweep of 160 samples
+// ======== continuous, double-buffered capture ========
+
+/// The number of samples in each of the continuous-capture DMA buffers: two screen widths, so
+/// that a trigger can be found anywhere in the first screen width and still be followed by a
+/// screen width of samples to display.
+pub const SAMPLE_COUNT: usize = 2 * DISPLAY_WIDTH;
+
+// Continuous-capture state, shared with the DMA1 channel 1 interrupt handler. Buffer indices are 0
+// for buffer A, 1 for buffer B. DMA_FILLING is `None` when continuous capture isn't running.
+static mut DMA_BUFFER: [(u32, usize); 2] = [(0, 0), (0, 0)]; // (address, length)
+static mut DMA_FILLING: Option<usize> = None; // buffer being written by DMA
+static mut DMA_COMPLETED: Option<usize> = None; // newest complete buffer, not yet taken
+static mut DMA_HELD: Option<usize> = None; // buffer of the DmaBuffer adc_dma_ready() handed out
+static mut STREAM_BUFFER: usize = 0; // buffer adc_dma_stream() is reading from
+static mut STREAM_POSITION: usize = 0; // next sample adc_dma_stream() will read from it
+static mut CAPTURE_PENDING: bool = false; // adc_capture_start()'s buffer is yet to be returned
+static mut SAMPLES_CAPTURED: u32 = 0; // per channel, in buffers DMA has completed, wrapping
+// the buffers DMA has completed, plus one each time capture restarts or changes rate, wrapping, so
+// that a buffer numbered one more than another straight follows it
+static mut DMA_SEQUENCE: u32 = 0;
+static mut DMA_COMPLETED_SEQUENCE: [u32; 2] = [0, 0]; // each buffer's number, as last completed
+
+/// Starts continuous TIM15-paced capture, in which DMA fills `buf_a` and `buf_b` alternately.
+/// `buf_b` is used at the length of `buf_a`. Any sweep in progress is abandoned. Completed buffers
+/// are retrieved with `adc_dma_ready()`.
+pub fn adc_dma_start(buf_a: &'static mut [u16], buf_b: &'static mut [u16]) {
+    debug_assert!(buf_b.len() >= buf_a.len() && buf_a.len() <= 0xffff);
+    adc_stop();
     cortex_m::interrupt::free(|cs| {
-        // enable DMA
         let dma1 = DMA1.borrow(cs);
-        unsafe { dma1.ccr1.modify(|_, w| w.en().bits(1)); }
-        // start ADC conversions (timer is already running)
+        dma1.ccr1.modify(|_, w| unsafe { w.en().bits(0) });
+        unsafe {
+            DMA_BUFFER = [(buf_a.as_mut_ptr() as u32, buf_a.len()),
+                          (buf_b.as_mut_ptr() as u32, buf_a.len())];
+            DMA_FILLING = Some(0);
+            DMA_COMPLETED = None;
+            DMA_HELD = None;
+            STREAM_BUFFER = 0;
+            STREAM_POSITION = 0;
+            DMA_SEQUENCE = DMA_SEQUENCE.wrapping_add(1);
+        }
+        // - keep ADC1 issuing DMA requests after each DMA transfer completes
         let adc1 = ADC1.borrow(cs);
-        unsafe { (*adc1).cr.modify(|_, w| w.adstart().bits(1)); }
+        adc1.cfgr.modify(|_, w| unsafe { w.dmacfg().bits(1).dmaen().bits(1) });
+        dma1.ccr1.modify(|_, w| unsafe {
+            w.circ().bits(0) // one-shot: the interrupt handler switches buffers
+             .tcie().bits(1) // interrupt on transfer completion
+        });
+        dma1_channel1_point_at(0);
+        adc1.cr.modify(|_, w| unsafe { w.adstart().bits(1) });
     });
 }
 
-/// Returns the number of samples transferred by DMA to RAM.
-pub fn get_transferred_sample_count() -> usize {
+/// Switches between one- and two-channel capture. In two-channel capture, each TIM15 trigger
+/// converts `CAPTURE_ADC_CHANNEL` then `CAPTURE_ADC_CHANNEL_B`, so the buffers hold interleaved
+/// pairs of samples (A, B, A, B, ...), and each pair takes two conversion times, which halves the
+/// maximum sample rate (see `max_sample_rate`). Continuous capture, if running, restarts at the
+/// beginning of the buffer being filled, so the pairs stay aligned with the buffer; any completed
+/// buffer not yet taken is discarded, since it holds samples in the old layout.
+pub fn set_channel_count(channels: usize) {
+    let channels = if channels == 2 { 2 } else { 1 };
+    adc_stop();
+    unsafe { CHANNEL_COUNT = channels; }
+    select_channels();
+    restart_filling();
+}
+
+/// Sets the ADC sample time of the capture channels, restarting continuous capture, if it is
+/// running, at the beginning of the buffer being filled. Longer sample times lower
+/// `max_sample_rate()`, so the caller should set the sample rate again afterward.
+pub fn set_sample_time(cycles: SampleCycles) {
+    adc_stop();
+    adc::set_sample_time(cycles);
+    select_channels();
+    restart_filling();
+}
+
+/// Returns the ADC sample time of the capture channels.
+pub fn sample_time() -> SampleCycles {
+    adc::sample_time()
+}
+
+// make the capture channel(s), CHANNEL_COUNT of them, ADC1's regular sequence
+fn select_channels() {
+    if channel_count() == 2 {
+        adc_select_channels(CAPTURE_ADC_CHANNEL, CAPTURE_ADC_CHANNEL_B);
+    } else {
+        adc_select_channel(CAPTURE_ADC_CHANNEL);
+    }
+}
+
+/// Stops capture while `read` makes software-triggered conversions of its own (e.g. with
+/// `read_vdda_mv`, which leave the capture configuration as they found it), then restarts
+/// continuous capture, if it is running, at the beginning of the buffer being filled, discarding
+/// the sweep in progress and any completed buffer not yet taken. Returns what `read` returns.
+pub fn with_capture_paused<R, F: FnOnce() -> R>(read: F) -> R {
+    adc_stop();
+    let result = read();
+    restart_filling();
+    result
+}
+
+// restart continuous capture, if it is running, at the beginning of the buffer being filled
+fn restart_filling() {
+    cortex_m::interrupt::free(|cs| unsafe {
+        if let Some(filling) = DMA_FILLING {
+            DMA_COMPLETED = None;
+            STREAM_BUFFER = filling;
+            STREAM_POSITION = 0;
+            DMA_SEQUENCE = DMA_SEQUENCE.wrapping_add(1);
+            dma1_channel1_point_at(filling);
+            let adc1 = ADC1.borrow(cs);
+            adc1.cr.modify(|_, w| w.adstart().bits(1));
+        }
+    });
+}
+
+/// Returns the number of channels being captured, 1 or 2.
+pub fn channel_count() -> usize {
+    unsafe { CHANNEL_COUNT }
+}
+
+/// Returns the length of the continuous-capture buffers, in samples, or 0 if continuous capture
+/// isn't running.
+pub fn buffer_length() -> usize {
+    unsafe {
+        match DMA_FILLING {
+            Some(_) => DMA_BUFFER[0].1,
+            None => 0,
+        }
+    }
+}
+
+/// Returns the fastest sample rate, per channel, that capture supports with the current number of
+/// channels and ADC sample time: `adc_max_sample_rate()` for one channel, half that for two.
+pub fn max_sample_rate() -> u32 {
+    adc_max_sample_rate() / channel_count() as u32
+}
+
+/// A completed continuous-capture buffer, handed out by `adc_dma_ready()`. DMA doesn't write to
+/// it while it is held, so its samples never alias the buffer being filled; dropping it gives it
+/// back, so that DMA fills both buffers in turn again, rather than refilling the other one and
+/// discarding its captures.
+pub struct DmaBuffer {
+    index: usize, // 0 for buffer A, 1 for buffer B
+}
+
+impl DmaBuffer {
+    /// Returns the buffer's samples, interleaved A, B, A, B, ... in two-channel capture.
+    pub fn samples(&self) -> &[u16] {
+        unsafe {
+            let (address, len) = DMA_BUFFER[self.index];
+            core::slice::from_raw_parts(address as *const u16, len)
+        }
+    }
+
+    /// Returns the number of the buffer, in the order DMA completed them, where one numbered one
+    /// more than another straight follows it: nothing was discarded between them, and neither the
+    /// sample rate nor the channels changed.
+    pub fn sequence(&self) -> u32 {
+        cortex_m::interrupt::free(|_| unsafe { DMA_COMPLETED_SEQUENCE[self.index] })
+    }
+
+    /// Returns where the first `edge` on the external trigger input came during the buffer's
+    /// capture: the index of the first sample of each channel taken after it. Returns `None` if
+    /// there wasn't one.
+    pub fn ext_trigger_position(&self, edge: Edge) -> Option<usize> {
+        let edge_index = if edge == Edge::Rising { 0 } else { 1 };
+        cortex_m::interrupt::free(|_| unsafe {
+            EXT_TRIGGERS[self.index][edge_index].map(|p| p / CHANNEL_COUNT)
+        })
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        cortex_m::interrupt::free(|_| unsafe {
+            if DMA_HELD == Some(self.index) {
+                DMA_HELD = None;
+            }
+        });
+    }
+}
+
+/// Returns the most recently completed continuous-capture buffer, if one has completed since the
+/// last call, and no buffer returned before is still held. (If DMA finishes a buffer while the
+/// other is held, that capture is discarded and the buffer refilled.)
+pub fn adc_dma_ready() -> Option<DmaBuffer> {
+    cortex_m::interrupt::free(|_| unsafe {
+        if DMA_HELD.is_some() {
+            return None;
+        }
+        DMA_HELD = DMA_COMPLETED.take();
+        DMA_HELD.map(|index| DmaBuffer { index: index })
+    })
+}
+
+/// Returns the number of samples of each channel in the buffers DMA has completed since capture
+/// began, whether or not they were taken, wrapping, e.g. for keeping time by the sample rate.
+pub fn samples_captured() -> u32 {
+    unsafe { core::ptr::read_volatile(&SAMPLES_CAPTURED) }
+}
+
+/// Copies the samples captured since the last call into `out`, as far as it has room, and returns
+/// the number copied, for following continuous capture sample by sample rather than a buffer at a
+/// time. Once DMA has moved on to the other buffer, the rest of the previous one is copied first.
+/// While a buffer from `adc_dma_ready()` is held, DMA keeps refilling the other one, so the two
+/// shouldn't be used together. Samples are lost if this isn't called at least once every
+/// buffer's worth of samples.
+pub fn adc_dma_stream(out: &mut [u16]) -> usize {
+    cortex_m::interrupt::free(|_| unsafe {
+        let filling = match DMA_FILLING {
+            Some(i) => i,
+            None => return 0,
+        };
+        let dma1 = DMA1.get();
+        let mut n = 0;
+        while n < out.len() {
+            let (address, len) = DMA_BUFFER[STREAM_BUFFER];
+            let written = if STREAM_BUFFER == filling {
+                len - (*dma1).cndtr1.read().ndt().bits() as usize
+            } else {
+                len
+            };
+            if STREAM_POSITION > written {
+                STREAM_POSITION = 0; // the buffer is being refilled
+            }
+            if STREAM_POSITION == written {
+                if STREAM_BUFFER == filling {
+                    break; // caught up
+                }
+                STREAM_BUFFER = filling;
+                STREAM_POSITION = 0;
+                continue;
+            }
+            let sample = (address as *const u16).offset(STREAM_POSITION as isize);
+            out[n] = core::ptr::read_volatile(sample);
+            STREAM_POSITION += 1;
+            n += 1;
+        }
+        n
+    })
+}
+
+/// The result of polling for something which may not have finished yet.
+pub enum Poll<T> {
+    Ready(T),
+    Pending,
+}
+
+/// Starts capturing a buffer afresh, for `adc_capture_poll()` to return once it is full, so that
+/// the caller can get on with other work meanwhile. This restarts continuous capture, which must
+/// be running, at the beginning of the buffer being filled, discarding the sweep in progress and
+/// any completed buffer not yet taken, so that every sample of the buffer returned is captured
+/// after this call (e.g. at a new sample rate). No buffer from `adc_dma_ready()` may be held
+/// meanwhile, or none can be returned.
+pub fn adc_capture_start() {
+    debug_assert!(unsafe { DMA_HELD.is_none() });
+    unsafe { CAPTURE_PENDING = true; }
+    adc_stop();
+    restart_filling();
+}
+
+/// Returns `Ready` with the buffer started by `adc_capture_start()` once it is full, or `Pending`
+/// until then, or if none was started. It is taken with `adc_dma_ready()`, and so held, like any
+/// buffer from that, until it is dropped.
+pub fn adc_capture_poll() -> Poll<DmaBuffer> {
+    if !unsafe { CAPTURE_PENDING } {
+        return Poll::Pending;
+    }
+    match adc_dma_ready() {
+        Some(buffer) => {
+            unsafe { CAPTURE_PENDING = false; }
+            Poll::Ready(buffer)
+        }
+        None => Poll::Pending,
+    }
+}
+
+/// Waits for a buffer captured entirely since the sample rate was last changed, and copies its
+/// channel A samples into `samples`, as far as it has room, returning the number copied. This
+/// busy-waits for up to a buffer's worth of samples, and no buffer from `adc_dma_ready()` may be
+/// held meanwhile.
+pub fn adc_dma_fresh(samples: &mut [u16]) -> usize {
+    adc_capture_start();
+    let held = adc_capture_wait();
+    let buffer = held.samples();
+    let stride = channel_count();
+    let n = core::cmp::min(samples.len(), buffer.len() / stride);
+    for i in 0..n {
+        samples[i] = buffer[i * stride];
+    }
+    n
+}
+
+// wait for the buffer started by adc_capture_start()
+fn adc_capture_wait() -> DmaBuffer {
+    loop {
+        if let Poll::Ready(buffer) = adc_capture_poll() {
+            return buffer;
+        }
+    }
+}
+
+/// Returns the continuous-capture buffer DMA is filling (0 for buffer A, 1 for buffer B), and the
+/// samples it has written to it so far, as far as its transfer count shows, for drawing a sweep
+/// as it is captured; or `None` if continuous capture isn't running. The samples returned stay
+/// put until DMA comes back around to this buffer, once it has filled the other one, so they
+/// need to be used straight away.
+pub fn adc_dma_filling() -> Option<(usize, &'static [u16])> {
+    cortex_m::interrupt::free(|_| unsafe {
+        DMA_FILLING.map(|i| {
+            let (address, len) = DMA_BUFFER[i];
+            let written = len - (*DMA1.get()).cndtr1.read().ndt().bits() as usize;
+            (i, core::slice::from_raw_parts(address as *const u16, written))
+        })
+    })
+}
+
+// point DMA1 channel 1 at continuous-capture buffer `i` and enable it
+fn dma1_channel1_point_at(i: usize) {
+    let dma1 = DMA1.get();
+    unsafe {
+        let (address, len) = DMA_BUFFER[i];
+        EXT_TRIGGERS[i] = [None; 2];
+        (*dma1).ccr1.modify(|_, w| w.en().bits(0));
+        (*dma1).cmar1.write(|w| w.bits(address)); // memory base address
+        (*dma1).cndtr1.write(|w| w.ndt().bits(len as u16)); // buffer size
+        (*dma1).ccr1.modify(|_, w| w.en().bits(1));
+    }
+}
+
+/// Handles the DMA1 channel 1 transfer-complete interrupt; called from the interrupt handler. In
+/// continuous capture, this moves DMA on to the other buffer, unless it is held by the reader.
+pub fn dma1ch1_interrupt() {
     let dma1 = DMA1.get();
-    160 - unsafe { (*dma1).cndtr1.read().ndt().bits() } as usize
+    unsafe {
+        let isr = (*dma1).isr.read();
+        if isr.tcif1().bits() != 0 {
+            // clear the DMA1 channel 1 transfer complete interrupt flag TCIF
+            (*dma1).ifcr.write(|w| w.ctcif1().bits(1));
+            if let Some(filled) = DMA_FILLING {
+                let samples = (DMA_BUFFER[filled].1 / CHANNEL_COUNT) as u32;
+                SAMPLES_CAPTURED = SAMPLES_CAPTURED.wrapping_add(samples);
+                DMA_SEQUENCE = DMA_SEQUENCE.wrapping_add(1);
+                DMA_COMPLETED_SEQUENCE[filled] = DMA_SEQUENCE;
+                let next = 1 - filled;
+                if DMA_HELD == Some(next) {
+                    // the reader still has the other buffer, so drop this capture
+                    dma1_channel1_point_at(filled);
+                } else {
+                    DMA_COMPLETED = Some(filled);
+                    DMA_FILLING = Some(next);
+                    dma1_channel1_point_at(next);
+                }
+            }
+        }
+    }
+}
+
+// ======== external trigger ========
+
+// The external trigger input is PD1, a logic-level input with a pull-down, on EXTI
+// line 1. Each edge on it interrupts, and is placed among the samples by how far DMA has gotten
+// through the buffer being filled: the first rising and the first falling edge of each buffer are
+// kept, to go with the buffer when adc_dma_ready() hands it out (see
+// `DmaBuffer::ext_trigger_position()`).
+
+// the GPIOD pin, and so the EXTI line, of the external trigger input
+const EXT_TRIGGER_PIN: u32 = 1;
+
+// by buffer, then rising and falling edge, the sample position of the first such edge
+static mut EXT_TRIGGERS: [[Option<usize>; 2]; 2] = [[None; 2]; 2];
+
+// set up PD1 as the external trigger input, with its EXTI interrupt masked until it is enabled
+fn ext_trigger_setup() {
+    cortex_m::interrupt::free(|cs| {
+        let rcc = RCC.borrow(cs);
+        rcc.ahbenr.modify(|_, w| w.iopden().enabled());
+        rcc.apb2enr.modify(|_, w| w.syscfgen().enabled());
+        // input, with a pull-down, so an unconnected input doesn't trigger
+        let gpiod = GPIOD.borrow(cs);
+        let shift = EXT_TRIGGER_PIN * 2;
+        gpiod.moder.modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << shift)) });
+        gpiod.pupdr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(0b11 << shift)) | (0b10 << shift))
+        });
+        // connect EXTI line 1 to port D, interrupting on both edges
+        let syscfg = SYSCFG.borrow(cs);
+        syscfg.exticr1.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(0b1111 << 4)) | (0b0011 << 4)) // EXTI1 field: PD
+        });
+        let exti = EXTI.borrow(cs);
+        exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << EXT_TRIGGER_PIN)) });
+        exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << EXT_TRIGGER_PIN)) });
+        exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << EXT_TRIGGER_PIN)) });
+        let nvic = cortex_m::peripheral::NVIC.borrow(cs);
+        unsafe { nvic.set_priority(Interrupt::Exti1, 0); }
+        nvic.enable(Interrupt::Exti1);
+    });
 }
 
-/// Returns a reference to the sampled data for channel 1. Use `get_transferred_sample_count()` to
-/// determine how many samples are valid.
-pub fn channel_1_data() -> &'static [u16] {
-    unsafe { &CAPTURE_CHANNEL_1 }
+/// Turns the timing of edges on the external trigger input on or off. While it is off, the input
+/// is ignored, so it costs nothing if the input is busy.
+pub fn ext_trigger_enable(enable: bool) {
+    cortex_m::interrupt::free(|cs| {
+        let exti = EXTI.borrow(cs);
+        exti.imr1.modify(|r, w| unsafe {
+            if enable {
+                w.bits(r.bits() | (1 << EXT_TRIGGER_PIN))
+            } else {
+                w.bits(r.bits() & !(1 << EXT_TRIGGER_PIN))
+            }
+        });
+        unsafe { EXT_TRIGGERS = [[None; 2]; 2]; }
+    });
 }
 
-/// Turns off DMA and prepares for the next sweep.
-pub fn finish_sweep() {
+/// Handles the EXTI line 1 interrupt; called from the interrupt handler. This records an edge on
+/// the external trigger input against the buffer being filled.
+pub fn exti1_interrupt() {
+    let exti = EXTI.get();
     let dma1 = DMA1.get();
-    // - disable DMA
-    unsafe { (*dma1).ccr1.modify(|_, w| w.en().bits(0)); }
-    // - re-set transfer length
-    unsafe { (*dma1).cndtr1.write(|w| w.ndt().bits(160)); }
+    unsafe {
+        (*exti).pr1.write(|w| w.bits(1 << EXT_TRIGGER_PIN)); // clear the pending bit
+        if let Some(filling) = DMA_FILLING {
+            // (the pin has settled at the level the edge went to)
+            let high = (*GPIOD.get()).idr.read().bits() & (1 << EXT_TRIGGER_PIN) != 0;
+            let edge_index = if high { 0 } else { 1 };
+            if EXT_TRIGGERS[filling][edge_index].is_none() {
+                let len = DMA_BUFFER[filling].1;
+                let position = len - (*dma1).cndtr1.read().ndt().bits() as usize;
+                EXT_TRIGGERS[filling][edge_index] = Some(position);
+            }
+        }
+    }
 }
 
-/// Checks the AC OVR overrun flag, and clears it if set. Returns its value before it was cleared.
+static mut ADC_OVERRUNS: u32 = 0; // times check_adc_ovr_flag() has found OVR set, wrapping
+
+/// Checks the ADC OVR overrun flag, and clears it if set, counting it for `adc_overruns()`.
+/// Returns its value before it was cleared.
 pub fn check_adc_ovr_flag() -> bool {
     // test and return ADC OVR flag
     let adc1 = ADC1.get();
     let ovr = unsafe { (*adc1).isr.read().ovr().bits() } != 0;
     if ovr {
         // - OVR was set, clear it
-        unsafe { (*adc1).isr.modify(|_, w| w.ovr().bits(1)); }
+        unsafe {
+            (*adc1).isr.modify(|_, w| w.ovr().bits(1));
+            ADC_OVERRUNS = ADC_OVERRUNS.wrapping_add(1);
+        }
     }
     ovr
 }
 
-/// Sets the timebase for sampling, to the specified number of samples per second.
-/// This sets the TIM15 update rate, and -FIX- should set the sample time as well, but doesn't yet.
+/// Returns the number of times `check_adc_ovr_flag()` has found an ADC overrun since power-on
+/// (wrapping).
+pub fn adc_overruns() -> u32 {
+    unsafe { ADC_OVERRUNS }
+}
+
+/// Sets the timebase for sampling, to the specified number of samples per second (per channel, in
+/// two-channel capture). Rates faster than the ADC can convert are limited to
+/// `max_sample_rate()`, which depends on the ADC sample time set by `set_sample_time()`. Returns
+/// the rate actually set.
+pub fn set_timebase(samples_per_second: u32) -> u32 {
+    let rate = core::cmp::min(samples_per_second, max_sample_rate());
+    set_sample_timer(rate).unwrap_or(0)
+}
+
+/// Programs TIM15 to trigger an ADC conversion sequence `hz` times per second, as near as
+/// `apb2_timer_divider()` can divide its clock down to it. Returns the rate actually achieved,
+/// rounded to the nearest whole sample per second, or `None` (leaving the timer unchanged) if `hz`
+/// is zero or faster than `max_sample_rate()`.
 // -FIX- this works well out to 1 sample per second, but it might be cool to implement very long
 // sample intervals, e.g. one sample per minute or more.
-pub fn set_timebase(samples_per_second: u32) {
-    let arr;
-    let psc;
-    if samples_per_second > 1097 {
-        arr = 72_000_000 / samples_per_second - 1;
-        psc = 0;
-    } else {
-        arr = (72_000_000 / 2250) / samples_per_second - 1;
-        psc = 2249;
+pub fn set_sample_timer(hz: u32) -> Option<u32> {
+    if hz == 0 || hz > max_sample_rate() {
+        return None;
     }
+    let (psc, arr) = apb2_timer_divider(hz);
     cortex_m::interrupt::free(|cs| {
         let tim15 = TIM15.borrow(cs);
-        tim15.arr.write(|w| unsafe { w.bits(arr) });
+        tim15.arr.write(|w| unsafe { w.bits(arr as u32) });
         tim15.psc.write(|w| unsafe { w.psc().bits(psc) });
         tim15.cnt.write(|w| unsafe { w.cnt().bits(0) });
         tim15.egr.write(|w| unsafe { w.ug().bits(1) }); // immediately update registers
+        // (the buffer being filled is partly at the old rate, so it doesn't follow on from the
+        // last one)
+        unsafe { DMA_SEQUENCE = DMA_SEQUENCE.wrapping_add(1); }
     });
+    Some(apb2_timer_rate(psc, arr))
+}
+
+// ======== calibration ========
+
+/// The sample rate used while calibrating, in samples per second: slow enough that each buffer
+/// spans a couple of cycles of 50Hz or 60Hz mains hum, so that averaging cancels it.
+const CALIBRATION_SAMPLE_RATE: u32 = 10_000;
+
+/// The number of buffers averaged for each calibration measurement.
+const CALIBRATION_BUFFERS: u32 = 4;
+
+/// Measures the offset, as the average reading with the input grounded, and makes it the offset
+/// of the calibration in use (see `calibration::set_measured_offset()`). This captures at its own
+/// sample rate, so the caller must set the timebase again afterward. Returns the new offset.
+pub fn calibrate_offset() -> u16 {
+    let offset = measure_input();
+    calibration::set_measured_offset(offset);
+    offset
+}
+
+/// Measures the scale, from the average reading with `known_mv` millivolts applied to the input
+/// itself, and makes it the scale of the calibration in use (see
+/// `calibration::set_measured_scale()`). This captures at its own sample rate, so the caller must
+/// set the timebase again afterward. Returns false, leaving the calibration unchanged, if the
+/// reading is implausible.
+pub fn calibrate_scale(known_mv: u16) -> bool {
+    if known_mv == 0 {
+        return false;
+    }
+    calibration::set_measured_scale(measure_input(), known_mv)
+}
+
+// the average reading over CALIBRATION_BUFFERS buffers captured at CALIBRATION_SAMPLE_RATE from
+// channel A
+fn measure_input() -> u16 {
+    set_timebase(CALIBRATION_SAMPLE_RATE);
+    let mut samples = [0u16; SAMPLE_COUNT];
+    let mut sum = 0;
+    for _ in 0..CALIBRATION_BUFFERS {
+        let n = adc_dma_fresh(&mut samples);
+        sum += mean(&samples[..n]) as u32;
+    }
+    ((sum + CALIBRATION_BUFFERS / 2) / CALIBRATION_BUFFERS) as u16
 }
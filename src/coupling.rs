@@ -0,0 +1,89 @@
+// stm32f3-oscilloscope - src/coupling.rs
+// AC/DC input coupling
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// An input stage with a DC-blocking capacitor in series with the input can have it bypassed, for
+// DC coupling, by a relay or analog switch driven from PD3: high selects AC coupling (the
+// capacitor in circuit), and low DC coupling (the capacitor bypassed). PD3 is driven low from
+// coupling_init() on, but floats before then, so the switch should be off, i.e. DC coupling, with
+// no drive. With the capacitor in circuit, the ADC side of it must be biased to about mid-scale,
+// since the ADC can't see below ground.
+//
+// Whether or not the switch is fitted, AC coupling is also done in software: the DC level of each
+// captured buffer, its mean, is taken out of the displayed trace, which is centered on mid-scale
+// instead, and the trigger level is taken relative to it.
+
+use core;
+
+use cortex_m;
+use stm32f30x::{GPIOD, RCC};
+
+use calibration::center_counts;
+use measure::mean;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Coupling {
+    Dc,
+    Ac,
+}
+
+// the GPIOD pin which drives the coupling switch
+const COUPLING_PIN: u32 = 3;
+
+/// Sets up PD3 as a push-pull output to drive the coupling switch, selecting DC coupling.
+pub fn coupling_init() {
+    cortex_m::interrupt::free(|cs| {
+        let rcc = RCC.borrow(cs);
+        rcc.ahbenr.modify(|_, w| w.iopden().enabled());
+        let gpiod = GPIOD.borrow(cs);
+        gpiod.bsrr.write(|w| unsafe { w.bits(1 << (COUPLING_PIN + 16)) }); // low, for DC
+        let shift = COUPLING_PIN * 2;
+        gpiod.moder.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(0b11 << shift)) | (0b01 << shift)) // output
+        });
+    });
+}
+
+/// Switches the input to `coupling`, on the switch driven from PD3.
+pub fn set_coupling(coupling: Coupling) {
+    let bit = match coupling {
+        Coupling::Ac => 1 << COUPLING_PIN,        // set: high
+        Coupling::Dc => 1 << (COUPLING_PIN + 16), // reset: low
+    };
+    cortex_m::interrupt::free(|cs| {
+        GPIOD.borrow(cs).bsrr.write(|w| unsafe { w.bits(bit) });
+    });
+}
+
+/// Returns the DC level of `samples`, their mean, as an offset from the center reading (see
+/// `center_counts`), in ADC counts.
+pub fn dc_offset(samples: &[u16]) -> i16 {
+    mean(samples) as i16 - center_counts() as i16
+}
+
+/// Takes `offset`, e.g. from `dc_offset`, out of each of `samples`, clipped to the 12-bit ADC
+/// range, so that a signal at that DC level is centered on the center reading.
+pub fn remove_dc(samples: &mut [u16], offset: i16) {
+    for sample in samples.iter_mut() {
+        *sample = core::cmp::max(0, core::cmp::min(*sample as i32 - offset as i32, 4095)) as u16;
+    }
+}
@@ -0,0 +1,124 @@
+// stm32f3-oscilloscope - src/cursors.rs
+// measurement cursors: two time cursors and two voltage cursors
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Cursor positions are kept in screen pixels, the time cursors as columns counted from the left,
+// and the voltage cursors as rows counted from the top, so they can be drawn directly. Only the
+// differences between them are converted to time and voltage, using the sample rate and vertical
+// gain the trace is drawn with.
+
+use st7735::y_to_sample;
+use vscale::VerticalScale;
+
+/// How far each button press moves a cursor, in pixels.
+const CURSOR_STEP: i32 = 4;
+
+/// The cursors, in the order button 4 selects them.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Cursor {
+    Time1,
+    Time2,
+    Volts1,
+    Volts2,
+}
+
+impl Cursor {
+    /// Returns the cursor's label, for showing which one is selected.
+    pub fn label(&self) -> &'static [u8] {
+        match *self {
+            Cursor::Time1 => b"t1",
+            Cursor::Time2 => b"t2",
+            Cursor::Volts1 => b"v1",
+            Cursor::Volts2 => b"v2",
+        }
+    }
+}
+
+pub struct Cursors {
+    positions: [u8; 4], // indexed by Cursor
+    width: u8,
+    height: u8,
+}
+
+impl Cursors {
+    /// Returns cursors for a screen `width` by `height` pixels, with the time cursors at a quarter
+    /// and three quarters of the way across it, and the voltage cursors likewise down it.
+    pub fn new(width: u8, height: u8) -> Cursors {
+        Cursors {
+            positions: [width / 4, width - width / 4, height / 4, height - height / 4],
+            width: width,
+            height: height,
+        }
+    }
+
+    /// Returns the column of time cursor `cursor`, or the row of voltage cursor `cursor`.
+    pub fn position(&self, cursor: Cursor) -> u8 {
+        self.positions[cursor as usize]
+    }
+
+    /// Moves `cursor` one step right (for a time cursor) or up (for a voltage cursor) if `step` is
+    /// positive, or left or down if it is negative, stopping at the edges of the screen. Returns
+    /// true if it moved.
+    pub fn move_cursor(&mut self, cursor: Cursor, step: i32) -> bool {
+        let (position, limit) = match cursor {
+            Cursor::Time1 | Cursor::Time2 => {
+                (self.positions[cursor as usize] as i32 + step * CURSOR_STEP, self.width)
+            }
+            Cursor::Volts1 | Cursor::Volts2 => {
+                (self.positions[cursor as usize] as i32 - step * CURSOR_STEP, self.height)
+            }
+        };
+        let position = if position < 0 {
+            0
+        } else if position > limit as i32 - 1 {
+            limit - 1
+        } else {
+            position as u8
+        };
+        let moved = position != self.positions[cursor as usize];
+        self.positions[cursor as usize] = position;
+        moved
+    }
+
+    /// Returns the time between the two time cursors, in microseconds, for a trace drawn with one
+    /// sample per column at `sample_rate` samples per second.
+    pub fn delta_microseconds(&self, sample_rate: u32) -> u32 {
+        if sample_rate == 0 {
+            return 0;
+        }
+        let columns = distance(self.position(Cursor::Time1), self.position(Cursor::Time2));
+        ((columns as u64 * 1_000_000 + sample_rate as u64 / 2) / sample_rate as u64) as u32
+    }
+
+    /// Returns the difference between the two voltage cursors, in ADC counts, for a trace drawn
+    /// at vertical scale `scale`.
+    pub fn delta_counts(&self, scale: &VerticalScale) -> u16 {
+        let v1 = y_to_sample(self.position(Cursor::Volts1) as i16, scale, 0);
+        let v2 = y_to_sample(self.position(Cursor::Volts2) as i16, scale, 0);
+        if v1 > v2 { v1 - v2 } else { v2 - v1 }
+    }
+}
+
+fn distance(a: u8, b: u8) -> u8 {
+    if a > b { a - b } else { b - a }
+}
@@ -0,0 +1,158 @@
+// stm32f3-oscilloscope - src/display.rs
+// the drawing primitives a display provides, as a trait
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The Display trait is the handful of primitives the screen's drawing comes down to: setting a
+// window of pixels, and pushing colors into it. A display only has to provide those and its size;
+// the rest have default versions built on them, which the tests below check against a stand-in
+// recording what is drawn. The ST7735 driver implements it (in src/st7735.rs).
+//
+// Only the self-test's color bars are drawn through the trait, so far. The traces, text, and the
+// rest of the screen are drawn by the driver's st7735_* functions directly, which go around it
+// for speed, e.g. with DMA fills and the trace's per-column erasing, and moving those over would
+// mean redoing them as trait methods; until then, the trait is for new drawing code which wants
+// to be tested on the host.
+
+use core;
+
+/// A screen which can be drawn on, with colors in RGB565.
+pub trait Display {
+    /// The width of the screen, in pixels, in its current orientation.
+    fn width(&self) -> u8;
+
+    /// The height of the screen, in pixels, in its current orientation.
+    fn height(&self) -> u8;
+
+    /// Sets the window, from (`x0`, `y0`) to (`x1`, `y1`) inclusive, which the colors pushed next
+    /// fill, across each row from the top left.
+    fn set_window(&mut self, x0: u8, y0: u8, x1: u8, y1: u8);
+
+    /// Pushes the color of the next pixel in the window.
+    fn push_color(&mut self, color: u16);
+
+    /// Fills the `w` by `h` pixel rectangle at (`x`, `y`) with `color`, clipped to the screen.
+    fn fill(&mut self, x: i16, y: i16, w: i16, h: i16, color: u16) {
+        let (x0, y0) = (core::cmp::max(x, 0), core::cmp::max(y, 0));
+        let x1 = core::cmp::min(x + w, self.width() as i16) - 1;
+        let y1 = core::cmp::min(y + h, self.height() as i16) - 1;
+        if x1 < x0 || y1 < y0 {
+            return;
+        }
+        self.set_window(x0 as u8, y0 as u8, x1 as u8, y1 as u8);
+        for _ in 0..(x1 - x0 + 1) as u32 * (y1 - y0 + 1) as u32 {
+            self.push_color(color);
+        }
+    }
+
+    /// Draws a vertical line `h` pixels long down from (`x`, `y`) in `color`, clipped to the
+    /// screen.
+    fn draw_vline(&mut self, x: i16, y: i16, h: i16, color: u16) {
+        self.fill(x, y, 1, h, color);
+    }
+
+    /// Draws the pixel at (`x`, `y`) in `color`, if it's on the screen.
+    fn draw_pixel(&mut self, x: i16, y: i16, color: u16) {
+        self.fill(x, y, 1, 1, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 4 by 3 screen which records what is drawn on it
+    struct Recorder {
+        pixels: [[u16; 4]; 3],
+        windows: Vec<(u8, u8, u8, u8)>,
+        x: u8,
+        y: u8,
+    }
+
+    impl Recorder {
+        fn new() -> Recorder {
+            Recorder { pixels: [[0; 4]; 3], windows: Vec::new(), x: 0, y: 0 }
+        }
+    }
+
+    impl Display for Recorder {
+        fn width(&self) -> u8 {
+            4
+        }
+
+        fn height(&self) -> u8 {
+            3
+        }
+
+        fn set_window(&mut self, x0: u8, y0: u8, x1: u8, y1: u8) {
+            self.windows.push((x0, y0, x1, y1));
+            self.x = x0;
+            self.y = y0;
+        }
+
+        fn push_color(&mut self, color: u16) {
+            let (x0, _, x1, _) = *self.windows.last().unwrap();
+            self.pixels[self.y as usize][self.x as usize] = color;
+            if self.x == x1 {
+                self.x = x0;
+                self.y += 1;
+            } else {
+                self.x += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn fill_covers_the_rectangle_row_by_row() {
+        let mut display = Recorder::new();
+        display.fill(1, 0, 2, 2, 7);
+        assert_eq!(display.windows, vec![(1, 0, 2, 1)]);
+        assert_eq!(display.pixels, [[0, 7, 7, 0], [0, 7, 7, 0], [0, 0, 0, 0]]);
+    }
+
+    #[test]
+    fn fill_is_clipped_to_the_screen() {
+        let mut display = Recorder::new();
+        display.fill(-2, 2, 4, 5, 9);
+        assert_eq!(display.windows, vec![(0, 2, 1, 2)]);
+        assert_eq!(display.pixels, [[0; 4], [0; 4], [9, 9, 0, 0]]);
+    }
+
+    #[test]
+    fn fill_off_the_screen_draws_nothing() {
+        let mut display = Recorder::new();
+        display.fill(4, 0, 2, 2, 1);
+        display.fill(0, -3, 2, 3, 1);
+        display.fill(1, 1, 0, 1, 1);
+        assert!(display.windows.is_empty());
+    }
+
+    #[test]
+    fn vline_and_pixel_are_one_column_fills() {
+        let mut display = Recorder::new();
+        display.draw_vline(3, 1, 5, 2);
+        display.draw_pixel(0, 0, 5);
+        display.draw_pixel(-1, 0, 5);
+        assert_eq!(display.windows, vec![(3, 1, 3, 2), (0, 0, 0, 0)]);
+        assert_eq!(display.pixels, [[5, 0, 0, 0], [0, 0, 0, 2], [0, 0, 0, 2]]);
+    }
+}
@@ -0,0 +1,64 @@
+// stm32f3-oscilloscope - src/elapsed.rs
+// rollover-safe comparisons of millisecond counts
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The millisecond count from timing::millis() wraps after about 49 days, so a time is never
+// compared with another directly: the difference between them, taken with wrapping arithmetic, is
+// the time from one to the other, across the wrap or not, as long as they're less than 49 days
+// apart. Everything timed with the count (delays, the trigger holdoff, the LED and backlight
+// timeouts) goes through here, so that this is the one place to get it right.
+
+/// Returns the milliseconds from `since_ms` to `now_ms`, both readings of a wrapping millisecond
+/// count.
+pub fn elapsed_ms(since_ms: u32, now_ms: u32) -> u32 {
+    now_ms.wrapping_sub(since_ms)
+}
+
+/// Returns true if at least `ms` milliseconds have passed from `since_ms` to `now_ms`, both
+/// readings of a wrapping millisecond count.
+pub fn has_elapsed(since_ms: u32, now_ms: u32, ms: u32) -> bool {
+    elapsed_ms(since_ms, now_ms) >= ms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_across_the_wrap() {
+        assert_eq!(elapsed_ms(0xffff_ffff, 0), 1);
+        assert_eq!(elapsed_ms(0xffff_ff00, 0x0000_0100), 0x200);
+        assert_eq!(elapsed_ms(1_000, 1_000), 0);
+    }
+
+    #[test]
+    fn has_elapsed_across_the_wrap() {
+        assert!(has_elapsed(0xffff_ffff, 0, 1));
+        assert!(!has_elapsed(0xffff_ffff, 0, 2));
+        assert!(has_elapsed(0xffff_ffff, 0, 0));
+        // a minute short of the rollover, as the millis-rollover feature starts the count, to a
+        // second past it
+        assert!(has_elapsed(0xffff_15a0, 999, 60_999));
+        assert!(!has_elapsed(0xffff_15a0, 999, 61_000));
+    }
+}
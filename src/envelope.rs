@@ -0,0 +1,154 @@
+// stm32f3-oscilloscope - src/envelope.rs
+// min/max envelope of the displayed sweeps, kept until reset ("infinite persistence")
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The envelope is the range of samples, lowest to highest, drawn in each screen column by every
+// sweep added to it since it was reset, so that a glitch seen only once in thousands of sweeps
+// still shows. It never fades or narrows until it is reset, and nothing else resets it: not
+// stopping and running the display, nor hiding the envelope, which only stops sweeps from being
+// added. It's kept in ADC counts, so it can be drawn at any gain.
+
+use core;
+
+use DISPLAY_WIDTH;
+
+/// The min/max envelope of the sweeps drawn, column by column.
+pub struct Envelope {
+    lowest: [u16; DISPLAY_WIDTH],
+    highest: [u16; DISPLAY_WIDTH],
+    len: usize, // columns, or 0 if nothing has been added since the last reset
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            lowest: [0; DISPLAY_WIDTH],
+            highest: [0; DISPLAY_WIDTH],
+            len: 0,
+        }
+    }
+
+    /// Widens the envelope to take in `samples`, spread across `columns` columns (at most
+    /// DISPLAY_WIDTH), as `st7735_draw_envelope` spreads them. Returns true if the envelope
+    /// changed. If the number of columns isn't what it was (e.g. the screen has been rotated), the
+    /// envelope starts again from `samples`, since its columns no longer line up with the
+    /// screen's.
+    pub fn add(&mut self, samples: &[u16], columns: usize) -> bool {
+        let columns = core::cmp::min(core::cmp::min(samples.len(), columns), DISPLAY_WIDTH);
+        if columns == 0 {
+            return false;
+        }
+        let fresh = self.len != columns;
+        let mut changed = fresh;
+        let mut start = 0;
+        for x in 0..columns {
+            let end = (x + 1) * samples.len() / columns;
+            if fresh {
+                self.lowest[x] = samples[start];
+                self.highest[x] = samples[start];
+            }
+            for &sample in samples[start..end].iter() {
+                if sample < self.lowest[x] {
+                    self.lowest[x] = sample;
+                    changed = true;
+                }
+                if sample > self.highest[x] {
+                    self.highest[x] = sample;
+                    changed = true;
+                }
+            }
+            start = end;
+        }
+        self.len = columns;
+        changed
+    }
+
+    /// Empties the envelope, so that it starts again from the next sweep added.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns the envelope's lowest and highest samples, one of each per column, or `None` if
+    /// nothing has been added since it was reset.
+    pub fn bounds(&self) -> Option<(&[u16], &[u16])> {
+        if self.len > 0 {
+            Some((&self.lowest[..self.len], &self.highest[..self.len]))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testgen::{dc, square};
+
+    #[test]
+    fn empty_until_a_sweep_is_added() {
+        let mut envelope = Envelope::new();
+        assert!(envelope.bounds().is_none());
+        assert!(envelope.add(&dc(2048, 320), 160));
+        envelope.reset();
+        assert!(envelope.bounds().is_none());
+    }
+
+    #[test]
+    fn widens_to_take_in_each_sweep_spread_across_the_columns() {
+        let mut envelope = Envelope::new();
+        assert!(envelope.add(&dc(2048, 320), 160));
+        assert!(!envelope.add(&dc(2048, 320), 160)); // (nothing new)
+        assert!(envelope.add(&dc(1000, 320), 160));
+        assert!(envelope.add(&dc(3000, 320), 160));
+        let (lowest, highest) = envelope.bounds().unwrap();
+        assert_eq!(lowest.len(), 160);
+        assert!(lowest.iter().all(|&s| s == 1000));
+        assert!(highest.iter().all(|&s| s == 3000));
+    }
+
+    #[test]
+    fn keeps_the_range_within_each_column() {
+        // a 625 Hz square wave, two periods across 320 samples: each column of 2 samples is all
+        // low or all high
+        let mut envelope = Envelope::new();
+        envelope.add(&square(625.0, 0.5, 1000.0, 2048.0, 320), 160);
+        let (lowest, highest) = envelope.bounds().unwrap();
+        assert_eq!(lowest, highest);
+        assert!(lowest[0] != lowest[40]);
+    }
+
+    #[test]
+    fn starts_again_when_the_columns_change() {
+        let mut envelope = Envelope::new();
+        envelope.add(&dc(1000, 320), 160);
+        assert!(envelope.add(&dc(3000, 128), 128));
+        let (lowest, highest) = envelope.bounds().unwrap();
+        assert_eq!(lowest.len(), 128);
+        assert!(lowest.iter().chain(highest.iter()).all(|&s| s == 3000));
+    }
+}
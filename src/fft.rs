@@ -0,0 +1,170 @@
+// stm32f3-oscilloscope - src/fft.rs
+// fixed-point FFT, for the spectrum display
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// A radix-2, decimation-in-time FFT in 32-bit fixed point, with twiddle factors in Q15 from a
+// quarter-wave sine table. The input is Hann windowed, to keep a tone that doesn't fall exactly on
+// a bin from spreading across the whole spectrum. With 12-bit samples, the transform can grow by
+// at most FFT_SIZE, to 2^20, so nothing overflows and no scaling is needed between stages.
+
+use core;
+
+use measure::{isqrt, mean};
+
+/// The number of samples transformed. SINE_Q15 must have FFT_SIZE / 4 + 1 entries.
+pub const FFT_SIZE: usize = 256;
+
+/// The number of frequency bins in the spectrum, from 0 up to (not including) half the sample
+/// rate, each sample rate / FFT_SIZE wide.
+pub const FFT_BINS: usize = FFT_SIZE / 2;
+
+/// The range of magnitudes shown by `fft_bar_height()`, in octaves (about 6dB each) below full
+/// scale.
+const FFT_RANGE_OCTAVES: u32 = 10;
+
+// the log2 of the magnitude of a full-scale sine (amplitude 2048 counts) centered in a bin, in Q8:
+// 2048 * FFT_SIZE / 2, halved by the window, or 2^17
+const FFT_FULL_SCALE_LOG2_Q8: u32 = 17 << 8;
+
+// sin(2 * pi * i / FFT_SIZE) for the first quarter wave, in Q15
+const SINE_Q15: [i16; FFT_SIZE / 4 + 1] = [
+        0,   804,  1608,  2410,  3212,  4011,  4808,  5602,
+     6393,  7179,  7962,  8739,  9512, 10278, 11039, 11793,
+    12539, 13279, 14010, 14732, 15446, 16151, 16846, 17530,
+    18204, 18868, 19519, 20159, 20787, 21403, 22005, 22594,
+    23170, 23731, 24279, 24811, 25329, 25832, 26319, 26790,
+    27245, 27683, 28105, 28510, 28898, 29268, 29621, 29956,
+    30273, 30571, 30852, 31113, 31356, 31580, 31785, 31971,
+    32137, 32285, 32412, 32521, 32609, 32678, 32728, 32757,
+    32767,
+];
+
+// the transform's working buffers, real and imaginary parts
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut FFT_RE: [i32; FFT_SIZE] = [0; FFT_SIZE];
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut FFT_IM: [i32; FFT_SIZE] = [0; FFT_SIZE];
+
+/// Computes the magnitude spectrum of the first `FFT_SIZE` of `samples` into `magnitudes`, with
+/// the DC level removed, so that bin 0 shows only what doesn't average out. Missing samples are
+/// taken to be at the DC level.
+pub fn fft_magnitudes(samples: &[u16], magnitudes: &mut [u32; FFT_BINS]) {
+    let dc = mean(samples) as i32;
+    unsafe {
+        for i in 0..FFT_SIZE {
+            let x = if i < samples.len() { samples[i] as i32 - dc } else { 0 };
+            // Hann window: (1 - cos) / 2
+            let window = (32767 - cosine_q15(i)) / 2;
+            FFT_RE[reverse_bits(i)] = (x * window) >> 15;
+            FFT_IM[reverse_bits(i)] = 0;
+        }
+        let mut half = 1;
+        while half < FFT_SIZE {
+            let stride = FFT_SIZE / (half * 2); // between twiddle factors
+            for k in 0..half {
+                // the twiddle factor, e^(-2 pi i k / (half * 2))
+                let wr = cosine_q15(k * stride) as i64;
+                let wi = -sine_q15(k * stride) as i64;
+                let mut i = k;
+                while i < FFT_SIZE {
+                    let j = i + half;
+                    let (br, bi) = (FFT_RE[j] as i64, FFT_IM[j] as i64);
+                    let tr = ((br * wr - bi * wi) >> 15) as i32;
+                    let ti = ((br * wi + bi * wr) >> 15) as i32;
+                    FFT_RE[j] = FFT_RE[i] - tr;
+                    FFT_IM[j] = FFT_IM[i] - ti;
+                    FFT_RE[i] += tr;
+                    FFT_IM[i] += ti;
+                    i += half * 2;
+                }
+            }
+            half *= 2;
+        }
+        for (bin, magnitude) in magnitudes.iter_mut().enumerate() {
+            let (re, im) = (FFT_RE[bin] as i64, FFT_IM[bin] as i64);
+            *magnitude = isqrt((re * re + im * im) as u64);
+        }
+    }
+}
+
+/// Returns the height of the bar showing `magnitude`, out of `max_height` pixels, on a
+/// logarithmic scale spanning FFT_RANGE_OCTAVES below full scale.
+pub fn fft_bar_height(magnitude: u32, max_height: u8) -> u8 {
+    let floor = FFT_FULL_SCALE_LOG2_Q8 - (FFT_RANGE_OCTAVES << 8);
+    let log = log2_q8(magnitude);
+    if log <= floor {
+        return 0;
+    }
+    let height = (log - floor) * max_height as u32 / (FFT_RANGE_OCTAVES << 8);
+    core::cmp::min(height, max_height as u32) as u8
+}
+
+/// Returns the width of each bin, in hertz, for samples captured at `sample_rate`.
+pub fn fft_bin_hz(sample_rate: u32) -> u32 {
+    sample_rate / FFT_SIZE as u32
+}
+
+// log2(x) in Q8, approximated between powers of two by a straight line, or 0 if x is 0
+fn log2_q8(x: u32) -> u32 {
+    if x == 0 {
+        return 0;
+    }
+    let msb = 31 - x.leading_zeros();
+    let fraction = if msb >= 8 { x >> (msb - 8) } else { x << (8 - msb) };
+    (msb << 8) | (fraction & 0xff)
+}
+
+// sin(2 * pi * i / FFT_SIZE), in Q15
+fn sine_q15(i: usize) -> i32 {
+    let quarter = FFT_SIZE / 4;
+    let i = i % FFT_SIZE;
+    if i <= quarter {
+        SINE_Q15[i] as i32
+    } else if i <= quarter * 2 {
+        SINE_Q15[quarter * 2 - i] as i32
+    } else if i <= quarter * 3 {
+        -(SINE_Q15[i - quarter * 2] as i32)
+    } else {
+        -(SINE_Q15[FFT_SIZE - i] as i32)
+    }
+}
+
+// cos(2 * pi * i / FFT_SIZE), in Q15
+fn cosine_q15(i: usize) -> i32 {
+    sine_q15(i + FFT_SIZE / 4)
+}
+
+// `i` with its low log2(FFT_SIZE) bits in reverse order, for the FFT's input permutation
+fn reverse_bits(i: usize) -> usize {
+    let mut reversed = 0;
+    let mut bit = 1;
+    while bit < FFT_SIZE {
+        reversed <<= 1;
+        if i & bit != 0 {
+            reversed |= 1;
+        }
+        bit <<= 1;
+    }
+    reversed
+}
@@ -0,0 +1,46 @@
+// stm32f3-oscilloscope - src/filter.rs
+// low-pass smoothing of sample buffers for display
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// The smoothing is for the display only, to cut down the hash on a noisy low-level signal: the
+// measurements are made on the samples as captured. Each pass is a [1 2 1] / 4 filter, which is
+// symmetric about each sample, so it smooths without shifting the waveform in time, and repeated
+// passes approach a Gaussian. The first and last samples are left alone.
+
+/// Smooths `samples` in place, with `strength` passes of the filter: 0 leaves them alone, 1 is
+/// the lightest smoothing, and each further pass widens the filter, to about the square root of
+/// `strength` samples either side.
+pub fn smooth(samples: &mut [u16], strength: u8) {
+    if samples.len() < 3 {
+        return;
+    }
+    for _ in 0..strength {
+        let mut previous = samples[0] as u32; // (before this pass changed it)
+        for i in 1..samples.len() - 1 {
+            let sample = samples[i] as u32;
+            samples[i] = ((previous + 2 * sample + samples[i + 1] as u32 + 2) / 4) as u16;
+            previous = sample;
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// stm32f3-oscilloscope - src/fmt.rs
+// number formatting for on-screen values
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// Without `format!`, on-screen numbers are formatted into caller-supplied buffers. Prefixes are
+// written in ASCII, since the font has no 'µ': "u" for micro-. The measurement readouts, which
+// have fixed formats of their own (see `measure::format_millivolts()` and the rest), are written
+// with `write_decimal()` and `copy_truncated()` from here too.
+
+use core;
+
+// the SI prefixes, from micro- (1000^-2) to mega- (1000^2)
+const PREFIXES: [&[u8]; 5] = [b"u", b"m", b"", b"k", b"M"];
+const LOWEST_EXPONENT: i32 = -2;
+const HIGHEST_EXPONENT: i32 = 2;
+
+/// Formats `value`, in thousandths of `unit`, into `buf` with three significant digits and an
+/// engineering SI prefix, e.g. 1500 with "V" gives b"1.50V", 2 gives b"2.00mV", and -47_000_000
+/// gives b"-47.0kV", while 0 gives b"0.00V". Returns the part of `buf` written, which is truncated
+/// if `buf` is too small (six bytes plus the length of `unit` is always enough).
+pub fn fmt_eng<'a>(value: i32, unit: &str, buf: &'a mut [u8]) -> &'a [u8] {
+    fmt_eng_scaled(value, -1, unit, buf)
+}
+
+/// Like `fmt_eng()`, but with `value` in units of 1000^`exponent` of `unit`, e.g. an `exponent`
+/// of -2 for microseconds, or 0 for whole hertz. `exponent` is clamped to -2 through 2. Values of
+/// 1000M or more are written in full, e.g. b"2000MHz".
+pub fn fmt_eng_scaled<'a>(value: i32, exponent: i32, unit: &str, buf: &'a mut [u8]) -> &'a [u8] {
+    let exponent = core::cmp::max(LOWEST_EXPONENT, core::cmp::min(exponent, HIGHEST_EXPONENT));
+    let magnitude = (value as i64).unsigned_abs();
+    let mut text = [0u8; 12];
+    let mut n = 0;
+    if value < 0 {
+        text[0] = b'-';
+        n = 1;
+    }
+    // pick the largest prefix that leaves at least 1 before the decimal point (zero gets none)
+    let mut prefix = if magnitude == 0 { 0 } else { exponent };
+    let mut divisor = 1u64;
+    while prefix < HIGHEST_EXPONENT && magnitude >= divisor * 1000 {
+        prefix += 1;
+        divisor *= 1000;
+    }
+    let (mut digits, mut decimals) = significant_digits(magnitude, divisor);
+    if digits >= 1000 && decimals > 0 {
+        // rounding carried into another digit, e.g. 9.996 -> 10.0
+        decimals -= 1;
+        digits = rounded_quotient(magnitude * pow10(decimals), divisor);
+    } else if digits >= 1000 && prefix < HIGHEST_EXPONENT {
+        // rounding carried into the next prefix, e.g. 999.6 -> 1.00k
+        prefix += 1;
+        divisor *= 1000;
+        let (d, p) = significant_digits(magnitude, divisor);
+        digits = d;
+        decimals = p;
+    }
+    n += write_decimal(digits as u32, decimals, &mut text[n..]);
+    n += copy_truncated(PREFIXES[(prefix - LOWEST_EXPONENT) as usize], &mut text[n..]);
+    let mut len = copy_truncated(&text[..n], buf);
+    len += copy_truncated(unit.as_bytes(), &mut buf[len..]);
+    &buf[..len]
+}
+
+/// Formats `value` into `buf` in decimal, with a leading '-' if negative, e.g. b"-120". Returns
+/// the part of `buf` written, which is truncated if `buf` is too small (11 bytes is always
+/// enough).
+pub fn fmt_i32(value: i32, buf: &mut [u8]) -> &[u8] {
+    let mut text = [0u8; 11];
+    let mut n = 0;
+    if value < 0 {
+        text[0] = b'-';
+        n = 1;
+    }
+    n += write_decimal(value.unsigned_abs(), 0, &mut text[n..]);
+    let len = copy_truncated(&text[..n], buf);
+    &buf[..len]
+}
+
+// The digits and number of decimal places giving three significant digits of `magnitude` /
+// `divisor`, which must be at least 1.
+fn significant_digits(magnitude: u64, divisor: u64) -> (u64, usize) {
+    let whole = magnitude / divisor;
+    let decimals = if whole < 10 { 2 } else if whole < 100 { 1 } else { 0 };
+    (rounded_quotient(magnitude * pow10(decimals), divisor), decimals)
+}
+
+// `n` / `d`, rounded to the nearest integer
+fn rounded_quotient(n: u64, d: u64) -> u64 {
+    (n + d / 2) / d
+}
+
+fn pow10(exponent: usize) -> u64 {
+    let mut p = 1;
+    for _ in 0..exponent {
+        p *= 10;
+    }
+    p
+}
+
+/// Writes `digits` into `buf` in decimal, with a decimal point before the last `decimals` of them
+/// (and as many leading zeros as that needs, e.g. b"0.05" for 5 with two decimals), and returns the
+/// number of bytes written. `buf` must have room for them all.
+pub fn write_decimal(digits: u32, decimals: usize, buf: &mut [u8]) -> usize {
+    let mut digits = digits;
+    // write the digits, least significant first, then reverse them
+    let mut n = 0;
+    while n <= decimals || digits > 0 {
+        if n == decimals && n > 0 {
+            buf[n] = b'.';
+            n += 1;
+        }
+        buf[n] = b'0' + (digits % 10) as u8;
+        digits /= 10;
+        n += 1;
+    }
+    buf[..n].reverse();
+    n
+}
+
+/// Copies as much of `source` as fits into `buf`, e.g. a unit after a number, and returns the
+/// number of bytes written.
+pub fn copy_truncated(source: &[u8], buf: &mut [u8]) -> usize {
+    let n = core::cmp::min(source.len(), buf.len());
+    buf[..n].copy_from_slice(&source[..n]);
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eng(value: i32, exponent: i32, unit: &str) -> Vec<u8> {
+        fmt_eng_scaled(value, exponent, unit, &mut [0; 16]).to_vec()
+    }
+
+    #[test]
+    fn eng_zero_and_negatives() {
+        assert_eq!(eng(0, -1, "V"), b"0.00V");
+        assert_eq!(eng(0, -2, "s"), b"0.00s");
+        assert_eq!(eng(-1500, -1, "V"), b"-1.50V");
+        assert_eq!(eng(-2, -1, "V"), b"-2.00mV");
+        assert_eq!(eng(-47_000_000, -1, "V"), b"-47.0kV");
+        assert_eq!(eng(i32::min_value(), 0, "Hz"), b"-2147MHz");
+    }
+
+    #[test]
+    fn eng_prefix_boundaries() {
+        assert_eq!(eng(999, -1, "V"), b"999mV");
+        assert_eq!(eng(1000, -1, "V"), b"1.00V");
+        assert_eq!(eng(999_999, 0, "Hz"), b"1.00MHz"); // rounding carries into the next prefix
+        assert_eq!(eng(999_499, 0, "Hz"), b"999kHz");
+        assert_eq!(eng(9_996, 0, "Hz"), b"10.0kHz"); // and into another digit
+        assert_eq!(eng(99_960, 0, "Hz"), b"100kHz");
+        assert_eq!(eng(1, -2, "s"), b"1.00us");
+        assert_eq!(eng(999_999_999, 0, "Hz"), b"1000MHz"); // nothing above mega-
+        assert_eq!(eng(1, 5, "Hz"), b"1.00MHz"); // (the exponent is clamped)
+    }
+
+    #[test]
+    fn eng_truncates_to_the_buffer() {
+        let mut buf = [0; 4];
+        assert_eq!(fmt_eng(1500, "V", &mut buf), b"1.50");
+        assert_eq!(fmt_eng(1500, "V", &mut []), b"");
+    }
+
+    #[test]
+    fn i32_in_decimal() {
+        let mut buf = [0; 11];
+        assert_eq!(fmt_i32(0, &mut buf), b"0");
+        assert_eq!(fmt_i32(-120, &mut buf), b"-120");
+        assert_eq!(fmt_i32(i32::max_value(), &mut buf), b"2147483647");
+        assert_eq!(fmt_i32(i32::min_value(), &mut buf), b"-2147483648");
+        assert_eq!(fmt_i32(-120, &mut buf[..2]), b"-1");
+    }
+
+    #[test]
+    fn decimals_get_leading_zeros() {
+        let mut buf = [0; 8];
+        let n = write_decimal(5, 2, &mut buf);
+        assert_eq!(&buf[..n], b"0.05");
+        let n = write_decimal(0, 0, &mut buf);
+        assert_eq!(&buf[..n], b"0");
+        let n = write_decimal(12345, 3, &mut buf);
+        assert_eq!(&buf[..n], b"12.345");
+    }
+}
@@ -0,0 +1,68 @@
+// stm32f3-oscilloscope - src/gpio.rs
+// checked GPIO pin configuration helpers
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// Each pin has a 2-bit field in MODER and in PUPDR, at bit 2 * pin. These helpers compute the
+// masks from the pin numbers, so that callers don't need to write register bits by hand (or
+// `unsafe` blocks, since svd2rust can't know which raw values are valid).
+
+use stm32f30x::GPIOD;
+
+/// The number of pins in a GPIO port.
+pub const GPIO_PINS: usize = 16;
+
+const MODER_INPUT: u32 = 0b00;
+const PUPDR_PULL_UP: u32 = 0b01;
+const PUPDR_PULL_DOWN: u32 = 0b10;
+
+/// Configures each of `pins` of GPIOD as an input with its pull-up enabled, e.g. for a pushbutton
+/// which shorts the pin to ground when pressed. GPIOD must already be powered on. Returns false,
+/// configuring nothing, if any pin number isn't less than `GPIO_PINS`.
+pub fn configure_input_pullup(gpiod: &GPIOD, pins: &[usize]) -> bool {
+    configure_inputs(gpiod, pins, PUPDR_PULL_UP)
+}
+
+/// Like `configure_input_pullup`, but with the pull-down enabled instead, e.g. for a pushbutton
+/// which connects the pin to the supply when pressed.
+pub fn configure_input_pulldown(gpiod: &GPIOD, pins: &[usize]) -> bool {
+    configure_inputs(gpiod, pins, PUPDR_PULL_DOWN)
+}
+
+// configure each of `pins` of GPIOD as an input, with `pull` in its PUPDR field
+fn configure_inputs(gpiod: &GPIOD, pins: &[usize], pull: u32) -> bool {
+    if pins.iter().any(|&pin| pin >= GPIO_PINS) {
+        return false;
+    }
+    let mut mask = 0u32;
+    let mut inputs = 0u32;
+    let mut pulls = 0u32;
+    for &pin in pins {
+        mask |= 0b11 << (pin * 2);
+        inputs |= MODER_INPUT << (pin * 2);
+        pulls |= pull << (pin * 2);
+    }
+    gpiod.moder.modify(|r, w| unsafe { w.bits((r.bits() & !mask) | inputs) });
+    gpiod.pupdr.modify(|r, w| unsafe { w.bits((r.bits() & !mask) | pulls) });
+    true
+}
@@ -23,9 +23,22 @@
 // OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
 // WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
 
+// Brightness is set with pulse-width modulation. Four of the LEDs are on pins which can be TIM1
+// outputs (alternate function 2), and get true hardware PWM, at about 1kHz:
+//   LD3  (PE9)  TIM1_CH1        LD7  (PE11) TIM1_CH2
+//   LD10 (PE13) TIM1_CH3        LD8  (PE14) TIM1_CH4
+// PE8, PE10, and PE12 are TIM1's complementary outputs, which can only show the inverse of their
+// channel's duty cycle, and PE15 is no timer output at all, so LD4, LD5, LD9, and LD6 fall back to
+// software PWM, driven by led_pwm_tick() from the 1ms SysTick exception. That has only
+// SOFT_PWM_STEPS levels of brightness, and a 125Hz period, which may flicker visibly.
+
 // extern crate stm32f30x;
 
-use stm32f30x::GPIOE;
+use cortex_m;
+use stm32f30x::{GPIOE, RCC, TIM1};
+
+use elapsed::has_elapsed;
+use timing::delay_ms;
 
 #[derive(Clone, Copy)]
 #[allow(unused)]
@@ -50,6 +63,7 @@ pub fn led_init(led: Led) {
 pub fn led_set(led: Led, state: bool) {
     let pin = led as u8;
     if pin >= 8 && pin <= 15 {
+        led_pwm_release(pin);
         let gpioe = GPIOE.get();
         if state {
             unsafe { (*gpioe).bsrr.write(|w| w.bits(1 << pin)); }
@@ -63,6 +77,7 @@ pub fn led_set(led: Led, state: bool) {
 pub fn led_on(led: Led) {
     let pin = led as u8;
     if pin >= 8 && pin <= 15 {
+        led_pwm_release(pin);
         let gpioe = GPIOE.get();
         unsafe { (*gpioe).bsrr.write(|w| w.bits(1 << pin)); }
     }
@@ -72,6 +87,7 @@ pub fn led_on(led: Led) {
 pub fn led_off(led: Led) {
     let pin = led as u8;
     if pin >= 8 && pin <= 15 {
+        led_pwm_release(pin);
         let gpioe = GPIOE.get();
         unsafe { (*gpioe).bsrr.write(|w| w.bits(1 << (pin + 16))); }
     }
@@ -81,6 +97,7 @@ pub fn led_off(led: Led) {
 pub fn led_toggle(led: Led) {
     let pin = led as u8;
     if pin >= 8 && pin <= 15 {
+        led_pwm_release(pin);
         let gpioe = GPIOE.get();
         let new_state = ( unsafe { (*gpioe).odr.read().bits() } & (1 << pin)) == 0;
         if new_state {
@@ -90,3 +107,255 @@ pub fn led_toggle(led: Led) {
         }
     }
 }
+
+/// Returns whether `led`'s pin reads back as driven high, i.e. lit, e.g. to check that it isn't
+/// shorted to ground.
+pub fn led_is_lit(led: Led) -> bool {
+    let pin = led as u8;
+    pin >= 8 && pin <= 15 && unsafe { (*GPIOE.get()).idr.read().bits() } & (1 << pin) != 0
+}
+
+// ======== brightness ========
+
+// TIM1 counts 0 through LED_PWM_ARR, so that a compare value of 0 is off, and 255 is fully on
+const LED_PWM_ARR: u32 = 254;
+// a TIM1 prescaler of 282, for (72MHz / 282) / 255 = 1001Hz
+const LED_PWM_PSC: u16 = 281;
+// the number of software PWM brightness levels above off, each one 1ms SysTick long
+const SOFT_PWM_STEPS: u8 = 8;
+
+static mut SOFT_PWM_ON_TICKS: [Option<u8>; 8] = [None; 8]; // by pin - 8, None if not dimmed
+static mut SOFT_PWM_PHASE: u8 = 0;
+
+/// Sets up TIM1 to generate the hardware PWM used by `led_set_brightness()`, with all of its
+/// outputs off.
+pub fn led_pwm_init() {
+    cortex_m::interrupt::free(|cs| {
+        // enable clock to TIM1
+        let rcc = RCC.borrow(cs);
+        rcc.apb2enr.modify(|_, w| w.tim1en().enabled());
+
+        let tim1 = TIM1.borrow(cs);
+        tim1.cr1.modify(|_, w| unsafe { w.arpe().bits(1) }); // ARR register is buffered
+        tim1.arr.write(|w| unsafe { w.bits(LED_PWM_ARR) });
+        tim1.psc.write(|w| unsafe { w.psc().bits(LED_PWM_PSC) });
+        // channels 1 through 4: PWM mode 1 (active while the count is below the compare value),
+        // with preloaded compare registers
+        tim1.ccmr1_output.write(|w| unsafe { w.bits(0x6868) });
+        tim1.ccmr2_output.write(|w| unsafe { w.bits(0x6868) });
+        tim1.ccr1.write(|w| unsafe { w.bits(0) });
+        tim1.ccr2.write(|w| unsafe { w.bits(0) });
+        tim1.ccr3.write(|w| unsafe { w.bits(0) });
+        tim1.ccr4.write(|w| unsafe { w.bits(0) });
+        tim1.ccer.write(|w| unsafe { w.bits(0x1111) }); // enable CC1 through CC4, active high
+        tim1.bdtr.write(|w| unsafe { w.bits(1 << 15) }); // main output enable (MOE)
+        tim1.egr.write(|w| unsafe { w.ug().bits(1) }); // immediately update registers
+        tim1.cr1.modify(|_, w| unsafe { w.cen().bits(1) });
+    });
+}
+
+/// Sets `led` to brightness `level`, from 0 (off) to 255 (fully on), using hardware PWM where its
+/// pin has a TIM1 channel, and software PWM where it doesn't. `led_pwm_init()` must have been
+/// called first. The LED stays dimmed until it is next switched with `led_set()`, `led_on()`,
+/// `led_off()`, or `led_toggle()`.
+#[allow(unused)]
+pub fn led_set_brightness(led: Led, level: u8) {
+    let pin = led as u8;
+    if pin < 8 || pin > 15 {
+        return;
+    }
+    let gpioe = GPIOE.get();
+    let tim1 = TIM1.get();
+    match led_timer_channel(pin) {
+        Some(channel) => unsafe {
+            let level = level as u32;
+            match channel {
+                1 => (*tim1).ccr1.write(|w| w.bits(level)),
+                2 => (*tim1).ccr2.write(|w| w.bits(level)),
+                3 => (*tim1).ccr3.write(|w| w.bits(level)),
+                _ => (*tim1).ccr4.write(|w| w.bits(level)),
+            }
+            // hand the pin over to TIM1: alternate function 2
+            let shift = (pin - 8) * 4;
+            (*gpioe).afrh.modify(|r, w| w.bits((r.bits() & !(0b1111 << shift)) | (2 << shift)));
+            let mask = !(0b11 << (pin * 2));
+            (*gpioe).moder.modify(|r, w| w.bits((r.bits() & mask) | (0b10 << (pin * 2))));
+        },
+        None => unsafe {
+            let on_ticks = ((level as u32 * SOFT_PWM_STEPS as u32 + 127) / 255) as u8;
+            cortex_m::interrupt::free(|_| SOFT_PWM_ON_TICKS[(pin - 8) as usize] = Some(on_ticks));
+        },
+    }
+}
+
+/// Advances the software PWM of the dimmed LEDs which have no hardware PWM; called from the
+/// SysTick exception handler, every millisecond.
+pub fn led_pwm_tick() {
+    let gpioe = GPIOE.get();
+    unsafe {
+        SOFT_PWM_PHASE = (SOFT_PWM_PHASE + 1) % SOFT_PWM_STEPS;
+        let mut bsrr = 0u32;
+        for (i, on_ticks) in SOFT_PWM_ON_TICKS.iter().enumerate() {
+            if let Some(on_ticks) = *on_ticks {
+                let pin = i + 8;
+                bsrr |= if SOFT_PWM_PHASE < on_ticks { 1 << pin } else { 1 << (pin + 16) };
+            }
+        }
+        if bsrr != 0 {
+            (*gpioe).bsrr.write(|w| w.bits(bsrr));
+        }
+    }
+}
+
+// the TIM1 channel which can drive `pin` on alternate function 2, if there is one
+fn led_timer_channel(pin: u8) -> Option<u8> {
+    match pin {
+        9 => Some(1),
+        11 => Some(2),
+        13 => Some(3),
+        14 => Some(4),
+        _ => None,
+    }
+}
+
+// stop dimming `pin`, returning it to plain output
+fn led_pwm_release(pin: u8) {
+    unsafe {
+        if led_timer_channel(pin).is_some() {
+            let gpioe = GPIOE.get();
+            let mask = !(0b11 << (pin * 2));
+            (*gpioe).moder.modify(|r, w| w.bits((r.bits() & mask) | (0b01 << (pin * 2))));
+        } else {
+            cortex_m::interrupt::free(|_| SOFT_PWM_ON_TICKS[(pin - 8) as usize] = None);
+        }
+    }
+}
+
+// ======== status codes ========
+
+// the number of LD3 blinks in each fault's code
+const ADC_FAULT_BLINKS: u32 = 2;
+const SPI_FAULT_BLINKS: u32 = 3;
+const LCD_FAULT_BLINKS: u32 = 4;
+const BUTTON_FAULT_BLINKS: u32 = 5;
+
+/// What the firmware has gotten to, or what stopped it, for `status()` to show on the LEDs, so
+/// that there is something to go on when it fails before the display is up.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    OutOfReset, // LD3 (north, red) on: running, but not yet set up
+    InitDone,   // LD3 off, and LD4 (northwest, blue) on: set up, and about to start sweeping
+                // (until indicate_acq_state() takes LD3 and LD4 over)
+    Sweep,      // LD5 (northeast, orange) toggled: a display sweep has been drawn
+    AdcFault,   // LD3 blinks twice, over and over: ADC1 wouldn't calibrate or become ready
+    SpiFault,   // LD3 blinks three times, over and over: SPI2 wouldn't enable, for the LCD
+    LcdRetry,   // LD3 blinks four times, once: the LCD didn't answer, and will be set up again
+    LcdFault,   // LD3 blinks four times, twice: the LCD never answered, so it runs without one
+    ButtonFault, // LD3 blinks five times, over and over: the button pins given aren't usable
+    LcdUnchecked, // LD4 flashes: there was no way to check that the LCD answered
+    SupplyOk,   // LD9 (southeast, blue) off: the supply voltage is fine
+    SupplyLow,  // LD9 on: the supply voltage is low, so the readings may be off
+}
+
+/// Shows `status` on the LEDs, as described for each `Status`. The other faults never return:
+/// their blink codes repeat, with a pause between, until reset. LcdRetry and LcdFault take a few
+/// seconds to show, and LcdUnchecked half a second. The LEDs must have been set up with
+/// `led_init()`, and, for the faults, SysTick must be running.
+pub fn status(status: Status) {
+    match status {
+        Status::OutOfReset => {
+            led_off(Led::LD4);
+            led_on(Led::LD3);
+        }
+        Status::InitDone => {
+            led_off(Led::LD3);
+            led_on(Led::LD4);
+        }
+        Status::Sweep => led_toggle(Led::LD5),
+        Status::AdcFault => blink_forever(ADC_FAULT_BLINKS),
+        Status::SpiFault => blink_forever(SPI_FAULT_BLINKS),
+        Status::LcdRetry => {
+            blink_code(LCD_FAULT_BLINKS);
+            led_on(Led::LD3); // (back to OutOfReset)
+        }
+        Status::LcdFault => {
+            blink_code(LCD_FAULT_BLINKS);
+            blink_code(LCD_FAULT_BLINKS);
+            led_on(Led::LD3); // (back to OutOfReset)
+        }
+        Status::ButtonFault => blink_forever(BUTTON_FAULT_BLINKS),
+        Status::LcdUnchecked => {
+            for _ in 0..3 {
+                led_on(Led::LD4);
+                delay_ms(80);
+                led_off(Led::LD4);
+                delay_ms(80);
+            }
+        }
+        Status::SupplyOk => led_off(Led::LD9),
+        Status::SupplyLow => led_on(Led::LD9),
+    }
+}
+
+// ======== acquisition state ========
+
+/// The acquisition states `indicate_acq_state()` shows on the LEDs, so that a glance at the board
+/// tells what the scope is doing, even with the display off or too small to read.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AcqState {
+    Triggered, // LD4 (northwest, blue) flashes: a sweep has just triggered
+    Running,   // LD3 (north, red) is left to the heartbeat, which the caller toggles with sweeps
+    Armed,     // LD3 pulses once a second: Normal or Single mode is waiting for a trigger
+    Stopped,   // LD3 on: the display is stopped, or Single mode is holding its captured sweep
+}
+
+// how long LD4 stays lit for each trigger, and LD3's pulse, and its period, while armed
+const TRIGGER_FLASH_MS: u32 = 30;
+const ARMED_PULSE_MS: u32 = 100;
+const ARMED_PERIOD_MS: u32 = 1000;
+
+static mut TRIGGER_FLASHED_MS: Option<u32> = None; // when LD4 was lit for the last trigger
+
+/// Shows `state` on the LEDs, as of `now_ms`, as described for each `AcqState`. Triggered goes
+/// along with the others: it is shown for each trigger, while one of the others is shown on each
+/// pass of the main loop, which also puts LD4 out again once its flash is over. (So LD4 stays lit
+/// while triggers come faster than its flash.)
+pub fn indicate_acq_state(state: AcqState, now_ms: u32) {
+    match state {
+        AcqState::Triggered => {
+            led_on(Led::LD4);
+            unsafe { TRIGGER_FLASHED_MS = Some(now_ms); }
+            return;
+        }
+        AcqState::Running => {}
+        AcqState::Armed => led_set(Led::LD3, now_ms % ARMED_PERIOD_MS < ARMED_PULSE_MS),
+        AcqState::Stopped => led_on(Led::LD3),
+    }
+    if let Some(ms) = unsafe { TRIGGER_FLASHED_MS } {
+        if has_elapsed(ms, now_ms, TRIGGER_FLASH_MS) {
+            led_off(Led::LD4);
+            unsafe { TRIGGER_FLASHED_MS = None; }
+        }
+    }
+}
+
+// blink LD3 `blinks` times, then pause with it off
+fn blink_code(blinks: u32) {
+    led_off(Led::LD3);
+    delay_ms(500);
+    for _ in 0..blinks {
+        led_on(Led::LD3);
+        delay_ms(200);
+        led_off(Led::LD3);
+        delay_ms(300);
+    }
+    delay_ms(1000);
+}
+
+// show the blink code for `blinks` until reset
+fn blink_forever(blinks: u32) {
+    led_off(Led::LD4);
+    loop {
+        blink_code(blinks);
+    }
+}
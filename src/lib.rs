@@ -0,0 +1,56 @@
+// stm32f3-oscilloscope - src/lib.rs
+// the signal processing, which touches no peripherals, so it builds (and is tested) on the host
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The firmware (src/main.rs) links this in as the `dsp` crate. Nothing here may touch a
+// peripheral: the tests run on the host, with
+//
+//     cargo test --lib --target x86_64-unknown-linux-gnu
+//
+// (or whatever the host's target is, since .cargo/config makes the Cortex-M3 the default).
+
+#![cfg_attr(not(test), no_std)]
+
+// (no_std brings in `core` by itself, but the tests are built with std, so name it for them)
+#[cfg(test)]
+extern crate core;
+
+pub mod acquire;
+pub mod calibration;
+pub mod display;
+pub mod elapsed;
+pub mod envelope;
+pub mod filter;
+pub mod fmt;
+pub mod measure;
+pub mod pretrigger;
+pub mod scope;
+pub mod trigger;
+pub mod vscale;
+
+#[cfg(test)]
+mod testgen;
+
+/// The widest the screen can be, in pixels, in any rotation: the panel's longer side. Buffers
+/// which hold something for each column of the screen are sized from this.
+pub const DISPLAY_WIDTH: usize = 160;
@@ -26,76 +26,313 @@
 
 // For a summary of the peripherals used, see docs/peripherals.rst
 
+#![feature(const_cell_new)]
 #![feature(core_intrinsics)]
 #![feature(used)]
 #![no_std]
 
 extern crate cortex_m;
 extern crate cortex_m_rt;
+extern crate dsp;
 extern crate stm32f30x;
 
+// the signal processing, from src/lib.rs, used here (and from the modules below) as if it were
+// declared alongside them
+use dsp::{acquire, calibration, display, elapsed, envelope, filter, fmt, measure, pretrigger,
+          scope, trigger, vscale};
+
+mod adc;
+mod autoset;
+mod button;
 mod capture;
+mod coupling;
+mod cursors;
+mod fft;
+mod gpio;
 mod led;
+mod math;
+mod menu;
+mod record;
+mod reference;
+mod parallax_8x12_extents;
 mod parallax_8x12_font;
+mod selftest;
+mod settings;
 mod siggen;
 mod st7735;
 mod sysclk;
+mod theme;
+mod timebase;
+mod timing;
+mod uart;
 
-use core::intrinsics::{volatile_load, volatile_store};
 use cortex_m::exception;
-use cortex_m::peripheral::{SCB, SYST, SystClkSource};
-use stm32f30x::{DMA1, GPIOD, RCC, interrupt};
+use cortex_m::peripheral::{SCB, SYST};
+use stm32f30x::{GPIOD, RCC, interrupt};
 
+use acquire::{AcqMode, Averager, OVERSAMPLE_MAX, PEAK_DETECT_SAMPLES_PER_COLUMN, decimate,
+              effective_half_bits, oversample_mode};
+use adc::{SampleCycles, read_temp_c, read_vdda_mv};
+use autoset::autoset;
+use button::{BUTTONS, BUTTON_CONFIG, BUTTON_COUNT, ButtonEvent, DEBOUNCE_MS, button_event,
+             button_init, button_is_down, button_wake_enable, button_wake_interrupt};
+use calibration::{MID_SCALE, calibration, counts_to_mv, probe_ratio, set_calibration,
+                  set_probe_ratio, set_zero_offset, span_to_mv, zero_offset};
 use led::*;
 use led::Led::*;
+use math::{math_subtract, math_to_samples};
+use measure::{WaveShape, format_frequency, format_microseconds, format_millivolts, format_percent,
+              format_uptime, mean, min_max};
+use menu::{menu_close, menu_draw, menu_is_editing, menu_is_open, menu_move, menu_open,
+           menu_show_value, menu_toggle_editing};
+use capture::{SAMPLE_COUNT, calibrate_offset, calibrate_scale};
+use coupling::{Coupling, coupling_init, dc_offset, remove_dc, set_coupling};
+use cursors::{Cursor, Cursors};
+use elapsed::has_elapsed;
+use envelope::Envelope;
+use fft::{FFT_BINS, fft_bar_height, fft_bin_hz, fft_magnitudes};
+use filter::smooth;
+use fmt::{fmt_eng_scaled, fmt_i32, write_decimal};
+use pretrigger::{HISTORY_MAX, pretrigger_available, pretrigger_clear, pretrigger_keep,
+                 pretrigger_stitch};
+use record::{RECORD_MAX, record_clamp_offset, record_clear, record_depth, record_len, record_store,
+             record_view, set_record_depth};
+use reference::{ref_matches, ref_samples, ref_store};
+use scope::Scope;
+use selftest::selftest;
+use settings::{Settings, settings_load, settings_save};
 use siggen::*;
 use st7735::*;
-use sysclk::set_sys_clock;
-
-// ======== required declarations for Rust and C linkage ========
-
-// the C functions we call from Rust
-extern "C" {
-    fn _st7735_initR(lcd_type: u8);
-    fn _st7735_drawFastHLine(x: i16, y: i16, h: i16, color: u16);
-    fn _st7735_drawFastVLine(x: i16, y: i16, h: i16, color: u16);
-    fn _st7735_drawPixel(x: i16, y: i16, color: u16);
-    fn _st7735_fillScreen(color: u16);
-    fn _st7735_setAddrWindow(x0: u8, y0: u8, x1: u8, y1: u8);
-    fn _st7735_setRotation(rotation: u8);
-    fn _st7735_get_height() -> u8;
-    fn _st7735_get_width() -> u8;
-}
-
-// the Rust functions in submodules that we call from C
-pub use st7735::{
-    st7735_send_cmd,
-    st7735_send_data,
-    lcd_cs0, lcd_cs1,
-    lcd_rst0, lcd_rst1,
-    st7735_fill_rect,
-};
+use sysclk::{SYSTICK_RELOAD, set_sys_clock, systick_use_ahb_clock};
+use theme::{Theme, Trace, color_map, set_color_map, set_theme, theme_colors, trace_color};
+use timebase::{TimeBase, timebase_apply, timebase_faster, timebase_slower};
+use timing::{SweepTimer, cycle_counter_init, delay_ms, millis, timing_advance, timing_tick,
+             uptime_seconds};
+use trigger::{Edge, Polarity, Trigger, TriggerMode, TriggerSource, TriggerType, WidthCompare};
+use trigger::Polarity::{High, Low};
+use trigger::WidthCompare::{Narrower, Wider};
+use uart::{Command, DumpFormat, UART_BAUD, uart_dropped_bytes, uart_init, uart_poll_command,
+           uart_send_csv, uart_send_measurements, uart_send_samples, uart_send_screenshot,
+           uart_write};
+use vscale::{VerticalScale, vscale_down, vscale_up};
 
 // ======== global (cough) state ========
 
-// constants and state for the LCD breakout board pushbuttons
-const BUTTONS: usize = 4;
-const BUTTON_PIN: [usize; BUTTONS] = [ 12, 13, 14, 15 ];
-static mut BUTTON_CHANGED: [bool; BUTTONS] = [ false, false, false, false];
-static mut BUTTON_STATE: [bool; BUTTONS] = [ false, false, false, false];
-static mut BUTTON_DEBOUNCE: [u32; BUTTONS] = [ 0, 0, 0, 0 ];
+// (the buffers below which only the CPU works on go in the faster CCM RAM when built with the
+// 'ccmram' feature, but DMA can't reach it, so the capture buffers stay in RAM; see memory.x)
+
+// the continuous-capture sample buffers, filled alternately by DMA, with room for SAMPLE_COUNT
+// samples from each of two channels (or twice as many from one, in Peak Detect mode, or up to
+// OVERSAMPLE_MAX times as many when oversampling), or a record of up to RECORD_MAX samples from one
+static mut CAPTURE_BUFFER_A: [u16; RECORD_MAX] = [0; RECORD_MAX];
+static mut CAPTURE_BUFFER_B: [u16; RECORD_MAX] = [0; RECORD_MAX];
+
+// an oversampled capture buffer, averaged down to a sample per column's worth
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut OVERSAMPLED_SAMPLES: [u16; SAMPLE_COUNT] = [0; SAMPLE_COUNT];
+
+// the two channels of a dual-trace capture buffer, separated
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut CHANNEL_A_SAMPLES: [u16; SAMPLE_COUNT] = [0; SAMPLE_COUNT];
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut CHANNEL_B_SAMPLES: [u16; SAMPLE_COUNT] = [0; SAMPLE_COUNT];
+
+// the dual-trace display's math trace, A - B, and the same as samples to draw
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut MATH_DIFFERENCE: [i16; SAMPLE_COUNT] = [0; SAMPLE_COUNT];
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut MATH_SAMPLES: [u16; SAMPLE_COUNT] = [0; SAMPLE_COUNT];
+
+// a copy of the samples currently displayed (a screen width of them, or more in Peak Detect mode),
+// for sending to the host
+const DISPLAYED_MAX: usize = DISPLAY_WIDTH * PEAK_DETECT_SAMPLES_PER_COLUMN;
+static mut DISPLAYED_SAMPLES: [u16; DISPLAYED_MAX] = [0; DISPLAYED_MAX];
+
+// a window of samples for a trigger too near the start of its buffer to have the trigger
+// position's worth before it, the rest taken from the end of the buffer before
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut STITCHED_SAMPLES: [u16; HISTORY_MAX] = [0; HISTORY_MAX];
+
+// smoothed copies of the displayed samples of each channel, for the display filter
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut SMOOTHED_SAMPLES: [u16; DISPLAYED_MAX] = [0; DISPLAYED_MAX];
+#[cfg_attr(feature = "ccmram", link_section = ".ccmram")]
+static mut SMOOTHED_SAMPLES_B: [u16; DISPLAYED_MAX] = [0; DISPLAYED_MAX];
+
+// The trace drawing takes a screen width of samples from each channel's part of a capture buffer
+// (or more, in Peak Detect mode), so these fail to compile if the buffers don't have room for that.
+#[allow(unused)]
+const SAMPLE_COUNT_COVERS_DISPLAY: [(); 1] = [(); (SAMPLE_COUNT >= DISPLAY_WIDTH) as usize];
+#[allow(unused)]
+const CAPTURE_BUFFER_COVERS_PEAK_DETECT: [(); 1] =
+    [(); (SAMPLE_COUNT * 2 >= SAMPLE_COUNT * PEAK_DETECT_SAMPLES_PER_COLUMN) as usize];
+#[allow(unused)]
+const CAPTURE_BUFFER_COVERS_TWO_CHANNELS: [(); 1] = [(); (RECORD_MAX >= SAMPLE_COUNT * 2) as usize];
+// (16x oversampling would need RECORD_MAX at 5120, which the RAM can't spare, so it's refused)
+#[allow(unused)]
+const CAPTURE_BUFFER_COVERS_OVERSAMPLING: [(); 1] =
+    [(); (RECORD_MAX >= SAMPLE_COUNT * OVERSAMPLE_MAX) as usize];
+
+// ======== constants ========
+
+// how to talk to the LCD: build with the 'software-spi' feature if SPI2 is unavailable
+#[cfg(not(feature = "software-spi"))]
+const LCD_TRANSPORT: St7735Transport = St7735Transport::HardSpi;
+#[cfg(feature = "software-spi")]
+const LCD_TRANSPORT: St7735Transport = St7735Transport::SoftSpi;
+
+// which LCD breakout board variant is connected, by the color of the tab on its screen protector:
+// build with the 'lcd-greentab' or 'lcd-blacktab' feature for those, otherwise red is assumed
+#[cfg(not(any(feature = "lcd-greentab", feature = "lcd-blacktab")))]
+const LCD_TYPE: St7735Type = St7735Type::RedTab;
+#[cfg(feature = "lcd-greentab")]
+const LCD_TYPE: St7735Type = St7735Type::GreenTab;
+#[cfg(all(feature = "lcd-blacktab", not(feature = "lcd-greentab")))]
+const LCD_TYPE: St7735Type = St7735Type::BlackTab;
+
+// how many times to set up the LCD, if it doesn't answer, before giving up on it
+const LCD_INIT_ATTEMPTS: u32 = 3;
+
+// the screen rotation (0 to 3, a quarter turn clockwise each) used until one is saved: landscape
+const DEFAULT_ROTATION: u8 = 3;
 
-fn button_get_changed(i: usize) -> bool {
-    unsafe { volatile_load(&BUTTON_CHANGED[i]) }
+// graticule divisions
+const GRATICULE_DIVS_X: u8 = 5; // 32 pixels per division
+const GRATICULE_DIVS_Y: u8 = 4;
+
+// timebases slower than this, in microseconds per division, roll the trace across the screen as
+// samples arrive, like a strip chart, instead of waiting for each buffer to fill
+const ROLL_US_PER_DIV: u32 = 100_000;
+
+// the heartbeat LED, LD3, toggles at most once every HEARTBEAT_MS, and only if a sweep has
+// completed since, so it blinks in step with the sweep rate up to 5Hz; while Normal or Single
+// mode waits for a trigger, or the display is stopped, it shows that instead (see AcqState)
+const HEARTBEAT_MS: u32 = 100;
+
+// the backlight dims to BACKLIGHT_DIM after BACKLIGHT_IDLE_MS with no button pressed, to save
+// power, and comes back up to BACKLIGHT_FULL at the next press (which does what it always does)
+const BACKLIGHT_IDLE_MS: u32 = 60_000;
+const BACKLIGHT_DIM: u8 = 32;
+const BACKLIGHT_FULL: u8 = 255;
+
+// while idle, the backlight is off, and the input is sampled at no more than IDLE_SAMPLE_RATE,
+// just fast enough to notice a signal arriving, with SysTick stopped and the CPU sleeping between
+// the capture's interrupts, or until a button press wakes it
+const IDLE_SAMPLE_RATE: u32 = 1000;
+
+// the supply voltage (VDDA, which the ADC's full scale follows) is checked every
+// SUPPLY_CHECK_MS, and lights LD9 while it is below SUPPLY_LOW_MV; the Discovery's 3V regulator
+// runs off USB, so a supply that low usually means a sagging USB port or cable; the chip's
+// temperature is read and shown along with it
+const SUPPLY_CHECK_MS: u32 = 2000;
+const SUPPLY_LOW_MV: u16 = 2850;
+
+// how long settings must go unchanged before they are saved to flash
+const SETTINGS_SAVE_DELAY_MS: u32 = 5000;
+
+// trigger settings: the level to begin with (after which it's adjusted, as a percentage of the
+// screen height, by the buttons or the host), and the hysteresis
+const TRIGGER_LEVEL: u16 = 2048; // mid-scale
+const TRIGGER_HYSTERESIS: u16 = 32; // about 26mV
+
+// trigger positions, as a percentage of the screen width from the left
+struct TriggerPosition {
+    percent: u8,
+    label: &'static [u8],
 }
-fn button_reset_changed(i: usize) {
-    unsafe { volatile_store(&mut BUTTON_CHANGED[i], false); }
+
+const TRIGGER_POSITIONS: [TriggerPosition; 5] = [
+    TriggerPosition { percent:   0, label: b"0%" },
+    TriggerPosition { percent:  25, label: b"25%" },
+    TriggerPosition { percent:  50, label: b"50%" },
+    TriggerPosition { percent:  75, label: b"75%" },
+    TriggerPosition { percent: 100, label: b"100%" },
+];
+
+// trigger sources: `mark` is shown beside the trigger mode
+struct TriggerSourceStep {
+    source: TriggerSource,
+    label: &'static [u8],
+    mark: &'static [u8],
 }
-fn button_get_state(i: usize) -> bool {
-    unsafe { volatile_load(&BUTTON_STATE[i]) }
+
+const TRIGGER_SOURCES: [TriggerSourceStep; 3] = [
+    TriggerSourceStep { source: TriggerSource::ChA, label: b"chA", mark: b"A" },
+    TriggerSourceStep { source: TriggerSource::ChB, label: b"chB", mark: b"B" },
+    TriggerSourceStep { source: TriggerSource::ExtPin, label: b"ext", mark: b"E" },
+];
+
+// trigger types, in the order buttons 2 and 3 step through them: edge, or high (H) or low (L)
+// pulses wider (>) or narrower (<) than a width
+struct TriggerTypeStep {
+    pulse: Option<(Polarity, WidthCompare, u32)>, // None for edge, or the width in microseconds
+    label: &'static [u8],
 }
 
-// ======== constants ========
+const TRIGGER_TYPES: [TriggerTypeStep; 13] = [
+    TriggerTypeStep { pulse: None, label: b"edge" },
+    TriggerTypeStep { pulse: Some((High, Wider,      10)), label: b"H>10u" },
+    TriggerTypeStep { pulse: Some((High, Wider,     100)), label: b"H>100u" },
+    TriggerTypeStep { pulse: Some((High, Wider,    1000)), label: b"H>1m" },
+    TriggerTypeStep { pulse: Some((High, Narrower,   10)), label: b"H<10u" },
+    TriggerTypeStep { pulse: Some((High, Narrower,  100)), label: b"H<100u" },
+    TriggerTypeStep { pulse: Some((High, Narrower, 1000)), label: b"H<1m" },
+    TriggerTypeStep { pulse: Some((Low,  Wider,      10)), label: b"L>10u" },
+    TriggerTypeStep { pulse: Some((Low,  Wider,     100)), label: b"L>100u" },
+    TriggerTypeStep { pulse: Some((Low,  Wider,    1000)), label: b"L>1m" },
+    TriggerTypeStep { pulse: Some((Low,  Narrower,   10)), label: b"L<10u" },
+    TriggerTypeStep { pulse: Some((Low,  Narrower,  100)), label: b"L<100u" },
+    TriggerTypeStep { pulse: Some((Low,  Narrower, 1000)), label: b"L<1m" },
+];
+
+// the idle times after which, with no button pressed and nothing triggering, the low-power idle
+// mode is entered, in the order buttons 2 and 3 step through them
+struct IdleStep {
+    ms: Option<u32>, // (None: never)
+    label: &'static [u8],
+}
+
+const IDLE_STEPS: [IdleStep; 4] = [
+    IdleStep { ms: None,          label: b"off" },
+    IdleStep { ms: Some(60_000),  label: b"1min" },
+    IdleStep { ms: Some(300_000), label: b"5min" },
+    IdleStep { ms: Some(900_000), label: b"15min" },
+];
+
+// the ADC sample times, in the order buttons 2 and 3 step through them: longer ones suit
+// higher-impedance sources, but lower the fastest sample rate
+struct SampleTimeStep {
+    cycles: SampleCycles,
+    label: &'static [u8],
+}
+
+const SAMPLE_TIMES: [SampleTimeStep; 8] = [
+    SampleTimeStep { cycles: SampleCycles::C1_5,   label: b"1.5" },
+    SampleTimeStep { cycles: SampleCycles::C2_5,   label: b"2.5" },
+    SampleTimeStep { cycles: SampleCycles::C4_5,   label: b"4.5" },
+    SampleTimeStep { cycles: SampleCycles::C7_5,   label: b"7.5" },
+    SampleTimeStep { cycles: SampleCycles::C19_5,  label: b"19.5" },
+    SampleTimeStep { cycles: SampleCycles::C61_5,  label: b"61.5" },
+    SampleTimeStep { cycles: SampleCycles::C181_5, label: b"181.5" },
+    SampleTimeStep { cycles: SampleCycles::C601_5, label: b"601.5" },
+];
+
+// trigger holdoffs
+struct Holdoff {
+    ms: u32,
+    label: &'static [u8],
+}
+
+const HOLDOFFS: [Holdoff; 8] = [
+    Holdoff { ms:    0, label: b"off" },
+    Holdoff { ms:   10, label: b"10ms" },
+    Holdoff { ms:   20, label: b"20ms" },
+    Holdoff { ms:   50, label: b"50ms" },
+    Holdoff { ms:  100, label: b"100ms" },
+    Holdoff { ms:  200, label: b"200ms" },
+    Holdoff { ms:  500, label: b"500ms" },
+    Holdoff { ms: 1000, label: b"1s" },
+];
 
 // signal generator frequencies
 struct SiggenFreq {
@@ -115,40 +352,265 @@ const SIGGEN_FREQUENCIES: [SiggenFreq; 9] = [
     SiggenFreq { frequency: 10000, label: b"10kHz" },
 ];
 
-// timebase intervals
-struct TimebaseInterval {
-    sample_rate: u32, // samples per second
+// acquisition modes, in the order buttons 2 and 3 step through them
+struct AcquisitionStep {
+    mode: AcqMode,
+    label: &'static [u8],
+}
+
+const ACQUISITION_STEPS: [AcquisitionStep; 8] = [
+    AcquisitionStep { mode: AcqMode::Sample,      label: b"sample" },
+    AcquisitionStep { mode: AcqMode::Oversample(2), label: b"os2" },
+    AcquisitionStep { mode: AcqMode::Oversample(4), label: b"os4" },
+    AcquisitionStep { mode: AcqMode::Average(2),  label: b"avg2" },
+    AcquisitionStep { mode: AcqMode::Average(4),  label: b"avg4" },
+    AcquisitionStep { mode: AcqMode::Average(8),  label: b"avg8" },
+    AcquisitionStep { mode: AcqMode::Average(16), label: b"avg16" },
+    AcquisitionStep { mode: AcqMode::PeakDetect,  label: b"peak" },
+];
+
+// persistence settings, in the order buttons 2 and 3 step through them: how many sweeps each
+// step of fading lasts, 0 for traces which never fade, or `None` for no persistence
+struct PersistenceStep {
+    decay: Option<u8>,
     label: &'static [u8],
 }
 
-const TIMEBASE_INTERVALS: [TimebaseInterval; 17] = [
-    TimebaseInterval { sample_rate:       1, label: b"32s" },
-    TimebaseInterval { sample_rate:      32, label: b"1s" },
-    TimebaseInterval { sample_rate:      64, label: b".5s" },
-    TimebaseInterval { sample_rate:     160, label: b".2s" },
-    TimebaseInterval { sample_rate:     320, label: b".1s" },
-    TimebaseInterval { sample_rate:     640, label: b"50ms" },
-    TimebaseInterval { sample_rate:    1600, label: b"20ms" },
-    TimebaseInterval { sample_rate:    3200, label: b"10ms" },
-    TimebaseInterval { sample_rate:    6400, label: b"5ms" },
-    TimebaseInterval { sample_rate:   16000, label: b"2ms" },
-    TimebaseInterval { sample_rate:   32000, label: b"1ms" },
-    TimebaseInterval { sample_rate:   64000, label: b".5ms" },
-    TimebaseInterval { sample_rate:  160000, label: b".2ms" },
-    TimebaseInterval { sample_rate:  320000, label: b".1ms" },
-    TimebaseInterval { sample_rate:  640000, label: b"~50us" }, // 49.777µs/div
-    TimebaseInterval { sample_rate: 1600000, label: b"20us" },
-    TimebaseInterval { sample_rate: 3130434, label: b"~10us" }, // 10.222µs/div
+const PERSISTENCE_STEPS: [PersistenceStep; 5] = [
+    PersistenceStep { decay: None,     label: b"off" },
+    PersistenceStep { decay: Some(1),  label: b"fast" },
+    PersistenceStep { decay: Some(4),  label: b"med" },
+    PersistenceStep { decay: Some(16), label: b"slow" },
+    PersistenceStep { decay: Some(0),  label: b"inf" },
 ];
 
+// input coupling settings, in the order buttons 2 and 3 step through them
+struct CouplingStep {
+    coupling: Coupling,
+    label: &'static [u8],
+}
+
+const COUPLINGS: [CouplingStep; 2] = [
+    CouplingStep { coupling: Coupling::Dc, label: b"DC" },
+    CouplingStep { coupling: Coupling::Ac, label: b"AC" },
+];
+
+// probe attenuations, in the order buttons 2 and 3 step through them
+struct ProbeStep {
+    ratio: u16,
+    label: &'static [u8],
+}
+
+const PROBES: [ProbeStep; 2] = [
+    ProbeStep { ratio:  1, label: b" 1:1" },
+    ProbeStep { ratio: 10, label: b"10:1" },
+];
+
+// display filter settings, in the order buttons 2 and 3 step through them: the number of
+// smoothing passes
+struct Smoothing {
+    strength: u8,
+    label: &'static [u8],
+}
+
+const SMOOTHINGS: [Smoothing; 4] = [
+    Smoothing { strength:  0, label: b"off" },
+    Smoothing { strength:  1, label: b"light" },
+    Smoothing { strength:  4, label: b"med" },
+    Smoothing { strength: 16, label: b"heavy" },
+];
+
+// how Peak Detect mode's traces, with more samples than columns, are fitted to the columns, in the
+// order buttons 2 and 3 step through them
+struct DecimationStep {
+    decimation: Decimation,
+    label: &'static [u8],
+}
+
+const DECIMATION_STEPS: [DecimationStep; 3] = [
+    DecimationStep { decimation: Decimation::Envelope, label: b"env" },
+    DecimationStep { decimation: Decimation::Average,  label: b"avg" },
+    DecimationStep { decimation: Decimation::Drop,     label: b"drop" },
+];
+
+// horizontal magnifications of the single-trace display, in the order buttons 2 and 3 step
+// through them
+struct Zoom {
+    zoom: u8,
+    label: &'static [u8],
+}
+
+const ZOOMS: [Zoom; 5] = [
+    Zoom { zoom:  1, label: b"off" },
+    Zoom { zoom:  2, label: b"x2" },
+    Zoom { zoom:  4, label: b"x4" },
+    Zoom { zoom:  8, label: b"x8" },
+    Zoom { zoom: 16, label: b"x16" },
+];
+
+// record depths of the single-trace display, in the order buttons 2 and 3 step through them: the
+// samples captured per buffer, or None for two screen widths
+struct RecordDepth {
+    depth: Option<usize>,
+    label: &'static [u8],
+}
+
+const RECORD_DEPTHS: [RecordDepth; 3] = [
+    RecordDepth { depth: None,       label: b"std" },
+    RecordDepth { depth: Some(1024), label: b"1k" },
+    RecordDepth { depth: Some(2048), label: b"2k" },
+];
+
+// display color themes, in the order buttons 2 and 3 step through them
+struct ThemeStep {
+    theme: Theme,
+    label: &'static [u8],
+}
+
+const THEMES: [ThemeStep; 3] = [
+    ThemeStep { theme: Theme::HighContrast, label: b"hi" },
+    ThemeStep { theme: Theme::Dim,          label: b"dim" },
+    ThemeStep { theme: Theme::Inverted,     label: b"inv" },
+];
+
+// dual-trace display: how far channel A's trace is moved up, and channel B's down, in pixels
+const DUAL_TRACE_OFFSET: i16 = 32;
+
+// the display modes, selected in turn with a double press of button 1
+#[derive(Clone, Copy, PartialEq)]
+enum DisplayMode {
+    Single,   // channel A against time
+    Dual,     // channels A and B against time, triggered by channel A
+    Xy,       // channel A against channel B
+    Spectrum, // channel A's frequency spectrum
+    Meter,    // big readouts of channel A's level, like a multimeter
+}
+
+// what is shown below the title, selected in turn with a double press of button 4
+#[derive(Clone, Copy, PartialEq)]
+enum Readout {
+    Measurements,
+    SweepTiming, // how long sweeps take, for tuning performance
+    Hidden,
+}
+
+// the most sweeps drawn per second, unless changed with set_display_rate_hz() (by the host's FPS
+// command): about the LCD panel's own refresh rate, since drawing sweeps faster than it shows them
+// only takes time from the buttons and the host, while capture goes on at its own rate
+const DISPLAY_RATE_DEFAULT_HZ: u32 = 50;
+
+// the least time from drawing one sweep to drawing the next, or 0 to draw every captured buffer
+// that the LCD bandwidth allows
+static mut DISPLAY_INTERVAL_MS: u32 = 1000 / DISPLAY_RATE_DEFAULT_HZ;
+
+// the setting which buttons 2 and 3 adjust, selected with button 4, or from the menu
+#[derive(Clone, Copy, PartialEq)]
+enum Adjust {
+    Timebase,
+    Gain,
+    Coupling,
+    Probe,
+    Zero,        // the zero offset of a center-zero front end
+    SampleTime,  // the ADC sample-and-hold time
+    TriggerMode, // also changed by button 1
+    TriggerPosition,
+    TriggerSource,
+    TriggerType,
+    TriggerLevel,
+    Holdoff,
+    Acquisition,
+    Smoothing,
+    Decimation,  // Peak Detect mode only
+    Math,        // dual-trace display only
+    Persistence, // single-trace display only
+    Envelope,    // button 3 starts a new min/max envelope, button 2 shows or hides it
+    Grading,     // intensity grading of the traces
+    Sweep,       // single-trace display only: drawing each sweep as it is captured
+    Zoom,        // single-trace display only
+    Interpolation, // of the magnified display
+    Pan,         // the magnified part of the single-trace display
+    Depth,       // of the single-trace display's record
+    View,        // the part of the record the stopped single-trace display shows
+    Reference,   // button 3 saves the displayed sweep as the reference, button 2 shows or hides it
+    Siggen,
+    Theme,
+    Color(Trace), // the color map's color for a trace, rather than the theme's
+    Idle,        // the time before the low-power idle mode
+    Cursor(Cursor), // the cursors are shown only while one of them is selected
+}
+
+// the order in which button 4 steps through the settings, and the menu lists them
+const ADJUST_ORDER: [Adjust; 37] = [
+    Adjust::Timebase,
+    Adjust::Gain,
+    Adjust::Coupling,
+    Adjust::Probe,
+    Adjust::Zero,
+    Adjust::SampleTime,
+    Adjust::TriggerMode,
+    Adjust::TriggerPosition,
+    Adjust::TriggerSource,
+    Adjust::TriggerType,
+    Adjust::TriggerLevel,
+    Adjust::Holdoff,
+    Adjust::Acquisition,
+    Adjust::Smoothing,
+    Adjust::Decimation,
+    Adjust::Math,
+    Adjust::Persistence,
+    Adjust::Envelope,
+    Adjust::Grading,
+    Adjust::Sweep,
+    Adjust::Zoom,
+    Adjust::Interpolation,
+    Adjust::Pan,
+    Adjust::Depth,
+    Adjust::View,
+    Adjust::Reference,
+    Adjust::Siggen,
+    Adjust::Theme,
+    Adjust::Color(Trace::A),
+    Adjust::Color(Trace::B),
+    Adjust::Color(Trace::Math),
+    Adjust::Color(Trace::Reference),
+    Adjust::Idle,
+    Adjust::Cursor(Cursor::Time1),
+    Adjust::Cursor(Cursor::Time2),
+    Adjust::Cursor(Cursor::Volts1),
+    Adjust::Cursor(Cursor::Volts2),
+];
+
+// trigger level adjustment step, in percent of the screen height, so that the trigger marker moves
+// by the same few rows at any gain
+const TRIGGER_LEVEL_STEP_PCT: u8 = 2;
+
 // ======== main ========
 
+// zeroes the statics placed in CCM RAM: unlike .bss, nothing clears its section at reset (it is
+// empty unless built with the 'ccmram' feature)
+fn ccmram_init() {
+    extern "C" {
+        static mut _sccmram: u32;
+        static mut _eccmram: u32;
+    }
+    unsafe {
+        let mut word = &mut _sccmram as *mut u32;
+        let end = &mut _eccmram as *mut u32;
+        while word < end {
+            core::ptr::write_volatile(word, 0);
+            word = word.offset(1);
+        }
+    }
+}
+
 #[inline(never)]
 fn main() {
     // set system clock to 72MHz
     set_sys_clock();
 
-    cortex_m::interrupt::free(|cs| {
+    ccmram_init();
+
+    let buttons_ok = cortex_m::interrupt::free(|cs| {
         // borrow peripherals
         let rcc = RCC.borrow(cs);
         let syst = SYST.borrow(cs);
@@ -163,180 +625,2135 @@ fn main() {
         led_init(LD3);
         led_init(LD4);
         led_init(LD5);
+        led_init(LD6);
+        led_init(LD7);
+        led_init(LD8);
+        led_init(LD9);
+        led_init(LD10);
+        led_pwm_init();
+        status(Status::OutOfReset);
 
         // enable Cortex-M SysTick counter
-        syst.set_reload(9000); // set to update every 9000 clocks, or every 1ms
+        systick_use_ahb_clock(syst);
+        syst.set_reload(SYSTICK_RELOAD); // set to update every 1ms
         // - set SysTick exception (interrupt) priority to lowest possible
         unsafe { scb.shpr[11].write(0xf0); } // write to PRI_15 field of SHPR3
         syst.clear_current();
-        // SVD has incorrect identifiers here, so the API is nonsensical:
-        // 'Core' means AHB, 'External' means AHB/8
-        syst.set_clock_source(SystClkSource::External);
         syst.enable_interrupt();
         syst.enable_counter();
 
-        // set up LCD breakout board pushbuttons
+        // start the cycle counter, for timing sweeps
+        cycle_counter_init();
+
+        // set up LCD breakout board pushbuttons (edit BUTTON_CONFIG for a board wired
+        // differently)
         // - GPIOD powered on above
-        gpiod.moder.modify(|_, w| w.moder12().input()
-                                   .moder13().input()
-                                   .moder14().input()
-                                   .moder15().input());
-        unsafe {
-            gpiod.pupdr.modify(|_, w| w.pupdr12().bits(0b01) // pull up
-                                       .pupdr13().bits(0b01)
-                                       .pupdr14().bits(0b01)
-                                       .pupdr15().bits(0b01));
-        }
+        button_init(gpiod, BUTTON_CONFIG)
     });
+    if !buttons_ok {
+        status(Status::ButtonFault);
+    }
+
+    // restore the settings saved in flash, or use the defaults if there are none, or if buttons 1
+    // and 4 are held at power-on, in which case LD5 flashes, and the defaults replace the saved
+    // settings once the buttons are let go
+    delay_ms(1); // (for the pull-ups to settle)
+    let factory_reset = buttons_down() & 0b1001 == 0b1001;
+    // (and if buttons 2 and 3 are held, run the self-test once everything is set up)
+    let self_test = buttons_down() & 0b0110 == 0b0110;
+    let saved = if factory_reset {
+        flash_led(LD5);
+        while buttons_down() != 0 {}
+        // (ignore the presses)
+        delay_ms(DEBOUNCE_MS);
+        for i in 0..BUTTON_COUNT {
+            button_event(i);
+        }
+        None
+    } else {
+        settings_load()
+    };
 
     // LCD setup
-    st7735_setup();
+    if !st7735_setup(LCD_TRANSPORT) {
+        status(Status::SpiFault);
+    }
     delay_ms(50);
-    st7735_initR(St7735Type::RedTab as u8);
-    st7735_setRotation(3); // landscape
-    st7735_fillScreen(St7735Color::Black as u16);
-    st7735_print(b"stm-scope", 0, 0, St7735Color::Green, St7735Color::Black);
-    //st7735_print(env!("CARGO_PKG_VERSION").as_ref(),
-    //             10 * 8, 0, St7735Color::Green, St7735Color::Black);
+    // (set it up again if it doesn't answer, in case it missed the first time, and if it never
+    // does, run without it, headless, sending the measurements to the host instead)
+    let mut headless = false;
+    let mut lcd_attempts = 0;
+    loop {
+        st7735_initR(LCD_TYPE);
+        lcd_attempts += 1;
+        if st7735_probe() {
+            break;
+        } else if lcd_attempts < LCD_INIT_ATTEMPTS {
+            status(Status::LcdRetry);
+        } else {
+            status(Status::LcdFault);
+            headless = true;
+            break;
+        }
+    }
+    st7735_set_headless(headless);
+    st7735_backlight_init();
+    if !ST7735_PROBE_WIRED {
+        status(Status::LcdUnchecked);
+    }
+    let mut rotation = saved.map_or(DEFAULT_ROTATION, |s| s.rotation);
+    st7735_setRotation(rotation);
+    st7735_draw_graticule(GRATICULE_DIVS_X, GRATICULE_DIVS_Y, theme_colors().graticule,
+                          theme_colors().background);
+    show_title();
 
     // signal generator (DAC, DMA, TIM, GPIO output) setup
     siggen_setup();
 
     // capture (ADC, DMA, TIM, GPIO input) setup
-    capture::setup();
+    if !capture::setup() {
+        status(Status::AdcFault);
+    }
+
+    // input coupling switch (GPIO output) setup
+    coupling_init();
 
-    // turn on LD4 (northwest, blue) to show we've gotten this far
-    led_on(LD4);
+    // host PC serial link (USART1, GPIO) setup
+    uart_init(UART_BAUD);
 
-    // paint graticule
-    let mut x = 32;
-    while x <= 128 {
-        let mut y = 32;
-        while y <= 96 {
-            st7735_drawPixel(x, 127 - y, St7735Color::Red as u16);
-            y += 32;
+    // the self-test, showing its results until a button is pressed, then putting the screen,
+    // and the LEDs, back the way they were
+    if self_test {
+        while buttons_down() != 0 {}
+        delay_ms(DEBOUNCE_MS);
+        selftest();
+        while buttons_down() == 0 {}
+        while buttons_down() != 0 {}
+        // (ignore the presses)
+        delay_ms(DEBOUNCE_MS);
+        for i in 0..BUTTON_COUNT {
+            button_event(i);
         }
-        x += 32;
+        st7735_paint_background();
+        show_title();
     }
 
-    // ======== main loop ========
+    // show we've gotten this far
+    status(Status::InitDone);
 
-    enum SweepState {
-        Before, // timer running, but capture not started
-        During, // capture running or finished, display in progress
-        After,  // capture and display finished
-    };
-    let mut state = SweepState::Before;
+    // ======== main loop ========
 
     let mut siggen_freq_index = 6; // 1kHz
+    let mut theme_index = 0; // high contrast
+    let mut idle_index = 0; // off
     set_siggen_freq_from_index(siggen_freq_index);
-    let mut timebase_index = TIMEBASE_INTERVALS.len() / 2; // -FIX- something in the middle
-    set_capture_timebase_from_index(timebase_index);
-    let mut previous_y = [255u8; 160];
-    let mut x_out = 0;
+    let mut timebase = saved.map_or(TimeBase::new(), |s| s.timebase);
+    let mut display_mode = DisplayMode::Single;
+    let mut acquisition_index = 0; // plain sampling
+    let mut acq_mode = ACQUISITION_STEPS[acquisition_index].mode;
+    let mut coupling_index = 0; // DC
+    let mut smoothing_index = 0; // off
+    let mut decimation_index = 0; // envelopes
+    let mut math = false; // whether the dual-trace display shows A - B
+    let mut sweep_in = false; // whether the single-trace display draws sweeps as they're captured
+    let mut persistence_index = 0; // off
+    let mut zoom_index = 0; // off
+    let mut pan: i32 = 0; // samples from the trigger point to the middle of the magnified display
+    let mut depth_index = 0; // two screen widths
+    let mut view_offset: i32 = 0; // samples from the held sweep to the part of the record shown
+    let mut sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+    let mut vscale = saved.map_or(VerticalScale::new(), |s| s.vscale);
+    let mut gain_warning = false; // whether the gain is shown as too high for the trace
+    show_vertical_scale(&vscale, gain_warning);
+    show_coupling(coupling_index);
+    let ratio = saved.map_or(1, |s| s.probe_ratio);
+    let mut probe_index = PROBES.iter().position(|p| p.ratio == ratio).unwrap_or(0);
+    set_probe_ratio(PROBES[probe_index].ratio);
+    show_probe(probe_index);
+    let level = saved.map_or(TRIGGER_LEVEL, |s| s.trigger_level);
+    let mut trigger = Trigger::new(level, Edge::Rising, TRIGGER_HYSTERESIS);
+    if let Some(s) = saved {
+        trigger.set_mode(s.trigger_mode);
+        set_calibration(s.calibration);
+        set_zero_offset(s.zero_offset);
+        set_color_map(s.color_map);
+    }
+    let mut trigger_position_index = 0;
+    let mut trigger_type_index = 0; // edge
+    trigger.set_position(TRIGGER_POSITIONS[trigger_position_index].percent);
+    let mut holdoff_index = 0; // off
+    let mut running = true; // false while the display is stopped, holding the last sweep
+    show_trigger_mode(&trigger, running);
+    let mut averager = Averager::new();
+    let mut displayed_len = 0; // samples in DISPLAYED_SAMPLES
+    let mut adjust = Adjust::Timebase;
+    let mut cursors = Cursors::new(st7735_get_width(), st7735_get_height());
+    let mut readout = Readout::Measurements;
+    let mut sweep_timer = SweepTimer::new();
+    let mut frame_ms = millis(); // when the last sweep was drawn from a captured buffer
+    show_adjust(adjust, b"");
+    let mut settings_changed_ms = if factory_reset { Some(millis()) } else { None };
+    let mut sweep_count: u32 = 0; // sweeps displayed
+    let mut roll_samples = 0; // samples rolled in since the last completed roll-mode sweep
+    let mut heartbeat_sweeps = sweep_count; // as of the last heartbeat toggle
+    let mut menu_sweep_count = sweep_count; // as of the last time the menu was drawn
+    let mut heartbeat_ms = millis();
+    let mut pressed_ms = millis(); // when a button was last seen down
+    let mut backlight_dimmed = false;
+    let mut triggered_ms = millis(); // when a sweep was last triggered
+    let mut idle = false; // in the low-power idle mode
+    let mut idle_rate = IDLE_SAMPLE_RATE; // the sample rate while idle
+    let mut idle_counted = 0; // samples captured while idle, as of the time last counted
+    let mut supply_ms = millis().wrapping_sub(SUPPLY_CHECK_MS); // check it straight away
+    let mut trigger_marker = None; // as drawn: (level, edge, column, gain index, trace offset)
+    let mut aliasing = false; // whether the last trace drawn is likely to be an alias
+    let mut wave_shape = None; // the guess at the shape of the last trace drawn
+    let mut shape_drawn = None; // as drawn
+    let mut uptime_drawn = None; // the uptime as drawn, in seconds
+    let mut clipping = false; // whether the last trace drawn was clipped at the ADC's range
+    let mut title_warning = None; // as shown over the title
+    let mut off_screen = false; // whether the last trace drawn ran off the screen
+    let mut frozen_redraw = false; // the stopped display's sweep needs drawing again
+    let mut ref_shown = false;
+    let mut ref_drawn = None; // as drawn: (gain index, trace offset, whether the rate matches)
+    let mut envelope = Envelope::new(); // the min/max envelope, kept while it's hidden
+    let mut envelope_shown = false;
+    let mut band_drawn = None; // the envelope band, as drawn: the gain index
+    let mut minimap_drawn = None; // as drawn: (record length, view start, view length)
+    let mut acq_mode_drawn = None; // as drawn: (acquisition index, oversampling factor)
+    let mut sweep_drawn = None; // as swept in so far: (buffer, first sample, columns drawn)
+
+    // start continuous capture
+    start_capture(1, samples_per_buffer(&timebase, display_mode, acq_mode));
 
     loop {
-        match state {
-            SweepState::Before => {
-                // begin the next sweep of 160 samples
-                capture::begin_sweep();
-                // turn on LD3 at the beginning of the capture sweep
-                led_on(LD3);
-                state = SweepState::During;
-                x_out = 0;
+        let rolling = is_rolling(&timebase, display_mode);
+        let per_column = samples_per_column(&timebase, display_mode, acq_mode);
+        let oversample = oversample_factor(&timebase, display_mode, acq_mode);
+        // (the persistence display is only used for the single-trace display, and any afterglow
+        // is erased when it isn't)
+        let persistent = PERSISTENCE_STEPS[persistence_index].decay.is_some() &&
+                         display_mode == DisplayMode::Single && !rolling;
+        if !persistent {
+            st7735_persist_clear();
+        }
+        let zoomed = ZOOMS[zoom_index].zoom > 1 && display_mode == DisplayMode::Single &&
+                     !rolling && !persistent;
+        // (the min/max envelope is only kept of, and drawn behind, the unmagnified single-trace
+        // display)
+        let enveloped = envelope_shown && display_mode == DisplayMode::Single && !rolling &&
+                        !persistent && !zoomed;
+        // (sweeps are only drawn as they're captured in the plain single-trace display: a sample
+        // per column, as captured, triggered on channel A, so that the part drawn early is the
+        // same as the whole sweep drawn once the buffer is complete)
+        let sweeping = sweep_in && display_mode == DisplayMode::Single && !rolling &&
+                       !persistent && !zoomed && !headless && acq_mode == AcqMode::Sample &&
+                       SMOOTHINGS[smoothing_index].strength == 0 &&
+                       COUPLINGS[coupling_index].coupling == Coupling::Dc &&
+                       trigger.source == TriggerSource::ChA;
+        if !running {
+            // stopped: hold the last sweep on the screen, leaving the captured buffers to be
+            // refilled unseen, but draw it again as the zoom or pan changes, or the view moves
+            // along the record it came from
+            if frozen_redraw && display_mode == DisplayMode::Single && !rolling && !persistent {
+                let frozen = unsafe {
+                    let frozen = &mut SMOOTHED_SAMPLES[..displayed_len];
+                    match record_view(view_offset, displayed_len) {
+                        Some((_, view)) => frozen.copy_from_slice(view),
+                        None => frozen.copy_from_slice(&DISPLAYED_SAMPLES[..displayed_len]),
+                    }
+                    if COUPLINGS[coupling_index].coupling == Coupling::Ac {
+                        let dc = dc_offset(frozen);
+                        remove_dc(frozen, dc);
+                    }
+                    smooth(frozen, SMOOTHINGS[smoothing_index].strength);
+                    &*frozen
+                };
+                if zoomed {
+                    let pre = trigger.pre_trigger_samples(displayed_len);
+                    let center = core::cmp::max(pre as i32 + pan, 0) as usize;
+                    draw_zoomed(frozen, center, ZOOMS[zoom_index].zoom, &vscale);
+                } else {
+                    st7735_draw_trace_multi(&[(frozen, trace_color(Trace::A), 0)], &vscale,
+                                            trace_decimation(acq_mode, decimation_index));
+                }
+            }
+            frozen_redraw = false;
+        } else if rolling {
+            // roll mode: scroll each sample in at the right as it arrives, untriggered
+            let mut new_samples = [0u16; 16];
+            let n = capture::adc_dma_stream(&mut new_samples);
+            for &sample in new_samples[..n].iter() {
+                st7735_roll_append(sample, &vscale, trace_color(Trace::A));
+            }
+            // (a sweep is a screen's width of samples)
+            roll_samples += n;
+            let width = st7735_get_width() as usize;
+            if roll_samples >= width {
+                roll_samples -= width;
+                sweep_count = sweep_count.wrapping_add(1);
+            }
+        } else if let Some(held) = next_frame(frame_ms) {
+            let drawn_sweeps = sweep_count;
+            sweep_timer.begin();
+            sweep_drawn = None;
+            // oversampling: average the buffer down to the samples the rest of the display sees
+            let buffer = if oversample > 1 {
+                unsafe {
+                    let n = decimate(held.samples(), oversample, &mut OVERSAMPLED_SAMPLES);
+                    &OVERSAMPLED_SAMPLES[..n]
+                }
+            } else {
+                held.samples()
+            };
+            if display_mode == DisplayMode::Xy {
+                // two-channel capture: plot channel A against channel B, untriggered
+                show_xy(buffer);
+                sweep_timer.end();
+                sweep_count = sweep_count.wrapping_add(1);
+                status(Status::Sweep);
+            } else if display_mode == DisplayMode::Spectrum {
+                // untriggered, since the spectrum doesn't depend on where the buffer starts
+                show_spectrum(buffer);
+                sweep_timer.end();
+                sweep_count = sweep_count.wrapping_add(1);
+                status(Status::Sweep);
+            } else if display_mode == DisplayMode::Meter {
+                // untriggered too, and left alone while the menu takes the readouts' place
+                if !menu_is_open() {
+                    show_meter(buffer, COUPLINGS[coupling_index].coupling == Coupling::Ac);
+                }
+                sweep_timer.end();
+                sweep_count = sweep_count.wrapping_add(1);
+                status(Status::Sweep);
+            } else {
+                // in dual-trace mode, separate the interleaved channels, and trigger on channel A
+                let (samples, samples_b) = if display_mode == DisplayMode::Dual {
+                    unsafe {
+                        deinterleave(buffer, &mut CHANNEL_A_SAMPLES, &mut CHANNEL_B_SAMPLES);
+                        (&CHANNEL_A_SAMPLES[..], Some(&CHANNEL_B_SAMPLES[..]))
+                    }
+                } else {
+                    (buffer, None)
+                };
+                // Find the trigger point, and display a screen width of samples around it (a
+                // window of them, with more than one per column in Peak Detect mode), with the
+                // trigger position's worth of them before it, so that repeated sweeps line up.
+                // The trigger must leave the rest of the window's worth after it, and that many
+                // before it, or as many of them as the end of the buffer before can make up for,
+                // if that one was straight before it (single-trace, without oversampling).
+                let width = st7735_get_width() as usize;
+                let window = core::cmp::min(width * per_column, samples.len());
+                let last_origin = samples.len() - window;
+                let pre = trigger.pre_trigger_samples(window);
+                let sequence = held.sequence();
+                let stitchable = samples_b.is_none() && oversample == 1 &&
+                                 trigger.source == TriggerSource::ChA && window <= HISTORY_MAX;
+                let before = if stitchable {
+                    core::cmp::min(pretrigger_available(sequence), pre)
+                } else {
+                    0
+                };
+                let was_armed = trigger.is_armed();
+                // with AC coupling, each channel's DC level is taken out of its trace, and the
+                // trigger level is relative to that of the channel triggered on
+                let ac = COUPLINGS[coupling_index].coupling == Coupling::Ac;
+                let dc = if ac { dc_offset(samples) } else { 0 };
+                let dc_b = match samples_b {
+                    Some(b) if ac => dc_offset(b),
+                    _ => 0,
+                };
+                trigger.offset = if trigger.source == TriggerSource::ChB { dc_b } else { dc };
+                // (the index found in the searched part of the buffer, which starts `pre -
+                // before` samples in, is `before` samples after where the displayed window
+                // starts; channel B can only be triggered on where it is captured)
+                let found = match (trigger.source, samples_b) {
+                    (TriggerSource::ExtPin, _) => {
+                        let position = held.ext_trigger_position(trigger.edge);
+                        let origin = match position.map(|p| p / oversample) {
+                            Some(p) if p >= pre && p - pre <= last_origin => Some(p - pre),
+                            _ => None,
+                        };
+                        trigger.check_external(origin, millis())
+                    }
+                    (TriggerSource::ChB, Some(b)) => {
+                        trigger.check(&b[pre..last_origin + pre + 1], sample_rate, millis())
+                    }
+                    _ => {
+                        let searched = &samples[pre - before..last_origin + pre + 1];
+                        trigger.check(searched, sample_rate, millis())
+                    }
+                };
+                // (a free-running sweep starts at the start of the buffer)
+                let found = found.map(|i| if trigger.was_triggered() { i } else { before });
+                if found.is_some() && trigger.was_triggered() {
+                    triggered_ms = millis();
+                    indicate_acq_state(AcqState::Triggered, triggered_ms);
+                }
+                let frame = match found {
+                    Some(i) => {
+                        // (a window starting before the buffer is stitched together, and isn't
+                        // from a buffer the record can be of)
+                        let (displayed, origin) = if i >= before {
+                            (&samples[i - before..i - before + window], Some(i - before))
+                        } else {
+                            unsafe {
+                                let stitched = &mut STITCHED_SAMPLES[..window];
+                                pretrigger_stitch(before - i, samples, stitched);
+                                (&*stitched, None)
+                            }
+                        };
+                        let displayed_b = samples_b.map(|b| &b[i..i + window]);
+                        if was_armed != trigger.is_armed() {
+                            // Single sweep captured: show it, and send it to the host
+                            show_trigger_mode(&trigger, running);
+                            uart_send_samples(displayed);
+                        }
+                        match acq_mode {
+                            // Average mode shows every so many triggered sweeps, averaged; Single
+                            // mode's sweep, and free-run sweeps, are shown as captured
+                            AcqMode::Average(count) if trigger.mode() != TriggerMode::Single &&
+                                                       trigger.was_triggered() => {
+                                let ready = match displayed_b {
+                                    Some(b) => averager.add(&[displayed, b], sample_rate, count),
+                                    None => averager.add(&[displayed], sample_rate, count),
+                                };
+                                if ready {
+                                    record_clear();
+                                    Some((averager.average(0),
+                                          displayed_b.map(|_| averager.average(1))))
+                                } else {
+                                    None
+                                }
+                            }
+                            _ => {
+                                averager.reset();
+                                // (the record is of the single-trace display only)
+                                match (samples_b, origin) {
+                                    (None, Some(origin)) => record_store(samples, origin),
+                                    _ => record_clear(),
+                                }
+                                Some((displayed, displayed_b))
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                if stitchable {
+                    pretrigger_keep(samples, sequence);
+                } else {
+                    pretrigger_clear();
+                }
+                if let Some((displayed, displayed_b)) = frame {
+                    // the display filter and AC coupling draw copies of the samples, smoothed or
+                    // with the DC level taken out, leaving the measurements below to be made on
+                    // them as captured
+                    let strength = SMOOTHINGS[smoothing_index].strength;
+                    let (shown, shown_b) = if strength > 0 || ac {
+                        unsafe {
+                            let a = &mut SMOOTHED_SAMPLES[..displayed.len()];
+                            a.copy_from_slice(displayed);
+                            remove_dc(a, dc);
+                            smooth(a, strength);
+                            let b = displayed_b.map(|b| {
+                                let copy = &mut SMOOTHED_SAMPLES_B[..b.len()];
+                                copy.copy_from_slice(b);
+                                remove_dc(copy, dc_b);
+                                smooth(copy, strength);
+                                &*copy
+                            });
+                            (&*a, b)
+                        }
+                    } else {
+                        (displayed, displayed_b)
+                    };
+                    if headless {
+                        // nothing to draw on
+                    } else if persistent {
+                        // add the new trace to the afterglow of the old ones, erasing any trace
+                        // drawn without persistence
+                        st7735_draw_trace(&[], &vscale, 0, trace_color(Trace::A));
+                        st7735_persist_update(shown, &vscale, trace_color(Trace::A));
+                    } else if zoomed {
+                        // magnify the part around the trigger point, moved by the pan
+                        let center = core::cmp::max(pre as i32 + pan, 0) as usize;
+                        draw_zoomed(shown, center, ZOOMS[zoom_index].zoom, &vscale);
+                    } else {
+                        // draw the new trace, erasing the previous one, over the envelope of it
+                        // and every sweep before it
+                        if enveloped && envelope.add(shown, st7735_get_width() as usize) {
+                            band_drawn = None; // (redrawn below)
+                        }
+                        let decimation = trace_decimation(acq_mode, decimation_index);
+                        // (with the math trace centered between the two channels)
+                        let shown_math = match shown_b {
+                            Some(b) if math => unsafe {
+                                let n = core::cmp::min(shown.len(), b.len());
+                                math_subtract(shown, b, &mut MATH_DIFFERENCE[..n]);
+                                math_to_samples(&MATH_DIFFERENCE[..n], &mut MATH_SAMPLES[..n]);
+                                Some(&MATH_SAMPLES[..n])
+                            },
+                            _ => None,
+                        };
+                        match (shown_b, shown_math) {
+                            (Some(b), Some(m)) => st7735_draw_trace_multi(&[
+                                (shown, trace_color(Trace::A), DUAL_TRACE_OFFSET),
+                                (b, trace_color(Trace::B), -DUAL_TRACE_OFFSET),
+                                (m, trace_color(Trace::Math), 0),
+                            ], &vscale, decimation),
+                            (Some(b), None) => st7735_draw_trace_multi(&[
+                                (shown, trace_color(Trace::A), DUAL_TRACE_OFFSET),
+                                (b, trace_color(Trace::B), -DUAL_TRACE_OFFSET),
+                            ], &vscale, decimation),
+                            (None, _) => {
+                                st7735_draw_trace_multi(&[(shown, trace_color(Trace::A), 0)],
+                                                        &vscale, decimation)
+                            }
+                        }
+                    }
+                    displayed_len = displayed.len();
+                    unsafe { DISPLAYED_SAMPLES[..displayed_len].copy_from_slice(displayed); }
+                    sweep_timer.end();
+                    // (measured on every sample, so a signal too fast for the columns shows up)
+                    let scope = Scope::new(sample_rate, &trigger, vscale, st7735_get_height());
+                    let hz = scope.frequency(samples);
+                    aliasing = hz.map_or(false, |hz| scope.aliasing_likely(hz, per_column));
+                    wave_shape = Some(scope.wave_shape(displayed));
+                    clipping = scope.is_clipping(displayed) ||
+                               displayed_b.map_or(false, |b| scope.is_clipping(b));
+                    off_screen = match shown_b {
+                        Some(b) => scope.is_off_screen(shown, DUAL_TRACE_OFFSET) ||
+                                   scope.is_off_screen(b, -DUAL_TRACE_OFFSET),
+                        None => scope.is_off_screen(shown, 0),
+                    };
+                    if headless {
+                        // send the measurements to the host, for want of a screen to show them
+                        let span = scope.peak_to_peak(displayed);
+                        let average = scope.mean(displayed);
+                        let rms = scope.ac_rms(displayed, average);
+                        uart_send_measurements(hz.map(|hz| (hz + 0.5) as u32),
+                                               span_to_mv(span as i32),
+                                               if ac { 0 } else { counts_to_mv(average) },
+                                               span_to_mv(rms as i32));
+                    } else if menu_is_open() {
+                        // the menu takes the readouts' place: it's drawn again below
+                    } else if let Adjust::Cursor(_) = adjust {
+                        // the cursor readouts take the measurements' place (and the timebase or
+                        // gain may have changed)
+                        show_cursor_deltas(&cursors, sample_rate / per_column as u32, &vscale);
+                    } else if readout == Readout::Measurements {
+                        show_frequency(b"f ", hz, 12);
+                        let span = scope.peak_to_peak(displayed);
+                        show_voltage(b"pp", span_to_mv(span as i32), 24);
+                        let average = scope.mean(displayed);
+                        // (AC coupling leaves no mean)
+                        show_voltage(b"av", if ac { 0 } else { counts_to_mv(average) }, 36);
+                        let rms = scope.ac_rms(displayed, average);
+                        show_voltage(b"ac", span_to_mv(rms as i32), 48);
+                        show_time(b"tr", scope.rise_time(displayed), 60);
+                        show_time(b"tf", scope.fall_time(displayed), 72);
+                        show_percent(b"du", scope.duty_cycle(displayed), 84);
+                        show_time(b"pw", scope.pulse_width(displayed), 96);
+                    } else if readout == Readout::SweepTiming {
+                        show_sweep_timing(&sweep_timer);
+                    }
+                    // (counted, for the host's STATUS command)
+                    capture::check_adc_ovr_flag();
+                    // show the end of each display sweep
+                    sweep_count = sweep_count.wrapping_add(1);
+                    status(Status::Sweep);
+                }
+            }
+            // (a buffer with no trigger in it leaves the throttle open for the next one)
+            if sweep_count != drawn_sweeps {
+                frame_ms = millis();
+            }
+            // (the buffer is given back as `held` goes out of scope, so that DMA goes on filling
+            // both buffers in turn, and the one taken next is the newest)
+        } else if sweeping && !trigger.is_holding(millis()) {
+            // while DMA fills the buffer, draw as much of the sweep as has been captured, from
+            // the trigger point, so that the trace sweeps in across the screen rather than
+            // appearing once the buffer is complete (the trigger is found just as check() will
+            // find it then, in the same place, so the whole sweep drawn over this matches it)
+            if let Some((filling, captured)) = capture::adc_dma_filling() {
+                let width = st7735_get_width() as usize;
+                let pre = trigger.pre_trigger_samples(width);
+                let end = core::cmp::min(capture::buffer_length().saturating_sub(width) + pre + 1,
+                                         captured.len());
+                let searched = &captured[core::cmp::min(pre, end)..end];
+                if let Some(origin) = trigger.find(searched, sample_rate) {
+                    let columns = core::cmp::min(captured.len() - origin, width);
+                    let drawn = match sweep_drawn {
+                        Some((buffer, first, drawn)) if buffer == filling && first == origin => {
+                            drawn
+                        }
+                        _ => 0,
+                    };
+                    if columns > drawn {
+                        st7735_draw_trace_partial(&captured[origin..origin + columns], &vscale,
+                                                  trace_color(Trace::A));
+                        sweep_drawn = Some((filling, origin, columns));
+                    }
+                }
             }
-            SweepState::During => {
-                // Plot data as it becomes available via DMA from ADC1
-                // - read the number of samples transfered by DMA controller
-                let x_in = capture::get_transferred_sample_count();
-                if x_in > x_out {
-                    // erase old plot
-                    let x = x_out as i16;
-                    let y = previous_y[x_out] as i16;
-                    if y < 255 {
-                        if x % 32 == 0 && y % 32 == 0 {
-                            st7735_drawPixel(x, y, St7735Color::Green as u16);
+        }
+
+        // the trace is drawn over the menu, so draw it again after each sweep
+        if menu_is_open() && sweep_count != menu_sweep_count {
+            show_menu();
+            menu_sweep_count = sweep_count;
+        }
+
+        // button 1 (left): re-arm Single mode if it has captured its sweep, otherwise change
+        // trigger mode; a long press returns to Auto mode and autosets the timebase, gain, and
+        // trigger level, and a double press steps through the single-trace, dual-trace, XY,
+        // spectrum, and meter displays
+        match button_event(0) {
+            Some(ButtonEvent::Double) => {
+                display_mode = match display_mode {
+                    DisplayMode::Single => DisplayMode::Dual,
+                    DisplayMode::Dual => DisplayMode::Xy,
+                    DisplayMode::Xy => DisplayMode::Spectrum,
+                    DisplayMode::Spectrum => DisplayMode::Meter,
+                    DisplayMode::Meter => DisplayMode::Single,
+                };
+                match display_mode {
+                    DisplayMode::Single => {
+                        // erase the meter
+                        st7735_paint_background_rect(0, METER_TOP, METER_WIDTH,
+                                                     METER_LINES * METER_LINE_HEIGHT);
+                        st7735_draw_bargraph(meter_bar_top(), METER_BAR_HEIGHT, 0,
+                                             trace_color(Trace::A));
+                        start_capture(1, SAMPLE_COUNT);
+                    }
+                    DisplayMode::Dual => start_capture(2, SAMPLE_COUNT),
+                    DisplayMode::Xy => {
+                        // erase the traces
+                        st7735_draw_trace(&[], &vscale, 0, trace_color(Trace::A));
+                        if readout != Readout::Hidden {
+                            st7735_paint_background_rect(0, 12, MEASUREMENTS_WIDTH,
+                                                         MEASUREMENTS_HEIGHT);
+                        }
+                    }
+                    DisplayMode::Spectrum => {
+                        st7735_draw_xy(&[], &[], trace_color(Trace::A)); // erase the points
+                        start_capture(1, SAMPLE_COUNT);
+                    }
+                    DisplayMode::Meter => {
+                        // erase the bars
+                        st7735_draw_spectrum(&[], spectrum_baseline(), trace_color(Trace::A));
+                    }
+                }
+                // (the maximum sample rate depends on the number of channels, and the spectrum
+                // display labels its frequency scale instead of the time per division)
+                sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+            }
+            Some(ButtonEvent::Long) => {
+                match autoset(pixels_per_div(), GRATICULE_DIVS_X as u32) {
+                    Some(settings) => {
+                        trigger.set_mode(TriggerMode::Auto);
+                        show_trigger_mode(&trigger, running);
+                        timebase = settings.timebase;
+                        vscale = settings.vscale;
+                        // (the trigger level is relative to the mean with AC coupling)
+                        trigger.level = if COUPLINGS[coupling_index].coupling == Coupling::Ac {
+                            TRIGGER_LEVEL
                         } else {
-                            st7735_drawPixel(x, y, St7735Color::Black as u16);
+                            settings.trigger_level
+                        };
+                        show_vertical_scale(&vscale, gain_warning);
+                        settings_changed_ms = Some(millis());
+                    }
+                    None => flash_led(LD9), // no signal found: settings unchanged
+                }
+                // (autoset leaves the sample timer at some other timebase, either way)
+                sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+            }
+            Some(_) => {
+                if trigger.mode() == TriggerMode::Single && !trigger.is_armed() {
+                    trigger.arm();
+                } else {
+                    let mode = match trigger.mode() {
+                        TriggerMode::Auto => TriggerMode::Normal,
+                        TriggerMode::Normal => TriggerMode::Single,
+                        TriggerMode::Single => TriggerMode::Auto,
+                    };
+                    trigger.set_mode(mode);
+                }
+                show_trigger_mode(&trigger, running);
+                settings_changed_ms = Some(millis());
+            }
+            None => {}
+        }
+        // buttons 2 and 3: step the selected setting down or up, twice for a double press, or, in
+        // the menu, move its highlight down or up, selecting the setting it lands on, until that
+        // is picked for editing; a long press of either stops the display, or runs it again
+        let (down_event, up_event) = (button_event(1), button_event(2));
+        if down_event == Some(ButtonEvent::Long) || up_event == Some(ButtonEvent::Long) {
+            running = !running;
+            view_offset = 0; // (back to the held sweep, when stopped next)
+            show_trigger_mode(&trigger, running);
+        }
+        let down = presses(down_event);
+        let up = presses(up_event);
+        let mut repaint = false; // whether the whole screen needs redrawing
+        let mut selected = None; // the setting newly selected for buttons 2 and 3 to adjust
+        let navigating = menu_is_open() && !menu_is_editing();
+        if navigating && up != down {
+            selected = Some(ADJUST_ORDER[menu_move(down - up)]);
+        }
+        let step: i32 = if up > down { 1 } else { -1 };
+        let steps = if navigating { 0 } else { (up - down).abs() };
+        if steps > 0 {
+            settings_changed_ms = Some(millis());
+        }
+        for _ in 0..steps {
+            match adjust {
+                Adjust::Timebase => {
+                    if step < 0 {
+                        timebase_slower(&mut timebase);
+                    } else {
+                        timebase_faster(&mut timebase);
+                    }
+                    sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                }
+                Adjust::Gain => {
+                    if step < 0 { vscale_down(&mut vscale); } else { vscale_up(&mut vscale); }
+                    show_vertical_scale(&vscale, gain_warning);
+                }
+                Adjust::Coupling => {
+                    coupling_index = step_index(coupling_index, step, COUPLINGS.len());
+                    let coupling = COUPLINGS[coupling_index].coupling;
+                    set_coupling(coupling);
+                    if coupling == Coupling::Dc {
+                        trigger.offset = 0;
+                    }
+                    show_coupling(coupling_index);
+                    frozen_redraw = true;
+                }
+                Adjust::Probe => {
+                    probe_index = step_index(probe_index, step, PROBES.len());
+                    set_probe_ratio(PROBES[probe_index].ratio);
+                    show_probe(probe_index);
+                    frozen_redraw = true;
+                }
+                Adjust::Zero => {
+                    // mid-scale to begin with, until calibrating the offset measures it
+                    set_zero_offset(if step > 0 { Some(MID_SCALE) } else { None });
+                    show_adjust(adjust, zero_label());
+                    frozen_redraw = true;
+                    trigger_marker = None; // (redrawn below, as are the reference and envelope)
+                    ref_drawn = None;
+                    band_drawn = None;
+                }
+                Adjust::SampleTime => {
+                    let index = step_index(sample_time_index(), step, SAMPLE_TIMES.len());
+                    capture::set_sample_time(SAMPLE_TIMES[index].cycles);
+                    // (a longer sample time may lower the fastest rate the timebase can have)
+                    sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                    show_adjust(adjust, SAMPLE_TIMES[index].label);
+                }
+                Adjust::TriggerMode => {
+                    let mode = match (trigger.mode(), step > 0) {
+                        (TriggerMode::Auto, true) | (TriggerMode::Single, false) => {
+                            TriggerMode::Normal
                         }
+                        (TriggerMode::Normal, true) | (TriggerMode::Auto, false) => {
+                            TriggerMode::Single
+                        }
+                        (TriggerMode::Single, true) | (TriggerMode::Normal, false) => {
+                            TriggerMode::Auto
+                        }
+                    };
+                    trigger.set_mode(mode);
+                    show_trigger_mode(&trigger, running);
+                }
+                Adjust::TriggerPosition => {
+                    trigger_position_index = step_index(trigger_position_index, step,
+                                                        TRIGGER_POSITIONS.len());
+                    let position = &TRIGGER_POSITIONS[trigger_position_index];
+                    trigger.set_position(position.percent);
+                    show_adjust(adjust, position.label);
+                }
+                Adjust::TriggerSource => {
+                    let index = step_index(trigger_source_index(trigger.source), step,
+                                           TRIGGER_SOURCES.len());
+                    trigger.source = TRIGGER_SOURCES[index].source;
+                    capture::ext_trigger_enable(trigger.source == TriggerSource::ExtPin);
+                    show_adjust(adjust, TRIGGER_SOURCES[index].label);
+                    show_trigger_mode(&trigger, running);
+                }
+                Adjust::TriggerType => {
+                    trigger_type_index = step_index(trigger_type_index, step,
+                                                    TRIGGER_TYPES.len());
+                    trigger.trigger_type = match TRIGGER_TYPES[trigger_type_index].pulse {
+                        Some((polarity, compare, us)) => {
+                            TriggerType::PulseWidth {
+                                polarity: polarity,
+                                compare: compare,
+                                width_us: us,
+                            }
+                        }
+                        None => TriggerType::Edge,
+                    };
+                    show_adjust(adjust, TRIGGER_TYPES[trigger_type_index].label);
+                }
+                Adjust::TriggerLevel => {
+                    let percent = trigger_level_pct(&trigger, &vscale, display_mode);
+                    let percent = if step < 0 {
+                        percent.saturating_sub(TRIGGER_LEVEL_STEP_PCT)
+                    } else {
+                        core::cmp::min(percent + TRIGGER_LEVEL_STEP_PCT, 100)
+                    };
+                    set_trigger_level_pct(&mut trigger, percent, &vscale, display_mode);
+                    show_trigger_level(&trigger);
+                }
+                Adjust::Holdoff => {
+                    holdoff_index = step_index(holdoff_index, step, HOLDOFFS.len());
+                    let holdoff = &HOLDOFFS[holdoff_index];
+                    trigger.set_holdoff_ms(holdoff.ms);
+                    show_adjust(adjust, holdoff.label);
+                }
+                Adjust::Acquisition => {
+                    acquisition_index = step_index(acquisition_index, step,
+                                                   ACQUISITION_STEPS.len());
+                    let acquisition = &ACQUISITION_STEPS[acquisition_index];
+                    acq_mode = acquisition.mode;
+                    averager.reset();
+                    // (Peak Detect and Oversample modes capture more samples per column)
+                    sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                    show_adjust(adjust, acquisition.label);
+                }
+                Adjust::Smoothing => {
+                    smoothing_index = step_index(smoothing_index, step, SMOOTHINGS.len());
+                    show_adjust(adjust, SMOOTHINGS[smoothing_index].label);
+                }
+                Adjust::Decimation => {
+                    decimation_index = step_index(decimation_index, step,
+                                                  DECIMATION_STEPS.len());
+                    frozen_redraw = true;
+                    show_adjust(adjust, DECIMATION_STEPS[decimation_index].label);
+                }
+                Adjust::Math => {
+                    math = step > 0;
+                    show_adjust(adjust, math_label(math));
+                }
+                Adjust::Persistence => {
+                    persistence_index = step_index(persistence_index, step,
+                                                   PERSISTENCE_STEPS.len());
+                    let persistence = &PERSISTENCE_STEPS[persistence_index];
+                    if let Some(decay) = persistence.decay {
+                        st7735_persist_set_decay(decay);
+                    }
+                    show_adjust(adjust, persistence.label);
+                }
+                Adjust::Envelope => {
+                    if step > 0 {
+                        envelope.reset();
+                        envelope_shown = true;
+                    } else {
+                        envelope_shown = !envelope_shown;
+                    }
+                    band_drawn = None; // (redrawn below)
+                    show_adjust(adjust, envelope_label(envelope_shown));
+                }
+                Adjust::Grading => {
+                    st7735_set_trace_grading(step > 0);
+                    frozen_redraw = true;
+                    show_adjust(adjust, grading_label());
+                }
+                Adjust::Sweep => {
+                    sweep_in = step > 0;
+                    show_adjust(adjust, sweep_label(sweep_in));
+                }
+                Adjust::Zoom => {
+                    zoom_index = step_index(zoom_index, step, ZOOMS.len());
+                    frozen_redraw = true;
+                    show_adjust(adjust, ZOOMS[zoom_index].label);
+                }
+                Adjust::Interpolation => {
+                    st7735_set_sinc_interpolation(step > 0);
+                    frozen_redraw = true;
+                    show_adjust(adjust, interpolation_label());
+                }
+                Adjust::Pan => {
+                    // by a quarter of the magnified part per step, up to half the displayed
+                    // samples either way
+                    let samples = st7735_get_width() as i32 * per_column as i32;
+                    let pan_step = core::cmp::max(samples / ZOOMS[zoom_index].zoom as i32 / 4, 1);
+                    pan = core::cmp::max(core::cmp::min(pan + step * pan_step, samples / 2),
+                                         -samples / 2);
+                    show_time_offset(adjust, pan, sample_rate);
+                    frozen_redraw = true;
+                }
+                Adjust::Depth => {
+                    depth_index = step_index(depth_index, step, RECORD_DEPTHS.len());
+                    set_record_depth(RECORD_DEPTHS[depth_index].depth);
+                    // (restarting capture for the new buffer length)
+                    sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                    show_adjust(adjust, RECORD_DEPTHS[depth_index].label);
+                }
+                Adjust::View => {
+                    // by half a screen per step, while the display is stopped
+                    if !running {
+                        let view_step = core::cmp::max(displayed_len as i32 / 2, 1);
+                        view_offset = record_clamp_offset(view_offset + step * view_step,
+                                                          displayed_len);
+                        frozen_redraw = true;
+                    }
+                    show_time_offset(adjust, view_offset, sample_rate);
+                }
+                Adjust::Reference => {
+                    if step > 0 && displayed_len > 0 {
+                        unsafe { ref_store(&DISPLAYED_SAMPLES[..displayed_len], sample_rate); }
+                        ref_shown = true;
+                        ref_drawn = None; // (redrawn below)
+                    } else if step < 0 {
+                        ref_shown = !ref_shown;
+                    }
+                    show_adjust(adjust, ref_label(ref_shown));
+                }
+                Adjust::Siggen => {
+                    siggen_freq_index = step_index(siggen_freq_index, step,
+                                                   SIGGEN_FREQUENCIES.len());
+                    set_siggen_freq_from_index(siggen_freq_index);
+                }
+                Adjust::Theme => {
+                    // (erasing the cursors and trigger marker in the old colors before the
+                    // repaint, which shows the new theme's label)
+                    theme_index = step_index(theme_index, step, THEMES.len());
+                    st7735_draw_cursors(None, theme_colors().cursor);
+                    st7735_erase_trigger_marker();
+                    set_theme(THEMES[theme_index].theme);
+                    repaint = true;
+                }
+                Adjust::Color(trace) => {
+                    // (the repaint shows the new color's label)
+                    let mut map = color_map();
+                    map.step(trace, step);
+                    set_color_map(map);
+                    repaint = true;
+                }
+                Adjust::Idle => {
+                    idle_index = step_index(idle_index, step, IDLE_STEPS.len());
+                    show_adjust(adjust, IDLE_STEPS[idle_index].label);
+                }
+                Adjust::Cursor(cursor) => {
+                    if cursors.move_cursor(cursor, step) {
+                        st7735_draw_cursors(Some(&cursors), theme_colors().cursor);
+                        show_cursor_deltas(&cursors, sample_rate / per_column as u32, &vscale);
+                    }
+                }
+            }
+        }
+        // button 4 (right): select the next setting for buttons 2 and 3 to adjust, or, in the
+        // menu, pick the highlighted one for editing, or put it back; a long press opens or closes
+        // the menu, and a double press steps through the readouts below the title: measurements,
+        // sweep timing, or neither
+        match button_event(3) {
+            Some(ButtonEvent::Double) if !menu_is_open() => {
+                readout = match readout {
+                    Readout::Measurements => Readout::SweepTiming,
+                    Readout::SweepTiming => Readout::Hidden,
+                    Readout::Hidden => Readout::Measurements,
+                };
+                // (the new readout appears with the next sweep)
+                st7735_paint_background_rect(0, 12, MEASUREMENTS_WIDTH, MEASUREMENTS_HEIGHT);
+            }
+            Some(ButtonEvent::Long) => {
+                // the menu takes the readouts' place (and they reappear with the next sweep once
+                // it has gone)
+                st7735_paint_background_rect(0, 12, MEASUREMENTS_WIDTH, MEASUREMENTS_HEIGHT);
+                if menu_is_open() {
+                    menu_close();
+                    if let Adjust::Cursor(_) = adjust {
+                        show_cursor_deltas(&cursors, sample_rate / per_column as u32, &vscale);
                     }
-                    // plot new value
-                    let raw_conversion = capture::channel_1_data()[x_out];
-                    let microvolts_per_lsb = 806u32; // 3.3v / 2^12 bits * 10^6
-                    let microvolts = raw_conversion as u32 * microvolts_per_lsb;
-                    // Note that the 3.3v * 10^6 just cancels out in these calculations; we could
-                    // just right shift by 5 bits. But later we'll want the vertical gain
-                    // represented in terms of voltage, so build it in now.
-                    let microvolts_per_y = 25_781u32; // 3.3v * 10^6 / 128 pixels
-                    let y = 127 - (microvolts / microvolts_per_y) as i16;
-                    if y < 0 { // (can't yet happen)
-                        st7735_drawPixel(x, 0, St7735Color::Red as u16);
-                        previous_y[x_out] = 0;
-                    } else if y > 127 {
-                        st7735_drawPixel(x, 127, St7735Color::Red as u16);
-                        previous_y[x_out] = 127;
+                } else {
+                    clear_status_line();
+                    let i = ADJUST_ORDER.iter().position(|&a| a == adjust).unwrap_or(0);
+                    menu_open(ADJUST_ORDER.len(), i);
+                    show_menu();
+                }
+                show_selected_adjust(adjust, &trigger, trigger_position_index, trigger_type_index,
+                                     holdoff_index, acquisition_index, smoothing_index,
+                                     decimation_index, math, persistence_index, envelope_shown,
+                                     zoom_index, pan, depth_index, view_offset, sample_rate,
+                                     siggen_freq_index,
+                                     ref_shown, theme_index, idle_index, sweep_in);
+            }
+            Some(_) if menu_is_open() => {
+                menu_toggle_editing();
+                show_selected_adjust(adjust, &trigger, trigger_position_index, trigger_type_index,
+                                     holdoff_index, acquisition_index, smoothing_index,
+                                     decimation_index, math, persistence_index, envelope_shown,
+                                     zoom_index, pan, depth_index, view_offset, sample_rate,
+                                     siggen_freq_index,
+                                     ref_shown, theme_index, idle_index, sweep_in);
+            }
+            Some(_) => {
+                let i = ADJUST_ORDER.iter().position(|&a| a == adjust).unwrap_or(0);
+                selected = Some(ADJUST_ORDER[(i + 1) % ADJUST_ORDER.len()]);
+            }
+            None => {}
+        }
+        if let Some(next) = selected {
+            let previous = adjust;
+            adjust = next;
+            // (the cursor readouts take the measurements' place, but not the menu's)
+            match (previous, adjust) {
+                (Adjust::Cursor(_), Adjust::Cursor(_)) => {}
+                (_, Adjust::Cursor(_)) => {
+                    st7735_draw_cursors(Some(&cursors), theme_colors().cursor);
+                    if !menu_is_open() {
+                        st7735_paint_background_rect(0, 12, MEASUREMENTS_WIDTH,
+                                                     MEASUREMENTS_HEIGHT);
+                        show_cursor_deltas(&cursors, sample_rate / per_column as u32, &vscale);
+                    }
+                }
+                (Adjust::Cursor(_), _) => {
+                    // (the readouts reappear with the next sweep)
+                    st7735_draw_cursors(None, theme_colors().cursor);
+                    if !menu_is_open() {
+                        st7735_paint_background_rect(0, 12, MEASUREMENTS_WIDTH,
+                                                     MEASUREMENTS_HEIGHT);
+                    }
+                }
+                _ => {}
+            }
+            if menu_is_open() {
+                show_menu(); // (with the highlight moved)
+            }
+            show_selected_adjust(adjust, &trigger, trigger_position_index, trigger_type_index,
+                                 holdoff_index, acquisition_index, smoothing_index,
+                                 decimation_index, math, persistence_index, envelope_shown,
+                                 zoom_index, pan, depth_index, view_offset, sample_rate,
+                                 siggen_freq_index,
+                                 ref_shown, theme_index, idle_index, sweep_in);
+        }
+
+        // commands from the host
+        if let Some(command) = uart_poll_command() {
+            // calibrating captures at its own sample rate, and can fail, so it's done before the
+            // command is answered; screenshots need the screen mirrored in RAM
+            let error: Option<&[u8]> = match command {
+                Command::CalibrateOffset => {
+                    calibrate_offset();
+                    // (which moves the display's center, with a zero offset)
+                    frozen_redraw = true;
+                    trigger_marker = None;
+                    ref_drawn = None;
+                    band_drawn = None;
+                    sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                    None
+                }
+                Command::CalibrateScale(mv) => {
+                    let ok = calibrate_scale(mv);
+                    sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                    if ok { None } else { Some(b"no signal") }
+                }
+                Command::Oversample(k) => {
+                    if set_oversample(k, &mut acquisition_index) {
+                        acq_mode = ACQUISITION_STEPS[acquisition_index].mode;
+                        averager.reset();
+                        sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                        None
                     } else {
-                        st7735_drawPixel(x, y, St7735Color::White as u16);
-                        previous_y[x_out] = y as u8;
+                        Some(b"oversample 1, 2 or 4")
                     }
-                    // end of sweep?
-                    x_out += 1;
-                    if x_out >= 160 {
-                        state = SweepState::After;
+                }
+                Command::Screenshot if !cfg!(feature = "lcd-framebuffer") => {
+                    Some(b"no framebuffer")
+                }
+                Command::SpiClock(_) if LCD_TRANSPORT != St7735Transport::HardSpi => {
+                    Some(b"software SPI")
+                }
+                _ => None,
+            };
+            if let Some(reason) = error {
+                uart_write(b"ERR ");
+                uart_write(reason);
+                uart_write(b"\r\n");
+            } else {
+                uart_write(b"OK\r\n");
+                match command {
+                    Command::TimeBase(t) => {
+                        timebase = t;
+                        sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+                    }
+                    Command::VerticalScale(v) => {
+                        vscale = v;
+                        show_vertical_scale(&vscale, gain_warning);
+                    }
+                    Command::Trigger(edge, level) => {
+                        trigger.edge = edge;
+                        trigger.level = level;
+                    }
+                    Command::Single => {
+                        trigger.set_mode(TriggerMode::Single);
+                        show_trigger_mode(&trigger, running);
+                    }
+                    Command::Dump(DumpFormat::Binary) => unsafe {
+                        uart_send_samples(&DISPLAYED_SAMPLES[..displayed_len])
+                    },
+                    Command::Dump(DumpFormat::Csv) => unsafe {
+                        uart_send_csv(&DISPLAYED_SAMPLES[..displayed_len], sample_rate, &timebase)
+                    },
+                    Command::Screenshot => {
+                        uart_send_screenshot(st7735_get_width(), st7735_get_height(),
+                                             |y, row| { st7735_capture_framebuffer(y, row); });
                     }
+                    Command::DisplayRate(hz) => set_display_rate_hz(hz as u32),
+                    Command::Status => {
+                        let mut buf = [0u8; 10];
+                        let n = write_decimal(capture::adc_overruns(), 0, &mut buf);
+                        uart_write(&buf[..n]);
+                        uart_write(b" ");
+                        let n = write_decimal(uart_dropped_bytes(), 0, &mut buf);
+                        uart_write(&buf[..n]);
+                        uart_write(b"\r\n");
+                    }
+                    Command::SpiClock(khz) => {
+                        let hz = st7735_set_spi_hz(khz as u32 * 1000);
+                        let mut buf = [0u8; 11];
+                        uart_write(fmt_i32((hz / 1000) as i32, &mut buf));
+                        uart_write(b"\r\n");
+                    }
+                    Command::Rotate(r) => {
+                        // erase the cursors and trigger marker where they are, then redraw
+                        // everything for the new screen shape
+                        rotation = r;
+                        st7735_draw_cursors(None, theme_colors().cursor);
+                        st7735_erase_trigger_marker();
+                        st7735_setRotation(rotation);
+                        cursors = Cursors::new(st7735_get_width(), st7735_get_height());
+                        repaint = true;
+                    }
+                    Command::CalibrateOffset | Command::CalibrateScale(_) |
+                    Command::Oversample(_) => {}
                 }
+                settings_changed_ms = Some(millis());
+            }
+        }
+
+        // the whole screen, redrawn in the current theme's colors after it is rotated or the
+        // theme changes (with the cursors and trigger marker already erased)
+        if repaint {
+            let colors = theme_colors();
+            st7735_draw_graticule(GRATICULE_DIVS_X, GRATICULE_DIVS_Y, colors.graticule,
+                                  colors.background);
+            show_title();
+            title_warning = None; // (redrawn below)
+            frozen_redraw = true;
+            sample_rate = set_capture_timebase(&timebase, display_mode, acq_mode);
+            show_vertical_scale(&vscale, gain_warning);
+            show_coupling(coupling_index);
+            show_probe(probe_index);
+            show_trigger_mode(&trigger, running);
+            acq_mode_drawn = None; // (redrawn below)
+            shape_drawn = None;
+            uptime_drawn = None;
+            supply_ms = millis().wrapping_sub(SUPPLY_CHECK_MS); // (redrawn below)
+            if let Adjust::Cursor(_) = adjust {
+                st7735_draw_cursors(Some(&cursors), colors.cursor);
+            }
+            if menu_is_open() {
+                show_menu();
+            }
+            show_selected_adjust(adjust, &trigger, trigger_position_index, trigger_type_index,
+                                 holdoff_index, acquisition_index, smoothing_index,
+                                 decimation_index, math, persistence_index, envelope_shown,
+                                 zoom_index, pan, depth_index, view_offset, sample_rate,
+                                 siggen_freq_index,
+                                 ref_shown, theme_index, idle_index, sweep_in);
+            trigger_marker = None; // (redrawn below)
+            ref_drawn = None;
+            band_drawn = None;
+            minimap_drawn = None;
+        }
+
+        // the warnings, for the trace displays only: over the title, that the input is clipped
+        // at the ends of the ADC range, or else that the trace is likely to be an alias, and, with
+        // the gain in red, that the trace runs off the screen at it
+        let traces = !rolling &&
+                     (display_mode == DisplayMode::Single || display_mode == DisplayMode::Dual);
+        let warning: Option<&'static [u8]> = match (traces, clipping, aliasing) {
+            (true, true, _) => Some(b"CLIP"),
+            (true, false, true) => Some(b"ALIAS"),
+            _ => None,
+        };
+        if warning != title_warning {
+            show_title_warning(warning);
+            title_warning = warning;
+        }
+        // the guess at the waveform's shape goes with the measurements
+        let shape = match wave_shape {
+            Some(shape) if traces && readout == Readout::Measurements && !menu_is_open() => {
+                Some(shape)
+            }
+            _ => None,
+        };
+        if shape != shape_drawn {
+            show_wave_shape(shape);
+            shape_drawn = shape;
+        }
+        // the uptime goes with the sweep timing, but it's kept up to date between sweeps (it
+        // disappears with the sweep timing, which is painted over when it gives way)
+        let cursor_selected = match adjust { Adjust::Cursor(_) => true, _ => false };
+        let uptime = if traces && readout == Readout::SweepTiming && !menu_is_open() &&
+                        !cursor_selected {
+            Some(uptime_seconds())
+        } else {
+            None
+        };
+        if uptime != uptime_drawn {
+            if let Some(seconds) = uptime {
+                show_uptime(seconds);
             }
-            SweepState::After => {
-                // Sweep is finished (both capture and display)
-                // - disable DMA and prepare for next sweep
-                capture::finish_sweep();
-                if capture::check_adc_ovr_flag() {
-                    #[cfg(debug_assertions)]
-                    st7735_print(b"OVR set", 0, 104, St7735Color::Green, St7735Color::Black);
-                }
-                // toggle LD5 at the end of each display sweep
-                led_toggle(LD5);
-                state = SweepState::Before;
+            uptime_drawn = uptime;
+        }
+        if (traces && off_screen) != gain_warning {
+            gain_warning = traces && off_screen;
+            show_vertical_scale(&vscale, gain_warning);
+        }
+        // LD10: clipping, or Single mode's sweep captured
+        led_set(LD10, (traces && clipping) ||
+                      (trigger.mode() == TriggerMode::Single && !trigger.is_armed()));
+
+        // the trigger marker, redrawn when the trigger, gain, or trace offset changes, and hidden
+        // while the display is untriggered or magnified
+        // (with no trigger level for the external trigger input, but on channel B's trace for it)
+        let trace_offset = trigger_trace_offset(&trigger, display_mode);
+        let marker = if display_mode == DisplayMode::Xy || display_mode == DisplayMode::Spectrum ||
+                        display_mode == DisplayMode::Meter || rolling || zoomed ||
+                        trigger.source == TriggerSource::ExtPin {
+            None
+        } else {
+            let column = trigger.pre_trigger_samples(st7735_get_width() as usize) as u8;
+            Some((trigger.level, trigger.leading_edge(), column, vscale.index(), trace_offset))
+        };
+        if marker != trigger_marker {
+            match marker {
+                Some((level, edge, column, _, offset)) => {
+                    st7735_draw_trigger_marker(level, edge, column, &vscale, offset,
+                                               theme_colors().marker);
+                }
+                None => st7735_erase_trigger_marker(),
             }
+            trigger_marker = marker;
+        }
+
+        // the envelope band, behind the reference and live traces, redrawn as it widens, or the
+        // gain changes
+        let band = if enveloped { Some(vscale.index()) } else { None };
+        if band != band_drawn {
+            st7735_draw_band(band.and(envelope.bounds()), &vscale, 0, trace_color(Trace::A));
+            band_drawn = band;
         }
 
-        // button 1 (left): change timebase
-        if button_get_changed(0) {
-            button_reset_changed(0);
-            if button_get_state(0) {
-                timebase_index = (timebase_index + 1) % TIMEBASE_INTERVALS.len();
-                set_capture_timebase_from_index(timebase_index);
+        // the reference trace, behind the live one in the time-based displays, redrawn when the
+        // gain or trace offset changes, and marked as not lining up with the live trace if that
+        // is drawn at a different rate from the reference, or magnified
+        let reference = match ref_samples() {
+            Some(_) if ref_shown && !rolling && (display_mode == DisplayMode::Single ||
+                                                 display_mode == DisplayMode::Dual) => {
+                let offset = if display_mode == DisplayMode::Dual { DUAL_TRACE_OFFSET } else { 0 };
+                let window = st7735_get_width() as usize * per_column;
+                Some((vscale.index(), offset, ref_matches(window, sample_rate) && !zoomed))
             }
+            _ => None,
+        };
+        if reference != ref_drawn {
+            let offset = reference.map_or(0, |(_, offset, _)| offset);
+            st7735_draw_ref(reference.and(ref_samples()), &vscale, offset,
+                            trace_color(Trace::Reference));
+            show_ref_status(reference.map(|(_, _, matches)| matches));
+            ref_drawn = reference;
         }
-        // button 4 (right): change signal generator frequency
-        if button_get_changed(3) {
-            button_reset_changed(3);
-            if button_get_state(3) {
-                siggen_freq_index = (siggen_freq_index + 1) % SIGGEN_FREQUENCIES.len();
-                set_siggen_freq_from_index(siggen_freq_index);
+
+        // the minimap, while the stopped single-trace display holds a sweep from a record with
+        // more to it than fits on the screen, showing which part of the record is shown
+        let minimap = if !running && display_mode == DisplayMode::Single && !rolling &&
+                         !persistent && record_len() > displayed_len {
+            record_view(view_offset, displayed_len).map(|(start, _)| {
+                (record_len(), start, displayed_len)
+            })
+        } else {
+            None
+        };
+        if minimap != minimap_drawn {
+            match minimap {
+                Some((total, start, window)) => {
+                    st7735_draw_minimap(total, start, window, theme_colors().text);
+                }
+                None => st7735_erase_minimap(),
             }
+            minimap_drawn = minimap;
+        }
+
+        // the acquisition mode, which when oversampling depends on whether the ADC can keep up at
+        // the timebase
+        let acq_mode_shown = Some((acquisition_index, oversample));
+        if acq_mode_shown != acq_mode_drawn {
+            show_acq_mode(acquisition_index, oversample);
+            acq_mode_drawn = acq_mode_shown;
+        }
+
+        // heartbeat, and the acquisition state
+        let now = millis();
+        let single = trigger.mode() == TriggerMode::Single;
+        if !running || (single && !trigger.is_armed()) {
+            indicate_acq_state(AcqState::Stopped, now);
+        } else if trigger.is_waiting(now) || single {
+            indicate_acq_state(AcqState::Armed, now);
+        } else {
+            indicate_acq_state(AcqState::Running, now);
+            if sweep_count != heartbeat_sweeps && has_elapsed(heartbeat_ms, now, HEARTBEAT_MS) {
+                led_toggle(LD3);
+                heartbeat_sweeps = sweep_count;
+                heartbeat_ms = now;
+            }
+        }
+
+        // backlight: dimmed while no button has been pressed for a while
+        if buttons_down() != 0 {
+            pressed_ms = now;
+        }
+        let dim = has_elapsed(pressed_ms, now, BACKLIGHT_IDLE_MS);
+        if dim != backlight_dimmed && !idle {
+            st7735_set_backlight(if dim { BACKLIGHT_DIM } else { BACKLIGHT_FULL });
+            backlight_dimmed = dim;
+        }
+
+        // low-power idle: entered once there has been no button pressed, and no triggered sweep,
+        // for the idle time, and left at the next press or trigger
+        let quiet = IDLE_STEPS[idle_index].ms.map_or(false, |ms| {
+            has_elapsed(pressed_ms, now, ms) && has_elapsed(triggered_ms, now, ms)
+        });
+        if quiet && !idle {
+            idle_rate = enter_idle(sample_rate);
+            idle_counted = capture::samples_captured();
+            idle = true;
+        } else if !quiet && idle {
+            sample_rate = wake(&timebase, display_mode, acq_mode);
+            backlight_dimmed = false;
+            idle = false;
+        }
+
+        // supply voltage and temperature, read between sweeps (the paused capture starts its
+        // buffer over)
+        if has_elapsed(supply_ms, now, SUPPLY_CHECK_MS) {
+            let (vdda, temp_c) = capture::with_capture_paused(|| (read_vdda_mv(), read_temp_c()));
+            show_supply(vdda);
+            show_temperature(temp_c);
+            status(if vdda < SUPPLY_LOW_MV { Status::SupplyLow } else { Status::SupplyOk });
+            supply_ms = now;
+        }
+
+        // save the settings once they have stopped changing for a while, to spare the flash
+        if let Some(ms) = settings_changed_ms {
+            if has_elapsed(ms, millis(), SETTINGS_SAVE_DELAY_MS) {
+                settings_save(&Settings {
+                    timebase: timebase,
+                    vscale: vscale,
+                    trigger_mode: trigger.mode(),
+                    trigger_level: trigger.level,
+                    calibration: calibration(),
+                    rotation: rotation,
+                    probe_ratio: probe_ratio(),
+                    zero_offset: zero_offset(),
+                    color_map: color_map(),
+                });
+                settings_changed_ms = None;
+            }
+        }
+
+        // while idle, sleep until the next interrupt: the capture's, or a button press's (which
+        // wakes the scope straight away, since the next check of the buttons sees it down), and
+        // then, with SysTick stopped, keep time by the samples captured meanwhile
+        if idle {
+            cortex_m::asm::wfi();
+            idle_catch_up(&mut idle_counted, idle_rate);
         }
     }
 }
 
+// the number of presses a button event counts as (a long press does something else)
+fn presses(event: Option<ButtonEvent>) -> i32 {
+    match event {
+        Some(ButtonEvent::Double) => 2,
+        Some(ButtonEvent::Short) => 1,
+        Some(ButtonEvent::Long) | None => 0,
+    }
+}
+
+// steps `index` by `step`, staying within 0..len
+fn step_index(index: usize, step: i32, len: usize) -> usize {
+    if step < 0 {
+        index.saturating_sub(1)
+    } else if index + 1 < len {
+        index + 1
+    } else {
+        index
+    }
+}
+
+// Enter the low-power idle mode: the backlight off, sampling slowed to IDLE_SAMPLE_RATE, if
+// `sample_rate` isn't already slower, and SysTick stopped, so that the CPU only wakes for the
+// capture's interrupts, or a button press's. Returns the sample rate set.
+fn enter_idle(sample_rate: u32) -> u32 {
+    st7735_set_backlight(0);
+    let rate = capture::set_timebase(core::cmp::min(sample_rate, IDLE_SAMPLE_RATE));
+    button_wake_enable(true);
+    cortex_m::interrupt::free(|cs| SYST.borrow(cs).disable_counter());
+    rate
+}
+
+// Leave the low-power idle mode, starting SysTick again, putting the backlight on, and sampling
+// back to the timebase. Returns the sample rate set, as set_capture_timebase().
+fn wake(timebase: &TimeBase, display_mode: DisplayMode, acq_mode: AcqMode) -> u32 {
+    cortex_m::interrupt::free(|cs| SYST.borrow(cs).enable_counter());
+    button_wake_enable(false);
+    st7735_set_backlight(BACKLIGHT_FULL);
+    set_capture_timebase(timebase, display_mode, acq_mode)
+}
+
+// While idle, with SysTick stopped, count the time taken to capture the samples since
+// `*counted`, at `rate` samples per second, updating it. Only whole milliseconds are counted, the
+// rest carrying over to next time. (Time spent with capture paused, e.g. to read the supply
+// voltage, goes uncounted.)
+fn idle_catch_up(counted: &mut u32, rate: u32) {
+    if rate == 0 {
+        return;
+    }
+    let samples = capture::samples_captured().wrapping_sub(*counted);
+    let ms = (samples as u64 * 1000 / rate as u64) as u32;
+    *counted = counted.wrapping_add((ms as u64 * rate as u64 / 1000) as u32);
+    timing_advance(ms);
+}
+
 fn set_siggen_freq_from_index(i: usize) {
-    let f = &SIGGEN_FREQUENCIES[i];
-    siggen_set_freq(f.frequency);
-    clear_status_line();
-    st7735_print(b"siggen freq:", 0, 116, St7735Color::Green, St7735Color::Black);
-    st7735_print(f.label, 104, 116, St7735Color::Green, St7735Color::Black);
+    siggen_set_freq(SIGGEN_FREQUENCIES[i].frequency);
+    show_adjust(Adjust::Siggen, SIGGEN_FREQUENCIES[i].label);
+}
+
+// The bottom line of the screen shows the time per division at its left, the vertical gain at its
+// right, and the setting selected for adjustment in between: a 4-character label and a 6-character
+// value. In portrait, there isn't room for all three, so the setting goes on the line above, at
+// the left.
+const STATUS_X: u8 = 56;
+const ADJUST_WIDTH: u8 = 80;
+const GAIN_WIDTH: u8 = 24;
+
+// the row of the status line
+fn status_y() -> u8 {
+    st7735_get_height() - 12
 }
 
-fn set_capture_timebase_from_index(i: usize) {
-    let t = &TIMEBASE_INTERVALS[i];
-    capture::set_timebase(t.sample_rate);
+// the position of the setting selected for adjustment
+fn adjust_position() -> (u8, u8) {
+    if st7735_get_width() >= STATUS_X + ADJUST_WIDTH + GAIN_WIDTH {
+        (STATUS_X, status_y())
+    } else {
+        (0, status_y() - 12)
+    }
+}
+
+// Show which setting buttons 2 and 3 adjust, with its `value` if that isn't shown elsewhere, in the
+// status line, or in its row of the menu while that is open.
+fn show_adjust(adjust: Adjust, value: &[u8]) {
+    if menu_is_open() {
+        return menu_show_value(adjust_label(adjust), value);
+    }
+    let colors = theme_colors();
     clear_status_line();
-    st7735_print(t.label, 0, 116, St7735Color::Green, St7735Color::Black);
-    st7735_print(b"/div", 8 * t.label.len() as u8, 116, St7735Color::Green, St7735Color::Black);
+    let (x, y) = adjust_position();
+    st7735_print(adjust_label(adjust), x, y, colors.text, colors.background);
+    st7735_print(value, x + 32, y, colors.text, colors.background);
+}
+
+// the label shown for `adjust`
+fn adjust_label(adjust: Adjust) -> &'static [u8] {
+    match adjust {
+        Adjust::Timebase => b"time/div",
+        Adjust::Gain => b"gain",
+        Adjust::Coupling => b"cpl",
+        Adjust::Probe => b"prb",
+        Adjust::Zero => b"zero",
+        Adjust::SampleTime => b"smp",
+        Adjust::TriggerMode => b"mode",
+        Adjust::TriggerPosition => b"trg",
+        Adjust::TriggerSource => b"src",
+        Adjust::TriggerType => b"type",
+        Adjust::TriggerLevel => b"lvl",
+        Adjust::Holdoff => b"hold",
+        Adjust::Acquisition => b"acq",
+        Adjust::Smoothing => b"filt",
+        Adjust::Decimation => b"fit",
+        Adjust::Math => b"math",
+        Adjust::Persistence => b"pers",
+        Adjust::Envelope => b"env",
+        Adjust::Grading => b"grad",
+        Adjust::Sweep => b"swp",
+        Adjust::Zoom => b"zoom",
+        Adjust::Interpolation => b"intp",
+        Adjust::Pan => b"pan",
+        Adjust::Depth => b"mem",
+        Adjust::View => b"view",
+        Adjust::Reference => b"ref",
+        Adjust::Siggen => b"gen",
+        Adjust::Theme => b"thm",
+        Adjust::Color(Trace::A) => b"colA",
+        Adjust::Color(Trace::B) => b"colB",
+        Adjust::Color(Trace::Math) => b"colM",
+        Adjust::Color(Trace::Reference) => b"colR",
+        Adjust::Idle => b"idle",
+        Adjust::Cursor(_) => b"cur",
+    }
+}
+
+// Draw the menu's rows, listing the settings in ADJUST_ORDER.
+fn show_menu() {
+    menu_draw(|i| adjust_label(ADJUST_ORDER[i]));
+}
+
+// Show `adjust` as the setting selected for adjustment, with its current value.
+fn show_selected_adjust(adjust: Adjust, trigger: &Trigger, trigger_position_index: usize,
+                        trigger_type_index: usize, holdoff_index: usize,
+                        acquisition_index: usize, smoothing_index: usize,
+                        decimation_index: usize, math: bool, persistence_index: usize,
+                        envelope_shown: bool, zoom_index: usize, pan: i32,
+                        depth_index: usize, view_offset: i32, sample_rate: u32,
+                        siggen_freq_index: usize, ref_shown: bool, theme_index: usize,
+                        idle_index: usize, sweep_in: bool) {
+    let value: &[u8] = match adjust {
+        Adjust::TriggerPosition => TRIGGER_POSITIONS[trigger_position_index].label,
+        Adjust::TriggerSource => TRIGGER_SOURCES[trigger_source_index(trigger.source)].label,
+        Adjust::TriggerType => TRIGGER_TYPES[trigger_type_index].label,
+        Adjust::Holdoff => HOLDOFFS[holdoff_index].label,
+        Adjust::Acquisition => ACQUISITION_STEPS[acquisition_index].label,
+        Adjust::Smoothing => SMOOTHINGS[smoothing_index].label,
+        Adjust::Decimation => DECIMATION_STEPS[decimation_index].label,
+        Adjust::Persistence => PERSISTENCE_STEPS[persistence_index].label,
+        Adjust::Envelope => envelope_label(envelope_shown),
+        Adjust::Grading => grading_label(),
+        Adjust::Zero => zero_label(),
+        Adjust::SampleTime => SAMPLE_TIMES[sample_time_index()].label,
+        Adjust::Sweep => sweep_label(sweep_in),
+        Adjust::Zoom => ZOOMS[zoom_index].label,
+        Adjust::Interpolation => interpolation_label(),
+        Adjust::Pan => return show_time_offset(adjust, pan, sample_rate),
+        Adjust::Depth => RECORD_DEPTHS[depth_index].label,
+        Adjust::View => return show_time_offset(adjust, view_offset, sample_rate),
+        Adjust::Math => math_label(math),
+        Adjust::Reference => ref_label(ref_shown),
+        Adjust::Siggen => SIGGEN_FREQUENCIES[siggen_freq_index].label,
+        Adjust::Theme => THEMES[theme_index].label,
+        Adjust::Color(trace) => color_map().label(trace),
+        Adjust::Idle => IDLE_STEPS[idle_index].label,
+        Adjust::Cursor(cursor) => cursor.label(),
+        Adjust::TriggerLevel => return show_trigger_level(trigger),
+        _ => b"", // shown in the corners of the screen
+    };
+    show_adjust(adjust, value);
+}
+
+// the value shown for the math trace setting
+fn math_label(math: bool) -> &'static [u8] {
+    if math { b"A-B" } else { b"off" }
+}
+
+// the value shown for the envelope setting
+fn envelope_label(shown: bool) -> &'static [u8] {
+    if shown { b"on" } else { b"off" }
+}
+
+// the value shown for the intensity grading setting
+fn grading_label() -> &'static [u8] {
+    if st7735_trace_grading() { b"on" } else { b"off" }
+}
+
+// the position of the current ADC sample time in SAMPLE_TIMES
+fn sample_time_index() -> usize {
+    let cycles = capture::sample_time();
+    SAMPLE_TIMES.iter().position(|step| step.cycles == cycles).unwrap_or(0)
+}
+
+// the value shown for the zero offset setting: whether there is one
+fn zero_label() -> &'static [u8] {
+    if zero_offset().is_some() { b"mid" } else { b"off" }
+}
+
+// the value shown for the sweep-in drawing setting
+fn sweep_label(sweep_in: bool) -> &'static [u8] {
+    if sweep_in { b"sweep" } else { b"buffer" }
+}
+
+// the value shown for the interpolation setting
+fn interpolation_label() -> &'static [u8] {
+    if st7735_sinc_interpolation() { b"sinc" } else { b"linear" }
+}
+
+// how the traces' samples are fitted to the columns in acquisition mode `acq_mode`: in Peak Detect
+// mode, which captures more samples than columns, as DECIMATION_STEPS[`decimation_index`] says
+// (as envelopes by default, so that glitches between columns show), otherwise a sample per column
+fn trace_decimation(acq_mode: AcqMode, decimation_index: usize) -> Decimation {
+    match acq_mode {
+        AcqMode::PeakDetect => DECIMATION_STEPS[decimation_index].decimation,
+        _ => Decimation::Drop,
+    }
+}
+
+// Draw the magnified part of `samples`, 1/`zoom` of them around sample `center`, interpolated
+// with sin(x)/x or linearly, as set.
+fn draw_zoomed(samples: &[u16], center: usize, zoom: u8, vscale: &VerticalScale) {
+    if st7735_sinc_interpolation() {
+        st7735_draw_trace_sinc(samples, center, zoom, vscale, trace_color(Trace::A));
+    } else {
+        st7735_draw_trace_zoomed(samples, center, zoom, vscale, trace_color(Trace::A));
+    }
+}
+
+// the value shown for the reference setting: whether the reference is shown, if there is one
+fn ref_label(ref_shown: bool) -> &'static [u8] {
+    match (ref_samples(), ref_shown) {
+        (None, _) => b"none",
+        (Some(_), true) => b"on",
+        (Some(_), false) => b"off",
+    }
+}
+
+// Show "REF" above the chip temperature while the reference trace is shown, in red with a '!' if
+// it doesn't line up with the live trace, or blanks if `matches` is `None`, as it isn't shown.
+fn show_ref_status(matches: Option<bool>) {
+    let colors = theme_colors();
+    let (label, color): (&[u8], St7735Color) = match matches {
+        Some(true) => (b"REF ", colors.text),
+        Some(false) => (b"REF!", colors.warning),
+        None => (b"    ", colors.text),
+    };
+    st7735_print(label, st7735_get_width() - 40, status_y() - 60, color, colors.background);
+}
+
+// Show the guess at the waveform's shape, in the right-hand column, or blanks if `shape` is
+// `None`, as it isn't shown.
+fn show_wave_shape(shape: Option<WaveShape>) {
+    let colors = theme_colors();
+    let mut buf = [b' '; 4];
+    if let Some(shape) = shape {
+        let label = shape.label();
+        buf[..label.len()].copy_from_slice(label);
+    }
+    st7735_print(&buf, st7735_get_width() - 40, status_y() - 84, colors.text, colors.background);
+}
+
+// Show `samples`, at `sample_rate`, as a time, as the value of `adjust`: the pan, the time from
+// the trigger point to the middle of the magnified display, or the view, the time from the held
+// sweep to the part of the record shown.
+fn show_time_offset(adjust: Adjust, samples: i32, sample_rate: u32) {
+    let mut buf = [0u8; 8];
+    let us = samples as i64 * 1_000_000 / core::cmp::max(sample_rate, 1) as i64;
+    let value = fmt_eng_scaled(us as i32, -2, "s", &mut buf);
+    // (only six characters fit between the label and the gain)
+    show_adjust(adjust, &value[..core::cmp::min(value.len(), 6)]);
 }
 
+// The offset of the trace triggered on: channel B's, in the dual-trace display, if it is the
+// source, or channel A's.
+fn trigger_trace_offset(trigger: &Trigger, display_mode: DisplayMode) -> i16 {
+    match display_mode {
+        DisplayMode::Dual if trigger.source == TriggerSource::ChB => -DUAL_TRACE_OFFSET,
+        DisplayMode::Dual => DUAL_TRACE_OFFSET,
+        _ => 0,
+    }
+}
+
+// How far up the screen, in percent, the trigger level is drawn at the gain `vscale`, clipped to
+// the screen, on the trace triggered on.
+fn trigger_level_pct(trigger: &Trigger, vscale: &VerticalScale, display_mode: DisplayMode) -> u8 {
+    let offset = trigger_trace_offset(trigger, display_mode);
+    vscale.sample_to_percent(trigger.level, st7735_get_height(), offset)
+}
+
+// Set the trigger level to the ADC count drawn `percent` (at most 100) of the way up the screen at
+// the gain `vscale`, on the trace triggered on. The level stays in counts, so a change of gain
+// moves its marker to wherever that count is drawn.
+fn set_trigger_level_pct(trigger: &mut Trigger, percent: u8, vscale: &VerticalScale,
+                         display_mode: DisplayMode) {
+    let offset = trigger_trace_offset(trigger, display_mode);
+    trigger.level = vscale.percent_to_sample(percent, st7735_get_height(), offset);
+}
+
+// Show the trigger level, in volts, as the value of the setting being adjusted.
+fn show_trigger_level(trigger: &Trigger) {
+    let mut buf = [b' '; 8];
+    let n = format_millivolts(counts_to_mv(trigger.level), &mut buf);
+    // (only six characters fit between the label and the gain)
+    show_adjust(Adjust::TriggerLevel, &buf[..core::cmp::min(n, 6)]);
+}
+
+// the width of a graticule division, in pixels (and so in samples)
+fn pixels_per_div() -> u32 {
+    st7735_get_width() as u32 / GRATICULE_DIVS_X as u32
+}
+
+// whether the display rolls, rather than sweeping: at slow timebases, in the single-trace display
+fn is_rolling(timebase: &TimeBase, display_mode: DisplayMode) -> bool {
+    display_mode == DisplayMode::Single && timebase.microseconds_per_div() > ROLL_US_PER_DIV
+}
+
+// The number of samples captured per screen column: more than one only in Peak Detect mode,
+// single-trace and not rolling (so that a buffer has room for them), and only if the ADC can keep
+// up.
+fn samples_per_column(timebase: &TimeBase, display_mode: DisplayMode, acq_mode: AcqMode)
+                      -> usize {
+    let samples = PEAK_DETECT_SAMPLES_PER_COLUMN;
+    if acq_mode == AcqMode::PeakDetect && display_mode == DisplayMode::Single &&
+       !is_rolling(timebase, display_mode) &&
+       !timebase.is_limited(pixels_per_div() * samples as u32) {
+        samples
+    } else {
+        1
+    }
+}
+
+// The number of samples Oversample mode captures, and averages down to one, per sample the display
+// sees: only with one channel captured, not rolling, and only if the ADC can keep up; 1 otherwise.
+// The ADC's speed limits the fastest timebase this works at to 1/`factor` of the fastest there is.
+fn oversample_factor(timebase: &TimeBase, display_mode: DisplayMode, acq_mode: AcqMode)
+                     -> usize {
+    match acq_mode {
+        AcqMode::Oversample(factor) => {
+            let factor = core::cmp::min(factor as usize, OVERSAMPLE_MAX);
+            if display_mode != DisplayMode::Dual && display_mode != DisplayMode::Xy &&
+               !is_rolling(timebase, display_mode) &&
+               !timebase.is_limited(pixels_per_div() * factor as u32) {
+                factor
+            } else {
+                1
+            }
+        }
+        _ => 1,
+    }
+}
+
+// Switch `acquisition_index` to the acquisition step which oversamples `k` times, or to plain
+// sampling for 1, for the host's OS command. Returns false, leaving it be, for any `k` Oversample
+// mode doesn't offer: only powers of two up to OVERSAMPLE_MAX fit in the capture buffers.
+fn set_oversample(k: u8, acquisition_index: &mut usize) -> bool {
+    let mode = match oversample_mode(k) {
+        Some(mode) => mode,
+        None => return false,
+    };
+    match ACQUISITION_STEPS.iter().position(|step| step.mode == mode) {
+        Some(i) => {
+            *acquisition_index = i;
+            true
+        }
+        None => false,
+    }
+}
+
+// The number of samples of each channel captured per buffer: SAMPLE_COUNT per screen column (times
+// the oversampling), or the record depth, if one is set, for the single-trace display showing a
+// sample per column.
+fn samples_per_buffer(timebase: &TimeBase, display_mode: DisplayMode, acq_mode: AcqMode)
+                      -> usize {
+    let per_column = samples_per_column(timebase, display_mode, acq_mode);
+    let oversample = oversample_factor(timebase, display_mode, acq_mode);
+    match record_depth() {
+        Some(depth) if display_mode == DisplayMode::Single && per_column == 1 && oversample == 1 &&
+                       !is_rolling(timebase, display_mode) => depth,
+        _ => SAMPLE_COUNT * per_column * oversample,
+    }
+}
+
+// Set the sample rate for `timebase`, and show the time per division at the bottom left of the
+// screen. The label is marked with '~' if the sample timer can only approximate the rate, or shown
+// in red if the rate is limited by the ADC, in which case the real time per division is longer.
+// In the spectrum display, the frequency per division is shown instead. Capture is restarted if
+// Peak Detect or Oversample mode needs a different number of samples per screen column, or the
+// record depth a different number per buffer. Returns the sample rate actually set, or when
+// oversampling, that of the samples it is averaged down to.
+fn set_capture_timebase(timebase: &TimeBase, display_mode: DisplayMode, acq_mode: AcqMode)
+                        -> u32 {
+    let per_column = samples_per_column(timebase, display_mode, acq_mode);
+    let oversample = oversample_factor(timebase, display_mode, acq_mode) as u32;
+    let samples = samples_per_buffer(timebase, display_mode, acq_mode);
+    let channels = capture::channel_count();
+    let length = capture::buffer_length();
+    if length != 0 && length != samples * channels {
+        start_capture(channels, samples);
+    }
+    let pixels_per_div = pixels_per_div();
+    let samples_per_div = pixels_per_div * per_column as u32 * oversample;
+    let rate = timebase_apply(timebase, samples_per_div);
+    let y = status_y();
+    let colors = theme_colors();
+    st7735_fill_rect(0, y as i16, STATUS_X as i16, 12, colors.background as u16);
+    let label = timebase.label();
+    if display_mode == DisplayMode::Spectrum {
+        // (each column is a bin)
+        let mut buf = [0u8; 8];
+        let hz_per_div = fft_bin_hz(rate / oversample) * pixels_per_div;
+        let label = fmt_eng_scaled(hz_per_div as i32, 0, "Hz", &mut buf);
+        st7735_print(label, 0, y, colors.text, colors.background);
+    } else if timebase.is_limited(samples_per_div) {
+        st7735_print(label, 0, y, colors.warning, colors.background);
+    } else if rate != timebase.sample_rate(samples_per_div) {
+        st7735_print(b"~", 0, y, colors.text, colors.background);
+        st7735_print(label, 8, y, colors.text, colors.background);
+    } else {
+        st7735_print(label, 0, y, colors.text, colors.background);
+    }
+    rate / oversample
+}
+
+// The measurements are shown below the title at the top left of the screen, one per line, each
+// as a two-character label, a space, and an 8-character value.
+const MEASUREMENTS_WIDTH: u8 = 88;
+const MEASUREMENTS_HEIGHT: u8 = 96; // eight lines
+
+// Show a measurement's `label` and formatted `value` at line `y`.
+fn show_measurement(label: &[u8], value: &[u8; 8], y: u8) {
+    let colors = theme_colors();
+    st7735_print(label, 0, y, colors.text, colors.background);
+    st7735_print(value, 24, y, colors.text, colors.background);
+}
+
+// Show a frequency measurement, or blanks if there is none.
+fn show_frequency(label: &[u8], hz: Option<f32>, y: u8) {
+    let mut buf = [b' '; 8];
+    if let Some(hz) = hz {
+        format_frequency(hz, &mut buf);
+    }
+    show_measurement(label, &buf, y);
+}
+
+fn show_voltage(label: &[u8], millivolts: i32, y: u8) {
+    let mut buf = [b' '; 8];
+    format_millivolts(millivolts, &mut buf);
+    show_measurement(label, &buf, y);
+}
+
+// Show a fraction as a percentage, or blanks if there is none.
+fn show_percent(label: &[u8], fraction: Option<f32>, y: u8) {
+    let mut buf = [b' '; 8];
+    if let Some(fraction) = fraction {
+        format_percent(fraction, &mut buf);
+    }
+    show_measurement(label, &buf, y);
+}
+
+// Show a time measurement, in seconds, or blanks if there is none.
+fn show_time(label: &[u8], seconds: Option<f32>, y: u8) {
+    let mut buf = [b' '; 8];
+    if let Some(seconds) = seconds {
+        format_microseconds((seconds * 1_000_000.0 + 0.5) as u32, &mut buf);
+    }
+    show_measurement(label, &buf, y);
+}
+
+// Show the sweep timing: the period between the last two sweeps and its average, and the time
+// taken to draw the last sweep and its average.
+fn show_sweep_timing(timer: &SweepTimer) {
+    let (period, period_average) = timer.period_us();
+    let (draw, draw_average) = timer.draw_us();
+    for &(label, us, y) in &[(b"sw", period, 12), (b"s~", period_average, 24),
+                             (b"dr", draw, 36), (b"d~", draw_average, 48)] {
+        let mut buf = [b' '; 8];
+        format_microseconds(us, &mut buf);
+        show_measurement(label, &buf, y);
+    }
+}
+
+// Show the uptime, `seconds` since power-on, below the sweep timing.
+fn show_uptime(seconds: u32) {
+    let mut buf = [b' '; 8];
+    format_uptime(seconds, &mut buf);
+    show_measurement(b"up", &buf, 60);
+}
+
+// the line at which the cursor readouts start, in place of the measurements
+const CURSOR_DELTAS_Y: u8 = 12;
+
+// Show the time between the time cursors, for a trace sampled at `sample_rate`, and the voltage
+// between the voltage cursors, at vertical scale `scale`.
+fn show_cursor_deltas(cursors: &Cursors, sample_rate: u32, scale: &VerticalScale) {
+    let mut buf = [b' '; 8];
+    format_microseconds(cursors.delta_microseconds(sample_rate), &mut buf);
+    show_measurement(b"dt", &buf, CURSOR_DELTAS_Y);
+    show_voltage(b"dV", span_to_mv(cursors.delta_counts(scale) as i32), CURSOR_DELTAS_Y + 12);
+}
+
+// Returns the newest captured buffer, if one is ready and the display rate allows drawing another
+// sweep since the last was drawn, at `frame_ms`. Buffers which aren't taken are refilled.
+fn next_frame(frame_ms: u32) -> Option<capture::DmaBuffer> {
+    let interval = unsafe { DISPLAY_INTERVAL_MS };
+    if !has_elapsed(frame_ms, millis(), interval) {
+        return None;
+    }
+    capture::adc_dma_ready()
+}
+
+// Sets the most sweeps drawn per second to `hz` (by default, DISPLAY_RATE_DEFAULT_HZ), or, for 0,
+// lets every captured buffer be drawn. Capture isn't slowed: buffers captured in between are
+// dropped, and the newest is drawn.
+fn set_display_rate_hz(hz: u32) {
+    unsafe { DISPLAY_INTERVAL_MS = if hz > 0 { 1000 / hz } else { 0 }; }
+}
+
+// (Re)starts continuous capture of `channels` channels, 1 or 2, with `samples` samples of each per
+// buffer. (The buffers only have room for more than SAMPLE_COUNT samples when capturing a single
+// channel.)
+fn start_capture(channels: usize, samples: usize) {
+    capture::set_channel_count(channels);
+    let length = samples * capture::channel_count();
+    unsafe {
+        capture::adc_dma_start(&mut CAPTURE_BUFFER_A[..length], &mut CAPTURE_BUFFER_B[..length]);
+    }
+}
+
+// Separates the two-channel capture `samples`, interleaved as channel A, channel B, channel A, ...,
+// into `ch_a` and `ch_b`, as far as they have room.
+fn deinterleave(samples: &[u16], ch_a: &mut [u16], ch_b: &mut [u16]) {
+    for (i, pair) in samples.chunks(2).enumerate() {
+        if i >= ch_a.len() || i >= ch_b.len() || pair.len() < 2 {
+            break;
+        }
+        ch_a[i] = pair[0];
+        ch_b[i] = pair[1];
+    }
+}
+
+// Plots the two-channel capture `samples`, interleaved as channel A, channel B, channel A, ...,
+// as an XY display.
+fn show_xy(samples: &[u16]) {
+    let mut ch_a = [0u16; XY_POINTS_MAX];
+    let mut ch_b = [0u16; XY_POINTS_MAX];
+    deinterleave(samples, &mut ch_a, &mut ch_b);
+    let pairs = core::cmp::min(samples.len() / 2, XY_POINTS_MAX);
+    st7735_draw_xy(&ch_a[..pairs], &ch_b[..pairs], trace_color(Trace::A));
+}
+
+// The spectrum's bars stand on the row above the status line, and reach up to the line below the
+// title.
+const SPECTRUM_TOP: u8 = 12;
+
+fn spectrum_baseline() -> u8 {
+    status_y() - 1
+}
+
+// Plots the frequency spectrum of `samples`, one bin per column, with the bar heights on a
+// logarithmic scale.
+fn show_spectrum(samples: &[u16]) {
+    let mut magnitudes = [0u32; FFT_BINS];
+    fft_magnitudes(samples, &mut magnitudes);
+    let max_height = spectrum_baseline() + 1 - SPECTRUM_TOP;
+    let mut heights = [0u8; FFT_BINS];
+    for (height, &magnitude) in heights.iter_mut().zip(magnitudes.iter()) {
+        *height = fft_bar_height(magnitude, max_height);
+    }
+    st7735_draw_spectrum(&heights, spectrum_baseline(), trace_color(Trace::A));
+}
+
+// The meter's readouts are shown below the title, one per line, each as a two-character label and
+// a value in double-size characters, with a bargraph of the mean level across the ADC range on
+// the row above the status line.
+const METER_TOP: u8 = 12;
+const METER_SCALE: u8 = 2;
+const METER_LINES: u8 = 4;
+const METER_LINE_HEIGHT: u8 = 12 * METER_SCALE;
+const METER_VALUE_CHARS: usize = 6;
+const METER_WIDTH: u8 = 24 + METER_VALUE_CHARS as u8 * 8 * METER_SCALE;
+const METER_BAR_HEIGHT: u8 = 6;
+
+fn meter_bar_top() -> u8 {
+    status_y() - 1 - METER_BAR_HEIGHT
+}
+
+// Show the meter readings of `samples`: their largest, smallest, mean, and peak-to-peak levels,
+// and the mean on the bargraph. With `ac` coupling, the levels are relative to the mean (and so
+// the mean reads zero).
+fn show_meter(samples: &[u16], ac: bool) {
+    let (min, max) = min_max(samples);
+    let average = mean(samples);
+    let level = |counts: u16| {
+        if ac { span_to_mv(counts as i32 - average as i32) } else { counts_to_mv(counts) }
+    };
+    show_meter_reading(b"mx", level(max), 0);
+    show_meter_reading(b"mn", level(min), 1);
+    show_meter_reading(b"av", level(average), 2);
+    show_meter_reading(b"pp", span_to_mv(max as i32 - min as i32), 3);
+    let length = average as u32 * st7735_get_width() as u32 / 4096;
+    st7735_draw_bargraph(meter_bar_top(), METER_BAR_HEIGHT, length as u8, trace_color(Trace::A));
+}
+
+// Show a meter reading's `label` and `millivolts`, in volts, on meter line `line`.
+fn show_meter_reading(label: &[u8], millivolts: i32, line: u8) {
+    let colors = theme_colors();
+    let y = METER_TOP + line * METER_LINE_HEIGHT;
+    let mut buf = [0u8; 8];
+    let value = fmt_eng_scaled(millivolts, -1, "V", &mut buf);
+    let mut text = [b' '; METER_VALUE_CHARS];
+    let n = core::cmp::min(value.len(), METER_VALUE_CHARS);
+    text[..n].copy_from_slice(&value[..n]);
+    // (the label centered on the value's line)
+    st7735_print(label, 0, y + METER_LINE_HEIGHT / 2 - 6, colors.text, colors.background);
+    st7735_print_scaled(24, y, &text, METER_SCALE, colors.text, colors.background);
+}
+
+// a bit for each button currently down, read straight from the pins: bit 0 for button 1, and so on
+fn buttons_down() -> u32 {
+    let idr = unsafe { (*GPIOD.get()).idr.read().bits() };
+    (0..BUTTON_COUNT).filter(|&i| button_is_down(idr, i)).fold(0, |bits, i| bits | (1 << i))
+}
+
+// Blink `led` a few times, taking about half a second.
+fn flash_led(led: Led) {
+    for _ in 0..3 {
+        led_on(led);
+        delay_ms(80);
+        led_off(led);
+        delay_ms(80);
+    }
+}
+
+// Show the trigger source and mode at the top right of the screen, with "HOLD" in red in place of
+// the mode's state if the display isn't `running`, and the mode on the LEDs:
+//   Auto   - LD6 (west, green)
+//   Normal - LD7 (east, green)
+//   Single - LD8 (southwest, orange) while armed, LD10 (south, red) once captured (which the
+//            main loop shows, since LD10 also shows clipping)
+fn show_trigger_mode(trigger: &Trigger, running: bool) {
+    let colors = theme_colors();
+    let mode = trigger.mode();
+    let (label, state): (&[u8], &[u8]) = match mode {
+        TriggerMode::Auto => (b"AUTO", b"    "),
+        TriggerMode::Normal => (b"NORM", b"    "),
+        TriggerMode::Single if trigger.is_armed() => (b"SNGL", b"ARM "),
+        TriggerMode::Single => (b"SNGL", b"STOP"),
+    };
+    let width = st7735_get_width();
+    let source = TRIGGER_SOURCES[trigger_source_index(trigger.source)].mark;
+    st7735_print(source, width - 80, 0, colors.marker, colors.background);
+    st7735_print(label, width - 72, 0, colors.text, colors.background);
+    if running {
+        st7735_print(state, width - 32, 0, colors.text, colors.background);
+    } else {
+        st7735_print(b"HOLD", width - 32, 0, colors.warning, colors.background);
+    }
+    led_set(LD6, mode == TriggerMode::Auto);
+    led_set(LD7, mode == TriggerMode::Normal);
+    led_set(LD8, mode == TriggerMode::Single && trigger.is_armed());
+}
+
+// the position of `source` in TRIGGER_SOURCES
+fn trigger_source_index(source: TriggerSource) -> usize {
+    TRIGGER_SOURCES.iter().position(|s| s.source == source).unwrap_or(0)
+}
+
+// Show the acquisition mode, if it isn't plain sampling, above the bottom right of the screen, or
+// when oversampling `oversample` times, the effective resolution that gives, e.g. "13bit" for 4x
+// or "12.5b" for 2x, in red if the ADC is too slow to oversample at all at the timebase.
+fn show_acq_mode(acquisition_index: usize, oversample: usize) {
+    let colors = theme_colors();
+    let mut buf = [b' '; 5];
+    let mut color = colors.text;
+    match ACQUISITION_STEPS[acquisition_index].mode {
+        AcqMode::Sample => {}
+        AcqMode::Oversample(_) => {
+            let half_bits = effective_half_bits(oversample);
+            let bits = (half_bits / 2) as u8;
+            buf = if half_bits % 2 == 0 {
+                [b'0' + bits / 10, b'0' + bits % 10, b'b', b'i', b't']
+            } else {
+                [b'0' + bits / 10, b'0' + bits % 10, b'.', b'5', b'b']
+            };
+            if oversample == 1 {
+                color = colors.warning;
+            }
+        }
+        _ => {
+            let label = ACQUISITION_STEPS[acquisition_index].label;
+            buf[..label.len()].copy_from_slice(label);
+        }
+    }
+    st7735_print(&buf, st7735_get_width() - 40, status_y() - 24, color, colors.background);
+}
+
+// Show the supply voltage `millivolts`, to the nearest 10mV, above the acquisition mode.
+fn show_supply(millivolts: u16) {
+    let colors = theme_colors();
+    let centivolts = core::cmp::min((millivolts as u32 + 5) / 10, 999);
+    let buf = [b'0' + (centivolts / 100) as u8, b'.', b'0' + (centivolts / 10 % 10) as u8,
+               b'0' + (centivolts % 10) as u8, b'V'];
+    st7735_print(&buf, st7735_get_width() - 40, status_y() - 36, colors.text,
+                 colors.background);
+}
+
+// Show the chip's temperature `temp_c`, in degrees Celsius, above the supply voltage.
+fn show_temperature(temp_c: i16) {
+    let colors = theme_colors();
+    let mut digits = [0u8; 11];
+    let digits = fmt_i32(temp_c as i32, &mut digits);
+    let mut buf = [b' '; 5];
+    let n = core::cmp::min(digits.len(), 3);
+    buf[3 - n..3].copy_from_slice(&digits[..n]);
+    buf[3] = b'C';
+    st7735_print(&buf, st7735_get_width() - 40, status_y() - 48, colors.text,
+                 colors.background);
+}
+
+// Show the input coupling, "DC" or "AC", above the reference trace's status.
+fn show_coupling(coupling_index: usize) {
+    let colors = theme_colors();
+    st7735_print(COUPLINGS[coupling_index].label, st7735_get_width() - 40, status_y() - 72,
+                 colors.text, colors.background);
+}
+
+// Show the probe's attenuation, e.g. "10:1", above the vertical gain.
+fn show_probe(probe_index: usize) {
+    st7735_print(PROBES[probe_index].label, st7735_get_width() - 40, status_y() - 12,
+                 theme_colors().text, theme_colors().background);
+}
+
+// Show the vertical gain at the bottom right of the screen, in red if `warning` that the trace
+// runs off the screen at it.
+fn show_vertical_scale(vscale: &VerticalScale, warning: bool) {
+    let colors = theme_colors();
+    let x = st7735_get_width() - GAIN_WIDTH;
+    let color = if warning { colors.warning } else { colors.text };
+    st7735_print(vscale.label(), x, status_y(), color, colors.background);
+}
+
+// Show the title at the top left of the screen, if there is room for it beside the trigger mode.
+fn show_title() {
+    let colors = theme_colors();
+    if st7735_get_width() >= 160 {
+        st7735_print(b"stm-scope", 0, 0, colors.text, colors.background);
+        //st7735_print(env!("CARGO_PKG_VERSION").as_ref(),
+        //             10 * 8, 0, colors.text, colors.background);
+    }
+}
+
+// Show `warning` in red over the title, at the top left of the screen, e.g. that the trace is
+// likely to be an alias of the signal, or put the title back if there is none.
+fn show_title_warning(warning: Option<&[u8]>) {
+    let colors = theme_colors();
+    // (covering the whole title, where there is one)
+    let width = if st7735_get_width() >= 160 { 72 } else { 40 };
+    st7735_paint_background_rect(0, 0, width, 12);
+    match warning {
+        Some(text) => st7735_print(text, 0, 0, colors.warning, colors.background),
+        None => show_title(),
+    }
+}
+
+// clear the setting selected for adjustment
 fn clear_status_line() {
-    st7735_fill_rect(0, 116, 160, 12, St7735Color::Black as u16);
+    let (x, y) = adjust_position();
+    st7735_fill_rect(x as i16, y as i16, ADJUST_WIDTH as i16, 12, theme_colors().background as u16);
 }
 
 // ======== exception handlers, including SysTick ========
@@ -350,46 +2767,18 @@ static EXCEPTIONS: exception::Handlers = exception::Handlers {
     ..exception::DEFAULT_HANDLERS
 };
 
-static mut TIMING_DELAY: u32 = 0;
-
 extern "C" fn systick_handler(_: exception::SysTick) {
     unsafe {
-        // decrement delay
-        if TIMING_DELAY != 0 {
-            TIMING_DELAY -= 1;
-        }
+        // count milliseconds, and time delays
+        timing_tick();
 
-        // read the buttons, with debounce
-        for i in 0..BUTTONS {
-            if BUTTON_DEBOUNCE[i] > 0 {
-                BUTTON_DEBOUNCE[i] -= 1;
-            } else {
-                let gpiod = GPIOD.get();
-                // buttons are short-to-ground-with-pull-up, so invert the logic
-                let state = ((*gpiod).idr.read().bits() & (1 << BUTTON_PIN[i])) == 0;
-                if state {
-                    if BUTTON_STATE[i] == false {
-                        BUTTON_STATE[i] = true;
-                        BUTTON_CHANGED[i] = true;
-                        BUTTON_DEBOUNCE[i] = 100;
-                    }
-                } else {
-                    if BUTTON_STATE[i] == true {
-                        BUTTON_STATE[i] = false;
-                        BUTTON_CHANGED[i] = true;
-                        BUTTON_DEBOUNCE[i] = 100;
-                    }
-                }
-            }
-        }
-    }
-}
+        // dim the LEDs which have no hardware PWM
+        led_pwm_tick();
 
-#[no_mangle]
-pub extern "C" fn delay_ms(ms: u32) {
-    unsafe {
-        volatile_store(&mut TIMING_DELAY, ms);
-        while volatile_load(&TIMING_DELAY) != 0 {}
+        // read the buttons, with debounce
+        let gpiod = GPIOD.get();
+        let idr = (*gpiod).idr.read().bits();
+        cortex_m::interrupt::free(|cs| BUTTONS.borrow(cs).borrow_mut().poll_from_isr(idr));
     }
 }
 
@@ -398,13 +2787,40 @@ pub extern "C" fn delay_ms(ms: u32) {
 #[link_section = ".rodata.interrupts"]
 static INTERRUPTS: interrupt::Handlers = interrupt::Handlers {
     Dma1Ch1: dma1ch1_interrupt_handler,
+    Dma1Ch5: dma1ch5_interrupt_handler,
+    Exti1: exti1_interrupt_handler,
+    Exti95: exti9_5_interrupt_handler,
+    Exti1510: exti15_10_interrupt_handler,
+    Usart1Exti25: usart1_interrupt_handler,
     ..interrupt::DEFAULT_HANDLERS
 };
 
 extern "C" fn dma1ch1_interrupt_handler(_ctxt: interrupt::Dma1Ch1) {
-    // turn off LD3 at the end of the capture sweep
-    led_off(LD3);
-    // clear the DMA1 channel 1 transfer complete interrupt flag TCIF
-    let dma1 = DMA1.get();
-    unsafe { (*dma1).ifcr.write(|w| w.ctcif1().bits(1)); }
+    // clear the interrupt flags, and switch buffers if capturing continuously
+    capture::dma1ch1_interrupt();
+}
+
+extern "C" fn dma1ch5_interrupt_handler(_ctxt: interrupt::Dma1Ch5) {
+    // finish a DMA fill of the LCD
+    st7735_dma_interrupt();
+}
+
+extern "C" fn exti1_interrupt_handler(_ctxt: interrupt::Exti1) {
+    // time the edge on the external trigger input
+    capture::exti1_interrupt();
+}
+
+extern "C" fn exti9_5_interrupt_handler(_ctxt: interrupt::Exti95) {
+    // a button press, waking the scope from idle
+    button_wake_interrupt();
+}
+
+extern "C" fn exti15_10_interrupt_handler(_ctxt: interrupt::Exti1510) {
+    // a button press, waking the scope from idle
+    button_wake_interrupt();
+}
+
+extern "C" fn usart1_interrupt_handler(_ctxt: interrupt::Usart1Exti25) {
+    // take the received byte into the serial link's receive buffer
+    uart::usart1_interrupt();
 }
@@ -0,0 +1,50 @@
+// stm32f3-oscilloscope - src/math.rs
+// math channels, computed from the captured ones
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The difference of two 12-bit channels, A - B, runs from -4095 to 4095, twice the ADC range, so
+// it is kept signed. To draw it as a trace, math_to_samples() moves it up by mid-scale, so that a
+// difference of zero is drawn at the middle of the screen, and clips it to the 12-bit range. That
+// loses nothing on the screen: even at 1x gain, the screen only spans half the ADC range either
+// side of the middle, so a difference beyond that is drawn at the top or bottom row regardless.
+
+use core;
+
+use calibration::center_counts;
+
+/// Sets each of `out` to the difference between the corresponding samples of `a` and `b`, a - b,
+/// in ADC counts. Only as many as the shortest of the three are computed.
+pub fn math_subtract(a: &[u16], b: &[u16], out: &mut [i16]) {
+    for ((difference, &a), &b) in out.iter_mut().zip(a.iter()).zip(b.iter()) {
+        *difference = a as i16 - b as i16;
+    }
+}
+
+/// Converts each of the differences `math` to a sample, as drawn by `st7735_draw_trace`: moved up
+/// by the center reading (see `center_counts`), and clipped to the 12-bit ADC range. Only as many
+/// as the shorter of the two are converted.
+pub fn math_to_samples(math: &[i16], out: &mut [u16]) {
+    let center = center_counts() as i32;
+    for (sample, &difference) in out.iter_mut().zip(math.iter()) {
+        *sample = core::cmp::max(0, core::cmp::min(difference as i32 + center, 4095)) as u16;
+    }
+}
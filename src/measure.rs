@@ -0,0 +1,627 @@
+// stm32f3-oscilloscope - src/measure.rs
+// measurements of captured sample buffers: frequency, levels, RMS, timing, and shape
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Measurements made on captured sample buffers, for display alongside the trace.
+
+use core;
+
+use fmt::{copy_truncated, write_decimal};
+use trigger::{Edge, find_trigger};
+
+/// Estimates the frequency of the signal in `samples`, captured at `sample_rate_hz`, from the
+/// average spacing of its rising crossings of `level`. As with the trigger, the signal must fall
+/// `hysteresis` counts below `level` between crossings, so that noise isn't counted as extra
+/// cycles. Returns `None` if there are fewer than two crossings.
+pub fn measure_frequency(samples: &[u16], sample_rate_hz: u32, level: u16, hysteresis: u16)
+    -> Option<f32>
+{
+    let first = find_trigger(samples, level, Edge::Rising, hysteresis)?;
+    let mut last = first;
+    let mut cycles = 0;
+    while let Some(i) = find_trigger(&samples[last + 1..], level, Edge::Rising, hysteresis) {
+        last += 1 + i;
+        cycles += 1;
+    }
+    if cycles == 0 {
+        return None;
+    }
+    Some(sample_rate_hz as f32 * cycles as f32 / (last - first) as f32)
+}
+
+// the fewest screen columns per period of a signal that are taken to draw it faithfully
+const ALIAS_COLUMNS_PER_PERIOD: f32 = 2.5;
+
+/// Returns true if the trace of a signal measured at `hz` is likely to be an alias of it, when
+/// drawn at `column_rate_hz` screen columns per second: if there are fewer than 2.5 columns per
+/// period of it. A signal above the Nyquist frequency, half the column rate, looks like one below
+/// it, so a signal measured close to that may really be something faster.
+pub fn aliasing_likely(hz: f32, column_rate_hz: u32) -> bool {
+    hz * ALIAS_COLUMNS_PER_PERIOD > column_rate_hz as f32
+}
+
+/// Returns the fraction of the time the signal in `samples` spends above `level`, over the
+/// complete cycles (from one rising crossing of `level` to the next) in `samples`. Crossings are
+/// found as by the trigger, with the same `hysteresis`. Returns `None` if there isn't a complete
+/// cycle.
+pub fn measure_duty_cycle(samples: &[u16], level: u16, hysteresis: u16) -> Option<f32> {
+    let mut rise = next_crossing(samples, 0, level, Edge::Rising, hysteresis)?;
+    let mut high = 0;
+    let mut total = 0;
+    while let Some(fall) = next_crossing(samples, rise, level, Edge::Falling, hysteresis) {
+        match next_crossing(samples, fall, level, Edge::Rising, hysteresis) {
+            Some(next_rise) => {
+                high += fall - rise;
+                total += next_rise - rise;
+                rise = next_rise;
+            }
+            None => break,
+        }
+    }
+    if total == 0 {
+        return None;
+    }
+    Some(high as f32 / total as f32)
+}
+
+/// Returns the width, in samples, of the first complete pulse in `samples`: the time from a
+/// rising crossing of `level` to the following falling one, found as by the trigger, with the
+/// same `hysteresis`. Returns `None` if there isn't a complete pulse.
+pub fn measure_pulse_width(samples: &[u16], level: u16, hysteresis: u16) -> Option<usize> {
+    let rise = next_crossing(samples, 0, level, Edge::Rising, hysteresis)?;
+    next_crossing(samples, rise, level, Edge::Falling, hysteresis).map(|fall| fall - rise)
+}
+
+// the index of the first `edge` crossing of `level` in `samples` after index `start`
+fn next_crossing(samples: &[u16], start: usize, level: u16, edge: Edge, hysteresis: u16)
+    -> Option<usize>
+{
+    find_trigger(&samples[start..], level, edge, hysteresis).map(|i| start + i)
+}
+
+/// Formats `hz` into `buf` with four significant digits and a Hz, kHz, or MHz suffix, e.g.
+/// b"1.000kHz" or b"50.00Hz", and returns the number of bytes written (at most 8).
+pub fn format_frequency(hz: f32, buf: &mut [u8; 8]) -> usize {
+    let (mut value, suffix): (f32, &[u8]) = if hz < 1_000.0 {
+        (hz, b"Hz")
+    } else if hz < 1_000_000.0 {
+        (hz / 1_000.0, b"kHz")
+    } else {
+        (hz / 1_000_000.0, b"MHz")
+    };
+    let mut decimals = if value < 10.0 { 3 } else if value < 100.0 { 2 } else { 1 };
+    for _ in 0..decimals {
+        value *= 10.0;
+    }
+    let mut digits = (value + 0.5) as u32;
+    if digits >= 10_000 && decimals > 1 {
+        // rounding carried into another digit, e.g. 9.9996 -> 10.00
+        digits = (digits + 5) / 10;
+        decimals -= 1;
+    }
+    let n = write_decimal(digits, decimals, buf);
+    n + copy_truncated(suffix, &mut buf[n..])
+}
+
+/// Formats `millivolts` into `buf` as volts with three decimal places, e.g. b"1.650V", or as
+/// millivolts if less than one volt, e.g. b"120mV", with a leading '-' if negative, and returns
+/// the number of bytes written (at most 8).
+pub fn format_millivolts(millivolts: i32, buf: &mut [u8; 8]) -> usize {
+    let mut n = 0;
+    if millivolts < 0 {
+        buf[0] = b'-';
+        n = 1;
+    }
+    let magnitude = core::cmp::min(millivolts.unsigned_abs(), 99_999);
+    if magnitude >= 1_000 {
+        n += write_decimal(magnitude, 3, &mut buf[n..]);
+        n + copy_truncated(b"V", &mut buf[n..])
+    } else {
+        n += write_decimal(magnitude, 0, &mut buf[n..]);
+        n + copy_truncated(b"mV", &mut buf[n..])
+    }
+}
+
+/// Formats `fraction` into `buf` as a percentage with one decimal place, e.g. b"50.0%", and
+/// returns the number of bytes written (at most 8). `fraction` is clamped to 0 through 1.
+pub fn format_percent(fraction: f32, buf: &mut [u8; 8]) -> usize {
+    let fraction = if fraction < 0.0 { 0.0 } else if fraction > 1.0 { 1.0 } else { fraction };
+    let n = write_decimal((fraction * 1000.0 + 0.5) as u32, 1, buf);
+    n + copy_truncated(b"%", &mut buf[n..])
+}
+
+/// Formats `microseconds` into `buf` as microseconds, e.g. b"120us", milliseconds with two
+/// decimal places, e.g. b"12.50ms", or seconds with two decimal places, e.g. b"1.25s", whichever
+/// suits its size, and returns the number of bytes written (at most 8).
+pub fn format_microseconds(microseconds: u32, buf: &mut [u8; 8]) -> usize {
+    let hundredths_of_ms = (microseconds as u64 + 5) / 10;
+    if microseconds < 1_000 {
+        let n = write_decimal(microseconds, 0, buf);
+        n + copy_truncated(b"us", &mut buf[n..])
+    } else if hundredths_of_ms < 100_000 {
+        let n = write_decimal(hundredths_of_ms as u32, 2, buf);
+        n + copy_truncated(b"ms", &mut buf[n..])
+    } else {
+        let hundredths_of_s = (microseconds as u64 + 5_000) / 10_000;
+        let n = write_decimal(hundredths_of_s as u32, 2, buf);
+        n + copy_truncated(b"s", &mut buf[n..])
+    }
+}
+
+/// Formats `seconds` into `buf` as hours, minutes, and seconds, e.g. b"01:02:03", or from 100
+/// hours on as hours and minutes, e.g. b"1234:05" (stopping at b"99999:59"), and returns the
+/// number of bytes written (at most 8).
+pub fn format_uptime(seconds: u32, buf: &mut [u8; 8]) -> usize {
+    let hours = seconds / 3600;
+    let minutes = seconds / 60 % 60;
+    if hours < 100 {
+        write_two_digits(hours, &mut buf[0..2]);
+        buf[2] = b':';
+        write_two_digits(minutes, &mut buf[3..5]);
+        buf[5] = b':';
+        write_two_digits(seconds % 60, &mut buf[6..8]);
+        8
+    } else {
+        let (hours, minutes) = if hours > 99_999 { (99_999, 59) } else { (hours, minutes) };
+        let n = write_decimal(hours, 0, buf);
+        buf[n] = b':';
+        write_two_digits(minutes, &mut buf[n + 1..n + 3]);
+        n + 3
+    }
+}
+
+// write `value`, less than 100, into the first two bytes of `buf`, with a leading zero
+fn write_two_digits(value: u32, buf: &mut [u8]) {
+    buf[0] = b'0' + (value / 10) as u8;
+    buf[1] = b'0' + (value % 10) as u8;
+}
+
+// ======== voltage ========
+
+/// Returns the smallest and the largest of `samples`, or (0, 0) if there are none.
+pub fn min_max(samples: &[u16]) -> (u16, u16) {
+    if samples.is_empty() {
+        return (0, 0);
+    }
+    let mut min = u16::max_value();
+    let mut max = 0;
+    for &sample in samples {
+        min = core::cmp::min(min, sample);
+        max = core::cmp::max(max, sample);
+    }
+    (min, max)
+}
+
+/// Returns the difference between the largest and smallest of `samples`, or 0 if there are none.
+pub fn peak_to_peak(samples: &[u16]) -> u16 {
+    let (min, max) = min_max(samples);
+    max - min
+}
+
+/// Returns the average of `samples`, rounded to the nearest count, or 0 if there are none.
+pub fn mean(samples: &[u16]) -> u16 {
+    if samples.is_empty() {
+        return 0;
+    }
+    // 12-bit samples, so a u32 sum can't overflow for fewer than a million of them
+    let sum = samples.iter().fold(0u32, |sum, &sample| sum + sample as u32);
+    let n = samples.len() as u32;
+    ((sum + n / 2) / n) as u16
+}
+
+// samples within this many counts of either end of the 12-bit ADC range are taken to be at it
+const CLIP_TOLERANCE: u16 = 8;
+
+/// The most samples which can be at the ends of the ADC range, or drawn beyond the screen, without
+/// the signal being taken to be clipped there, rather than just touching it.
+pub const CLIP_SAMPLES: usize = 3;
+
+/// Returns true if more than `CLIP_SAMPLES` of `samples` are at, or within a few counts of,
+/// either end of the 12-bit ADC range, so that the signal is likely to go beyond it, and is
+/// clipped.
+pub fn is_clipping(samples: &[u16]) -> bool {
+    let at_rails = samples.iter()
+        .filter(|&&sample| sample <= CLIP_TOLERANCE || sample >= 4095 - CLIP_TOLERANCE)
+        .count();
+    at_rails > CLIP_SAMPLES
+}
+
+/// Returns the RMS of the AC component of `samples`, in ADC counts, after subtracting
+/// `dc_offset` (normally their mean) from each, or 0 if there are none. This uses integer math
+/// only, since there is no hardware floating point to make it quick.
+pub fn measure_rms(samples: &[u16], dc_offset: u16) -> u16 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let sum_of_squares = samples.iter().fold(0u64, |sum, &sample| {
+        let ac = sample as i32 - dc_offset as i32;
+        sum + (ac * ac) as u64
+    });
+    isqrt(sum_of_squares / samples.len() as u64) as u16
+}
+
+/// Returns the integer square root of `n`, the largest `r` such that `r * r <= n`.
+pub fn isqrt(n: u64) -> u32 {
+    // bit-by-bit method: find the result one bit at a time, from the most significant
+    let mut rem = n;
+    let mut root = 0u64;
+    let mut bit = 1u64 << 62; // the highest power of four representable
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root as u32
+}
+
+// ======== edge timing ========
+
+/// Returns the 10%-90% rise time, in seconds, of the first rising edge in `samples`, captured at
+/// `sample_rate_hz`, where 0% and 100% are the smallest and largest samples. The threshold
+/// crossings are interpolated between samples. Returns `None` if no edge rises from below the 10%
+/// threshold to the 90% one without falling back below 10% on the way.
+pub fn measure_rise_time(samples: &[u16], sample_rate_hz: u32) -> Option<f32> {
+    transition_time(samples, sample_rate_hz, Edge::Rising)
+}
+
+/// Like `measure_rise_time`, but returns the 90%-10% fall time of the first falling edge.
+pub fn measure_fall_time(samples: &[u16], sample_rate_hz: u32) -> Option<f32> {
+    transition_time(samples, sample_rate_hz, Edge::Falling)
+}
+
+// the time between the 10% and 90% crossings of the first complete `edge` in `samples`, found by
+// looking for a rising edge in the samples themselves, or in their inverse for a falling edge
+fn transition_time(samples: &[u16], sample_rate_hz: u32, edge: Edge) -> Option<f32> {
+    let amplitude = peak_to_peak(samples);
+    if amplitude == 0 || sample_rate_hz == 0 {
+        return None;
+    }
+    let value = |sample: u16| -> f32 {
+        match edge {
+            Edge::Rising => sample as f32,
+            Edge::Falling => -(sample as f32),
+        }
+    };
+    let bottom = samples.iter().fold(value(samples[0]), |bottom, &s| {
+        if value(s) < bottom { value(s) } else { bottom }
+    });
+    let low = bottom + amplitude as f32 * 0.1;
+    let high = bottom + amplitude as f32 * 0.9;
+    let mut start = None; // where the edge crossed `low`
+    for i in 1..samples.len() {
+        let a = value(samples[i - 1]);
+        let b = value(samples[i]);
+        if a < low && b >= low {
+            start = Some(crossing(i, a, b, low));
+        } else if b < low {
+            start = None;
+        }
+        if let Some(start) = start {
+            if a < high && b >= high {
+                return Some((crossing(i, a, b, high) - start) / sample_rate_hz as f32);
+            }
+        }
+    }
+    None
+}
+
+// the position, in samples, at which the signal crosses `level` going up from `a`, at sample
+// `i - 1`, to `b`, at sample `i`, by linear interpolation
+fn crossing(i: usize, a: f32, b: f32, level: f32) -> f32 {
+    (i - 1) as f32 + (level - a) / (b - a)
+}
+
+// ======== waveform shape ========
+
+/// A guess at the shape of a waveform, by `classify_waveform`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WaveShape {
+    Sine,
+    Square,
+    Triangle,
+    Unknown, // none of those, or too small to tell
+}
+
+impl WaveShape {
+    /// Returns the label shown for the shape, e.g. b"sine".
+    pub fn label(&self) -> &'static [u8] {
+        match *self {
+            WaveShape::Sine => b"sine",
+            WaveShape::Square => b"squ",
+            WaveShape::Triangle => b"tri",
+            WaveShape::Unknown => b"?",
+        }
+    }
+}
+
+// the smallest peak-to-peak amplitude, in ADC counts, whose shape is guessed at
+const SHAPE_MIN_AMPLITUDE: u16 = 64;
+
+/// Guesses whether the waveform in `samples` is roughly a sine, square, or triangle wave, from
+/// its crest factor (its peak over its RMS, both about the middle of its range) and the fraction
+/// of the time it spends in transition, between the 10% and 90% levels of its range. A square
+/// wave's crest factor is 1, and it is hardly ever in transition; a sine's is 1.41, and it spends
+/// 59% of the time in transition; a triangle's is 1.73, and it spends 80%. Returns
+/// `WaveShape::Unknown` for anything too far from all of those, e.g. most noise, or smaller than
+/// `SHAPE_MIN_AMPLITUDE` counts peak to peak. Only the shape counts, so `samples` should span a
+/// cycle or more, but needn't be a whole number of them. Neither statistic depends on the order of
+/// the samples, so a pulse train of any duty cycle is taken for a square wave, and a ramp (or
+/// noise spread evenly over its range) for a triangle.
+pub fn classify_waveform(samples: &[u16]) -> WaveShape {
+    let (min, max) = min_max(samples);
+    if max - min < SHAPE_MIN_AMPLITUDE {
+        return WaveShape::Unknown;
+    }
+    let middle = (min as i32 + max as i32) / 2;
+    let low = min + (max - min) / 10;
+    let high = max - (max - min) / 10;
+    let mut sum_of_squares = 0u64;
+    let mut transitions = 0;
+    for &sample in samples {
+        let d = sample as i32 - middle;
+        sum_of_squares += (d * d) as u64;
+        if sample > low && sample < high {
+            transitions += 1;
+        }
+    }
+    let n = samples.len() as u64;
+    // the crest factor squared, in Q8 (so 256 for a square wave, 512 for a sine, 768 for a
+    // triangle), and the percentage of the time in transition
+    let half = (max - min) as u64 / 2;
+    let crest_q8 = half * half * 256 * n / core::cmp::max(sum_of_squares, 1);
+    let transition_pct = transitions as u64 * 100 / n;
+    if crest_q8 < 384 && transition_pct < 25 {
+        WaveShape::Square
+    } else if (384..640).contains(&crest_q8) && (45..70).contains(&transition_pct) {
+        WaveShape::Sine
+    } else if (640..896).contains(&crest_q8) && transition_pct >= 70 {
+        WaveShape::Triangle
+    } else {
+        WaveShape::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testgen::{TEST_RATE, add_noise, dc, noise, ramp, sine, square, triangle};
+
+    // true if `a` is within `tolerance` (as a fraction) of `b`
+    fn close(a: f32, b: f32, tolerance: f32) -> bool {
+        (a - b).abs() <= b.abs() * tolerance
+    }
+
+    // `format` applied to `value`, as text
+    fn formatted<T>(format: fn(T, &mut [u8; 8]) -> usize, value: T) -> Vec<u8> {
+        let mut buf = [0; 8];
+        let n = format(value, &mut buf);
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn millivolts_zero_negative_and_boundaries() {
+        assert_eq!(formatted(format_millivolts, 0), b"0mV");
+        assert_eq!(formatted(format_millivolts, 999), b"999mV");
+        assert_eq!(formatted(format_millivolts, 1000), b"1.000V");
+        assert_eq!(formatted(format_millivolts, -120), b"-120mV");
+        assert_eq!(formatted(format_millivolts, -1650), b"-1.650V");
+        assert_eq!(formatted(format_millivolts, i32::min_value()), b"-99.999V");
+    }
+
+    #[test]
+    fn frequency_prefix_boundaries() {
+        assert_eq!(formatted(format_frequency, 0.0), b"0.000Hz");
+        assert_eq!(formatted(format_frequency, 50.0), b"50.00Hz");
+        assert_eq!(formatted(format_frequency, 9.9996), b"10.00Hz");
+        assert_eq!(formatted(format_frequency, 1_000.0), b"1.000kHz");
+        assert_eq!(formatted(format_frequency, 2_500_000.0), b"2.500MHz");
+    }
+
+    #[test]
+    fn percent_and_time_boundaries() {
+        assert_eq!(formatted(format_percent, -0.5), b"0.0%");
+        assert_eq!(formatted(format_percent, 0.5), b"50.0%");
+        assert_eq!(formatted(format_percent, 2.0), b"100.0%");
+        assert_eq!(formatted(format_microseconds, 0), b"0us");
+        assert_eq!(formatted(format_microseconds, 999), b"999us");
+        assert_eq!(formatted(format_microseconds, 1_000), b"1.00ms");
+        assert_eq!(formatted(format_microseconds, 999_994), b"999.99ms");
+        assert_eq!(formatted(format_microseconds, 999_995), b"1.00s");
+        assert_eq!(formatted(format_uptime, 3723), b"01:02:03");
+        assert_eq!(formatted(format_uptime, 100 * 3600 + 5 * 60), b"100:05");
+        assert_eq!(formatted(format_uptime, u32::max_value()), b"99999:59");
+    }
+
+    #[test]
+    fn frequency_of_a_sine() {
+        let s = sine(2_500.0, 1000.0, 2048.0, 320);
+        let hz = measure_frequency(&s, TEST_RATE, 2048, 50).unwrap();
+        assert!(close(hz, 2_500.0, 0.01), "{}", hz);
+    }
+
+    #[test]
+    fn frequency_needs_two_crossings() {
+        let part_cycle = sine(100.0, 1000.0, 2048.0, 320);
+        assert!(measure_frequency(&part_cycle, TEST_RATE, 2048, 50).is_none());
+        assert!(measure_frequency(&dc(2048, 320), TEST_RATE, 2048, 50).is_none());
+    }
+
+    #[test]
+    fn duty_cycle_of_a_square_wave() {
+        let s = square(1_000.0, 0.25, 1000.0, 2048.0, 320);
+        let duty = measure_duty_cycle(&s, 2048, 50).unwrap();
+        assert!(close(duty, 0.25, 0.001), "{}", duty);
+        assert_eq!(measure_pulse_width(&s, 2048, 50), Some(25));
+    }
+
+    #[test]
+    fn rms_of_sine_and_square() {
+        let s = sine(1_000.0, 1000.0, 2048.0, 300);
+        assert_eq!(mean(&s), 2048);
+        assert_eq!(measure_rms(&s, mean(&s)), 707); // 1000 / sqrt(2)
+        let s = square(1_000.0, 0.5, 1000.0, 2048.0, 300);
+        assert_eq!(measure_rms(&s, mean(&s)), 1000);
+    }
+
+    #[test]
+    fn isqrt_of_known_values() {
+        for &(n, root) in [(0, 0), (1, 1), (2, 1), (3, 1), (4, 2), (15, 3), (16, 4), (99, 9),
+                           (100, 10), (1 << 32, 1 << 16), (u32::max_value() as u64, 65535),
+                           (4_000_000_000_000, 2_000_000)].iter() {
+            assert_eq!(isqrt(n), root, "isqrt({})", n);
+        }
+        // the largest root of a 64-bit value, and the value either side of a perfect square
+        assert_eq!(isqrt(u64::max_value()), u32::max_value());
+        let r = 3_037_000_499u64;
+        assert_eq!(isqrt(r * r), r as u32);
+        assert_eq!(isqrt(r * r - 1), r as u32 - 1);
+        assert_eq!(isqrt(r * r + 1), r as u32);
+    }
+
+    #[test]
+    fn rms_is_of_the_ac_component() {
+        assert_eq!(measure_rms(&[], 2048), 0);
+        // a DC level measured about itself has none, but about another level it all counts
+        assert_eq!(measure_rms(&dc(3000, 100), 3000), 0);
+        assert_eq!(measure_rms(&dc(3000, 100), 2000), 1000);
+        // full-scale swings don't overflow the accumulator, even over a long buffer
+        let s = square(1_000.0, 0.5, 2048.0, 2048.0, 20_000);
+        assert_eq!(measure_rms(&s, 2048), 2047);
+        // a triangle's RMS is its amplitude over the square root of 3
+        let s = triangle(1_000.0, 1200.0, 2048.0, 300);
+        assert_eq!(measure_rms(&s, mean(&s)), 693);
+    }
+
+    #[test]
+    fn rise_time_of_a_ramp() {
+        // rises 2000 counts over a period of 100 samples, so 10%-90% takes 80 samples
+        let s = ramp(1_000.0, 1000.0, 2048.0, 150);
+        let t = measure_rise_time(&s, TEST_RATE).unwrap();
+        assert!(close(t, 80.0 / TEST_RATE as f32, 0.02), "{}", t);
+    }
+
+    #[test]
+    fn edge_times_are_interpolated() {
+        // 0 to 1000 in ten steps of 100, so the 10% and 90% levels fall exactly on samples
+        let rising: Vec<u16> = (0..11).map(|i| i * 100).collect();
+        let t = measure_rise_time(&rising, 1_000_000).unwrap();
+        assert!(close(t, 8e-6, 1e-4), "{}", t);
+        // steps of 300 put the 10% crossing a third of the way between samples 0 and 1, and the
+        // 90% crossing at sample 3
+        let steps = [0u16, 300, 600, 900, 1000, 1000];
+        let t = measure_rise_time(&steps, 1_000_000).unwrap();
+        assert!(close(t, (3.0 - 1.0 / 3.0) * 1e-6, 1e-4), "{}", t);
+        let falling: Vec<u16> = steps.iter().rev().cloned().collect();
+        let t = measure_fall_time(&falling, 1_000_000).unwrap();
+        assert!(close(t, (3.0 - 1.0 / 3.0) * 1e-6, 1e-4), "{}", t);
+    }
+
+    #[test]
+    fn fall_time_of_a_triangle() {
+        // falls 2000 counts over half a period of 100 samples, so 90%-10% takes 40 samples
+        let s = triangle(1_000.0, 1000.0, 2048.0, 150);
+        let t = measure_fall_time(&s, TEST_RATE).unwrap();
+        assert!(close(t, 40.0 / TEST_RATE as f32, 0.02), "{}", t);
+        let t = measure_rise_time(&s, TEST_RATE).unwrap();
+        assert!(close(t, 40.0 / TEST_RATE as f32, 0.02), "{}", t);
+    }
+
+    #[test]
+    fn edges_must_span_the_thresholds() {
+        // a signal which only rises has no falling edge, and one which only falls no rising one
+        let rising: Vec<u16> = (0..11).map(|i| i * 100).collect();
+        let falling: Vec<u16> = rising.iter().rev().cloned().collect();
+        assert_eq!(measure_fall_time(&rising, 1_000_000), None);
+        assert_eq!(measure_rise_time(&falling, 1_000_000), None);
+        // a runt, which falls back below 10% before reaching 90%, isn't timed from its start
+        let runt = [0u16, 500, 0, 1000];
+        let t = measure_rise_time(&runt, 1_000_000).unwrap();
+        assert!(close(t, 0.8e-6, 1e-4), "{}", t);
+        // nor is DC, or anything without a sample rate
+        assert_eq!(measure_rise_time(&[500; 10], 1_000_000), None);
+        assert_eq!(measure_rise_time(&rising, 0), None);
+    }
+
+    #[test]
+    fn classifies_synthetic_shapes() {
+        // over a few cycles, and over one and a bit (not a whole number of them), at two sizes
+        for &(freq, n) in [(1_000.0, 300), (800.0, 160), (2_500.0, 320)].iter() {
+            for &amp in [200.0, 1800.0].iter() {
+                let shapes = [(sine(freq, amp, 2048.0, n), WaveShape::Sine),
+                              (square(freq, 0.5, amp, 2048.0, n), WaveShape::Square),
+                              (triangle(freq, amp, 2048.0, n), WaveShape::Triangle)];
+                for &(ref s, shape) in shapes.iter() {
+                    assert!(classify_waveform(s) == shape, "{} {}", freq, amp);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn classifier_gives_up_on_other_signals() {
+        let unknown = |s: &[u16]| classify_waveform(s) == WaveShape::Unknown;
+        assert!(unknown(&[]));
+        assert!(unknown(&sine(1_000.0, 20.0, 2048.0, 300))); // too small to tell
+        // noise which bunches up in the middle of its range, as most does: the sum of two noises
+        // spread evenly is most often about their middle
+        assert!(unknown(&add_noise(&noise(99, 500.0, 2048.0, 300), 12345, 500.0)));
+    }
+
+    #[test]
+    fn classifier_looks_only_at_the_distribution() {
+        let shape = |s: Vec<u16>| classify_waveform(&s);
+        assert!(shape(square(1_000.0, 0.1, 1000.0, 2048.0, 300)) == WaveShape::Square);
+        assert!(shape(ramp(1_000.0, 1000.0, 2048.0, 300)) == WaveShape::Triangle);
+        assert!(shape(noise(99, 1000.0, 2048.0, 3000)) == WaveShape::Triangle);
+        // and a little noise doesn't change it
+        let noisy = add_noise(&sine(1_000.0, 1000.0, 2048.0, 300), 3, 30.0);
+        assert!(shape(noisy) == WaveShape::Sine);
+        assert_eq!(WaveShape::Unknown.label(), b"?");
+    }
+
+    #[test]
+    fn clipped_signal_is_detected() {
+        let clipped = sine(1_000.0, 3000.0, 2048.0, 300);
+        assert!(is_clipping(&clipped));
+        assert_eq!(min_max(&clipped), (0, 4095));
+        assert!(!is_clipping(&sine(1_000.0, 1000.0, 2048.0, 300)));
+    }
+
+    #[test]
+    fn dc_has_no_ac_measurements() {
+        let s = dc(1234, 300);
+        assert_eq!(mean(&s), 1234);
+        assert_eq!(peak_to_peak(&s), 0);
+        assert_eq!(measure_rms(&s, mean(&s)), 0);
+        assert!(measure_duty_cycle(&s, 1234, 50).is_none());
+        assert!(measure_rise_time(&s, TEST_RATE).is_none());
+        assert!(classify_waveform(&s) == WaveShape::Unknown);
+    }
+}
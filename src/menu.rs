@@ -0,0 +1,177 @@
+// stm32f3-oscilloscope - src/menu.rs
+// on-screen settings menu
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// The menu lists the settings down the left of the screen, below the title, where the readouts
+// usually are, a screenful of rows at a time. One item is highlighted: moving the highlight past
+// the top or bottom row scrolls the list, and past either end of the list wraps around to the
+// other. Once the highlighted item is picked for editing, its value is stepped in place, in its
+// row, instead of the highlight moving, until it is picked again.
+//
+// This only keeps track of the highlight and draws the rows: the caller decides what the items
+// are, reads the buttons, and changes the settings.
+
+use core;
+
+use st7735::st7735_print;
+use theme::theme_colors;
+
+/// The row (of pixels) the menu's first row is drawn at.
+pub const MENU_Y: u8 = 12;
+
+/// The most rows shown at once.
+pub const MENU_ROWS: usize = 8;
+
+// each row is a label, padded to at least four characters, then a space, then the value, in
+// eleven characters
+const ROW_CHARS: usize = 11;
+const LABEL_CHARS: usize = 4;
+
+struct Menu {
+    open: bool,
+    count: usize,       // the number of items
+    highlighted: usize, // the index of the highlighted item
+    top: usize,         // the index of the item shown in the first row
+    editing: bool,      // whether the highlighted item's value is being stepped
+    value: [u8; ROW_CHARS], // the highlighted item's value, as last shown
+    value_len: usize,
+}
+
+static mut MENU: Menu = Menu {
+    open: false,
+    count: 0,
+    highlighted: 0,
+    top: 0,
+    editing: false,
+    value: [b' '; ROW_CHARS],
+    value_len: 0,
+};
+
+/// Opens the menu, with `count` items, and with the one at index `highlighted` highlighted (and
+/// not being edited). Nothing is drawn until `menu_draw()` is called.
+pub fn menu_open(count: usize, highlighted: usize) {
+    unsafe {
+        MENU.open = count > 0;
+        MENU.count = count;
+        MENU.highlighted = core::cmp::min(highlighted, count.saturating_sub(1));
+        MENU.top = scrolled_top(0, MENU.highlighted);
+        MENU.editing = false;
+        MENU.value_len = 0;
+    }
+}
+
+/// Closes the menu. Its rows are left on the screen, for the caller to paint over.
+pub fn menu_close() {
+    unsafe {
+        MENU.open = false;
+        MENU.editing = false;
+    }
+}
+
+pub fn menu_is_open() -> bool {
+    unsafe { MENU.open }
+}
+
+/// Returns true if the highlighted item has been picked for editing.
+pub fn menu_is_editing() -> bool {
+    unsafe { MENU.open && MENU.editing }
+}
+
+/// Picks the highlighted item for editing, or puts it back if it already was.
+pub fn menu_toggle_editing() {
+    unsafe { MENU.editing = MENU.open && !MENU.editing; }
+}
+
+/// Moves the highlight `step` items down the list (up if negative), wrapping around at its ends
+/// and scrolling it as needed, and returns the index of the item now highlighted. Its value is
+/// forgotten until `menu_show_value()` shows the new one.
+pub fn menu_move(step: i32) -> usize {
+    unsafe {
+        if MENU.count > 0 {
+            let n = MENU.count as i32;
+            MENU.highlighted = ((MENU.highlighted as i32 + step % n + n) % n) as usize;
+            MENU.value_len = 0;
+            MENU.top = scrolled_top(MENU.top, MENU.highlighted);
+        }
+        MENU.highlighted
+    }
+}
+
+/// Draws the rows of the menu shown, with each item's label from `label`, given its index, and the
+/// highlighted item's value beside it.
+pub fn menu_draw<F: Fn(usize) -> &'static [u8]>(label: F) {
+    let (top, count) = unsafe { (MENU.top, MENU.count) };
+    for row in 0..core::cmp::min(MENU_ROWS, count - top) {
+        draw_row(row, label(top + row));
+    }
+}
+
+/// Shows `value` as the value of the highlighted item, whose label is `label`, in its row. It is
+/// drawn again along with the rest of the menu, until the highlight moves.
+pub fn menu_show_value(label: &[u8], value: &[u8]) {
+    unsafe {
+        let n = core::cmp::min(value.len(), ROW_CHARS);
+        MENU.value[..n].copy_from_slice(&value[..n]);
+        MENU.value_len = n;
+    }
+    if menu_is_open() {
+        draw_row(unsafe { MENU.highlighted - MENU.top }, label);
+    }
+}
+
+// draw the row `row` of the menu shown, whose item's label is `label`: the highlighted item's
+// label is drawn in inverse video, or, while it is being edited, its value is instead
+fn draw_row(row: usize, label: &[u8]) {
+    let colors = theme_colors();
+    let menu = unsafe { &MENU };
+    let mut text = [b' '; ROW_CHARS];
+    let label_len = core::cmp::min(label.len(), ROW_CHARS - 1);
+    text[..label_len].copy_from_slice(&label[..label_len]);
+    let value_start = core::cmp::max(label_len, LABEL_CHARS) + 1;
+    let highlighted = menu.top + row == menu.highlighted;
+    if highlighted {
+        let n = core::cmp::min(menu.value_len, ROW_CHARS - value_start);
+        text[value_start..value_start + n].copy_from_slice(&menu.value[..n]);
+    }
+    let (normal, inverse) = ((colors.text, colors.background), (colors.background, colors.text));
+    let (label_colors, value_colors) = match (highlighted, menu.editing) {
+        (true, false) => (inverse, normal),
+        (true, true) => (normal, inverse),
+        (false, _) => (normal, normal),
+    };
+    let y = MENU_Y + row as u8 * 12;
+    st7735_print(&text[..value_start], 0, y, label_colors.0, label_colors.1);
+    st7735_print(&text[value_start..], value_start as u8 * 8, y, value_colors.0, value_colors.1);
+}
+
+// the item to show in the first row, scrolled from `top` as little as needed to show the item
+// `highlighted`
+fn scrolled_top(top: usize, highlighted: usize) -> usize {
+    if highlighted < top {
+        highlighted
+    } else if highlighted >= top + MENU_ROWS {
+        highlighted + 1 - MENU_ROWS
+    } else {
+        top
+    }
+}
@@ -0,0 +1,39 @@
+// 8 x 12 font glyph extents - characters 0..127
+// The leftmost lit column and the number of columns spanned by each glyph in
+// parallax_8x12_font, for proportional printing. Derived from that font's bitmaps, so the
+// Parallax license below applies.
+
+// Copyright (c) 2006 Parallax, Inc.
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies of the Software, and to permit persons to whom the Software
+// is furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE
+// WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR
+// COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE,
+// ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Blank glyphs (space and the control characters with no bitmap) are given a span of 4 columns,
+// so that spaces still separate words.
+pub const FONT_8X12_EXTENTS: [(u8, u8); 128] = [
+    (1, 6), (1, 6), (1, 6), (1, 6), (0, 8), (0, 8), (0, 8), (0, 8), // 0x00
+    (1, 6), (1, 5), (3, 5), (0, 5), (3, 5), (0, 5), (0, 8), (3, 2), // 0x08
+    (0, 8), (0, 8), (3, 5), (0, 5), (0, 8), (0, 8), (0, 8), (1, 6), // 0x10
+    (0, 8), (0, 8), (0, 8), (0, 4), (0, 4), (0, 8), (4, 4), (0, 4), // 0x18
+    (0, 4), (2, 4), (1, 6), (0, 7), (1, 6), (1, 6), (0, 7), (2, 3), // 0x20
+    (1, 5), (1, 5), (0, 8), (1, 6), (2, 3), (1, 6), (2, 3), (0, 7), // 0x28
+    (0, 7), (1, 6), (1, 6), (1, 6), (0, 7), (1, 6), (1, 6), (0, 7), // 0x30
+    (1, 6), (1, 6), (2, 3), (2, 3), (1, 6), (1, 6), (1, 6), (1, 6), // 0x38
+    (0, 7), (1, 6), (0, 7), (0, 7), (0, 7), (0, 7), (0, 7), (0, 7), // 0x40
+    (0, 7), (2, 4), (0, 7), (0, 7), (0, 7), (0, 7), (0, 7), (0, 7), // 0x48
+    (0, 7), (0, 7), (0, 7), (1, 6), (1, 6), (1, 6), (1, 6), (0, 7), // 0x50
+    (1, 6), (1, 6), (0, 7), (2, 4), (0, 7), (2, 4), (0, 7), (0, 8), // 0x58
+    (2, 4), (0, 7), (0, 7), (1, 6), (0, 7), (1, 6), (1, 6), (0, 7), // 0x60
+    (0, 7), (1, 6), (1, 6), (0, 7), (1, 6), (0, 7), (1, 6), (1, 6), // 0x68
+    (0, 7), (0, 7), (0, 7), (1, 6), (1, 6), (0, 7), (1, 6), (0, 7), // 0x70
+    (0, 7), (1, 6), (1, 6), (1, 6), (3, 2), (1, 6), (0, 8), (1, 6), // 0x78
+];
@@ -0,0 +1,186 @@
+// stm32f3-oscilloscope - src/pretrigger.rs
+// samples kept from before each capture buffer, for a trigger found near its start
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// A trigger found in a capture buffer is displayed with the trigger position's worth of samples
+// before it. Where it comes too near the start of the buffer for that, the rest are taken from the
+// end of the buffer before, if that one is straight before it (see `DmaBuffer::sequence()`).
+// The ends of the buffers are kept in a ring, each one written over the oldest samples in it, so
+// copying the latest of them out may have to wrap around from its end to its start.
+
+use core;
+
+use DISPLAY_WIDTH;
+
+/// The most samples kept from before a buffer: a screen width of them, at the two per column of
+/// Peak Detect mode.
+pub const HISTORY_MAX: usize = 2 * DISPLAY_WIDTH;
+
+struct History {
+    ring: [u16; HISTORY_MAX],
+    next: usize,           // where the next sample kept goes in `ring`
+    len: usize,            // how many samples `ring` holds, at most HISTORY_MAX
+    sequence: Option<u32>, // the number of the buffer they came from, if any
+}
+
+static mut HISTORY: History = History { ring: [0; HISTORY_MAX], next: 0, len: 0, sequence: None };
+
+impl History {
+    // the number of samples kept from straight before buffer number `sequence`
+    fn available(&self, sequence: u32) -> usize {
+        match self.sequence {
+            Some(s) if s == sequence.wrapping_sub(1) => self.len,
+            _ => 0,
+        }
+    }
+
+    // keep the end of `samples`, buffer number `sequence`, after those already kept if it
+    // straight follows them, or in place of them if not
+    fn keep(&mut self, samples: &[u16], sequence: u32) {
+        if self.available(sequence) == 0 {
+            self.len = 0;
+        }
+        let n = core::cmp::min(samples.len(), HISTORY_MAX);
+        for &sample in samples[samples.len() - n..].iter() {
+            self.ring[self.next] = sample;
+            self.next = (self.next + 1) % HISTORY_MAX;
+        }
+        self.len = core::cmp::min(self.len + n, HISTORY_MAX);
+        self.sequence = Some(sequence);
+    }
+
+    // copy the latest `out.len()` samples kept into `out`, oldest first, returning false, having
+    // copied nothing, if there aren't that many
+    fn copy_latest(&self, out: &mut [u16]) -> bool {
+        let n = out.len();
+        if n > self.len {
+            return false;
+        }
+        // the oldest of them run up to the end of the ring, and the rest wrap around to its start
+        let start = (self.next + HISTORY_MAX - n) % HISTORY_MAX;
+        let first = core::cmp::min(n, HISTORY_MAX - start);
+        out[..first].copy_from_slice(&self.ring[start..start + first]);
+        out[first..].copy_from_slice(&self.ring[..n - first]);
+        true
+    }
+
+    // fill `out` with the last `before` samples kept, then the start of `samples`
+    fn stitch(&self, before: usize, samples: &[u16], out: &mut [u16]) -> bool {
+        let before = core::cmp::min(before, out.len());
+        let after = out.len() - before;
+        if after > samples.len() || !self.copy_latest(&mut out[..before]) {
+            return false;
+        }
+        out[before..].copy_from_slice(&samples[..after]);
+        true
+    }
+}
+
+/// Returns the number of samples kept from straight before capture buffer number `sequence`, as
+/// numbered by `DmaBuffer::sequence()`: all of them, if the last buffer kept was the one before
+/// it, or none.
+pub fn pretrigger_available(sequence: u32) -> usize {
+    unsafe { HISTORY.available(sequence) }
+}
+
+/// Keeps the end of `samples`, capture buffer number `sequence`, for the buffer after it, as far
+/// as there is room.
+pub fn pretrigger_keep(samples: &[u16], sequence: u32) {
+    unsafe { HISTORY.keep(samples, sequence); }
+}
+
+/// Forgets the samples kept, e.g. because the buffer taken wasn't one they can be kept from.
+pub fn pretrigger_clear() {
+    unsafe { HISTORY.sequence = None; }
+}
+
+/// Fills `out` with a window of samples which starts `before` samples ahead of `samples`: the
+/// latest `before` of those kept, then as many of `samples` as fit. Returns false, leaving `out`
+/// unfilled, if fewer than `before` are kept, or `samples` is too short.
+pub fn pretrigger_stitch(before: usize, samples: &[u16], out: &mut [u16]) -> bool {
+    unsafe { HISTORY.stitch(before, samples, out) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testgen::ramp;
+
+    fn history() -> History {
+        History { ring: [0; HISTORY_MAX], next: 0, len: 0, sequence: None }
+    }
+
+    #[test]
+    fn keeps_only_a_buffer_straight_before() {
+        let mut h = history();
+        h.keep(&[1, 2, 3], 7);
+        assert_eq!(h.available(8), 3);
+        assert_eq!(h.available(9), 0);
+        assert_eq!(h.available(7), 0);
+        // a buffer which doesn't follow on replaces what was kept
+        h.keep(&[4, 5], 9);
+        let mut out = [0; 3];
+        assert!(!h.copy_latest(&mut out));
+        assert!(h.copy_latest(&mut out[..2]));
+        assert_eq!(&out[..2], &[4, 5]);
+    }
+
+    #[test]
+    fn copies_across_the_end_of_the_ring() {
+        // a continuous ramp, captured in buffers which don't divide the ring evenly, so that the
+        // latest samples kept wrap around its end
+        let signal = ramp(250.0, 1000.0, 2048.0, 2000);
+        let buffer = 150;
+        let mut h = history();
+        for (i, chunk) in signal.chunks(buffer).take(5).enumerate() {
+            h.keep(chunk, i as u32);
+        }
+        assert_eq!(h.len, HISTORY_MAX);
+        assert!(h.next != 0);
+        for &n in [1, HISTORY_MAX - h.next, HISTORY_MAX - h.next + 1, HISTORY_MAX].iter() {
+            let mut out = vec![0; n];
+            assert!(h.copy_latest(&mut out));
+            assert!(out[..] == signal[5 * buffer - n..5 * buffer], "{} samples", n);
+        }
+    }
+
+    #[test]
+    fn stitches_the_window_before_a_buffer() {
+        let signal = ramp(250.0, 1000.0, 2048.0, 2000);
+        let buffer = 300;
+        let mut h = history();
+        for i in 0..3 {
+            h.keep(&signal[i * buffer..(i + 1) * buffer], i as u32);
+        }
+        // a trigger 10 samples into the next buffer, with 60 before it on the screen
+        let next = &signal[3 * buffer..4 * buffer];
+        assert_eq!(h.available(3), HISTORY_MAX);
+        let mut window = [0; 160];
+        assert!(h.stitch(50, next, &mut window));
+        assert!(window[..] == signal[3 * buffer - 50..3 * buffer + 110]);
+        // more than is kept, or a buffer too short for the rest of the window
+        assert!(!h.stitch(HISTORY_MAX + 1, next, &mut [0; HISTORY_MAX + 1]));
+        assert!(!h.stitch(50, &next[..100], &mut window));
+    }
+}
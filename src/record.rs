@@ -0,0 +1,100 @@
+// stm32f3-oscilloscope - src/record.rs
+// the deep record behind the single-trace display
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// The record is a copy of the whole capture buffer the last single-trace sweep was displayed
+// from, taken by record_store(), so that once the display is stopped the view can be moved along
+// it, beyond the screen width of it that was displayed. Normally a buffer is only two screen widths
+// long, but set_record_depth() can make the buffers, and so the record, deeper, at the cost of
+// sweeps taking longer to capture.
+
+use core;
+
+/// The most samples the record (and each continuous-capture buffer) can hold.
+pub const RECORD_MAX: usize = 2048;
+
+struct Record {
+    samples: [u16; RECORD_MAX],
+    len: usize,
+    origin: usize, // where the displayed sweep starts
+}
+
+static mut RECORD: Record = Record { samples: [0; RECORD_MAX], len: 0, origin: 0 };
+static mut DEPTH: Option<usize> = None;
+
+/// Sets the number of samples captured per buffer for the single-trace display to `depth`, at
+/// most RECORD_MAX, or to the usual number if it is `None`. Capture must be restarted for this to
+/// take effect.
+pub fn set_record_depth(depth: Option<usize>) {
+    unsafe { DEPTH = depth.map(|d| core::cmp::min(d, RECORD_MAX)); }
+}
+
+/// Returns the depth set by `set_record_depth()`.
+pub fn record_depth() -> Option<usize> {
+    unsafe { DEPTH }
+}
+
+/// Saves `samples`, the capture buffer a sweep starting at `origin` in it has been displayed from,
+/// as the record, as far as there is room, replacing the one saved before.
+pub fn record_store(samples: &[u16], origin: usize) {
+    let len = core::cmp::min(samples.len(), RECORD_MAX);
+    unsafe {
+        RECORD.samples[..len].copy_from_slice(&samples[..len]);
+        RECORD.len = len;
+        RECORD.origin = core::cmp::min(origin, len);
+    }
+}
+
+/// Forgets the record, e.g. because the sweep displayed wasn't taken straight from a buffer.
+pub fn record_clear() {
+    unsafe { RECORD.len = 0; }
+}
+
+/// Returns the number of samples in the record, 0 if there is none.
+pub fn record_len() -> usize {
+    unsafe { RECORD.len }
+}
+
+/// Returns `window` samples of the record, starting `offset` samples after the start of the
+/// displayed sweep (or before it, if negative), but moved as far as needed to stay within the
+/// record, along with where they start in it. Returns `None` if the record is shorter than
+/// `window`.
+pub fn record_view(offset: i32, window: usize) -> Option<(usize, &'static [u16])> {
+    let (len, origin) = unsafe { (RECORD.len, RECORD.origin) };
+    if window == 0 || len < window {
+        return None;
+    }
+    let last = (len - window) as i32;
+    let start = core::cmp::max(0, core::cmp::min(origin as i32 + offset, last)) as usize;
+    Some((start, unsafe { &RECORD.samples[start..start + window] }))
+}
+
+/// Returns the distance from the start of the displayed sweep at which `record_view()` would
+/// start a `window`-sample view asked for at `offset`, so that an offset stepped past either end of
+/// the record can be brought back to it.
+pub fn record_clamp_offset(offset: i32, window: usize) -> i32 {
+    match record_view(offset, window) {
+        Some((start, _)) => start as i32 - unsafe { RECORD.origin } as i32,
+        None => 0,
+    }
+}
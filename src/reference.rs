@@ -0,0 +1,77 @@
+// stm32f3-oscilloscope - src/reference.rs
+// a saved reference waveform, to compare the live trace against
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The reference is a screen width of samples, one per column, taken from a displayed sweep by
+// ref_store(), along with the rate they were captured at. It's only meaningful against a live
+// trace at the same rate, so ref_matches() tells whether the timebase has changed since.
+
+use core;
+
+use st7735::DISPLAY_WIDTH;
+
+struct Reference {
+    samples: [u16; DISPLAY_WIDTH],
+    len: usize,
+    column_rate: u32, // samples per second, as saved
+}
+
+static mut REFERENCE: Option<Reference> = None;
+
+/// Saves `samples`, captured at `sample_rate` samples per second, as the reference, replacing any
+/// saved before. If there are more samples than screen columns, they are decimated to fit, as
+/// `st7735_draw_trace` does, and the saved rate is reduced to match.
+pub fn ref_store(samples: &[u16], sample_rate: u32) {
+    let len = core::cmp::min(samples.len(), DISPLAY_WIDTH);
+    if len == 0 {
+        return;
+    }
+    let mut reference = Reference {
+        samples: [0; DISPLAY_WIDTH],
+        len: len,
+        column_rate: (sample_rate as u64 * len as u64 / samples.len() as u64) as u32,
+    };
+    for x in 0..len {
+        reference.samples[x] = samples[x * samples.len() / len];
+    }
+    unsafe {
+        REFERENCE = Some(reference);
+    }
+}
+
+/// Returns the saved reference samples, one per column, or `None` if none have been saved.
+pub fn ref_samples() -> Option<&'static [u16]> {
+    unsafe { REFERENCE.as_ref().map(|r| &r.samples[..r.len]) }
+}
+
+/// Returns true if the reference was saved at the rate a live trace of `samples` samples, captured
+/// at `sample_rate`, is drawn at, so that the two line up, or false if not, or if there is no
+/// reference.
+pub fn ref_matches(samples: usize, sample_rate: u32) -> bool {
+    let len = core::cmp::min(samples, DISPLAY_WIDTH);
+    match unsafe { REFERENCE.as_ref() } {
+        Some(r) if len > 0 => {
+            r.column_rate == (sample_rate as u64 * len as u64 / samples as u64) as u32
+        }
+        _ => false,
+    }
+}
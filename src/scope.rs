@@ -0,0 +1,152 @@
+// stm32f3-oscilloscope - src/scope.rs
+// the signal processing, as pure methods on the settings it depends on
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// Nothing here touches the hardware, or any global state: a `Scope` is a copy of the settings
+// which the trigger, measurements, and scaling depend on, and each of its methods works only on the
+// samples it is given, so that all of it can be built and run on a host, as well as on the board.
+// The hardware (ADC, SysTick, and ST7735) stays with main().
+
+use measure::{CLIP_SAMPLES, WaveShape, aliasing_likely, classify_waveform, is_clipping, mean,
+              measure_duty_cycle, measure_fall_time, measure_frequency, measure_pulse_width,
+              measure_rise_time, measure_rms, min_max, peak_to_peak};
+use trigger::{Edge, Trigger, find_trigger};
+use vscale::VerticalScale;
+
+#[derive(Clone, Copy)]
+pub struct Scope {
+    pub sample_rate: u32, // samples per second, per channel
+    pub level: u16,       // the trigger level, which the crossing-based measurements use too
+    pub edge: Edge,
+    pub hysteresis: u16,
+    pub vscale: VerticalScale,
+    pub height: u8, // screen rows
+}
+
+impl Scope {
+    /// Takes a copy of the settings: the trigger's level (in the samples as captured), edge, and
+    /// hysteresis, the sample rate, and the vertical scale, on a screen `height` rows high.
+    pub fn new(sample_rate: u32, trigger: &Trigger, vscale: VerticalScale, height: u8) -> Scope {
+        Scope {
+            sample_rate,
+            level: trigger.sample_level(),
+            edge: trigger.edge,
+            hysteresis: trigger.hysteresis,
+            vscale,
+            height,
+        }
+    }
+
+    /// Returns the index of the first trigger point in `samples`, as by `find_trigger`.
+    #[allow(unused)]
+    pub fn trigger_index(&self, samples: &[u16]) -> Option<usize> {
+        find_trigger(samples, self.level, self.edge, self.hysteresis)
+    }
+
+    /// Returns the frequency of the signal in `samples`, in hertz, as by `measure_frequency`.
+    pub fn frequency(&self, samples: &[u16]) -> Option<f32> {
+        measure_frequency(samples, self.sample_rate, self.level, self.hysteresis)
+    }
+
+    /// Returns the fraction of the time the signal spends above the trigger level, as by
+    /// `measure_duty_cycle`.
+    pub fn duty_cycle(&self, samples: &[u16]) -> Option<f32> {
+        measure_duty_cycle(samples, self.level, self.hysteresis)
+    }
+
+    /// Returns the width of the first complete high pulse, in seconds, as by
+    /// `measure_pulse_width`.
+    pub fn pulse_width(&self, samples: &[u16]) -> Option<f32> {
+        measure_pulse_width(samples, self.level, self.hysteresis)
+            .map(|width| width as f32 / self.sample_rate as f32)
+    }
+
+    /// Returns the 10%-90% rise time of the first rising edge, in seconds.
+    pub fn rise_time(&self, samples: &[u16]) -> Option<f32> {
+        measure_rise_time(samples, self.sample_rate)
+    }
+
+    /// Returns the 90%-10% fall time of the first falling edge, in seconds.
+    pub fn fall_time(&self, samples: &[u16]) -> Option<f32> {
+        measure_fall_time(samples, self.sample_rate)
+    }
+
+    /// Returns the difference between the largest and smallest samples, in ADC counts.
+    pub fn peak_to_peak(&self, samples: &[u16]) -> u16 {
+        peak_to_peak(samples)
+    }
+
+    /// Returns the smallest and the largest samples, in ADC counts.
+    pub fn min_max(&self, samples: &[u16]) -> (u16, u16) {
+        min_max(samples)
+    }
+
+    /// Returns the mean of the samples, in ADC counts.
+    pub fn mean(&self, samples: &[u16]) -> u16 {
+        mean(samples)
+    }
+
+    /// Returns the RMS of the samples' difference from `dc_offset`, in ADC counts.
+    pub fn ac_rms(&self, samples: &[u16], dc_offset: u16) -> u16 {
+        measure_rms(samples, dc_offset)
+    }
+
+    /// Returns a guess at the shape of the waveform, as by `classify_waveform`.
+    pub fn wave_shape(&self, samples: &[u16]) -> WaveShape {
+        classify_waveform(samples)
+    }
+
+    /// Returns true if the signal is clipped at the ends of the ADC range, as by `is_clipping`.
+    pub fn is_clipping(&self, samples: &[u16]) -> bool {
+        is_clipping(samples)
+    }
+
+    /// Returns true if more than `CLIP_SAMPLES` of `samples` are drawn beyond the top or bottom
+    /// of the screen, at offset `offset`, so that a lower gain would show them.
+    pub fn is_off_screen(&self, samples: &[u16], offset: i16) -> bool {
+        let bottom = self.height as i16 - 1;
+        let beyond = samples.iter()
+            .map(|&sample| self.sample_to_y(sample, offset))
+            .filter(|&y| y < 0 || y > bottom)
+            .count();
+        beyond > CLIP_SAMPLES
+    }
+
+    /// Returns true if the trace of a signal measured at `hz` is likely to be an alias of it, when
+    /// drawn with `per_column` samples to each screen column, as by `aliasing_likely`.
+    pub fn aliasing_likely(&self, hz: f32, per_column: usize) -> bool {
+        aliasing_likely(hz, self.sample_rate / per_column as u32)
+    }
+
+    /// Returns the screen row at which `sample` is drawn, moved up by `offset` pixels.
+    pub fn sample_to_y(&self, sample: u16, offset: i16) -> i16 {
+        self.vscale.sample_to_y(sample, self.height, offset)
+    }
+
+    /// Returns the smallest sample drawn at screen row `y`, at offset `offset`.
+    #[allow(unused)]
+    pub fn y_to_sample(&self, y: i16, offset: i16) -> u16 {
+        self.vscale.y_to_sample(y, self.height, offset)
+    }
+}
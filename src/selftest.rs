@@ -0,0 +1,204 @@
+// stm32f3-oscilloscope - src/selftest.rs
+// power-on self-test of the display, ADC, LEDs, and serial link
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Each check can be run alone, and gives a CheckResult; selftest() runs them all, showing each
+// result on the screen, under the display check's color bars, and sending it to the host, as
+//   TEST <check> PASS|FAIL|SKIP
+// on a line of its own, followed by "TEST DONE PASS" (or FAIL, if any check failed).
+//
+// Some checks can't tell everything: the LCD can only be checked for an answer with the
+// 'lcd-readback' feature, and whether the color bars look right is left to the eye; an LED's pin
+// can be read back as driven, but not whether it lights; and the serial link can only be checked
+// with its TX (PC4) and RX (PC5) jumpered together, so that what it sends is echoed back.
+
+use core;
+
+use adc::{read_temp_c, read_vdda_mv};
+use display::Display;
+use elapsed::has_elapsed;
+use led::{Led, led_is_lit, led_off, led_on};
+use st7735::*;
+use theme::theme_colors;
+use timing::{delay_ms, millis};
+use uart::{uart_read_byte, uart_write, uart_write_byte};
+
+/// The outcome of a self-test check.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CheckResult {
+    Pass,
+    Fail,
+    Skipped, // there was no way to check, e.g. the serial link's TX and RX aren't jumpered
+}
+
+// the color bars, left to right, over the top half of the screen
+const BARS: [St7735Color; 8] = [
+    St7735Color::White, St7735Color::Yellow, St7735Color::Green, St7735Color::Blue,
+    St7735Color::Red, St7735Color::Magenta, St7735Color::Grey, St7735Color::Black,
+];
+
+// the text printed under the color bars, as much of it as fits
+const PATTERN: &'static [u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+// the plausible range of the supply voltage, in millivolts, and of the chip's temperature, in
+// degrees Celsius (its operating range)
+const VDDA_MIN_MV: u16 = 2000;
+const VDDA_MAX_MV: u16 = 3600;
+const TEMP_MIN_C: i16 = -40;
+const TEMP_MAX_C: i16 = 105;
+
+// the LEDs, clockwise around the compass from north, and how long each is lit
+const LEDS: [Led; 8] = [Led::LD3, Led::LD5, Led::LD7, Led::LD9, Led::LD10, Led::LD8, Led::LD6,
+                        Led::LD4];
+const LED_MS: u32 = 100;
+
+// the bytes sent for the serial link's loopback, and how long to wait for each to come back
+const LOOPBACK_BYTES: [u8; 2] = [0x55, 0xaa];
+const LOOPBACK_MS: u32 = 10;
+
+/// Paints color bars over the top half of the screen, and a line of text under them. Passes if
+/// the LCD answers `st7735_probe()`, or is skipped if there's no way to check that.
+pub fn selftest_display() -> CheckResult {
+    let colors = theme_colors();
+    draw_bars(&mut St7735, bars_height(), colors.background);
+    let chars = core::cmp::min(PATTERN.len(), st7735_get_width() as usize / 8);
+    st7735_print(&PATTERN[..chars], 0, bars_height(), colors.text, colors.background);
+    if !ST7735_PROBE_WIRED {
+        CheckResult::Skipped
+    } else if st7735_probe() {
+        CheckResult::Pass
+    } else {
+        CheckResult::Fail
+    }
+}
+
+/// Reads the internal reference and temperature sensor. Passes if the supply voltage and the
+/// temperature worked out from them are plausible. This stops any ongoing conversions.
+pub fn selftest_adc() -> CheckResult {
+    let vdda = read_vdda_mv();
+    let temp = read_temp_c();
+    if vdda >= VDDA_MIN_MV && vdda <= VDDA_MAX_MV && temp >= TEMP_MIN_C && temp <= TEMP_MAX_C {
+        CheckResult::Pass
+    } else {
+        CheckResult::Fail
+    }
+}
+
+/// Lights each LED in turn, clockwise from north, leaving them all off. Passes if each one's pin
+/// reads back as driven high while it's lit, and low once it isn't.
+pub fn selftest_leds() -> CheckResult {
+    for &led in LEDS.iter() {
+        led_off(led);
+    }
+    let mut result = CheckResult::Pass;
+    for &led in LEDS.iter() {
+        led_on(led);
+        delay_ms(LED_MS);
+        if !led_is_lit(led) {
+            result = CheckResult::Fail;
+        }
+        led_off(led);
+        if led_is_lit(led) {
+            result = CheckResult::Fail;
+        }
+    }
+    result
+}
+
+/// Sends a few bytes over the serial link, and checks that they come back. Passes if they all do,
+/// or is skipped if nothing comes back, since TX and RX aren't jumpered together.
+pub fn selftest_uart() -> CheckResult {
+    while uart_read_byte().is_some() {} // (anything already received)
+    for (i, &byte) in LOOPBACK_BYTES.iter().enumerate() {
+        uart_write_byte(byte);
+        match read_byte_within(LOOPBACK_MS) {
+            Some(echo) if echo == byte => {}
+            None if i == 0 => return CheckResult::Skipped,
+            _ => return CheckResult::Fail,
+        }
+    }
+    CheckResult::Pass
+}
+
+/// Runs each check, showing its result on the screen under the color bars, and sending it to the
+/// host. Returns true if none of them failed. The screen is left showing the results.
+pub fn selftest() -> bool {
+    let display = selftest_display();
+    report(b"LCD", display, 0);
+    let adc = selftest_adc();
+    report(b"ADC", adc, 1);
+    let leds = selftest_leds();
+    report(b"LEDs", leds, 2);
+    let uart = selftest_uart();
+    report(b"UART", uart, 3);
+    let passed = [display, adc, leds, uart].iter().all(|&r| r != CheckResult::Fail);
+    report(b"DONE", if passed { CheckResult::Pass } else { CheckResult::Fail }, 4);
+    passed
+}
+
+// the height of the color bars, with the rest of the screen left for the text under them
+fn bars_height() -> u8 {
+    st7735_get_height() / 2 - 12
+}
+
+// fill `display` with `background`, then draw the color bars over its top `height` rows
+fn draw_bars<D: Display>(display: &mut D, height: u8, background: St7735Color) {
+    let (width, height) = (display.width() as i16, height as i16);
+    let screen_height = display.height() as i16;
+    display.fill(0, 0, width, screen_height, background as u16);
+    for (i, &color) in BARS.iter().enumerate() {
+        let x = width * i as i16 / BARS.len() as i16;
+        let next = width * (i as i16 + 1) / BARS.len() as i16;
+        display.fill(x, 0, next - x, height, color as u16);
+    }
+}
+
+// show `result` of the check called `name` on the `line`th line under the pattern, and send it
+// to the host
+fn report(name: &[u8], result: CheckResult, line: u8) {
+    let colors = theme_colors();
+    let (text, color): (&[u8], St7735Color) = match result {
+        CheckResult::Pass => (b"PASS", colors.text),
+        CheckResult::Fail => (b"FAIL", colors.warning),
+        CheckResult::Skipped => (b"SKIP", colors.text),
+    };
+    let y = bars_height() + 12 * (line + 1);
+    st7735_print(name, 0, y, colors.text, colors.background);
+    st7735_print(text, 40, y, color, colors.background);
+    uart_write(b"TEST ");
+    uart_write(name);
+    uart_write_byte(b' ');
+    uart_write(text);
+    uart_write(b"\r\n");
+}
+
+// the next byte received within `ms` milliseconds, if one is
+fn read_byte_within(ms: u32) -> Option<u8> {
+    let start = millis();
+    while !has_elapsed(start, millis(), ms) {
+        if let Some(byte) = uart_read_byte() {
+            return Some(byte);
+        }
+    }
+    None
+}
@@ -0,0 +1,216 @@
+// stm32f3-oscilloscope - src/vscale.rs
+// vertical scale (display gain) settings
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Scope settings are kept in the last 2KB page of the STM32F303VC's 256KB flash, which memory.x
+// leaves out of the FLASH region so the linker won't put code there. The page holds a single
+// record of half-words:
+//   0     SETTINGS_MAGIC
+//   1     timebase step index
+//   2     vertical gain step index
+//   3     trigger mode (0 = Auto, 1 = Normal, 2 = Single)
+//   4     trigger level
+//   5     calibration offset, in ADC counts
+//   6     calibration scale, in ADC counts per volt
+//   7     screen rotation (0 to 3)
+//   8     probe attenuation (1 or 10)
+//   9     zero offset, in ADC counts, or 0xffff for none
+//   10    trace color map: the position of each trace's color in the table of trace colors, four
+//         bits each, channel A's in the low bits, then channel B's, the math trace's, and the
+//         reference trace's
+//   11, 12 CRC-32 of half-words 0 through 10, low half first
+// An erased page reads as all ones, which fails the magic check.
+
+use core::ptr;
+
+use cortex_m;
+use stm32f30x::FLASH;
+
+use calibration::Calibration;
+use theme::{ColorMap, TRACE_COUNT};
+use timebase::TimeBase;
+use trigger::TriggerMode;
+use vscale::VerticalScale;
+
+const SETTINGS_PAGE: u32 = 0x0803_f800;
+const SETTINGS_MAGIC: u16 = 0x5c0e; // (changed with the layout)
+const SETTINGS_WORDS: usize = 13;
+
+// the zero offset word's value when there is no zero offset
+const NO_ZERO_OFFSET: u16 = 0xffff;
+
+// flash controller key sequence (RM0316 4.2.2)
+const FLASH_KEY1: u32 = 0x4567_0123;
+const FLASH_KEY2: u32 = 0xcdef_89ab;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub timebase: TimeBase,
+    pub vscale: VerticalScale,
+    pub trigger_mode: TriggerMode,
+    pub trigger_level: u16,
+    pub calibration: Calibration,
+    pub rotation: u8,
+    pub probe_ratio: u16,
+    pub zero_offset: Option<u16>,
+    pub color_map: ColorMap,
+}
+
+impl Settings {
+    fn to_words(&self) -> [u16; SETTINGS_WORDS] {
+        let mode = match self.trigger_mode {
+            TriggerMode::Auto => 0,
+            TriggerMode::Normal => 1,
+            TriggerMode::Single => 2,
+        };
+        let mut colors = 0;
+        for (i, &index) in self.color_map.indices().iter().enumerate() {
+            colors |= (index as u16 & 0xf) << (i * 4);
+        }
+        let mut words = [
+            SETTINGS_MAGIC,
+            self.timebase.index() as u16,
+            self.vscale.index() as u16,
+            mode,
+            self.trigger_level,
+            self.calibration.offset,
+            self.calibration.counts_per_volt,
+            self.rotation as u16,
+            self.probe_ratio,
+            self.zero_offset.unwrap_or(NO_ZERO_OFFSET),
+            colors,
+            0,
+            0,
+        ];
+        let crc = crc32(&words[..11]);
+        words[11] = crc as u16;
+        words[12] = (crc >> 16) as u16;
+        words
+    }
+
+    fn from_words(words: &[u16; SETTINGS_WORDS]) -> Option<Settings> {
+        let crc = crc32(&words[..11]);
+        if words[0] != SETTINGS_MAGIC || words[11] != crc as u16 ||
+           words[12] != (crc >> 16) as u16 {
+            return None;
+        }
+        if words[6] == 0 || words[7] > 3 || (words[8] != 1 && words[8] != 10) {
+            return None; // no usable scale, no such rotation, or no such probe
+        }
+        let zero_offset = match words[9] {
+            NO_ZERO_OFFSET => None,
+            zero if zero <= 4095 => Some(zero),
+            _ => return None,
+        };
+        let mut indices = [0; TRACE_COUNT];
+        for (i, index) in indices.iter_mut().enumerate() {
+            *index = (words[10] >> (i * 4)) as u8 & 0xf;
+        }
+        let color_map = match ColorMap::from_indices(indices) {
+            Some(map) => map,
+            None => return None, // no such trace color
+        };
+        let trigger_mode = match words[3] {
+            0 => TriggerMode::Auto,
+            1 => TriggerMode::Normal,
+            2 => TriggerMode::Single,
+            _ => return None,
+        };
+        match (TimeBase::from_index(words[1] as u8), VerticalScale::from_index(words[2] as u8)) {
+            (Some(timebase), Some(vscale)) => Some(Settings {
+                timebase: timebase,
+                vscale: vscale,
+                trigger_mode: trigger_mode,
+                trigger_level: words[4],
+                calibration: Calibration { offset: words[5], counts_per_volt: words[6] },
+                rotation: words[7] as u8,
+                probe_ratio: words[8],
+                zero_offset: zero_offset,
+                color_map: color_map,
+            }),
+            _ => None,
+        }
+    }
+}
+
+// the standard (IEEE 802.3) CRC-32, over the bytes of `words`, low byte first
+fn crc32(words: &[u16]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &word in words {
+        for &byte in &[word as u8, (word >> 8) as u8] {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            }
+        }
+    }
+    !crc
+}
+
+/// Reads the saved settings from flash, returning `None` if there are none, or they are corrupt.
+pub fn settings_load() -> Option<Settings> {
+    let mut words = [0u16; SETTINGS_WORDS];
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = unsafe { ptr::read_volatile((SETTINGS_PAGE as *const u16).offset(i as isize)) };
+    }
+    Settings::from_words(&words)
+}
+
+/// Erases the settings page of flash, and writes `settings` to it. This stalls the CPU for the
+/// duration of the page erase (around 20-40ms), so interrupts will be late. Returns true if the
+/// settings read back correctly.
+pub fn settings_save(settings: &Settings) -> bool {
+    let words = settings.to_words();
+    cortex_m::interrupt::free(|cs| {
+        let flash = FLASH.borrow(cs);
+        // unlock the flash controller
+        if flash.cr.read().lock().bits() != 0 {
+            flash.keyr.write(|w| unsafe { w.fkeyr().bits(FLASH_KEY1) });
+            flash.keyr.write(|w| unsafe { w.fkeyr().bits(FLASH_KEY2) });
+        }
+        while flash.sr.read().bsy().bits() != 0 {}
+
+        // erase the page
+        flash.cr.modify(|_, w| unsafe { w.per().bits(1) });
+        flash.ar.write(|w| unsafe { w.far().bits(SETTINGS_PAGE) });
+        flash.cr.modify(|_, w| unsafe { w.strt().bits(1) });
+        while flash.sr.read().bsy().bits() != 0 {}
+        flash.sr.write(|w| unsafe { w.eop().bits(1) }); // clear end-of-operation flag
+        flash.cr.modify(|_, w| unsafe { w.per().bits(0) });
+
+        // program the settings, a half-word at a time
+        flash.cr.modify(|_, w| unsafe { w.pg().bits(1) });
+        for (i, &word) in words.iter().enumerate() {
+            unsafe {
+                ptr::write_volatile((SETTINGS_PAGE as *mut u16).offset(i as isize), word);
+            }
+            while flash.sr.read().bsy().bits() != 0 {}
+            flash.sr.write(|w| unsafe { w.eop().bits(1) });
+        }
+        flash.cr.modify(|_, w| unsafe { w.pg().bits(0) });
+
+        // lock the flash controller again
+        flash.cr.modify(|_, w| unsafe { w.lock().bits(1) });
+    });
+    settings_load().is_some()
+}
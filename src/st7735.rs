@@ -23,29 +23,31 @@
 
 #![allow(non_snake_case)]
 
+use core;
 use core::ptr;
 
 use cortex_m;
-use stm32f30x::{GPIOB, RCC};
-#[cfg(not(feature = "software-spi"))]
-use stm32f30x::SPI2;
+use stm32f30x::{DMA1, GPIOB, GPIOC, RCC, SPI2, TIM3};
+use stm32f30x::interrupt::Interrupt;
 
+use sysclk::PCLK1_HZ;
+
+use cursors::{Cursor, Cursors};
+use display::Display;
+use parallax_8x12_extents;
 use parallax_8x12_font;
-use { // C functions
-    _st7735_drawFastHLine,
-    _st7735_drawFastVLine,
-    _st7735_drawPixel,
-    _st7735_fillScreen,
-    _st7735_get_height,
-    _st7735_get_width,
-    _st7735_initR,
-    _st7735_setAddrWindow,
-    _st7735_setRotation
-};
+use timing::delay_ms;
+#[cfg(feature = "lcd-readback")]
+use timing::delay_us;
+use trigger::Edge;
+use vscale::VerticalScale;
 
 // ======== ST7735 "type" and color enums ========
 
+// the ST7735R breakout board variants, marked by the color of the tab on the screen protector,
+// which differ in where the visible area sits in the controller's RAM, and in color order
 #[allow(unused)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum St7735Type {
     GreenTab = 0,
     RedTab,
@@ -53,20 +55,110 @@ pub enum St7735Type {
 }
 
 #[allow(unused)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum St7735Color {
     Black = 0,
     Blue = 0x001f,
     Green = 0x07e0,
+    Cyan = 0x07ff,
     Red = 0xf800,
     White = 0xffff,
+    Yellow = 0xffe0,
+    Magenta = 0xf81f,
+    Grey = 0x7bef, // half-intensity white
+    DarkRed = 0x7800,
+    DarkGreen = 0x03e0,
+    Olive = 0x7be0, // half-intensity yellow
+    Purple = 0x780f, // half-intensity magenta
+}
+
+// ======== transport selection ========
+
+/// How bytes get to the LCD controller: through the SPI2 peripheral, or by bit-banging the same
+/// pins, for boards where SPI2 can't be used.
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum St7735Transport {
+    SoftSpi,
+    HardSpi,
+}
+
+static mut TRANSPORT: St7735Transport = St7735Transport::HardSpi;
+
+static mut HEADLESS: bool = false; // no LCD answered: nothing is sent to it
+
+/// Sets whether to run without the LCD, e.g. if `st7735_probe()` found it isn't there, so that
+/// nothing is sent to it, and drawing can't block on a dead SPI bus.
+pub fn st7735_set_headless(headless: bool) {
+    unsafe { HEADLESS = headless; }
+}
+
+/// Returns whether `st7735_set_headless()` has set the LCD to be run without.
+#[allow(unused)]
+pub fn st7735_is_headless() -> bool {
+    unsafe { HEADLESS }
+}
+
+/// Sets up the GPIO pins (and SPI2, if used) for talking to the LCD controller via `transport`.
+/// Returns false if SPI2 wouldn't enable as a master. (Bit-banging can't fail.)
+pub fn st7735_setup(transport: St7735Transport) -> bool {
+    unsafe { TRANSPORT = transport; }
+    match transport {
+        St7735Transport::HardSpi => hard_spi_setup(),
+        St7735Transport::SoftSpi => {
+            soft_spi_setup();
+            true
+        }
+    }
+}
+
+#[inline]
+fn st7735_send_byte(data: u8) {
+    if unsafe { HEADLESS } {
+        return;
+    }
+    match unsafe { TRANSPORT } {
+        St7735Transport::HardSpi => hard_spi_send_byte(data),
+        St7735Transport::SoftSpi => soft_spi_send_byte(data),
+    }
+}
+
+fn lcd_dc() -> bool {
+    0 != unsafe { (*GPIOB.get()).odr.read().odr12().bits() } // read PB12: A0/RS/DC
+}
+
+// send a command byte to the LCD controller
+pub fn st7735_send_cmd(cmd: u8) {
+    st7735_dma_wait();
+    if lcd_dc() {
+        // drain the transmit FIFO before switching A0/DC
+        if unsafe { TRANSPORT } == St7735Transport::HardSpi {
+            spi2_wait_while_busy();
+        }
+        lcd_dc0();
+    }
+    st7735_send_byte(cmd);
+}
+
+// send a data byte to the LCD controller
+pub fn st7735_send_data(data: u8) {
+    st7735_dma_wait();
+    if !lcd_dc() {
+        // drain the transmit FIFO before switching A0/DC
+        if unsafe { TRANSPORT } == St7735Transport::HardSpi {
+            spi2_wait_while_busy();
+        }
+        lcd_dc1();
+    }
+    st7735_send_byte(data);
 }
 
 // ======== hardware SPI ========
 
-// set up the hardware to use hardware SPI: SPI2 on PB13 (SCK/SCL) and PB15 (SDA/MOSI)
-#[cfg(not(feature = "software-spi"))]
-pub fn st7735_setup() {
+// set up the hardware to use hardware SPI: SPI2 on PB13 (SCK/SCL) and PB15 (SDA/MOSI), returning
+// false if SPI2 didn't end up enabled, or took a mode fault
+fn hard_spi_setup() -> bool {
+    let mut ok = false;
     cortex_m::interrupt::free(|cs| {
         let rcc = RCC.borrow(cs);
         let gpiob = GPIOB.borrow(cs);
@@ -111,7 +203,7 @@ pub fn st7735_setup() {
              .ssm().bits(1)      // SPI_NSS_Soft
              .ssi().bits(1)      // part of 'SPI_Mode_Master'! will mode fault without this!
              .lsbfirst().bits(0) // SPI_FirstBit_MSB
-             .br().bits(0b010)   // f_PCLK/8 - 72MHz/2/8 = 4.5MHz, or near the ST7735's limit
+             .br().bits(spi_baud_rate_bits(ST7735_SPI_DEFAULT_HZ))
              .mstr().bits(1)     // SPI_Mode_Master
              .cpol().bits(0)     // SPI_CPOL_Low
              .cpha().bits(0)     // SPI_CPHA_1Edge
@@ -122,12 +214,54 @@ pub fn st7735_setup() {
         spi2.cr1.modify(|_, w| unsafe { w.spe().bits(1) });
         // set direction to transmit
         spi2.cr1.modify(|_, w| unsafe { w.bidioe().bits(1) });
+        ok = spi2.cr1.read().spe().bits() != 0 && spi2.sr.read().modf().bits() == 0;
+    });
+    if ok {
+        spi_dma_setup();
+    }
+    ok
+}
+
+/// The SPI2 clock rate used until `st7735_set_spi_hz()` changes it: PCLK1 / 8, or 4.5MHz. (The
+/// ST7735 is specified for writes at up to about 15MHz, but not over long jumper wires.)
+pub const ST7735_SPI_DEFAULT_HZ: u32 = PCLK1_HZ / 8;
+
+/// Sets the SPI2 clock for the LCD to the fastest rate it can make, PCLK1 divided by 2, 4, ...
+/// 256, that is no faster than `hz` (or to the slowest, if all of them are), and returns the rate
+/// set. Slowing it down can cure garbage on the screen from long wires. When the LCD is
+/// bit-banged, which isn't paced, this does nothing, and returns 0.
+pub fn st7735_set_spi_hz(hz: u32) -> u32 {
+    if unsafe { TRANSPORT } != St7735Transport::HardSpi {
+        return 0;
+    }
+    let br = spi_baud_rate_bits(hz);
+    // finish sending first: the rate can only be changed while SPI2 is disabled
+    st7735_dma_wait();
+    unsafe {
+        while (*SPI2.get()).sr.read().txe().bits() == 0 {}
+    }
+    spi2_wait_while_busy();
+    cortex_m::interrupt::free(|cs| {
+        let spi2 = SPI2.borrow(cs);
+        spi2.cr1.modify(|_, w| unsafe { w.spe().bits(0) });
+        spi2.cr1.modify(|_, w| unsafe { w.br().bits(br) });
+        spi2.cr1.modify(|_, w| unsafe { w.spe().bits(1) });
     });
+    PCLK1_HZ >> (br + 1)
+}
+
+// the SPI CR1 BR field value for the fastest clock, PCLK1 / 2^(BR + 1), that is no faster than
+// `hz`, or for the slowest
+fn spi_baud_rate_bits(hz: u32) -> u8 {
+    let mut br = 0;
+    while br < 7 && PCLK1_HZ >> (br + 1) > hz {
+        br += 1;
+    }
+    br
 }
 
 // send a byte of data to the LCD via hardware SPI
-#[cfg(not(feature = "software-spi"))]
-fn st7735_send_byte(data_in: u8) {
+fn hard_spi_send_byte(data_in: u8) {
     unsafe {
         while (*SPI2.get()).sr.read().txe().bits() == 0 {}
         // This is what I first naïvely tried:
@@ -142,49 +276,148 @@ fn st7735_send_byte(data_in: u8) {
     }
 }
 
-#[cfg(not(feature = "software-spi"))]
 fn spi2_wait_while_busy() {
     unsafe {
         while (*SPI2.get()).sr.read().bsy().bits() != 0 {}
     }
 }
 
-#[cfg(not(feature = "software-spi"))]
-fn lcd_dc() -> bool {
-    0 != unsafe { (*GPIOB.get()).odr.read().odr12().bits() } // read PB12: A0/RS/DC
+// ======== DMA fills over hardware SPI ========
+
+// Filling an area with one color goes faster with DMA1 channel 5, paced by SPI2's transmit
+// requests, which sends the same color from memory over and over while the CPU gets on with
+// something else. SPI2 is switched to 16-bit frames for the transfer, so that each request sends a
+// whole pixel, high byte first, and back to 8-bit frames by the transfer-complete interrupt.
+// Anything else sent to the LCD waits for the transfer to finish first. The chip select (PB10) is
+// asserted for the whole transfer, and released by the interrupt once SPI2 has sent the last
+// pixel, ending it; it is asserted again, as it is otherwise held from st7735_initR() on, before
+// the next byte is sent.
+
+// below this many pixels, fills are sent by the CPU, since DMA would save next to nothing
+const DMA_FILL_MIN_PIXELS: usize = 64;
+
+static mut DMA_COLOR: u16 = 0; // the color DMA is sending
+static mut DMA_BUSY: bool = false; // a DMA fill is under way
+static mut DMA_CS_RELEASED: bool = false; // the last fill released the chip select
+
+// set up DMA1 channel 5 to send DMA_COLOR to SPI2, and enable its transfer-complete interrupt
+fn spi_dma_setup() {
+    cortex_m::interrupt::free(|cs| {
+        let rcc = RCC.borrow(cs);
+        rcc.ahbenr.modify(|_, w| w.dmaen().enabled()); // should be 'dma1en'
+        let dma1 = DMA1.borrow(cs);
+        dma1.ccr5.modify(|_, w| unsafe {
+            w.mem2mem().bits(0)  // memory-to-memory mode disabled
+             .pl().bits(0b01)    // medium priority, below the capture's
+             .msize().bits(0b01) // memory data size 16 bits
+             .psize().bits(0b01) // peripheral data size 16 bits
+             .minc().bits(0)     // memory increment disabled: the same color every time
+             .pinc().bits(0)     // peripheral increment disabled
+             .circ().bits(0)     // one-shot (not circular) mode
+             .dir().bits(1)      // transfer direction: memory -> peripheral
+             .tcie().bits(1)     // trigger interrupt on transfer completion
+        });
+        let spi2_dr_address: u32 = &SPI2.borrow(cs).dr as *const _ as u32;
+        dma1.cpar5.write(|w| unsafe { w.bits(spi2_dr_address) }); // peripheral base address
+        dma1.cmar5.write(|w| unsafe { w.bits(&DMA_COLOR as *const _ as u32) }); // memory address
+        // (below the capture's priority, since finishing a fill can wait)
+        let nvic = cortex_m::peripheral::NVIC.borrow(cs);
+        unsafe { nvic.set_priority(Interrupt::Dma1Ch5, 0x80); }
+        nvic.enable(Interrupt::Dma1Ch5);
+    });
 }
 
-// send a command byte to the LCD controller
-#[cfg(not(feature = "software-spi"))]
-#[no_mangle]
-#[used]
-pub extern "C" fn st7735_send_cmd(cmd: u8) {
-    if lcd_dc() {
-        // drain the transmit FIFO before switching A0/DC
-        spi2_wait_while_busy();
-        lcd_dc0();
+// Send `pixels` pixels (at most 65535) of `color` into the address window by DMA, returning while
+// the transfer goes on.
+fn spi_dma_fill(color: u16, pixels: usize) {
+    st7735_dma_wait();
+    // drain the transmit FIFO of the address window commands before changing the frame size
+    unsafe {
+        while (*SPI2.get()).sr.read().txe().bits() == 0 {}
+    }
+    spi2_wait_while_busy();
+    lcd_dc1();
+    lcd_cs0(); // (already asserted, and kept that way until the transfer is done)
+    cortex_m::interrupt::free(|cs| unsafe {
+        DMA_COLOR = color;
+        DMA_BUSY = true;
+        let spi2 = SPI2.borrow(cs);
+        let dma1 = DMA1.borrow(cs);
+        spi2.cr1.modify(|_, w| w.spe().bits(0));
+        spi2.cr2.modify(|_, w| w.ds().bits(0b1111)); // SPI_DataSize_16b
+        dma1.cndtr5.write(|w| w.ndt().bits(pixels as u16)); // number of pixels
+        dma1.ccr5.modify(|_, w| w.en().bits(1));
+        spi2.cr2.modify(|_, w| w.txdmaen().bits(1));
+        spi2.cr1.modify(|_, w| w.spe().bits(1));
+    });
+    #[cfg(feature = "lcd-framebuffer")]
+    for _ in 0..pixels {
+        mirror_write(color);
     }
-    st7735_send_byte(cmd);
 }
 
-// send a data byte to the LCD controller
-#[cfg(not(feature = "software-spi"))]
-#[no_mangle]
-#[used]
-pub extern "C" fn st7735_send_data(data: u8) {
-    if !lcd_dc() {
-        // drain the transmit FIFO before switching A0/DC
-        spi2_wait_while_busy();
-        lcd_dc1();
+// wait for any DMA fill to finish, before something else is sent to the LCD, and assert the chip
+// select again if the fill released it
+fn st7735_dma_wait() {
+    unsafe {
+        while ptr::read_volatile(&DMA_BUSY) {}
+        if ptr::read_volatile(&DMA_CS_RELEASED) {
+            DMA_CS_RELEASED = false;
+            lcd_cs0();
+        }
+    }
+}
+
+/// Handles the DMA1 channel 5 transfer-complete interrupt, at the end of a DMA fill; called from
+/// the interrupt handler. This waits for SPI2 to send the last pixel, then releases the chip
+/// select, ending the transfer, and puts SPI2 back to 8-bit frames for the CPU.
+pub fn st7735_dma_interrupt() {
+    let dma1 = DMA1.get();
+    let spi2 = SPI2.get();
+    unsafe {
+        if (*dma1).isr.read().tcif5().bits() != 0 {
+            // clear the DMA1 channel 5 transfer complete interrupt flag TCIF
+            (*dma1).ifcr.write(|w| w.ctcif5().bits(1));
+            (*dma1).ccr5.modify(|_, w| w.en().bits(0));
+            while (*spi2).sr.read().txe().bits() == 0 {}
+            spi2_wait_while_busy();
+            lcd_cs1();
+            DMA_CS_RELEASED = true;
+            (*spi2).cr1.modify(|_, w| w.spe().bits(0));
+            (*spi2).cr2.modify(|_, w| w.txdmaen().bits(0).ds().bits(0b0111)); // SPI_DataSize_8b
+            (*spi2).cr1.modify(|_, w| w.spe().bits(1));
+            DMA_BUSY = false;
+        }
+    }
+}
+
+/// Fills the whole screen with `color`, by DMA when the LCD is on hardware SPI, in which case this
+/// returns while the fill is still going on (whatever is sent to the LCD next waits for it).
+pub fn st7735_fill_dma(color: u16) {
+    let (width, height) = (st7735_get_width(), st7735_get_height());
+    #[cfg(feature = "lcd-framebuffer")]
+    mirror_reset_palette();
+    st7735_setAddrWindow(0, 0, width - 1, height - 1);
+    st7735_fill_window(width as usize * height as usize, color);
+}
+
+// Send `pixels` pixels of `color` into the address window: by DMA, if it's worth it and the LCD
+// is on hardware SPI, or else one at a time.
+fn st7735_fill_window(pixels: usize, color: u16) {
+    let dma = unsafe { TRANSPORT == St7735Transport::HardSpi && !HEADLESS };
+    if dma && pixels >= DMA_FILL_MIN_PIXELS {
+        spi_dma_fill(color, pixels);
+    } else {
+        for _ in 0..pixels {
+            st7735_send_color(color);
+        }
     }
-    st7735_send_byte(data);
 }
 
 // ======== software SPI ========
 
 // set up the hardware to use software SPI: bit-banging on PB13 (SCK/SCL) and PB15 (SDA/MOSI)
-#[cfg(feature = "software-spi")]
-pub fn st7735_setup() {
+fn soft_spi_setup() {
     cortex_m::interrupt::free(|cs| {
         let rcc = RCC.borrow(cs);
         let gpiob = GPIOB.borrow(cs);
@@ -217,8 +450,7 @@ pub fn st7735_setup() {
 }
 
 // send a byte of data to the LCD controller via bit-banged SPI
-#[cfg(feature = "software-spi")]
-fn st7735_send_byte(data_in: u8) {
+fn soft_spi_send_byte(data_in: u8) {
     let mut data = data_in;
     for _ in 0..8 {
         if (data & 0x80) != 0 {
@@ -233,45 +465,21 @@ fn st7735_send_byte(data_in: u8) {
     }
 }
 
-#[cfg(feature = "software-spi")]
 fn lcd_sck1() {
     unsafe { (*GPIOB.get()).bsrr.write(|w| w.bs13().set()); } // set PB13: SCK/SCL
 }
 
-#[cfg(feature = "software-spi")]
 fn lcd_sck0() {
     unsafe { (*GPIOB.get()).brr.write(|w| w.br13().bits(1)); } // reset PB13: SCK/SCL
 }
 
-// send a command byte to the LCD controller
-#[cfg(feature = "software-spi")]
-#[no_mangle]
-#[used]
-pub extern "C" fn st7735_send_cmd(cmd: u8) {
-    lcd_dc0();
-    st7735_send_byte(cmd);
-}
-
-// send a data byte to the LCD controller
-#[cfg(feature = "software-spi")]
-#[no_mangle]
-#[used]
-pub extern "C" fn st7735_send_data(data: u8) {
-    lcd_dc1();
-    st7735_send_byte(data);
-}
-
 // ======== SPI/GPIO manipulation functions for both hardware and software modes ========
 
-#[no_mangle]
-#[used]
-pub extern "C" fn lcd_cs1() {
+pub fn lcd_cs1() {
     unsafe { (*GPIOB.get()).bsrr.write(|w| w.bs10().set()); } // set PB10: CSE/CS
 }
 
-#[no_mangle]
-#[used]
-pub extern "C" fn lcd_cs0() {
+pub fn lcd_cs0() {
     unsafe { (*GPIOB.get()).brr.write(|w| w.br10().bits(1)); } // reset PB10: CSE/CS
 }
 
@@ -283,56 +491,449 @@ fn lcd_dc0() {
     unsafe { (*GPIOB.get()).brr.write(|w| w.br12().bits(1)); } // reset PB12: A0/RS/DC
 }
 
-#[no_mangle]
-#[used]
-pub extern "C" fn lcd_rst1() {
+pub fn lcd_rst1() {
     unsafe { (*GPIOB.get()).bsrr.write(|w| w.bs14().set()); } // set PB14: RST
 }
 
-#[no_mangle]
-#[used]
-pub extern "C" fn lcd_rst0() {
+pub fn lcd_rst0() {
     unsafe { (*GPIOB.get()).brr.write(|w| w.br14().bits(1)); } // reset PB14: RST
 }
 
-// ======== wrappers for (unsafe) C functions ========
+// ======== ST7735 controller initialization and basic drawing ========
+
+// The code in this section was ported from the Adafruit ST7735 library, which bore this notice:
+//
+//   This is a library for the Adafruit 1.8" SPI display.
+//
+//   This library works with the Adafruit 1.8" TFT Breakout w/SD card
+//     ----> http://www.adafruit.com/products/358
+//   as well as Adafruit raw 1.8" TFT display
+//     ----> http://www.adafruit.com/products/618
+//
+//   Check out the links above for our tutorials and wiring diagrams
+//   These displays use SPI to communicate, 4 or 5 pins are required to
+//   interface (RST is optional)
+//   Adafruit invests time and resources providing this open source code,
+//   please support Adafruit and open-source hardware by purchasing
+//   products from Adafruit!
+//
+//   Written by Limor Fried/Ladyada for Adafruit Industries.
+//   MIT license, all text above must be included in any redistribution
 
-pub fn st7735_initR(lcd_type: u8) { unsafe { _st7735_initR(lcd_type) } }
+const ST7735_TFTWIDTH: u8 = 128;
+const ST7735_TFTHEIGHT: u8 = 160;
 
+/// The widest the screen can be, in pixels, in any rotation. It's defined with the signal
+/// processing, which sizes its own buffers from it, so this fails to compile if it isn't the
+/// panel's longer side.
+pub use dsp::DISPLAY_WIDTH;
 #[allow(unused)]
-pub fn st7735_drawFastHLine(x: i16, y: i16, w: i16, color: u16) {
-    unsafe { _st7735_drawFastHLine(x, y, w, color) }
+const DISPLAY_WIDTH_IS_PANEL_HEIGHT: [(); 1] =
+    [(); (DISPLAY_WIDTH == ST7735_TFTHEIGHT as usize) as usize];
+
+const ST7735_SWRESET: u8 = 0x01;
+#[cfg(feature = "lcd-readback")]
+const ST7735_RDDID: u8 = 0x04;
+const ST7735_SLPOUT: u8 = 0x11;
+const ST7735_NORON: u8 = 0x13;
+const ST7735_INVOFF: u8 = 0x20;
+const ST7735_DISPON: u8 = 0x29;
+const ST7735_CASET: u8 = 0x2a;
+const ST7735_RASET: u8 = 0x2b;
+const ST7735_RAMWR: u8 = 0x2c;
+const ST7735_COLMOD: u8 = 0x3a;
+const ST7735_MADCTL: u8 = 0x36;
+const ST7735_FRMCTR1: u8 = 0xb1;
+const ST7735_FRMCTR2: u8 = 0xb2;
+const ST7735_FRMCTR3: u8 = 0xb3;
+const ST7735_INVCTR: u8 = 0xb4;
+const ST7735_PWCTR1: u8 = 0xc0;
+const ST7735_PWCTR2: u8 = 0xc1;
+const ST7735_PWCTR3: u8 = 0xc2;
+const ST7735_PWCTR4: u8 = 0xc3;
+const ST7735_PWCTR5: u8 = 0xc4;
+const ST7735_VMCTR1: u8 = 0xc5;
+const ST7735_GMCTRP1: u8 = 0xe0;
+const ST7735_GMCTRN1: u8 = 0xe1;
+
+const MADCTL_MY: u8 = 0x80;
+const MADCTL_MX: u8 = 0x40;
+const MADCTL_MV: u8 = 0x20;
+const MADCTL_RGB: u8 = 0x00;
+const MADCTL_BGR: u8 = 0x08;
+
+// the panel type, as given to st7735_initR()
+static mut LCD_TYPE: St7735Type = St7735Type::RedTab;
+// the current screen size, which depends on rotation
+static mut WIDTH: u8 = ST7735_TFTWIDTH;
+static mut HEIGHT: u8 = ST7735_TFTHEIGHT;
+// the offset of the visible area within the controller's RAM, which depends on the panel type
+static mut COLSTART: u8 = 0;
+static mut ROWSTART: u8 = 0;
+// the same offset, in screen x and y, which depends on rotation as well
+static mut XSTART: u8 = 0;
+static mut YSTART: u8 = 0;
+
+// Rather than a bazillion st7735_send_cmd() and st7735_send_data() calls, screen initialization
+// commands and arguments are organized in these tables. Each begins with the number of commands
+// in it, then each command is followed by its argument count, its arguments, and, if DELAY is set
+// in the argument count, a post-command delay in milliseconds (255 meaning 500ms).
+const DELAY: u8 = 0x80;
+
+// Init for 7735R, part 1 (red or green tab)
+static RCMD1: &'static [u8] = &[
+    15,                       // 15 commands in list:
+    ST7735_SWRESET, DELAY,    //  1: Software reset, 0 args, w/delay
+      150,                    //     150 ms delay
+    ST7735_SLPOUT, DELAY,     //  2: Out of sleep mode, 0 args, w/delay
+      255,                    //     500 ms delay
+    ST7735_FRMCTR1, 3,        //  3: Frame rate ctrl - normal mode, 3 args:
+      0x01, 0x2c, 0x2d,       //     Rate = fosc/(1x2+40) * (LINE+2C+2D)
+    ST7735_FRMCTR2, 3,        //  4: Frame rate control - idle mode, 3 args:
+      0x01, 0x2c, 0x2d,       //     Rate = fosc/(1x2+40) * (LINE+2C+2D)
+    ST7735_FRMCTR3, 6,        //  5: Frame rate ctrl - partial mode, 6 args:
+      0x01, 0x2c, 0x2d,       //     Dot inversion mode
+      0x01, 0x2c, 0x2d,       //     Line inversion mode
+    ST7735_INVCTR, 1,         //  6: Display inversion ctrl, 1 arg, no delay:
+      0x07,                   //     No inversion
+    ST7735_PWCTR1, 3,         //  7: Power control, 3 args, no delay:
+      0xa2,
+      0x02,                   //     -4.6V
+      0x84,                   //     AUTO mode
+    ST7735_PWCTR2, 1,         //  8: Power control, 1 arg, no delay:
+      0xc5,                   //     VGH25 = 2.4C VGSEL = -10 VGH = 3 * AVDD
+    ST7735_PWCTR3, 2,         //  9: Power control, 2 args, no delay:
+      0x0a,                   //     Opamp current small
+      0x00,                   //     Boost frequency
+    ST7735_PWCTR4, 2,         // 10: Power control, 2 args, no delay:
+      0x8a,                   //     BCLK/2, Opamp current small & Medium low
+      0x2a,
+    ST7735_PWCTR5, 2,         // 11: Power control, 2 args, no delay:
+      0x8a, 0xee,
+    ST7735_VMCTR1, 1,         // 12: Power control, 1 arg, no delay:
+      0x0e,
+    ST7735_INVOFF, 0,         // 13: Don't invert display, no args, no delay
+    ST7735_MADCTL, 1,         // 14: Memory access control (directions), 1 arg:
+      0xc0,                   //     row addr/col addr, bottom to top refresh, RGB order
+    ST7735_COLMOD, 1 | DELAY, // 15: Set color mode, 1 arg + delay:
+      0x05,                   //     16-bit color 5-6-5 color format
+      10,                     //     10 ms delay
+];
+
+// Init for 7735R, part 2 (green tab only)
+static RCMD2GREEN: &'static [u8] = &[
+    2,                        //  2 commands in list:
+    ST7735_CASET, 4,          //  1: Column addr set, 4 args, no delay:
+      0x00, 0x02,             //     XSTART = 0
+      0x00, 0x7f + 0x02,      //     XEND = 129
+    ST7735_RASET, 4,          //  2: Row addr set, 4 args, no delay:
+      0x00, 0x01,             //     XSTART = 0
+      0x00, 0x9f + 0x01,      //     XEND = 160
+];
+
+// Init for 7735R, part 2 (red tab only)
+static RCMD2RED: &'static [u8] = &[
+    2,                        //  2 commands in list:
+    ST7735_CASET, 4,          //  1: Column addr set, 4 args, no delay:
+      0x00, 0x00,             //     XSTART = 0
+      0x00, 0x7f,             //     XEND = 127
+    ST7735_RASET, 4,          //  2: Row addr set, 4 args, no delay:
+      0x00, 0x00,             //     XSTART = 0
+      0x00, 0x9f,             //     XEND = 159
+];
+
+// Init for 7735R, part 3 (red or green tab)
+static RCMD3: &'static [u8] = &[
+    4,                        //  4 commands in list:
+    ST7735_GMCTRP1, 16,       //  1: Magical unicorn dust, 16 args, no delay:
+      0x02, 0x1c, 0x07, 0x12,
+      0x37, 0x32, 0x29, 0x2d,
+      0x29, 0x25, 0x2b, 0x39,
+      0x00, 0x01, 0x03, 0x10,
+    ST7735_GMCTRN1, 16,       //  2: Sparkles and rainbows, 16 args, no delay:
+      0x03, 0x1d, 0x07, 0x06,
+      0x2e, 0x2c, 0x29, 0x2d,
+      0x2e, 0x2e, 0x37, 0x3f,
+      0x00, 0x00, 0x02, 0x10,
+    ST7735_NORON, DELAY,      //  3: Normal display on, no args, w/delay
+      10,                     //     10 ms delay
+    ST7735_DISPON, DELAY,     //  4: Main screen turn on, no args w/delay
+      100,                    //     100 ms delay
+];
+
+// issue the series of LCD commands in one of the tables above
+fn command_list(list: &[u8]) {
+    let mut i = 1;
+    for _ in 0..list[0] {
+        st7735_send_cmd(list[i]);
+        let args = list[i + 1];
+        i += 2;
+        for _ in 0..(args & !DELAY) {
+            st7735_send_data(list[i]);
+            i += 1;
+        }
+        if args & DELAY != 0 {
+            let ms = list[i];
+            i += 1;
+            delay_ms(if ms == 255 { 500 } else { ms as u32 });
+        }
+    }
 }
 
+// Initialization for ST7735R screens (green, red, or black tabs), in portrait orientation
+pub fn st7735_initR(lcd_type: St7735Type) {
+    delay_ms(50);
+    // toggle RST low to reset; CS low so it'll listen to us
+    lcd_cs0();
+    lcd_rst1();
+    delay_ms(500);
+    lcd_rst0();
+    delay_ms(500);
+    lcd_rst1();
+    delay_ms(500);
+    command_list(RCMD1);
+    unsafe {
+        LCD_TYPE = lcd_type;
+    }
+    if lcd_type == St7735Type::GreenTab {
+        command_list(RCMD2GREEN);
+        unsafe {
+            COLSTART = 2;
+            ROWSTART = 1;
+        }
+    } else {
+        command_list(RCMD2RED);
+        unsafe {
+            COLSTART = 0;
+            ROWSTART = 0;
+        }
+    }
+    command_list(RCMD3);
+
+    // set the panel's color order, and the portrait orientation which RCMD1 set up, adjusted for
+    // the panel type
+    st7735_setRotation(0);
+}
+
+/// Returns the panel type given to `st7735_initR()`.
 #[allow(unused)]
-pub fn st7735_drawFastVLine(x: i16, y: i16, h: i16, color: u16) {
-    unsafe { _st7735_drawFastVLine(x, y, h, color) }
+pub fn st7735_get_type() -> St7735Type {
+    unsafe { LCD_TYPE }
 }
 
-pub fn st7735_drawPixel(x: i16, y: i16, color: u16) { unsafe { _st7735_drawPixel(x, y, color) } }
+// ======== probing ========
+
+// The controller answers reads on its SDA line, which is PB15 here, but not every board lets that
+// answer back to the microcontroller, so reading it is left to the 'lcd-readback' feature.
+
+/// Whether `st7735_probe()` really checks for the LCD controller: only when built with the
+/// 'lcd-readback' feature, for boards whose SDA line can be read back.
+pub const ST7735_PROBE_WIRED: bool = cfg!(feature = "lcd-readback");
+
+// the RDDID response the datasheet gives for the ST7735R: manufacturer, version, and module IDs
+#[cfg(feature = "lcd-readback")]
+const ST7735_ID: u32 = 0x7c89f0;
+
+/// Checks that the LCD controller is answering after `st7735_initR()`, by reading back its display
+/// ID and comparing it with the ST7735R's. Without the 'lcd-readback' feature, there is no way to
+/// check, so this returns true.
+#[cfg(feature = "lcd-readback")]
+pub fn st7735_probe() -> bool {
+    st7735_send_cmd(ST7735_RDDID);
+    if unsafe { TRANSPORT } == St7735Transport::HardSpi {
+        spi2_wait_while_busy();
+    }
+    let gpiob = GPIOB.get();
+    let mut id = 0u32;
+    unsafe {
+        // take SCK and SDA from SPI2, if it has them: SCK as an output, and SDA as an input
+        let moder = (*gpiob).moder.read().bits();
+        lcd_sck0();
+        (*gpiob).moder.write(|w| w.bits((moder & !(0b11 << 26 | 0b11 << 30)) | (0b01 << 26)));
+        // the controller shifts out a dummy bit, then the 24-bit ID, on falling edges of SCK, so
+        // read each bit just after a rising edge (slowly: reads need a longer clock cycle)
+        for _ in 0..25 {
+            lcd_sck1();
+            delay_us(1);
+            id = (id << 1) | (((*gpiob).idr.read().bits() >> 15) & 1);
+            lcd_sck0();
+            delay_us(1);
+        }
+        (*gpiob).moder.write(|w| w.bits(moder));
+    }
+    // end the read (CS may be jumpered low, in which case the next command ends it)
+    lcd_cs1();
+    lcd_cs0();
+    id & 0xff_ffff == ST7735_ID
+}
 
-pub fn st7735_fillScreen(color: u16) { unsafe { _st7735_fillScreen(color) } }
+#[cfg(not(feature = "lcd-readback"))]
+pub fn st7735_probe() -> bool {
+    true
+}
 
+#[inline]
 pub fn st7735_setAddrWindow(x0: u8, y0: u8, x1: u8, y1: u8) {
-    unsafe { _st7735_setAddrWindow(x0, y0, x1, y1) }
+    let (xstart, ystart) = unsafe { (XSTART, YSTART) };
+    st7735_send_cmd(ST7735_CASET); // Column addr set
+    st7735_send_data(0x00);
+    st7735_send_data(x0 + xstart); // XSTART
+    st7735_send_data(0x00);
+    st7735_send_data(x1 + xstart); // XEND
+
+    st7735_send_cmd(ST7735_RASET); // Row addr set
+    st7735_send_data(0x00);
+    st7735_send_data(y0 + ystart); // YSTART
+    st7735_send_data(0x00);
+    st7735_send_data(y1 + ystart); // YEND
+
+    st7735_send_cmd(ST7735_RAMWR); // write to RAM
+    #[cfg(feature = "lcd-framebuffer")]
+    mirror_set_window(x0, y0, x1, y1);
+}
+
+// draw color pixel on screen
+#[allow(unused)]
+pub fn st7735_drawPixel(x: i16, y: i16, color: u16) {
+    if x < 0 || x >= st7735_get_width() as i16 || y < 0 || y >= st7735_get_height() as i16 {
+        return;
+    }
+    st7735_setAddrWindow(x as u8, y as u8, (x + 1) as u8, (y + 1) as u8);
+    st7735_send_color(color);
+}
+
+#[allow(unused)]
+pub fn st7735_drawFastVLine(x: i16, y: i16, h: i16, color: u16) {
+    let width = st7735_get_width() as i16;
+    let height = st7735_get_height() as i16;
+    // Rudimentary clipping
+    if x < 0 || x >= width || y < 0 || y >= height || h <= 0 {
+        return;
+    }
+    let h = core::cmp::min(h, height - y);
+    st7735_setAddrWindow(x as u8, y as u8, x as u8, (y + h - 1) as u8);
+    for _ in 0..h {
+        st7735_send_color(color);
+    }
+}
+
+#[allow(unused)]
+pub fn st7735_drawFastHLine(x: i16, y: i16, w: i16, color: u16) {
+    let width = st7735_get_width() as i16;
+    let height = st7735_get_height() as i16;
+    // Rudimentary clipping
+    if x < 0 || x >= width || y < 0 || y >= height || w <= 0 {
+        return;
+    }
+    let w = core::cmp::min(w, width - x);
+    st7735_setAddrWindow(x as u8, y as u8, (x + w - 1) as u8, y as u8);
+    for _ in 0..w {
+        st7735_send_color(color);
+    }
+}
+
+#[allow(unused)]
+pub fn st7735_fillScreen(color: u16) {
+    st7735_fill_dma(color);
+}
+
+/// Sets the screen orientation to `rotation` (modulo 4) quarter turns from portrait: 0 and 2 are
+/// portrait, 1 and 3 landscape. The width and
+/// height change to suit, and drawing adapts to them, but what is on the screen is not redrawn.
+pub fn st7735_setRotation(rotation: u8) {
+    st7735_send_cmd(ST7735_MADCTL);
+    let (madctl, width, height) = match rotation % 4 { // can't be higher than 3
+        // portrait
+        0 => (MADCTL_MX | MADCTL_MY, ST7735_TFTWIDTH, ST7735_TFTHEIGHT),
+        // landscape
+        1 => (MADCTL_MY | MADCTL_MV, ST7735_TFTHEIGHT, ST7735_TFTWIDTH),
+        // portrait, inverted
+        2 => (0, ST7735_TFTWIDTH, ST7735_TFTHEIGHT),
+        // landscape, inverted
+        _ => (MADCTL_MX | MADCTL_MV, ST7735_TFTHEIGHT, ST7735_TFTWIDTH),
+    };
+    // The red tab panel this was developed on, and black tab panels, have RGB color filters;
+    // green tab panels have BGR ones.
+    let color_order = match st7735_get_type() {
+        St7735Type::GreenTab => MADCTL_BGR,
+        St7735Type::RedTab | St7735Type::BlackTab => MADCTL_RGB,
+    };
+    st7735_send_data(madctl | color_order);
+    unsafe {
+        WIDTH = width;
+        HEIGHT = height;
+        // in landscape, screen x runs along the controller's rows
+        if madctl & MADCTL_MV != 0 {
+            XSTART = ROWSTART;
+            YSTART = COLSTART;
+        } else {
+            XSTART = COLSTART;
+            YSTART = ROWSTART;
+        }
+    }
 }
 
-pub fn st7735_setRotation(rotation: u8) { unsafe { _st7735_setRotation(rotation) } }
+#[inline]
+pub fn st7735_get_height() -> u8 { unsafe { HEIGHT } }
+
+#[inline]
+pub fn st7735_get_width() -> u8 { unsafe { WIDTH } }
 
-pub fn st7735_get_height() -> u8 { unsafe { _st7735_get_height() } }
+// ======== backlight ========
+
+// The breakout board's backlight (its J1-2, through the board's current-limiting resistors) is
+// assumed to be wired to PC6 rather than straight to 3V, so that it can be dimmed: PC6 is TIM3
+// channel 1, on alternate function 2, which drives it with PWM at about 1kHz. Wired to 3V, the
+// backlight just stays fully on.
+
+// TIM3 counts 0 through BACKLIGHT_PWM_ARR, so that a compare value of 0 is off, and 255 is fully on
+const BACKLIGHT_PWM_ARR: u32 = 254;
+// a TIM3 prescaler of 282, for (72MHz / 282) / 255 = 1001Hz
+const BACKLIGHT_PWM_PSC: u16 = 281;
+
+/// Sets up PC6 and TIM3 to drive the backlight with PWM, and turns it fully on.
+pub fn st7735_backlight_init() {
+    cortex_m::interrupt::free(|cs| {
+        // enable clock to GPIOC and TIM3
+        let rcc = RCC.borrow(cs);
+        rcc.ahbenr.modify(|_, w| w.iopcen().enabled());
+        rcc.apb1enr.modify(|_, w| w.tim3en().enabled());
+
+        let tim3 = TIM3.borrow(cs);
+        tim3.cr1.modify(|_, w| unsafe { w.arpe().bits(1) }); // ARR register is buffered
+        tim3.arr.write(|w| unsafe { w.bits(BACKLIGHT_PWM_ARR) });
+        tim3.psc.write(|w| unsafe { w.psc().bits(BACKLIGHT_PWM_PSC) });
+        // channel 1: PWM mode 1 (active while the count is below the compare value), with a
+        // preloaded compare register
+        tim3.ccmr1_output.write(|w| unsafe { w.bits(0x0068) });
+        tim3.ccr1.write(|w| unsafe { w.bits(255) });
+        tim3.ccer.write(|w| unsafe { w.bits(0x0001) }); // enable CC1, active high
+        tim3.egr.write(|w| unsafe { w.ug().bits(1) }); // immediately update registers
+        tim3.cr1.modify(|_, w| unsafe { w.cen().bits(1) });
+
+        // hand PC6 over to TIM3: alternate function 2
+        let gpioc = GPIOC.borrow(cs);
+        gpioc.afrl.modify(|r, w| unsafe { w.bits((r.bits() & !(0b1111 << 24)) | (2 << 24)) });
+        gpioc.moder.modify(|r, w| unsafe { w.bits((r.bits() & !(0b11 << 12)) | (0b10 << 12)) });
+    });
+}
 
-pub fn st7735_get_width() -> u8 { unsafe { _st7735_get_width() } }
+/// Sets the backlight to brightness `level`, from 0 (off, to save power) to 255 (fully on).
+/// `st7735_backlight_init()` must have been called first.
+pub fn st7735_set_backlight(level: u8) {
+    unsafe { (*TIM3.get()).ccr1.write(|w| w.bits(level as u32)); }
+}
 
 // ======== drawing routines ========
 
 pub fn st7735_send_color(color: u16) {
     st7735_send_data((color >> 8) as u8);
     st7735_send_data((color & 0xff) as u8);
+    #[cfg(feature = "lcd-framebuffer")]
+    mirror_write(color);
 }
 
-#[no_mangle]
-#[used]
-pub extern "C" fn st7735_fill_rect(x: i16, y: i16, w0: i16, h0: i16, color: u16) {
+pub fn st7735_fill_rect(x: i16, y: i16, w0: i16, h0: i16, color: u16) {
     let width = st7735_get_width() as i16;
     let height = st7735_get_height() as i16;
     let mut w = w0;
@@ -353,67 +954,1550 @@ pub extern "C" fn st7735_fill_rect(x: i16, y: i16, w0: i16, h0: i16, color: u16)
         y_end = height - 1;
         h = height - y;
     }
-    st7735_setAddrWindow(x as u8, y as u8, x_end as u8, y_end as u8);
-    for _ in 0..(w * h) {
-        st7735_send_color(color)
+    if w <= 0 || h <= 0 {
+        return;
     }
+    st7735_setAddrWindow(x as u8, y as u8, x_end as u8, y_end as u8);
+    st7735_fill_window(w as usize * h as usize, color);
 }
 
-// ======== text printing ========
+// ======== framebuffer mirror ========
 
-fn st7735_putc_unchecked(x: u8, y: u8, c: u8, fg: St7735Color, bg: St7735Color) {
-    if c >= 128 {
-        return;
+// The breakout boards only wire up the panel's data input, so its frame memory can't be read
+// back. Instead, when built with the 'lcd-framebuffer' feature, every pixel sent to the panel is
+// also recorded in RAM. A full 16-bit copy of the screen wouldn't leave any RAM for anything else,
+// so the mirror holds a 4-bit index into a palette of the first 16 colors drawn since the whole
+// screen was last painted. That is fewer than the scope can use at once (the theme's colors, the
+// trace colors, and their graded and persistence shades), so further colors are recorded as the
+// nearest palette color, and a screenshot may show some of them remapped. Each full-screen paint,
+// e.g. for a new theme, starts the palette again, so that old colors don't crowd out new ones.
+#[cfg(feature = "lcd-framebuffer")]
+const MIRROR_PIXELS: usize = ST7735_TFTWIDTH as usize * ST7735_TFTHEIGHT as usize;
+#[cfg(feature = "lcd-framebuffer")]
+const MIRROR_PALETTE_SIZE: usize = 16;
+
+#[cfg(feature = "lcd-framebuffer")]
+static mut MIRROR: [u8; MIRROR_PIXELS / 2] = [0; MIRROR_PIXELS / 2]; // 10kB
+#[cfg(feature = "lcd-framebuffer")]
+static mut MIRROR_PALETTE: [u16; MIRROR_PALETTE_SIZE] = [0; MIRROR_PALETTE_SIZE]; // black first
+#[cfg(feature = "lcd-framebuffer")]
+static mut MIRROR_COLORS: usize = 1;
+// the address window set by st7735_setAddrWindow() (x0, y0, x1, y1), and the next pixel in it
+#[cfg(feature = "lcd-framebuffer")]
+static mut MIRROR_WINDOW: (u8, u8, u8, u8) = (0, 0, 0, 0);
+#[cfg(feature = "lcd-framebuffer")]
+static mut MIRROR_NEXT: (u8, u8) = (0, 0);
+
+/// Copies row `y` of the screen, as drawn since the screen was last rotated, into `row`, up to
+/// the screen width, for taking screenshots. Returns false, leaving `row` alone, if `y` is off
+/// the screen or the screen contents aren't available, because this was built without the
+/// 'lcd-framebuffer' feature.
+#[cfg(feature = "lcd-framebuffer")]
+pub fn st7735_capture_framebuffer(y: u8, row: &mut [u16]) -> bool {
+    let width = st7735_get_width() as usize;
+    if y >= st7735_get_height() {
+        return false;
     }
-    st7735_setAddrWindow(x, y, x + 7, y + 11);
-    for yrow in 0..12 {
-        let mut bits = parallax_8x12_font::FONT_8X12[(c as usize) * 12 + yrow];
-        for _ in 0..8 {
-            if bits & 0b1 == 0b1 {
-                st7735_send_color(fg as u16);
-            } else {
-                st7735_send_color(bg as u16);
-            }
-            bits >>= 1;
-        }
+    for (x, pixel) in row.iter_mut().take(width).enumerate() {
+        let i = y as usize * width + x;
+        let index = unsafe { (MIRROR[i / 2] >> (i % 2 * 4)) & 0xf };
+        *pixel = unsafe { MIRROR_PALETTE[index as usize] };
     }
+    true
 }
 
-#[allow(unused)]
-pub fn st7735_putc(x: u8, y: u8, c: u8, fg: St7735Color, bg: St7735Color) {
-    let height = st7735_get_height();
+#[cfg(not(feature = "lcd-framebuffer"))]
+pub fn st7735_capture_framebuffer(_y: u8, _row: &mut [u16]) -> bool {
+    false
+}
+
+// Forget the palette, other than black, because every pixel is about to be painted again.
+#[cfg(feature = "lcd-framebuffer")]
+fn mirror_reset_palette() {
+    unsafe { MIRROR_COLORS = 1; }
+}
+
+// Start recording pixels at the top left of the window from (`x0`, `y0`) to (`x1`, `y1`).
+#[cfg(feature = "lcd-framebuffer")]
+fn mirror_set_window(x0: u8, y0: u8, x1: u8, y1: u8) {
+    unsafe {
+        MIRROR_WINDOW = (x0, y0, x1, y1);
+        MIRROR_NEXT = (x0, y0);
+    }
+}
+
+// Record `color` as the next pixel in the window, which fills across then down, like the panel's.
+#[cfg(feature = "lcd-framebuffer")]
+fn mirror_write(color: u16) {
+    let (x0, y0, x1, y1) = unsafe { MIRROR_WINDOW };
+    let (x, y) = unsafe { MIRROR_NEXT };
     let width = st7735_get_width();
-    if x > width - 8 || y > height - 12 {
-        return;
+    if x < width && y < st7735_get_height() {
+        let i = y as usize * width as usize + x as usize;
+        let shift = i % 2 * 4;
+        let index = mirror_palette_index(color);
+        unsafe { MIRROR[i / 2] = (MIRROR[i / 2] & !(0xf << shift)) | (index << shift); }
+    }
+    unsafe {
+        MIRROR_NEXT = if x < x1 {
+            (x + 1, y)
+        } else if y < y1 {
+            (x0, y + 1)
+        } else {
+            (x0, y0) // (the panel wraps around, too)
+        };
     }
-    st7735_putc_unchecked(x, y, c, fg, bg);
 }
 
-#[allow(unused)]
-pub fn st7735_print(text: &[u8], x0: u8, y: u8, fg: St7735Color, bg: St7735Color) {
-    let height = unsafe { st7735_get_height() };
-    let width = unsafe { st7735_get_width() };
-    let mut x = x0;
-    if y > height - 12 {
-        return;
+// the palette index for `color`, adding it to the palette if there's room, otherwise the nearest
+#[cfg(feature = "lcd-framebuffer")]
+fn mirror_palette_index(color: u16) -> u8 {
+    let colors = unsafe { MIRROR_COLORS };
+    let palette = unsafe { &mut MIRROR_PALETTE };
+    if let Some(i) = palette[..colors].iter().position(|&c| c == color) {
+        return i as u8;
     }
-    for c in text {
-        if x > width - 8 {
-            return;
+    if colors < MIRROR_PALETTE_SIZE {
+        palette[colors] = color;
+        unsafe { MIRROR_COLORS = colors + 1; }
+        return colors as u8;
+    }
+    // (the distance between colors, in RGB565 units)
+    let components = |c: u16| [(c >> 11) as i32, ((c >> 5) & 0x3f) as i32, (c & 0x1f) as i32];
+    let target = components(color);
+    let mut nearest = 0;
+    let mut nearest_distance = i32::max_value();
+    for (i, &c) in palette.iter().enumerate() {
+        let mut distance = 0;
+        for (a, b) in components(c).iter().zip(target.iter()) {
+            distance += (a - b) * (a - b);
+        }
+        if distance < nearest_distance {
+            nearest = i;
+            nearest_distance = distance;
         }
-        st7735_putc_unchecked(x, y, *c, fg, bg);
-        x += 8;
     }
+    nearest as u8
 }
 
-// #[allow(unused)]
-// pub fn st7735_print_hex_u32(i: u32, x: u8, y: u8, fg: St7735Color, bg: St7735Color) {
-//     const C: [u8; 16] = *b"0123456789abcdef";
-//     let mut buf = [0u8; 4];
-//
-//     buf[0] = C[((i >> 12) & 0xf) as usize];
-//     buf[1] = C[((i >>  8) & 0xf) as usize];
-//     buf[2] = C[((i >>  4) & 0xf) as usize];
-//     buf[3] = C[( i        & 0xf) as usize];
-//     st7735_print(&buf, x, y, fg, bg);
-// }
+// ======== sample scaling ========
+
+/// Returns the screen row at which `sample` is drawn at vertical scale `scale`, moved up by
+/// `offset` pixels, on the screen as currently rotated (see `VerticalScale::sample_to_y`).
+pub fn sample_to_y(sample: u16, scale: &VerticalScale, offset: i16) -> i16 {
+    scale.sample_to_y(sample, st7735_get_height(), offset)
+}
+
+/// The inverse of `sample_to_y`, on the screen as currently rotated (see
+/// `VerticalScale::y_to_sample`).
+pub fn y_to_sample(y: i16, scale: &VerticalScale, offset: i16) -> u16 {
+    scale.y_to_sample(y, st7735_get_height(), offset)
+}
+
+// ======== background and trace ========
+
+// The background is a solid color, with a graticule of dotted division lines in half-intensity
+// color, plus a full-intensity center crosshair with tick marks every fifth of a division.
+struct Graticule {
+    divs_x: u8, // 0 for no graticule
+    divs_y: u8,
+    color: u16,
+    background: u16,
+    width: u8,
+    height: u8,
+}
+
+static mut GRATICULE: Graticule = Graticule {
+    divs_x: 0, divs_y: 0, color: 0, background: 0, width: 0, height: 0
+};
+
+const GRATICULE_SUBDIVISIONS: u16 = 5;
+const GRATICULE_TICK_LENGTH: u8 = 2; // on each side of the axis
+
+// is `p` on one of `divs` lines spread evenly from 0 to `n - 1`?
+fn graticule_on_line(p: u8, divs: u16, n: u8) -> bool {
+    // the lines are at floor(i * (n - 1) / divs), for i in 0..divs+1
+    let span = n as u16 - 1;
+    let r = (p as u16 * divs) % span;
+    r == 0 || span - r < divs
+}
+
+fn distance(a: u8, b: u8) -> u8 {
+    if a > b { a - b } else { b - a }
+}
+
+fn st7735_background_color(x: u8, y: u8) -> u16 {
+    if let Some(color) = cursor_color(x, y) {
+        return color;
+    }
+    if let Some(color) = trigger_marker_color(x, y) {
+        return color;
+    }
+    if let Some(color) = minimap_color(x, y) {
+        return color;
+    }
+    if let Some(color) = ref_color(x, y) {
+        return color;
+    }
+    if let Some(color) = band_color(x, y) {
+        return color;
+    }
+    let g = unsafe { &GRATICULE };
+    if g.divs_x == 0 || g.divs_y == 0 || x >= g.width || y >= g.height {
+        return g.background;
+    }
+    let center_x = (g.width - 1) / 2;
+    let center_y = (g.height - 1) / 2;
+    let near_x_axis = distance(y, center_y) <= GRATICULE_TICK_LENGTH;
+    let near_y_axis = distance(x, center_x) <= GRATICULE_TICK_LENGTH;
+    let divs_x = g.divs_x as u16;
+    let divs_y = g.divs_y as u16;
+    if x == center_x || y == center_y ||
+       (near_x_axis && graticule_on_line(x, divs_x * GRATICULE_SUBDIVISIONS, g.width)) ||
+       (near_y_axis && graticule_on_line(y, divs_y * GRATICULE_SUBDIVISIONS, g.height)) {
+        g.color
+    } else if (graticule_on_line(x, divs_x, g.width) && y % 2 == 0) ||
+              (graticule_on_line(y, divs_y, g.height) && x % 2 == 0) {
+        (g.color >> 1) & 0x7bef // half intensity
+    } else {
+        g.background
+    }
+}
+
+/// Sets the graticule to `divs_x` by `divs_y` divisions, drawn in `color` over `background`, then
+/// repaints the whole screen with it, erasing any trace and text. Trace erasure repaints the
+/// graticule wherever the trace was, so this only needs to be called again if the graticule, its
+/// colors, or the screen rotation changes.
+pub fn st7735_draw_graticule(divs_x: u8, divs_y: u8, color: St7735Color,
+                             background: St7735Color) {
+    unsafe {
+        GRATICULE = Graticule {
+            divs_x: divs_x,
+            divs_y: divs_y,
+            color: color as u16,
+            background: background as u16,
+            width: st7735_get_width(),
+            height: st7735_get_height(),
+        };
+    }
+    st7735_paint_background();
+}
+
+/// Paints the background (including graticule) over the whole screen, erasing any trace.
+pub fn st7735_paint_background() {
+    #[cfg(feature = "lcd-framebuffer")]
+    mirror_reset_palette();
+    st7735_paint_background_rect(0, 0, st7735_get_width(), st7735_get_height());
+    unsafe {
+        TRACE_Y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+        TRACE_SPANS = [[None; TRACE_COLUMNS]; TRACE_MAX];
+        XY_POINT_COUNT = 0;
+        SPECTRUM_HEIGHTS = [0; TRACE_COLUMNS];
+        BARGRAPH = None;
+        PERSIST_INTENSITY = [0; PERSIST_BYTES];
+        PERSIST_ACTIVE = false;
+    }
+}
+
+/// Paints the background (including graticule) over the `w` by `h` pixel rectangle at (`x`, `y`),
+/// e.g. to erase text. Any part of the trace within the rectangle reappears when the trace next
+/// moves there.
+pub fn st7735_paint_background_rect(x: u8, y: u8, w: u8, h: u8) {
+    let x_end = core::cmp::min(x as u16 + w as u16, st7735_get_width() as u16) as u8;
+    let y_end = core::cmp::min(y as u16 + h as u16, st7735_get_height() as u16) as u8;
+    if x >= x_end || y >= y_end {
+        return;
+    }
+    st7735_setAddrWindow(x, y, x_end - 1, y_end - 1);
+    for py in y..y_end {
+        for px in x..x_end {
+            st7735_send_color(st7735_background_color(px, py));
+        }
+    }
+}
+
+const TRACE_COLUMNS: usize = DISPLAY_WIDTH;
+const TRACE_NONE: u8 = 255; // no trace in this column
+
+/// The most traces `st7735_draw_trace_multi` can draw at once.
+pub const TRACE_MAX: usize = 3;
+
+// The trace y coordinate of each column of each trace, the span of pixels (top, bottom) each
+// lights in each column, and each trace's color, as last drawn. A trace is drawn as vertical
+// segments joining each column's y to the previous column's, so its spans follow from its y
+// coordinates, but an envelope's span in a column covers all the samples which fell into it.
+static mut TRACE_Y: [[u8; TRACE_COLUMNS]; TRACE_MAX] = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+static mut TRACE_SPANS: [[Option<(u8, u8)>; TRACE_COLUMNS]; TRACE_MAX] =
+    [[None; TRACE_COLUMNS]; TRACE_MAX];
+static mut TRACE_COLOR: [u16; TRACE_MAX] = [0; TRACE_MAX];
+static mut TRACE_GRADED: bool = false; // whether the traces were drawn with intensity grading
+
+// Intensity grading mimics an analog scope, whose beam lights the steep parts of a trace more
+// dimly than the flat parts, since it spends less time on each point of them: each column of a
+// connected trace is drawn in a shade of its color which depends on how many rows it spans.
+static mut TRACE_GRADING: bool = false;
+
+/// Turns intensity grading of the traces drawn by `st7735_draw_trace` and the like on or off.
+/// Envelopes aren't graded, since their columns' heights aren't slopes. This takes effect as the
+/// traces are next drawn.
+pub fn st7735_set_trace_grading(enabled: bool) {
+    unsafe { TRACE_GRADING = enabled; }
+}
+
+/// Returns whether intensity grading is on.
+pub fn st7735_trace_grading() -> bool {
+    unsafe { TRACE_GRADING }
+}
+
+/// How `st7735_draw_trace_multi` draws more samples than there are columns: the samples are
+/// divided evenly among the columns, and each column shows its share of them in one of these
+/// ways.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Decimation {
+    Drop,     // the first of them, dropping the rest: the fastest
+    Average,  // their mean, which smooths out noise, but also narrow glitches
+    Envelope, // lit from the lowest of them to the highest: the best at catching glitches
+}
+
+/// Draws `samples` as a connected trace across the screen, one sample per column, with each
+/// column's vertical line segment joining its sample to the previous column's. The 12-bit sample
+/// range is scaled to the screen height (0 at the bottom), magnified by the gain of `scale` about
+/// mid-scale, then moved up by `y_offset` pixels. Samples which fall off the screen saturate at
+/// its top or bottom row. If there are more samples than columns, samples are decimated to fit,
+/// as by `Decimation::Drop`.
+///
+/// The previously drawn trace is erased as this one is drawn, by repainting the background only
+/// where the old trace's pixels are not also in the new one, and only pixels not already lit are
+/// drawn, so there's no need to clear the screen between sweeps.
+pub fn st7735_draw_trace(samples: &[u16], scale: &VerticalScale, y_offset: i16,
+                         color: St7735Color) {
+    st7735_draw_trace_multi(&[(samples, color, y_offset)], scale, Decimation::Drop);
+}
+
+/// Like `st7735_draw_trace`, but draws up to `TRACE_MAX` traces at once, each given as a tuple of
+/// (samples, color, y_offset), decimated to fit the columns as `decimation` says (as envelopes,
+/// with `Decimation::Envelope`, like `st7735_draw_envelope`). Where traces cross, later ones are
+/// drawn over earlier ones. All the previously drawn traces are erased, including any beyond the
+/// number drawn this time, but a pixel is only repainted if its color changes, so one trace's
+/// pixels are never erased just because another trace also passed through them.
+pub fn st7735_draw_trace_multi(channels: &[(&[u16], St7735Color, i16)], scale: &VerticalScale,
+                               decimation: Decimation) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let mut new_y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+    let mut new_spans = [[None; TRACE_COLUMNS]; TRACE_MAX];
+    let mut new_colors = unsafe { TRACE_COLOR };
+    for t in 0..core::cmp::min(channels.len(), TRACE_MAX) {
+        let (samples, color, y_offset) = channels[t];
+        new_colors[t] = color as u16;
+        if decimation == Decimation::Envelope {
+            envelope_columns(samples, scale, y_offset, width, &mut new_y[t], &mut new_spans[t]);
+        } else {
+            trace_columns(samples, scale, y_offset, width, decimation, &mut new_y[t]);
+        }
+    }
+    if decimation == Decimation::Envelope {
+        // (envelopes aren't graded, since their columns' heights aren't slopes)
+        trace_redraw(&new_y, &new_spans, &new_colors, false);
+    } else {
+        trace_redraw(&new_y, &trace_spans(&new_y), &new_colors, st7735_trace_grading());
+    }
+}
+
+// Fill in the trace y coordinate of each of the first `width` columns of a trace of `samples`,
+// decimated as `decimation` says (dropping or averaging), leaving any columns after the last
+// sample alone.
+fn trace_columns(samples: &[u16], scale: &VerticalScale, y_offset: i16, width: usize,
+                 decimation: Decimation, y: &mut [u8; TRACE_COLUMNS]) {
+    let columns = core::cmp::min(samples.len(), width);
+    for x in 0..columns {
+        let start = x * samples.len() / columns;
+        let sample = if decimation == Decimation::Average {
+            let end = (x + 1) * samples.len() / columns;
+            let sum = samples[start..end].iter().fold(0, |sum, &s| sum + s as u32);
+            let n = (end - start) as u32;
+            ((sum + n / 2) / n) as u16
+        } else {
+            samples[start]
+        };
+        y[x] = trace_y(sample, scale, y_offset);
+    }
+}
+
+/// Draws a magnified part of `samples` as a trace across the screen, scaled and erased as by
+/// `st7735_draw_trace` (with no offset): 1/`zoom` of them, centered on sample `center` as far as
+/// the ends of `samples` allow, stretched to the screen width with linear interpolation between
+/// samples, so that the shape of fast edges shows. A `zoom` of 1 shows all of `samples`.
+pub fn st7735_draw_trace_zoomed(samples: &[u16], center: usize, zoom: u8, scale: &VerticalScale,
+                                color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let mut new_y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+    let mut new_colors = unsafe { TRACE_COLOR };
+    new_colors[0] = color as u16;
+    if samples.len() >= 2 && width >= 2 {
+        let (start, shown) = zoom_window(samples.len(), center, zoom);
+        let last = start + shown - 1;
+        for x in 0..width {
+            // the column's position among the samples, in 1/256ths of a sample, with the first
+            // sample shown at the left edge and the last at the right
+            let position = x * (shown - 1) * 256 / (width - 1);
+            let i = start + position / 256;
+            let fraction = (position % 256) as u32;
+            let a = samples[i] as u32;
+            let b = samples[core::cmp::min(i + 1, last)] as u32;
+            let sample = (a * (256 - fraction) + b * fraction + 128) / 256;
+            new_y[0][x] = trace_y(sample as u16, scale, 0);
+        }
+    }
+    trace_redraw(&new_y, &trace_spans(&new_y), &new_colors, st7735_trace_grading());
+}
+
+// the first of the samples shown by st7735_draw_trace_zoomed() from `len` samples, and how many
+// it shows: at least two, and no more than there are
+fn zoom_window(len: usize, center: usize, zoom: u8) -> (usize, usize) {
+    let shown = core::cmp::min(core::cmp::max(len / core::cmp::max(zoom, 1) as usize, 2), len);
+    let start = core::cmp::min(center.saturating_sub(shown / 2), len - shown);
+    (start, shown)
+}
+
+/// Draws `samples` as a min/max envelope across the screen, for peak detection: the samples are
+/// divided evenly among the columns, and each column is lit from the lowest to the highest of
+/// its samples (and of the previous column's last one, so the envelope joins up), so that a
+/// spike only one sample long still shows, however many samples fall into each column. With one
+/// sample per column, this is the same as `st7735_draw_trace`, which it is otherwise scaled and
+/// erased like. (This is `st7735_draw_trace_multi` with `Decimation::Envelope`, for one trace.)
+#[allow(unused)]
+pub fn st7735_draw_envelope(samples: &[u16], scale: &VerticalScale, y_offset: i16,
+                            color: St7735Color) {
+    st7735_draw_trace_multi(&[(samples, color, y_offset)], scale, Decimation::Envelope);
+}
+
+// Fill in the trace y coordinate and span of each of the first `width` columns of an envelope of
+// `samples`, as drawn by st7735_draw_envelope(), leaving any columns after the last sample alone.
+fn envelope_columns(samples: &[u16], scale: &VerticalScale, y_offset: i16, width: usize,
+                    y: &mut [u8; TRACE_COLUMNS], spans: &mut [Option<(u8, u8)>; TRACE_COLUMNS]) {
+    let columns = core::cmp::min(samples.len(), width);
+    let mut start = 0;
+    for x in 0..columns {
+        let end = (x + 1) * samples.len() / columns;
+        let first = if x > 0 { start - 1 } else { 0 }; // (joining the previous column)
+        let mut lowest = samples[first];
+        let mut highest = samples[first];
+        for &sample in samples[first..end].iter() {
+            lowest = core::cmp::min(lowest, sample);
+            highest = core::cmp::max(highest, sample);
+        }
+        // (the highest sample is nearest the top)
+        spans[x] = Some((trace_y(highest, scale, y_offset), trace_y(lowest, scale, y_offset)));
+        y[x] = trace_y(samples[end - 1], scale, y_offset);
+        start = end;
+    }
+}
+
+/// Scrolls the trace drawn by `st7735_draw_trace` one column to the left, and appends `sample` as
+/// its rightmost column, scaled as by `st7735_draw_trace` (with no offset) and drawn in `color`,
+/// like a strip chart recorder. Until the trace fills the screen, it grows in from the right. Any
+/// other traces are erased. As with `st7735_draw_trace`, only the pixels which change are
+/// repainted.
+pub fn st7735_roll_append(sample: u16, scale: &VerticalScale, color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    if width == 0 {
+        return;
+    }
+    let mut new_y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+    let mut new_colors = unsafe { TRACE_COLOR };
+    new_colors[0] = color as u16;
+    unsafe {
+        new_y[0][..width - 1].copy_from_slice(&TRACE_Y[0][1..width]);
+    }
+    new_y[0][width - 1] = trace_y(sample, scale, 0);
+    trace_redraw(&new_y, &trace_spans(&new_y), &new_colors, st7735_trace_grading());
+}
+
+/// Draws `samples` as the leftmost columns of a trace, one sample per column, scaled as by
+/// `st7735_draw_trace` (with no offset) and drawn in `color`, leaving the rest of the previously
+/// drawn trace in place beyond a one-column gap, and erasing any other traces, so that drawing
+/// more of a sweep's samples each time sweeps the new trace in over the old one, as an analog
+/// scope's beam does. As with `st7735_draw_trace`, only the pixels which change are repainted.
+pub fn st7735_draw_trace_partial(samples: &[u16], scale: &VerticalScale, color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let mut new_y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+    let mut new_colors = unsafe { TRACE_COLOR };
+    new_colors[0] = color as u16;
+    unsafe {
+        new_y[0] = TRACE_Y[0];
+    }
+    let columns = core::cmp::min(samples.len(), width);
+    for x in 0..columns {
+        new_y[0][x] = trace_y(samples[x], scale, 0);
+    }
+    if columns < width {
+        new_y[0][columns] = TRACE_NONE; // the gap ahead of the beam
+    }
+    trace_redraw(&new_y, &trace_spans(&new_y), &new_colors, st7735_trace_grading());
+}
+
+// Redraw the traces as lighting `new_spans`, with the y coordinates `new_y`, in `new_colors`,
+// intensity graded if `graded`, repainting only the pixels whose color changes, and record them
+// as drawn.
+fn trace_redraw(new_y: &[[u8; TRACE_COLUMNS]; TRACE_MAX],
+                new_spans: &[[Option<(u8, u8)>; TRACE_COLUMNS]; TRACE_MAX],
+                new_colors: &[u16; TRACE_MAX], graded: bool) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let (old_colors, old_graded) = unsafe { (TRACE_COLOR, TRACE_GRADED) };
+    for x in 0..width {
+        let mut old_column = [None; TRACE_MAX];
+        let mut new_column = [None; TRACE_MAX];
+        for t in 0..TRACE_MAX {
+            old_column[t] = unsafe { TRACE_SPANS[t][x] };
+            new_column[t] = new_spans[t][x];
+        }
+        trace_repaint_column(x as u8, &old_column, &old_colors, old_graded, &new_column,
+                             new_colors, graded);
+    }
+    unsafe {
+        TRACE_Y = *new_y;
+        TRACE_SPANS = *new_spans;
+        TRACE_COLOR = *new_colors;
+        TRACE_GRADED = graded;
+    }
+}
+
+// the spans lit by traces with the y coordinates `y`, each column joined to the previous one
+fn trace_spans(y: &[[u8; TRACE_COLUMNS]; TRACE_MAX])
+               -> [[Option<(u8, u8)>; TRACE_COLUMNS]; TRACE_MAX] {
+    let mut spans = [[None; TRACE_COLUMNS]; TRACE_MAX];
+    for t in 0..TRACE_MAX {
+        let mut previous = TRACE_NONE;
+        for x in 0..TRACE_COLUMNS {
+            spans[t][x] = trace_span(previous, y[t][x]);
+            previous = y[t][x];
+        }
+    }
+    spans
+}
+
+// the screen row of `sample`, as by sample_to_y(), saturated at the top and bottom rows
+fn trace_y(sample: u16, scale: &VerticalScale, y_offset: i16) -> u8 {
+    let y = sample_to_y(sample, scale, y_offset);
+    let height = st7735_get_height() as i16;
+    if y < 0 {
+        0
+    } else if y > height - 1 {
+        (height - 1) as u8
+    } else {
+        y as u8
+    }
+}
+
+// the span of pixels (top, bottom) lit in a column with trace y coordinate `y`, following a column
+// with trace y coordinate `previous`
+fn trace_span(previous: u8, y: u8) -> Option<(u8, u8)> {
+    if y == TRACE_NONE {
+        None
+    } else if previous == TRACE_NONE {
+        Some((y, y))
+    } else if previous < y {
+        Some((previous, y))
+    } else {
+        Some((y, previous))
+    }
+}
+
+// the color of the trace pixel at (`x`, `y`), as last drawn, or `None` if no trace lights it
+fn trace_color_at(x: u8, y: u8) -> Option<u16> {
+    let x = x as usize;
+    if x >= TRACE_COLUMNS {
+        return None;
+    }
+    let mut spans = [None; TRACE_MAX];
+    unsafe {
+        for t in 0..TRACE_MAX {
+            spans[t] = TRACE_SPANS[t][x];
+        }
+        trace_pixel_color(y, &spans, &TRACE_COLOR, TRACE_GRADED)
+    }
+}
+
+// the color of the pixel in row `y` of a column where the traces light `spans` in `colors`,
+// intensity graded if `graded`, or `None` if no trace lights it; later traces are drawn over
+// earlier ones
+fn trace_pixel_color(y: u8, spans: &[Option<(u8, u8)>; TRACE_MAX], colors: &[u16; TRACE_MAX],
+                     graded: bool) -> Option<u16> {
+    let mut color = None;
+    for t in 0..TRACE_MAX {
+        if let Some((top, bottom)) = spans[t] {
+            if y >= top && y <= bottom {
+                color = Some(if graded {
+                    graded_color(colors[t], bottom - top)
+                } else {
+                    colors[t]
+                });
+            }
+        }
+    }
+    color
+}
+
+// `color` shaded for a column of a trace which spans `rows` rows below its top one: full
+// intensity where the trace is nearly flat, down to 3/8 where it is steepest
+fn graded_color(color: u16, rows: u8) -> u16 {
+    let half = (color >> 1) & 0x7bef;
+    let quarter = (color >> 2) & 0x39e7;
+    let eighth = (color >> 3) & 0x18e3;
+    if rows < 2 {
+        color
+    } else if rows < 6 {
+        half + quarter
+    } else if rows < 16 {
+        half
+    } else {
+        quarter + eighth
+    }
+}
+
+// repaint the pixels of column `x` whose color changes from that of the old traces to that of the
+// new ones, in runs of consecutive changed pixels
+fn trace_repaint_column(x: u8, old_spans: &[Option<(u8, u8)>; TRACE_MAX],
+                        old_colors: &[u16; TRACE_MAX], old_graded: bool,
+                        new_spans: &[Option<(u8, u8)>; TRACE_MAX], new_colors: &[u16; TRACE_MAX],
+                        new_graded: bool) {
+    // only rows within one of the spans can change
+    let mut top = TRACE_NONE;
+    let mut bottom = 0;
+    for span in old_spans.iter().chain(new_spans.iter()) {
+        if let Some((t, b)) = *span {
+            top = core::cmp::min(top, t);
+            bottom = core::cmp::max(bottom, b);
+        }
+    }
+    if top == TRACE_NONE {
+        return;
+    }
+    let mut run_top = None;
+    for y in top as u16..bottom as u16 + 2 {
+        let changed = y <= bottom as u16 &&
+                      trace_pixel_color(y as u8, old_spans, old_colors, old_graded) !=
+                      trace_pixel_color(y as u8, new_spans, new_colors, new_graded);
+        match (changed, run_top) {
+            (true, None) => run_top = Some(y as u8),
+            (false, Some(run)) => {
+                trace_paint(x, run, y as u8 - 1, new_spans, new_colors, new_graded);
+                run_top = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+// paint rows `top` through `bottom` of column `x` with the traces lighting `spans` in `colors`,
+// intensity graded if `graded`, or with the background where there are none
+fn trace_paint(x: u8, top: u8, bottom: u8, spans: &[Option<(u8, u8)>; TRACE_MAX],
+               colors: &[u16; TRACE_MAX], graded: bool) {
+    st7735_setAddrWindow(x, top, x, bottom);
+    for y in top..bottom + 1 {
+        match trace_pixel_color(y, spans, colors, graded) {
+            Some(c) => st7735_send_color(c),
+            None => st7735_send_color(st7735_background_color(x, y)),
+        }
+    }
+}
+
+// ======== sin(x)/x interpolation ========
+
+// Linear interpolation between samples turns a sine wave with only a few samples per cycle into a
+// triangle wave. For a signal with nothing above half the sample rate, sin(x)/x interpolation
+// reconstructs what was between the samples instead. It is approximated here by a Lanczos kernel
+// (sin(x)/x windowed by a wider sin(x)/x) six samples wide, tabulated at 16 positions between
+// samples, in Q2.14 fixed point. A signal with sharp edges rings where it is drawn this way.
+
+static mut TRACE_SINC: bool = false; // whether magnified traces are drawn with sin(x)/x
+
+/// Sets whether the magnified trace is drawn with sin(x)/x interpolation, by
+/// `st7735_draw_trace_sinc`, rather than linear interpolation.
+pub fn st7735_set_sinc_interpolation(enabled: bool) {
+    unsafe { TRACE_SINC = enabled; }
+}
+
+/// Returns whether sin(x)/x interpolation is on.
+pub fn st7735_sinc_interpolation() -> bool {
+    unsafe { TRACE_SINC }
+}
+
+// the kernel's weights for the samples from two before to three after the one at or before the
+// position, for each 1/16th of a sample it is past that one
+const SINC_PHASES: usize = 16;
+const SINC_KERNEL: [[i32; 6]; SINC_PHASES] = [
+    [     0,      0,  16384,      0,      0,      0],
+    [   190,   -772,  16272,    919,   -232,      7],
+    [   335,  -1393,  15936,   1977,   -501,     30],
+    [   436,  -1862,  15384,   3157,   -799,     68],
+    [   493,  -2184,  14628,   4440,  -1114,    121],
+    [   513,  -2367,  13685,   5800,  -1432,    185],
+    [   499,  -2425,  12583,   7206,  -1736,    257],
+    [   459,  -2372,  11349,   8624,  -2007,    331],
+    [   401,  -2226,  10017,  10017,  -2226,    401],
+    [   331,  -2007,   8624,  11349,  -2372,    459],
+    [   257,  -1736,   7206,  12583,  -2425,    499],
+    [   185,  -1432,   5799,  13686,  -2367,    513],
+    [   121,  -1114,   4441,  14627,  -2184,    493],
+    [    68,   -799,   3158,  15383,  -1862,    436],
+    [    30,   -501,   1977,  15936,  -1393,    335],
+    [     7,   -232,    919,  16272,   -772,    190],
+];
+
+/// Like `st7735_draw_trace_zoomed`, but interpolating between samples with sin(x)/x rather than
+/// linearly, so that a band-limited signal with only a few samples per cycle looks smooth. This
+/// takes several times as long. Samples beyond those shown, as far as the ends of `samples`, are
+/// used for the interpolation too.
+pub fn st7735_draw_trace_sinc(samples: &[u16], center: usize, zoom: u8, scale: &VerticalScale,
+                              color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let mut new_y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+    let mut new_colors = unsafe { TRACE_COLOR };
+    new_colors[0] = color as u16;
+    if samples.len() >= 2 && width >= 2 {
+        let (start, shown) = zoom_window(samples.len(), center, zoom);
+        let last = samples.len() as i32 - 1;
+        for x in 0..width {
+            // the column's position among the samples, in 1/16ths of a sample, rounded
+            let position = (x * (shown - 1) * SINC_PHASES * 2 / (width - 1) + 1) / 2;
+            let i = (start + position / SINC_PHASES) as i32;
+            let weights = &SINC_KERNEL[position % SINC_PHASES];
+            let mut sum = 0i32;
+            for (k, &weight) in weights.iter().enumerate() {
+                let j = core::cmp::max(0, core::cmp::min(i + k as i32 - 2, last)) as usize;
+                sum += samples[j] as i32 * weight;
+            }
+            // (the kernel's negative lobes can overshoot the ADC range)
+            let sample = core::cmp::max(0, core::cmp::min((sum + (1 << 13)) >> 14, 4095));
+            new_y[0][x] = trace_y(sample as u16, scale, 0);
+        }
+    }
+    trace_redraw(&new_y, &trace_spans(&new_y), &new_colors, st7735_trace_grading());
+}
+
+// ======== persistence display ========
+
+// Like the phosphor of an analog scope, each pixel of the persistence display glows at an
+// intensity from 0 (dark) to PERSIST_LEVELS, set to full when a trace passes through it, and
+// fading one step at a time afterward. Two bits per pixel, for the largest screen: 5kB.
+const PERSIST_PIXELS: usize = ST7735_TFTWIDTH as usize * ST7735_TFTHEIGHT as usize;
+const PERSIST_BYTES: usize = PERSIST_PIXELS / 4;
+const PERSIST_LEVELS: u8 = 3;
+
+static mut PERSIST_INTENSITY: [u8; PERSIST_BYTES] = [0; PERSIST_BYTES];
+static mut PERSIST_ACTIVE: bool = false; // whether any pixel might be glowing
+static mut PERSIST_COLOR: u16 = 0;
+static mut PERSIST_DECAY: u8 = 1; // sweeps per intensity step, or 0 for no fading
+static mut PERSIST_SWEEPS: u8 = 0; // since the last step
+
+/// Sets how quickly the persistence display fades: by one intensity step every `sweeps` calls to
+/// `st7735_persist_update`, so that a trace disappears after three times that many, or never, if
+/// `sweeps` is 0.
+pub fn st7735_persist_set_decay(sweeps: u8) {
+    unsafe {
+        PERSIST_DECAY = sweeps;
+        PERSIST_SWEEPS = 0;
+    }
+}
+
+/// Adds `samples` to the persistence display, as an envelope across the screen, scaled as by
+/// `st7735_draw_envelope` (with no offset), at full intensity in `color`, after first fading what
+/// was already there if it's due. The display glows in one color, which this changes for the
+/// older traces too. Only pixels whose intensity changes are repainted, and this draws nothing
+/// else, so any ordinary trace should be erased before persistence is used.
+pub fn st7735_persist_update(samples: &[u16], scale: &VerticalScale, color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let height = st7735_get_height() as usize;
+    let mut new_y = [TRACE_NONE; TRACE_COLUMNS];
+    let mut spans = [None; TRACE_COLUMNS];
+    envelope_columns(samples, scale, 0, width, &mut new_y, &mut spans);
+    unsafe {
+        let fade = PERSIST_DECAY != 0 && {
+            PERSIST_SWEEPS += 1;
+            PERSIST_SWEEPS >= PERSIST_DECAY
+        };
+        if fade {
+            PERSIST_SWEEPS = 0;
+        }
+        let recolor = PERSIST_COLOR != color as u16;
+        PERSIST_COLOR = color as u16;
+        for x in 0..width {
+            // (unless pixels are fading or changing color, only the new trace's can change)
+            let (top, bottom) = match spans[x] {
+                _ if fade || recolor => (0, height - 1),
+                Some((top, bottom)) => (top as usize, bottom as usize),
+                None => continue,
+            };
+            for y in top..bottom + 1 {
+                let lit = match spans[x] {
+                    Some((t, b)) => y >= t as usize && y <= b as usize,
+                    None => false,
+                };
+                let i = y * width + x;
+                let old = persist_level(i);
+                let new = if lit {
+                    PERSIST_LEVELS
+                } else if fade && old > 0 {
+                    old - 1
+                } else {
+                    old
+                };
+                if new != old || (recolor && new > 0) {
+                    persist_set_level(i, new);
+                    let c = match persist_color(new) {
+                        Some(c) => c,
+                        None => st7735_background_color(x as u8, y as u8),
+                    };
+                    st7735_drawPixel(x as i16, y as i16, c);
+                }
+            }
+        }
+        PERSIST_ACTIVE = true;
+    }
+}
+
+/// Erases the persistence display, repainting the background wherever it glows.
+pub fn st7735_persist_clear() {
+    unsafe {
+        if !PERSIST_ACTIVE {
+            return;
+        }
+        let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+        let height = st7735_get_height() as usize;
+        for y in 0..height {
+            for x in 0..width {
+                if persist_level(y * width + x) > 0 {
+                    persist_set_level(y * width + x, 0);
+                    st7735_drawPixel(x as i16, y as i16,
+                                     st7735_background_color(x as u8, y as u8));
+                }
+            }
+        }
+        PERSIST_ACTIVE = false;
+        PERSIST_SWEEPS = 0;
+    }
+}
+
+// the intensity of pixel `i`, numbered across the rows from the top left
+fn persist_level(i: usize) -> u8 {
+    unsafe { (PERSIST_INTENSITY[i / 4] >> (i % 4 * 2)) & 0b11 }
+}
+
+fn persist_set_level(i: usize, level: u8) {
+    let shift = i % 4 * 2;
+    unsafe {
+        PERSIST_INTENSITY[i / 4] = (PERSIST_INTENSITY[i / 4] & !(0b11 << shift)) | (level << shift);
+    }
+}
+
+// the color of a persistence display pixel glowing at `level`, or `None` if it's dark: full,
+// half, or quarter intensity
+fn persist_color(level: u8) -> Option<u16> {
+    let color = unsafe { PERSIST_COLOR };
+    match level {
+        0 => None,
+        1 => Some((color >> 2) & 0x39e7),
+        2 => Some((color >> 1) & 0x7bef),
+        _ => Some(color),
+    }
+}
+
+// the color of the persistence display at (`x`, `y`), or `None` if it's dark there
+fn persist_color_at(x: u8, y: u8) -> Option<u16> {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    if x as usize >= width || y >= st7735_get_height() {
+        return None;
+    }
+    persist_color(persist_level(y as usize * width + x as usize))
+}
+
+// ======== XY display ========
+
+/// The most points `st7735_draw_xy` plots: one per column of the widest screen.
+pub const XY_POINTS_MAX: usize = DISPLAY_WIDTH;
+
+// The points last drawn by st7735_draw_xy(), so they can be erased before the next ones are drawn.
+static mut XY_POINTS: [(u8, u8); XY_POINTS_MAX] = [(0, 0); XY_POINTS_MAX];
+static mut XY_POINT_COUNT: usize = 0;
+
+/// Plots each pair of samples from `ch_a` and `ch_b` as a single point, with the channel A sample
+/// giving its x coordinate (0 at the left) and the channel B sample its y coordinate (0 at the
+/// bottom), each 12-bit range scaled to fill the screen. Up to `XY_POINTS_MAX` are plotted; extra
+/// samples, or samples in the longer slice without a partner, are ignored. The points previously
+/// drawn by this are erased first, by repainting the background under them.
+pub fn st7735_draw_xy(ch_a: &[u16], ch_b: &[u16], color: St7735Color) {
+    let width = st7735_get_width() as u32;
+    let full_scale = VerticalScale::new(); // 1x
+    unsafe {
+        for &(x, y) in XY_POINTS[..XY_POINT_COUNT].iter() {
+            st7735_drawPixel(x as i16, y as i16, st7735_background_color(x, y));
+        }
+        let count = core::cmp::min(core::cmp::min(ch_a.len(), ch_b.len()), XY_POINTS_MAX);
+        for i in 0..count {
+            let x = ((core::cmp::min(ch_a[i], 4095) as u32 * width) >> 12) as u8;
+            let y = trace_y(ch_b[i], &full_scale, 0);
+            st7735_drawPixel(x as i16, y as i16, color as u16);
+            XY_POINTS[i] = (x, y);
+        }
+        XY_POINT_COUNT = count;
+    }
+}
+
+// ======== spectrum display ========
+
+// The height of each bar last drawn by st7735_draw_spectrum(), and the row they stood on, so they
+// can be erased as the next ones are drawn.
+static mut SPECTRUM_HEIGHTS: [u8; TRACE_COLUMNS] = [0; TRACE_COLUMNS];
+static mut SPECTRUM_BASELINE: u8 = 0;
+
+/// Draws `heights` as a bar graph, one bar per column from the left edge of the screen, each
+/// `height` pixels tall and standing on row `baseline`. The bars previously drawn are erased as
+/// these are drawn, by repainting the background only where a bar has shrunk, and only the part
+/// of a bar which has grown is drawn. Drawing no bars just erases them.
+pub fn st7735_draw_spectrum(heights: &[u8], baseline: u8, color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let baseline = core::cmp::min(baseline, st7735_get_height() - 1);
+    unsafe {
+        if baseline != SPECTRUM_BASELINE {
+            // (the old bars stood somewhere else, so erase them all)
+            for x in 0..width {
+                let old = SPECTRUM_HEIGHTS[x];
+                if old > 0 {
+                    spectrum_paint(x as u8, SPECTRUM_BASELINE + 1 - old, SPECTRUM_BASELINE, None);
+                }
+            }
+            SPECTRUM_HEIGHTS = [0; TRACE_COLUMNS];
+            SPECTRUM_BASELINE = baseline;
+        }
+        for x in 0..width {
+            let old = SPECTRUM_HEIGHTS[x];
+            let new = if x < heights.len() { core::cmp::min(heights[x], baseline + 1) } else { 0 };
+            if new > old {
+                spectrum_paint(x as u8, baseline + 1 - new, baseline - old, Some(color as u16));
+            } else if new < old {
+                spectrum_paint(x as u8, baseline + 1 - old, baseline - new, None);
+            }
+            SPECTRUM_HEIGHTS[x] = new;
+        }
+    }
+}
+
+// paint rows `top` through `bottom` of column `x` in `color`, or with the background if none
+fn spectrum_paint(x: u8, top: u8, bottom: u8, color: Option<u16>) {
+    st7735_setAddrWindow(x, top, x, bottom);
+    for y in top..bottom + 1 {
+        match color {
+            Some(c) => st7735_send_color(c),
+            None => st7735_send_color(st7735_background_color(x, y)),
+        }
+    }
+}
+
+// ======== bargraph ========
+
+// the bargraph as last drawn: (top, height, length)
+static mut BARGRAPH: Option<(u8, u8, u8)> = None;
+
+/// Draws a horizontal bar, like a multimeter's bargraph, lit `length` pixels from the left edge of
+/// the screen, and `height` rows high from row `top`, in `color`. Only the pixels which change from
+/// the bar last drawn in the same place are repainted, so it can move every sweep without
+/// flickering. A `length` of 0 erases it.
+pub fn st7735_draw_bargraph(top: u8, height: u8, length: u8, color: St7735Color) {
+    let length = core::cmp::min(length, st7735_get_width());
+    let old = match unsafe { BARGRAPH } {
+        Some((t, h, l)) if t == top && h == height => l,
+        Some((t, h, l)) => {
+            st7735_paint_background_rect(0, t, l, h);
+            0
+        }
+        None => 0,
+    };
+    if length > old {
+        st7735_fill_rect(old as i16, top as i16, (length - old) as i16, height as i16,
+                         color as u16);
+    } else if length < old {
+        st7735_paint_background_rect(length, top, old - length, height);
+    }
+    unsafe {
+        BARGRAPH = if length > 0 { Some((top, height, length)) } else { None };
+    }
+}
+
+// ======== cursors ========
+
+// The measurement cursors, as last drawn by st7735_draw_cursors(): the columns of the two time
+// cursors, the rows of the two voltage cursors, and their color. They are drawn as part of the
+// background, so the trace passes over them, and erasing the trace restores them.
+static mut CURSOR_LINES: Option<([u8; 2], [u8; 2], u16)> = None;
+
+// the cursor color of the pixel at (`x`, `y`), if it is on a dash of one of the cursors
+fn cursor_color(x: u8, y: u8) -> Option<u16> {
+    match unsafe { CURSOR_LINES } {
+        Some((columns, rows, color)) => {
+            if ((x == columns[0] || x == columns[1]) && y % 4 < 2) ||
+               ((y == rows[0] || y == rows[1]) && x % 4 < 2) {
+                Some(color)
+            } else {
+                None
+            }
+        }
+        None => None,
+    }
+}
+
+/// Draws `cursors` as dashed lines in `color`, the time cursors vertical and the voltage cursors
+/// horizontal, erasing the previously drawn ones, or just erases them if `cursors` is `None`. The
+/// cursors are drawn under the trace, and are preserved as it is redrawn.
+pub fn st7735_draw_cursors(cursors: Option<&Cursors>, color: St7735Color) {
+    let old = unsafe { CURSOR_LINES };
+    unsafe {
+        CURSOR_LINES = cursors.map(|c| {
+            ([c.position(Cursor::Time1), c.position(Cursor::Time2)],
+             [c.position(Cursor::Volts1), c.position(Cursor::Volts2)],
+             color as u16)
+        });
+    }
+    // repaint every line which was or is now a cursor
+    for lines in [old, unsafe { CURSOR_LINES }].iter() {
+        if let Some((columns, rows, _)) = *lines {
+            for &x in columns.iter() {
+                overlay_repaint(x, 0, x, st7735_get_height() - 1);
+            }
+            for &y in rows.iter() {
+                overlay_repaint(0, y, st7735_get_width() - 1, y);
+            }
+        }
+    }
+}
+
+// repaint the rectangle of pixels from (`x0`, `y0`) to (`x1`, `y1`) with the trace where it is
+// lit, or the persistence display where it glows, and otherwise the background, including any
+// cursors and trigger marker
+fn overlay_repaint(x0: u8, y0: u8, x1: u8, y1: u8) {
+    if x1 >= st7735_get_width() || y1 >= st7735_get_height() {
+        return;
+    }
+    st7735_setAddrWindow(x0, y0, x1, y1);
+    for y in y0..y1 + 1 {
+        for x in x0..x1 + 1 {
+            match trace_color_at(x, y).or_else(|| persist_color_at(x, y)) {
+                Some(c) => st7735_send_color(c),
+                None => st7735_send_color(st7735_background_color(x, y)),
+            }
+        }
+    }
+}
+
+// ======== reference trace ========
+
+// The reference trace, as last drawn by st7735_draw_ref(): the span of pixels (top, bottom) it
+// lights in each column, and its color. Like the cursors, it is drawn as part of the background,
+// under the cursors, trigger marker, and minimap.
+static mut REF_SPANS: [Option<(u8, u8)>; TRACE_COLUMNS] = [None; TRACE_COLUMNS];
+static mut REF_COLOR: u16 = 0;
+
+// the reference trace color of the pixel at (`x`, `y`), if the reference lights it
+fn ref_color(x: u8, y: u8) -> Option<u16> {
+    if x as usize >= TRACE_COLUMNS {
+        return None;
+    }
+    match unsafe { REF_SPANS[x as usize] } {
+        Some((top, bottom)) if y >= top && y <= bottom => Some(unsafe { REF_COLOR }),
+        _ => None,
+    }
+}
+
+/// Draws the reference `samples` as a trace in `color` at half intensity, scaled as by
+/// `st7735_draw_trace`, erasing the previously drawn reference, or just erases it if `samples` is
+/// `None`. The reference is drawn under the trace, and is preserved as it is redrawn.
+pub fn st7735_draw_ref(samples: Option<&[u16]>, scale: &VerticalScale, y_offset: i16,
+                       color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let mut y = [[TRACE_NONE; TRACE_COLUMNS]; TRACE_MAX];
+    if let Some(samples) = samples {
+        let columns = core::cmp::min(samples.len(), width);
+        for x in 0..columns {
+            y[0][x] = trace_y(samples[x * samples.len() / columns], scale, y_offset);
+        }
+    }
+    let new_spans = trace_spans(&y)[0];
+    let old_spans = unsafe { REF_SPANS };
+    unsafe {
+        REF_SPANS = new_spans;
+        REF_COLOR = ((color as u16) >> 1) & 0x7bef; // half intensity
+    }
+    // repaint every column the reference was or is now in
+    for x in 0..width {
+        for spans in [old_spans[x], new_spans[x]].iter() {
+            if let Some((top, bottom)) = *spans {
+                overlay_repaint(x as u8, top, x as u8, bottom);
+            }
+        }
+    }
+}
+
+// ======== envelope band ========
+
+// The envelope band, as last drawn by st7735_draw_band(): the span of pixels (top, bottom) it
+// fills in each column, and its color. Like the reference trace, it is drawn as part of the
+// background, under the reference.
+static mut BAND_SPANS: [Option<(u8, u8)>; TRACE_COLUMNS] = [None; TRACE_COLUMNS];
+static mut BAND_COLOR: u16 = 0;
+
+// the envelope band color of the pixel at (`x`, `y`), if the band fills it
+fn band_color(x: u8, y: u8) -> Option<u16> {
+    if x as usize >= TRACE_COLUMNS {
+        return None;
+    }
+    match unsafe { BAND_SPANS[x as usize] } {
+        Some((top, bottom)) if y >= top && y <= bottom => Some(unsafe { BAND_COLOR }),
+        _ => None,
+    }
+}
+
+/// Draws the band `lowest` to `highest`, a column filled from each of the highest samples down to
+/// the lowest, in `color` at quarter intensity, scaled as by `st7735_draw_trace`, or just erases
+/// the band if `band` is `None`. Only the columns which change are repainted (all of them, if the
+/// color does). The band is drawn under the reference and the trace, and is preserved as they are
+/// redrawn.
+pub fn st7735_draw_band(band: Option<(&[u16], &[u16])>, scale: &VerticalScale, y_offset: i16,
+                        color: St7735Color) {
+    let width = core::cmp::min(st7735_get_width() as usize, TRACE_COLUMNS);
+    let mut new_spans = [None; TRACE_COLUMNS];
+    if let Some((lowest, highest)) = band {
+        let columns = core::cmp::min(core::cmp::min(lowest.len(), highest.len()), width);
+        for x in 0..columns {
+            // (the highest sample is nearest the top)
+            new_spans[x] = Some((trace_y(highest[x], scale, y_offset),
+                                 trace_y(lowest[x], scale, y_offset)));
+        }
+    }
+    let old_spans = unsafe { BAND_SPANS };
+    let new_color = ((color as u16) >> 2) & 0x39e7; // quarter intensity
+    let recolor = unsafe { BAND_COLOR } != new_color;
+    unsafe {
+        BAND_SPANS = new_spans;
+        BAND_COLOR = new_color;
+    }
+    // repaint the rows of each changed column which the band was or is now in
+    for x in 0..width {
+        if old_spans[x] == new_spans[x] && !recolor {
+            continue;
+        }
+        let rows = match (old_spans[x], new_spans[x]) {
+            (Some((t0, b0)), Some((t1, b1))) => {
+                Some((core::cmp::min(t0, t1), core::cmp::max(b0, b1)))
+            }
+            (Some(span), None) | (None, Some(span)) => Some(span),
+            (None, None) => None,
+        };
+        if let Some((top, bottom)) = rows {
+            overlay_repaint(x as u8, top, x as u8, bottom);
+        }
+    }
+}
+
+// ======== trigger marker ========
+
+// The trigger marker, as last drawn by st7735_draw_trigger_marker(): a left-pointing arrowhead at
+// the right edge of the screen, on the trigger level's row, and an arrow pointing in the direction
+// of the trigger edge at the trigger point itself. Like the cursors, it is drawn as part of the
+// background.
+#[derive(Clone, Copy)]
+struct TriggerMarker {
+    row: u8,
+    column: u8,
+    edge: Edge,
+    color: u16,
+}
+
+static mut TRIGGER_MARKER: Option<TriggerMarker> = None;
+
+const TRIGGER_MARKER_SIZE: u8 = 4; // the arrows' half-height, and the arrowhead's width - 1
+
+// the trigger marker color of the pixel at (`x`, `y`), if it is part of the marker
+fn trigger_marker_color(x: u8, y: u8) -> Option<u16> {
+    let m = match unsafe { TRIGGER_MARKER } {
+        Some(m) => m,
+        None => return None,
+    };
+    let size = TRIGGER_MARKER_SIZE;
+    let dy = distance(y, m.row);
+    if dy > size {
+        return None;
+    }
+    // the level arrowhead, widening to the right from its point
+    let point = st7735_get_width() - 1 - size;
+    if x >= point && dy <= x - point {
+        return Some(m.color);
+    }
+    // the edge arrow: a shaft, with a head at the top for a rising edge, or the bottom for falling
+    let dx = distance(x, m.column);
+    let head = match m.edge {
+        Edge::Rising => y < m.row && size - dy >= dx,
+        Edge::Falling => y > m.row && size - dy >= dx,
+    };
+    if dx == 0 || (head && dy >= size - 2) {
+        Some(m.color)
+    } else {
+        None
+    }
+}
+
+/// Draws the trigger marker in `color`, for a trigger on `edge` at `level` counts, at vertical
+/// scale `scale` and offset `offset` (as for the trace), with the trigger point at screen column
+/// `column`, erasing the previously drawn marker. Like the cursors, the marker is drawn under the
+/// trace, and is preserved as it is redrawn. A level beyond the top or bottom of the screen is
+/// marked at that edge.
+pub fn st7735_draw_trigger_marker(level: u16, edge: Edge, column: u8, scale: &VerticalScale,
+                                  offset: i16, color: St7735Color)
+{
+    let bottom = st7735_get_height() as i16 - 1;
+    let row = core::cmp::max(0, core::cmp::min(sample_to_y(level, scale, offset), bottom)) as u8;
+    let column = core::cmp::min(column, st7735_get_width() - 1);
+    trigger_marker_replace(Some(TriggerMarker {
+        row: row,
+        column: column,
+        edge: edge,
+        color: color as u16,
+    }));
+}
+
+/// Erases the trigger marker, if it is drawn.
+pub fn st7735_erase_trigger_marker() {
+    trigger_marker_replace(None);
+}
+
+// replace the trigger marker with `marker`, repainting the areas covered by both
+fn trigger_marker_replace(marker: Option<TriggerMarker>) {
+    let old = unsafe { TRIGGER_MARKER };
+    unsafe {
+        TRIGGER_MARKER = marker;
+    }
+    let size = TRIGGER_MARKER_SIZE;
+    let right = st7735_get_width() - 1;
+    let bottom = st7735_get_height() - 1;
+    for m in [old, marker].iter() {
+        if let Some(m) = *m {
+            let top = m.row.saturating_sub(size);
+            let lowest = core::cmp::min(m.row.saturating_add(size), bottom);
+            overlay_repaint(right - size, top, right, lowest);
+            overlay_repaint(m.column.saturating_sub(size), top,
+                            core::cmp::min(m.column.saturating_add(size), right), lowest);
+        }
+    }
+}
+
+// ======== record minimap ========
+
+// The minimap, as last drawn by st7735_draw_minimap(): a bar across the screen, just above the
+// bottom line of text, standing for a whole record, with the columns from `left` to `right` filled
+// in for the part of it displayed, and a half-intensity line along its middle for the rest. Like
+// the cursors, it is drawn as part of the background.
+#[derive(Clone, Copy)]
+struct Minimap {
+    left: u8,
+    right: u8,
+    color: u16,
+}
+
+static mut MINIMAP: Option<Minimap> = None;
+
+const MINIMAP_HEIGHT: u8 = 3;
+
+// the top row of the minimap, leaving a row between it and the bottom line of text
+fn minimap_top() -> u8 {
+    st7735_get_height() - 12 - 1 - MINIMAP_HEIGHT
+}
+
+// the minimap color of the pixel at (`x`, `y`), if it is part of the minimap
+fn minimap_color(x: u8, y: u8) -> Option<u16> {
+    let m = match unsafe { MINIMAP } {
+        Some(m) => m,
+        None => return None,
+    };
+    let top = minimap_top();
+    if y < top || y >= top + MINIMAP_HEIGHT {
+        None
+    } else if x >= m.left && x <= m.right {
+        Some(m.color)
+    } else if y == top + MINIMAP_HEIGHT / 2 {
+        Some((m.color >> 1) & 0x7bef) // half intensity
+    } else {
+        None
+    }
+}
+
+/// Draws the minimap in `color`, for a record of `total` samples of which the `window` samples
+/// from `offset` on are displayed, erasing the previously drawn minimap. Like the cursors, the
+/// minimap is drawn under the trace, and is preserved as it is redrawn.
+pub fn st7735_draw_minimap(total: usize, offset: usize, window: usize, color: St7735Color) {
+    let width = st7735_get_width() as usize;
+    let total = core::cmp::max(total, 1);
+    let left = core::cmp::min(offset * width / total, width - 1);
+    let end = core::cmp::min((offset + window) * width / total, width);
+    minimap_replace(Some(Minimap {
+        left: left as u8,
+        right: (core::cmp::max(end, left + 1) - 1) as u8,
+        color: color as u16,
+    }));
+}
+
+/// Erases the minimap, if it is drawn.
+pub fn st7735_erase_minimap() {
+    minimap_replace(None);
+}
+
+// replace the minimap with `minimap`, repainting its rows if either is drawn
+fn minimap_replace(minimap: Option<Minimap>) {
+    let old = unsafe { MINIMAP };
+    unsafe {
+        MINIMAP = minimap;
+    }
+    if old.is_some() || minimap.is_some() {
+        let top = minimap_top();
+        overlay_repaint(0, top, st7735_get_width() - 1, top + MINIMAP_HEIGHT - 1);
+    }
+}
+
+// ======== text printing ========
+
+fn st7735_putc_unchecked(x: u8, y: u8, c: u8, fg: St7735Color, bg: St7735Color) {
+    if c >= 128 {
+        return;
+    }
+    st7735_setAddrWindow(x, y, x + 7, y + 11);
+    for yrow in 0..12 {
+        let mut bits = parallax_8x12_font::FONT_8X12[(c as usize) * 12 + yrow];
+        for _ in 0..8 {
+            if bits & 0b1 == 0b1 {
+                st7735_send_color(fg as u16);
+            } else {
+                st7735_send_color(bg as u16);
+            }
+            bits >>= 1;
+        }
+    }
+}
+
+#[allow(unused)]
+pub fn st7735_putc(x: u8, y: u8, c: u8, fg: St7735Color, bg: St7735Color) {
+    let height = st7735_get_height();
+    let width = st7735_get_width();
+    if x > width - 8 || y > height - 12 {
+        return;
+    }
+    st7735_putc_unchecked(x, y, c, fg, bg);
+}
+
+#[allow(unused)]
+pub fn st7735_print(text: &[u8], x0: u8, y: u8, fg: St7735Color, bg: St7735Color) {
+    let height = unsafe { st7735_get_height() };
+    let width = unsafe { st7735_get_width() };
+    let mut x = x0;
+    if y > height - 12 {
+        return;
+    }
+    for c in text {
+        if x > width - 8 {
+            return;
+        }
+        st7735_putc_unchecked(x, y, *c, fg, bg);
+        x += 8;
+    }
+}
+
+// ======== scaled text printing ========
+
+// Like st7735_putc_unchecked(), but with each font pixel drawn as a `scale` x `scale` block, and
+// only the top-left `width` x `height` pixels of the enlarged glyph drawn, for clipping.
+fn st7735_putc_scaled_unchecked(x: u8, y: u8, c: u8, scale: u8, width: u8, height: u8,
+                                fg: St7735Color, bg: St7735Color) {
+    if c >= 128 {
+        return;
+    }
+    st7735_setAddrWindow(x, y, x + (width - 1), y + (height - 1));
+    for py in 0..height {
+        let bits = parallax_8x12_font::FONT_8X12[(c as usize) * 12 + (py / scale) as usize];
+        for px in 0..width {
+            if (bits >> (px / scale)) & 0b1 == 0b1 {
+                st7735_send_color(fg as u16);
+            } else {
+                st7735_send_color(bg as u16);
+            }
+        }
+    }
+}
+
+/// Prints `text` at (`x0`, `y`) in the 8x12 font enlarged `scale` times in each direction, by
+/// replicating pixels, so each character cell is 8 * `scale` by 12 * `scale` pixels. Characters
+/// which run off the right or bottom edge of the screen are clipped there.
+#[allow(unused)]
+pub fn st7735_print_scaled(x0: u8, y: u8, text: &[u8], scale: u8, fg: St7735Color,
+                           bg: St7735Color) {
+    let height = st7735_get_height() as u32;
+    let width = st7735_get_width() as u32;
+    if scale == 0 || y as u32 >= height {
+        return;
+    }
+    let cell_width = 8 * scale as u32;
+    let visible_height = core::cmp::min(12 * scale as u32, height - y as u32);
+    let mut x = x0 as u32;
+    for &c in text {
+        if x >= width {
+            return;
+        }
+        let visible_width = core::cmp::min(cell_width, width - x);
+        st7735_putc_scaled_unchecked(x as u8, y, c, scale, visible_width as u8,
+                                     visible_height as u8, fg, bg);
+        x += cell_width;
+    }
+}
+
+// ======== rotated text printing ========
+
+// Like st7735_putc_unchecked(), but with the glyph turned a quarter turn counterclockwise, into a
+// 12 x 8 cell whose bottom-left corner is (`x`, `y`): the font's rows become the cell's columns,
+// sent transposed into the address window.
+#[cfg(feature = "rotated-text")]
+fn st7735_putc_rotated_unchecked(x: u8, y: u8, c: u8, fg: St7735Color, bg: St7735Color) {
+    if c >= 128 {
+        return;
+    }
+    st7735_setAddrWindow(x, y - 7, x + 11, y);
+    for py in 0..8 {
+        // (the glyph's rightmost column is the cell's top row)
+        let column = 7 - py;
+        for px in 0..12 {
+            let bits = parallax_8x12_font::FONT_8X12[(c as usize) * 12 + px];
+            if (bits >> column) & 0b1 == 0b1 {
+                st7735_send_color(fg as u16);
+            } else {
+                st7735_send_color(bg as u16);
+            }
+        }
+    }
+}
+
+/// Prints `text` turned a quarter turn counterclockwise, so that it reads up the screen from
+/// (`x`, `y0`), e.g. as a vertical axis label along the left edge: each character cell is 12
+/// pixels wide and 8 high, with `y0` the bottom row of the first. Characters which would run off
+/// the top of the screen aren't drawn, nor is anything if the cells would run off its right edge.
+/// (Built only with the 'rotated-text' feature, to spare the flash otherwise.)
+#[cfg(feature = "rotated-text")]
+#[allow(unused)]
+pub fn st7735_draw_text_rotated(text: &[u8], x: u8, y0: u8, fg: St7735Color, bg: St7735Color) {
+    let height = st7735_get_height();
+    let width = st7735_get_width();
+    if x > width - 12 || y0 > height - 1 {
+        return;
+    }
+    let mut y = y0 as i16;
+    for &c in text {
+        if y < 7 {
+            return;
+        }
+        st7735_putc_rotated_unchecked(x, y as u8, c, fg, bg);
+        y -= 8;
+    }
+}
+
+// ======== proportional text printing ========
+
+// columns of background left after each proportional glyph
+const PROP_SPACING: u8 = 1;
+
+// The width in pixels that `c` advances a proportional print, including the spacing after it.
+fn prop_advance(c: u8) -> u8 {
+    if c >= 128 {
+        return 0;
+    }
+    parallax_8x12_extents::FONT_8X12_EXTENTS[c as usize].1 + PROP_SPACING
+}
+
+// Like st7735_putc_unchecked(), but draws only the columns `c` spans, followed by the spacing.
+fn st7735_putc_prop_unchecked(x: u8, y: u8, c: u8, fg: St7735Color, bg: St7735Color) {
+    let (first, span) = parallax_8x12_extents::FONT_8X12_EXTENTS[c as usize];
+    st7735_setAddrWindow(x, y, x + span + PROP_SPACING - 1, y + 11);
+    for yrow in 0..12 {
+        let mut bits = parallax_8x12_font::FONT_8X12[(c as usize) * 12 + yrow] >> first;
+        for column in 0..span + PROP_SPACING {
+            if column < span && bits & 0b1 == 0b1 {
+                st7735_send_color(fg as u16);
+            } else {
+                st7735_send_color(bg as u16);
+            }
+            bits >>= 1;
+        }
+    }
+}
+
+/// Prints `text` at (`x0`, `y`) in the 8x12 font, but with each glyph only as wide as its
+/// bitmap (plus a column of spacing), so that labels pack more tightly than with
+/// `st7735_print()`. Printing stops at the first glyph which doesn't fit on screen. Returns the
+/// x coordinate just past the last glyph printed, e.g. for clearing whatever was there before.
+#[allow(unused)]
+pub fn st7735_print_prop(x0: u8, y: u8, text: &[u8], fg: St7735Color, bg: St7735Color) -> u8 {
+    let height = st7735_get_height();
+    let width = st7735_get_width();
+    let mut x = x0;
+    if y > height - 12 {
+        return x;
+    }
+    for &c in text {
+        let advance = prop_advance(c);
+        if advance == 0 {
+            continue;
+        }
+        if x as u32 + advance as u32 > width as u32 {
+            break;
+        }
+        st7735_putc_prop_unchecked(x, y, c, fg, bg);
+        x += advance;
+    }
+    x
+}
+
+/// Returns the width in pixels `st7735_print_prop()` would use to print `text`, ignoring the
+/// screen edge.
+#[allow(unused)]
+pub fn st7735_prop_width(text: &[u8]) -> u32 {
+    text.iter().map(|&c| prop_advance(c) as u32).sum()
+}
+
+// #[allow(unused)]
+// pub fn st7735_print_hex_u32(i: u32, x: u8, y: u8, fg: St7735Color, bg: St7735Color) {
+//     const C: [u8; 16] = *b"0123456789abcdef";
+//     let mut buf = [0u8; 4];
+//
+//     buf[0] = C[((i >> 12) & 0xf) as usize];
+//     buf[1] = C[((i >>  8) & 0xf) as usize];
+//     buf[2] = C[((i >>  4) & 0xf) as usize];
+//     buf[3] = C[( i        & 0xf) as usize];
+//     st7735_print(&buf, x, y, fg, bg);
+// }
+
+// ======== the Display trait ========
+
+/// The ST7735 LCD, through its driver, which must have been set up with `st7735_setup()` and
+/// `st7735_initR()`.
+pub struct St7735;
+
+impl Display for St7735 {
+    fn width(&self) -> u8 {
+        st7735_get_width()
+    }
+
+    fn height(&self) -> u8 {
+        st7735_get_height()
+    }
+
+    fn set_window(&mut self, x0: u8, y0: u8, x1: u8, y1: u8) {
+        st7735_setAddrWindow(x0, y0, x1, y1);
+    }
+
+    fn push_color(&mut self, color: u16) {
+        st7735_send_color(color);
+    }
+}
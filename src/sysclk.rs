@@ -24,8 +24,46 @@
 extern crate cortex_m;
 
 use cortex_m::asm;
+use cortex_m::peripheral::{Syst, SystClkSource};
 use stm32f30x::{FLASH, RCC};
 
+/// The system clock frequency set by `set_sys_clock()`, which is also the HCLK/AHB and APB2
+/// timer clock frequency.
+pub const SYSCLK_HZ: u32 = 72_000_000;
+
+/// The APB1 peripheral clock frequency (PCLK1), half of SYSCLK_HZ, which clocks SPI2.
+pub const PCLK1_HZ: u32 = SYSCLK_HZ / 2;
+
+/// The SysTick interrupt rate, which drives the millisecond clock, button polling, and LED PWM.
+pub const SYSTICK_HZ: u32 = 1000;
+
+/// The SysTick reload value for `SYSTICK_HZ` when SysTick is clocked from AHB, as set up by
+/// `systick_use_ahb_clock()`. SysTick counts down from the reload value to zero, inclusive, so
+/// each period is one clock longer than the reload value.
+pub const SYSTICK_RELOAD: u32 = SYSCLK_HZ / SYSTICK_HZ - 1;
+
+/// The clock of the APB2 timers, such as TIM15, which paces capture: SYSCLK_HZ, since PCLK2 is
+/// HCLK undivided.
+pub const APB2_TIMER_HZ: u32 = SYSCLK_HZ;
+
+/// Returns the prescaler and reload register values (PSC, ARR), each one less than what it divides
+/// by, which divide `APB2_TIMER_HZ` down to the nearest to `hz` updates per second, choosing the
+/// smallest prescaler (and so the finest rate resolution) that lets the reload value fit in 16
+/// bits. `hz` must be 1 through `APB2_TIMER_HZ`.
+pub fn apb2_timer_divider(hz: u32) -> (u16, u16) {
+    let ticks = (APB2_TIMER_HZ + hz / 2) / hz; // timer clocks per update
+    let psc = (ticks - 1) / 65536;
+    let arr = ticks / (psc + 1) - 1;
+    (psc as u16, arr as u16)
+}
+
+/// Returns the update rate of an APB2 timer divided by the register values `psc` and `arr`, as
+/// from `apb2_timer_divider()`, rounded to the nearest whole hertz.
+pub fn apb2_timer_rate(psc: u16, arr: u16) -> u32 {
+    let divisor = (psc as u32 + 1) * (arr as u32 + 1);
+    (APB2_TIMER_HZ + divisor / 2) / divisor
+}
+
 // set_sys_clock()
 // Set the system clock to 72MHz, using the 8MHz external clock from ST-Link.
 // This assumes the clock and PLL are still in their reset state, and turns
@@ -72,3 +110,12 @@ pub fn set_sys_clock() {
         rcc.cr.modify(|_, w| unsafe { w.hsion().bits(0) });
     });
 }
+
+/// Clocks SysTick from HCLK, the AHB clock, at `SYSCLK_HZ`.
+pub fn systick_use_ahb_clock(syst: &Syst) {
+    // The CSR CLKSOURCE bit selects either the processor clock or an implementation-defined
+    // "external" clock, which on the STM32F3 is AHB/8. cortex-m takes its names for these from
+    // the ARM documentation, as 'Core' and 'External', so set_clock_source() reads as nonsense
+    // against ST's clock tree: 'Core' here means AHB.
+    syst.set_clock_source(SystClkSource::Core);
+}
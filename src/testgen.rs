@@ -0,0 +1,156 @@
+// stm32f3-oscilloscope - src/testgen.rs
+// synthetic waveforms, for testing the signal processing against known answers
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// This is only built for the tests, which have std, so the generators can use floating point and
+// return `Vec`s. Each waveform is `n` samples of 12-bit ADC counts, captured at `TEST_RATE`
+// samples per second, swinging `amp` counts either side of `offset`; each sample is rounded, and
+// clipped to 0 through 4095 as the ADC would clip it, so that clipped signals can be made by
+// driving them past the rails. They're deterministic, noise included (it comes from a seeded
+// generator), so a test which fails fails the same way every time.
+
+use std::f64::consts::PI;
+
+/// The sample rate the waveforms are taken to be captured at, in samples per second.
+pub const TEST_RATE: u32 = 100_000;
+
+/// A sine wave of `freq` hertz, starting at `offset` on its way up.
+pub fn sine(freq: f64, amp: f64, offset: f64, n: usize) -> Vec<u16> {
+    (0..n).map(|i| to_counts(offset + amp * (2.0 * PI * phase(freq, i)).sin())).collect()
+}
+
+/// A square wave of `freq` hertz, high for the first `duty` (0 through 1) of each period.
+pub fn square(freq: f64, duty: f64, amp: f64, offset: f64, n: usize) -> Vec<u16> {
+    (0..n).map(|i| {
+        let high = phase(freq, i) < duty;
+        to_counts(if high { offset + amp } else { offset - amp })
+    }).collect()
+}
+
+/// A triangle wave of `freq` hertz, starting at its lowest.
+pub fn triangle(freq: f64, amp: f64, offset: f64, n: usize) -> Vec<u16> {
+    (0..n).map(|i| {
+        let p = phase(freq, i);
+        let rising = if p < 0.5 { 2.0 * p } else { 2.0 - 2.0 * p }; // 0 to 1, and back
+        to_counts(offset - amp + 2.0 * amp * rising)
+    }).collect()
+}
+
+/// A ramp (sawtooth) of `freq` hertz, rising steadily from its lowest to its highest over each
+/// period, then dropping straight back.
+pub fn ramp(freq: f64, amp: f64, offset: f64, n: usize) -> Vec<u16> {
+    (0..n).map(|i| to_counts(offset - amp + 2.0 * amp * phase(freq, i))).collect()
+}
+
+/// A DC level, with no signal on it at all.
+pub fn dc(level: u16, n: usize) -> Vec<u16> {
+    vec![level; n]
+}
+
+/// White noise, uniform between `offset - amp` and `offset + amp`, from a generator seeded with
+/// `seed` (any value but zero, which is taken as 1).
+pub fn noise(seed: u32, amp: f64, offset: f64, n: usize) -> Vec<u16> {
+    let mut rng = Rng::new(seed);
+    (0..n).map(|_| to_counts(offset + amp * rng.next_signed())).collect()
+}
+
+/// Returns `samples` with noise like `noise()`'s added to them.
+pub fn add_noise(samples: &[u16], seed: u32, amp: f64) -> Vec<u16> {
+    let mut rng = Rng::new(seed);
+    samples.iter().map(|&s| to_counts(s as f64 + amp * rng.next_signed())).collect()
+}
+
+// the fraction of a period of `freq` hertz that sample `i` comes at, 0 up to 1
+fn phase(freq: f64, i: usize) -> f64 {
+    let p = freq * i as f64 / TEST_RATE as f64;
+    p - p.floor()
+}
+
+// a level, rounded to the nearest count, and clipped to the 12-bit ADC range
+fn to_counts(level: f64) -> u16 {
+    level.round().max(0.0).min(4095.0) as u16
+}
+
+// a 32-bit xorshift generator: poor randomness, but plenty for noise, and the same everywhere
+struct Rng(u32);
+
+impl Rng {
+    fn new(seed: u32) -> Rng {
+        Rng(if seed == 0 { 1 } else { seed })
+    }
+
+    // the next value, from -1 up to 1
+    fn next_signed(&mut self) -> f64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x as f64 / 2_147_483_648.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_swings_about_the_offset() {
+        let s = sine(1_000.0, 1000.0, 2048.0, 100); // one period
+        assert_eq!(s[0], 2048);
+        assert_eq!(s[25], 3048);
+        assert_eq!(s[50], 2048);
+        assert_eq!(s[75], 1048);
+    }
+
+    #[test]
+    fn square_is_high_for_its_duty_cycle() {
+        let s = square(1_000.0, 0.25, 500.0, 2048.0, 100);
+        assert!(s[..25].iter().all(|&x| x == 2548));
+        assert!(s[25..].iter().all(|&x| x == 1548));
+    }
+
+    #[test]
+    fn triangle_and_ramp_rise_steadily() {
+        let t = triangle(1_000.0, 1000.0, 2048.0, 100);
+        assert_eq!((t[0], t[25], t[50], t[75]), (1048, 2048, 3048, 2048));
+        let r = ramp(1_000.0, 1000.0, 2048.0, 100);
+        assert_eq!((r[0], r[25], r[50], r[99]), (1048, 1548, 2048, 3028));
+    }
+
+    #[test]
+    fn overdriven_signals_clip_at_the_rails() {
+        let s = sine(1_000.0, 3000.0, 2048.0, 100);
+        assert_eq!(s[25], 4095);
+        assert_eq!(s[75], 0);
+    }
+
+    #[test]
+    fn noise_is_deterministic_and_bounded() {
+        let a = noise(12345, 100.0, 2048.0, 1000);
+        assert!(a == noise(12345, 100.0, 2048.0, 1000));
+        assert!(a != noise(54321, 100.0, 2048.0, 1000));
+        assert!(a.iter().all(|&x| x >= 1948 && x <= 2148));
+        assert!(noise(0, 100.0, 2048.0, 10) == noise(1, 100.0, 2048.0, 10));
+    }
+}
@@ -0,0 +1,225 @@
+// stm32f3-oscilloscope - src/theme.rs
+// display color themes
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+
+// Everything on the screen is drawn in the colors of the current theme, so that switching themes
+// changes the graticule, traces, and text together. A theme only picks the colors; the screen has
+// to be repainted for a change to show everywhere.
+//
+// The traces, though, can each be given a color of their own in the color map, e.g. to keep them
+// distinguishable for someone who can't tell red from green, which then holds whatever the theme.
+// A trace left at "theme" is drawn in the theme's color for it, which is all of them to begin with.
+
+use st7735::St7735Color;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Theme {
+    HighContrast, // bright colors on black
+    Dim,          // half-intensity colors on black, for a dark room
+    Inverted,     // dark colors on white, for daylight
+}
+
+/// The colors each part of the display is drawn in.
+pub struct ThemeColors {
+    pub background: St7735Color,
+    pub graticule: St7735Color, // (with its division lines at half intensity)
+    pub text: St7735Color,
+    pub warning: St7735Color, // text which needs attention
+    pub trace: St7735Color,   // channel A
+    pub trace_b: St7735Color, // channel B, on the dual-trace display
+    pub math: St7735Color,
+    pub marker: St7735Color, // the trigger marker, and the trigger source mark
+    pub cursor: St7735Color,
+    pub reference: St7735Color, // (drawn at half intensity)
+}
+
+const HIGH_CONTRAST: ThemeColors = ThemeColors {
+    background: St7735Color::Black,
+    graticule: St7735Color::Red,
+    text: St7735Color::Green,
+    warning: St7735Color::Red,
+    trace: St7735Color::White,
+    trace_b: St7735Color::Green,
+    math: St7735Color::Magenta,
+    marker: St7735Color::Yellow,
+    cursor: St7735Color::Yellow,
+    reference: St7735Color::White,
+};
+
+const DIM: ThemeColors = ThemeColors {
+    background: St7735Color::Black,
+    graticule: St7735Color::DarkRed,
+    text: St7735Color::DarkGreen,
+    warning: St7735Color::Red, // (still bright enough to be noticed)
+    trace: St7735Color::Grey,
+    trace_b: St7735Color::DarkGreen,
+    math: St7735Color::Purple,
+    marker: St7735Color::Olive,
+    cursor: St7735Color::Olive,
+    reference: St7735Color::Grey,
+};
+
+const INVERTED: ThemeColors = ThemeColors {
+    background: St7735Color::White,
+    graticule: St7735Color::Blue,
+    text: St7735Color::Black,
+    warning: St7735Color::Red,
+    trace: St7735Color::Black,
+    trace_b: St7735Color::DarkGreen,
+    math: St7735Color::Purple,
+    marker: St7735Color::Magenta,
+    cursor: St7735Color::Magenta,
+    reference: St7735Color::Grey,
+};
+
+/// The traces whose colors the color map sets.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Trace {
+    A,
+    B,
+    Math,
+    Reference,
+}
+
+/// The number of traces in the color map.
+pub const TRACE_COUNT: usize = 4;
+
+// the colors a trace can be given, in the order buttons 2 and 3 step through them (None: the
+// theme's)
+struct TraceColorStep {
+    color: Option<St7735Color>,
+    label: &'static [u8],
+}
+
+const TRACE_COLORS: [TraceColorStep; 10] = [
+    TraceColorStep { color: None,                       label: b"theme" },
+    TraceColorStep { color: Some(St7735Color::White),   label: b"white" },
+    TraceColorStep { color: Some(St7735Color::Grey),    label: b"grey" },
+    TraceColorStep { color: Some(St7735Color::Red),     label: b"red" },
+    TraceColorStep { color: Some(St7735Color::Green),   label: b"green" },
+    TraceColorStep { color: Some(St7735Color::Blue),    label: b"blue" },
+    TraceColorStep { color: Some(St7735Color::Cyan),    label: b"cyan" },
+    TraceColorStep { color: Some(St7735Color::Yellow),  label: b"yellow" },
+    TraceColorStep { color: Some(St7735Color::Magenta), label: b"magnta" },
+    TraceColorStep { color: Some(St7735Color::Purple),  label: b"purple" },
+];
+
+/// The color each trace is drawn in: a position in the table of trace colors for each, in `Trace`
+/// order, the first of which is the theme's color.
+#[derive(Clone, Copy)]
+pub struct ColorMap {
+    indices: [u8; TRACE_COUNT],
+}
+
+impl ColorMap {
+    /// Returns the color map which leaves every trace in the theme's color.
+    pub fn new() -> ColorMap {
+        ColorMap { indices: [0; TRACE_COUNT] }
+    }
+
+    /// Returns the position of each trace's color in the table of trace colors, e.g. for saving
+    /// the map.
+    pub fn indices(&self) -> [u8; TRACE_COUNT] {
+        self.indices
+    }
+
+    /// Returns the color map with the trace colors at positions `indices`, or `None` if one of
+    /// them isn't in the table.
+    pub fn from_indices(indices: [u8; TRACE_COUNT]) -> Option<ColorMap> {
+        if indices.iter().all(|&i| (i as usize) < TRACE_COLORS.len()) {
+            Some(ColorMap { indices: indices })
+        } else {
+            None
+        }
+    }
+
+    /// Steps the color of `trace` to the next one in the table (or the previous one, if `step` is
+    /// negative), wrapping around at the ends.
+    pub fn step(&mut self, trace: Trace, step: i32) {
+        let i = &mut self.indices[trace_position(trace)];
+        let len = TRACE_COLORS.len() as i32;
+        *i = ((*i as i32 + step % len + len) % len) as u8;
+    }
+
+    /// Returns the label for the color of `trace`, e.g. b"cyan", or b"theme" if it has the
+    /// theme's.
+    pub fn label(&self, trace: Trace) -> &'static [u8] {
+        TRACE_COLORS[self.indices[trace_position(trace)] as usize].label
+    }
+
+    // the color given to `trace`, if it isn't left to the theme
+    fn color(&self, trace: Trace) -> Option<St7735Color> {
+        TRACE_COLORS[self.indices[trace_position(trace)] as usize].color
+    }
+}
+
+// the position of `trace` in the color map
+fn trace_position(trace: Trace) -> usize {
+    match trace {
+        Trace::A => 0,
+        Trace::B => 1,
+        Trace::Math => 2,
+        Trace::Reference => 3,
+    }
+}
+
+static mut THEME: Theme = Theme::HighContrast;
+
+static mut COLOR_MAP: ColorMap = ColorMap { indices: [0; TRACE_COUNT] };
+
+/// Makes `theme` the one whose colors everything is drawn in from now on.
+pub fn set_theme(theme: Theme) {
+    unsafe { THEME = theme; }
+}
+
+/// Returns the colors of the current theme.
+pub fn theme_colors() -> &'static ThemeColors {
+    match unsafe { THEME } {
+        Theme::HighContrast => &HIGH_CONTRAST,
+        Theme::Dim => &DIM,
+        Theme::Inverted => &INVERTED,
+    }
+}
+
+/// Makes `map` the color map the traces are drawn with from now on.
+pub fn set_color_map(map: ColorMap) {
+    unsafe { COLOR_MAP = map; }
+}
+
+/// Returns the current color map.
+pub fn color_map() -> ColorMap {
+    unsafe { COLOR_MAP }
+}
+
+/// Returns the color `trace` is drawn in: its color in the color map, or the current theme's.
+pub fn trace_color(trace: Trace) -> St7735Color {
+    color_map().color(trace).unwrap_or_else(|| {
+        let colors = theme_colors();
+        match trace {
+            Trace::A => colors.trace,
+            Trace::B => colors.trace_b,
+            Trace::Math => colors.math,
+            Trace::Reference => colors.reference,
+        }
+    })
+}
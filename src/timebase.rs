@@ -0,0 +1,147 @@
+// stm32f3-oscilloscope - src/vscale.rs
+// horizontal timebase (time per division) settings
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// Each step's sample rate is chosen so that one division of the graticule spans the step's time
+// interval, which gives one sample per pixel column. The fastest steps ask for more than the ADC
+// can deliver, so they are limited to capture::max_sample_rate() (adc_max_sample_rate(), which
+// depends on the ADC sample time, or half that when capturing two channels), and the trace is then
+// stretched horizontally by that much.
+
+use capture;
+
+struct TimePerDiv {
+    microseconds: u32,
+    label: &'static [u8],
+}
+
+const TIME_PER_DIV: [TimePerDiv; 18] = [
+    TimePerDiv { microseconds:       10, label: b"10us" },
+    TimePerDiv { microseconds:       20, label: b"20us" },
+    TimePerDiv { microseconds:       50, label: b"50us" },
+    TimePerDiv { microseconds:      100, label: b".1ms" },
+    TimePerDiv { microseconds:      200, label: b".2ms" },
+    TimePerDiv { microseconds:      500, label: b".5ms" },
+    TimePerDiv { microseconds:     1000, label: b"1ms" },
+    TimePerDiv { microseconds:     2000, label: b"2ms" },
+    TimePerDiv { microseconds:     5000, label: b"5ms" },
+    TimePerDiv { microseconds:    10000, label: b"10ms" },
+    TimePerDiv { microseconds:    20000, label: b"20ms" },
+    TimePerDiv { microseconds:    50000, label: b"50ms" },
+    TimePerDiv { microseconds:   100000, label: b".1s" },
+    TimePerDiv { microseconds:   200000, label: b".2s" },
+    TimePerDiv { microseconds:   500000, label: b".5s" },
+    TimePerDiv { microseconds:  1000000, label: b"1s" },
+    TimePerDiv { microseconds:  2000000, label: b"2s" },
+    TimePerDiv { microseconds: 32000000, label: b"32s" }, // strip-chart like, at 32 pixels/div
+];
+
+// the default step: 1ms/div
+const TIME_PER_DIV_DEFAULT: usize = 6;
+
+#[derive(Clone, Copy)]
+pub struct TimeBase {
+    index: usize, // into TIME_PER_DIV
+}
+
+impl TimeBase {
+    pub fn new() -> TimeBase {
+        TimeBase { index: TIME_PER_DIV_DEFAULT }
+    }
+
+    /// Returns the position of this setting in the table of steps, e.g. for saving it.
+    pub fn index(&self) -> u8 {
+        self.index as u8
+    }
+
+    /// Returns the setting at position `index` in the table of steps, or `None` if there isn't
+    /// one.
+    pub fn from_index(index: u8) -> Option<TimeBase> {
+        if (index as usize) < TIME_PER_DIV.len() {
+            Some(TimeBase { index: index as usize })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the setting whose label is `label`, e.g. b"1ms", or `None` if there isn't one.
+    pub fn from_label(label: &[u8]) -> Option<TimeBase> {
+        for (i, step) in TIME_PER_DIV.iter().enumerate() {
+            if step.label == label {
+                return Some(TimeBase { index: i });
+            }
+        }
+        None
+    }
+
+    /// Returns the time per division, in microseconds.
+    #[allow(unused)]
+    pub fn microseconds_per_div(&self) -> u32 {
+        TIME_PER_DIV[self.index].microseconds
+    }
+
+    /// Returns the label for the current time per division, e.g. b"20us".
+    pub fn label(&self) -> &'static [u8] {
+        TIME_PER_DIV[self.index].label
+    }
+
+    /// Returns the sample rate, in samples per second, needed to give one sample per pixel when
+    /// there are `pixels_per_div` pixels per division. This may be more than the ADC can do.
+    pub fn sample_rate(&self, pixels_per_div: u32) -> u32 {
+        let us = TIME_PER_DIV[self.index].microseconds;
+        (pixels_per_div * 1_000_000 + us / 2) / us
+    }
+
+    /// Returns true if the ADC can't sample fast enough for this timebase at `pixels_per_div`.
+    pub fn is_limited(&self, pixels_per_div: u32) -> bool {
+        self.sample_rate(pixels_per_div) > capture::max_sample_rate()
+    }
+}
+
+/// Steps `timebase` to the next shorter time per division, if there is one. Returns true if it
+/// changed.
+pub fn timebase_faster(timebase: &mut TimeBase) -> bool {
+    if timebase.index > 0 {
+        timebase.index -= 1;
+        true
+    } else {
+        false
+    }
+}
+
+/// Steps `timebase` to the next longer time per division, if there is one. Returns true if it
+/// changed.
+pub fn timebase_slower(timebase: &mut TimeBase) -> bool {
+    if timebase.index + 1 < TIME_PER_DIV.len() {
+        timebase.index += 1;
+        true
+    } else {
+        false
+    }
+}
+
+/// Programs the sample timer for `timebase` at `pixels_per_div`, capping the rate at
+/// `capture::max_sample_rate()`. Returns the sample rate actually set.
+pub fn timebase_apply(timebase: &TimeBase, pixels_per_div: u32) -> u32 {
+    capture::set_timebase(timebase.sample_rate(pixels_per_div))
+}
@@ -0,0 +1,201 @@
+// stm32f3-oscilloscope - src/timing.rs
+// millisecond timekeeping and busy-wait delays, driven by the SysTick exception
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// SysTick is set up by main() to fire every millisecond, and its handler calls timing_tick(),
+// which is all that touches the counters here (but for timing_advance(), which catches them up on
+// time spent with SysTick stopped): the millisecond count, which wraps after about 49 days, so
+// that everything timed with it compares counts with wrapping arithmetic, and the uptime, in whole
+// seconds, which is counted apart from it so that it carries on past the millisecond count's
+// rollover. (The comparisons are in src/elapsed.rs, whose tests try them across the rollover; the
+// millis-rollover feature starts the millisecond count a minute short of it, to try out the rest
+// of the timing code on the board.) delay_us() doesn't use them, since it needs finer resolution
+// than SysTick gives: it counts CPU cycles instead. For measuring how long things take, the DWT
+// cycle counter counts CPU cycles too, wrapping after about 60 seconds.
+
+use core::intrinsics::volatile_load;
+
+use cortex_m;
+use cortex_m::peripheral::{DCB, DWT};
+
+use elapsed::has_elapsed;
+use sysclk::SYSCLK_HZ;
+
+// CPU cycles taken by each iteration of delay_us()'s loop: a nop, a decrement, and a taken branch,
+// which refills the pipeline
+const DELAY_US_CYCLES_PER_LOOP: u32 = 4;
+
+// where the millisecond count starts: at zero, or a minute short of its rollover
+#[cfg(not(feature = "millis-rollover"))]
+const MILLIS_START: u32 = 0;
+#[cfg(feature = "millis-rollover")]
+const MILLIS_START: u32 = 0xffff_15a0; // 2^32 - 60,000
+
+static mut MILLIS: u32 = MILLIS_START;
+
+// the uptime: whole seconds since SysTick was started, and milliseconds into the next one
+static mut UPTIME_SECONDS: u32 = 0;
+static mut UPTIME_MS: u16 = 0;
+
+/// Counts the passing of a millisecond; called from the SysTick exception handler.
+pub fn timing_tick() {
+    unsafe {
+        MILLIS = MILLIS.wrapping_add(1);
+        UPTIME_MS += 1;
+        if UPTIME_MS == 1000 {
+            UPTIME_MS = 0;
+            UPTIME_SECONDS = UPTIME_SECONDS.wrapping_add(1);
+        }
+    }
+}
+
+/// Counts the passing of `ms` milliseconds at once, e.g. ones spent with SysTick stopped, as
+/// measured some other way. This must not be called while SysTick is running.
+pub fn timing_advance(ms: u32) {
+    unsafe {
+        MILLIS = MILLIS.wrapping_add(ms);
+        let ms = UPTIME_MS as u32 + ms;
+        UPTIME_SECONDS = UPTIME_SECONDS.wrapping_add(ms / 1000);
+        UPTIME_MS = (ms % 1000) as u16;
+    }
+}
+
+/// Returns the number of milliseconds since SysTick was started (plus `MILLIS_START`), wrapping
+/// after about 49 days, so times should be compared with `elapsed_ms()` or `has_elapsed()`, e.g.
+/// `has_elapsed(start, millis(), ms)`.
+pub fn millis() -> u32 {
+    unsafe { volatile_load(&MILLIS) }
+}
+
+/// Returns the number of whole seconds since SysTick was started, which carries on counting past
+/// the rollover of `millis()` (wrapping only after some 136 years).
+pub fn uptime_seconds() -> u32 {
+    unsafe { volatile_load(&UPTIME_SECONDS) }
+}
+
+/// Waits for `ms` milliseconds, give or take the one in progress. Each wait only reads the
+/// millisecond count, so waits interrupting one another don't disturb each other, but this must
+/// not be called from an interrupt handler of the same or higher priority than SysTick, which
+/// would never see the count change.
+pub fn delay_ms(ms: u32) {
+    let start = millis();
+    // (so a wait across the count's rollover isn't cut short)
+    while !has_elapsed(start, millis(), ms) {}
+}
+
+/// Waits for at least `us` microseconds, by counting CPU cycles at SYSCLK_HZ, for delays too short
+/// for `delay_ms()`. Interrupts taken during the wait lengthen it.
+#[allow(unused)]
+pub fn delay_us(us: u32) {
+    let loops = us * (SYSCLK_HZ / 1_000_000 / DELAY_US_CYCLES_PER_LOOP);
+    for _ in 0..loops {
+        cortex_m::asm::nop();
+    }
+}
+
+// ======== cycle counter ========
+
+/// Starts the DWT cycle counter, which `cycles()` reads.
+pub fn cycle_counter_init() {
+    cortex_m::interrupt::free(|cs| {
+        let dcb = DCB.borrow(cs);
+        let dwt = DWT.borrow(cs);
+        unsafe {
+            dcb.demcr.modify(|r| r | (1 << 24)); // TRCENA: enable the DWT
+            dwt.cyccnt.write(0);
+            dwt.ctrl.modify(|r| r | 1); // CYCCNTENA
+        }
+    });
+}
+
+/// Returns the number of CPU cycles since `cycle_counter_init()`, wrapping.
+pub fn cycles() -> u32 {
+    cortex_m::interrupt::free(|cs| DWT.borrow(cs).cyccnt.read())
+}
+
+// convert a number of CPU cycles to microseconds
+fn cycles_to_us(cycles: u32) -> u32 {
+    cycles / (SYSCLK_HZ / 1_000_000)
+}
+
+/// Times sweeps: how long each takes to draw, from `begin()` to `end()`, and the period between
+/// the beginnings of successive sweeps, which includes waiting for capture. A `begin()` not
+/// followed by an `end()`, e.g. for a buffer which didn't trigger, doesn't count. Averages are
+/// exponential, weighting each new sweep by 1/8.
+pub struct SweepTimer {
+    started: u32,            // cycles() at the last begin()
+    last_start: Option<u32>, // cycles() at the begin() of the last completed sweep
+    draw: u32,               // in cycles
+    draw_average: u32,
+    period: u32,
+    period_average: u32,
+}
+
+impl SweepTimer {
+    pub fn new() -> SweepTimer {
+        SweepTimer {
+            started: 0,
+            last_start: None,
+            draw: 0,
+            draw_average: 0,
+            period: 0,
+            period_average: 0,
+        }
+    }
+
+    /// Marks the start of a sweep, once its buffer has been captured.
+    pub fn begin(&mut self) {
+        self.started = cycles();
+    }
+
+    /// Marks the end of the sweep begun by the last `begin()`, once it has been drawn.
+    pub fn end(&mut self) {
+        self.draw = cycles().wrapping_sub(self.started);
+        self.draw_average = average(self.draw_average, self.draw);
+        if let Some(last_start) = self.last_start {
+            self.period = self.started.wrapping_sub(last_start);
+            self.period_average = average(self.period_average, self.period);
+        }
+        self.last_start = Some(self.started);
+    }
+
+    /// Returns the time taken to draw the last sweep, and the average, in microseconds.
+    pub fn draw_us(&self) -> (u32, u32) {
+        (cycles_to_us(self.draw), cycles_to_us(self.draw_average))
+    }
+
+    /// Returns the period between the last two sweeps, and the average, in microseconds.
+    pub fn period_us(&self) -> (u32, u32) {
+        (cycles_to_us(self.period), cycles_to_us(self.period_average))
+    }
+}
+
+// an exponential moving average, updated with `value` (starting from `value`, if the average is
+// still zero)
+fn average(average: u32, value: u32) -> u32 {
+    if average == 0 {
+        value
+    } else {
+        (average as u64 * 7 / 8 + value as u64 / 8) as u32
+    }
+}
@@ -0,0 +1,494 @@
+// stm32f3-oscilloscope - src/trigger.rs
+// edge trigger detection on captured sample buffers
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// The trigger works like a Schmitt trigger: before a rising edge can fire, the signal must first
+// have been at or below `level - hysteresis`, so noise riding on a signal which is sitting right
+// at the trigger level can't retrigger on every wiggle. (Falling edges are the mirror image.)
+//
+// The pulse-width trigger uses the same thresholds: a high pulse begins like a rising edge, and
+// ends once the signal is back at or below `level - hysteresis`. (Low pulses are the mirror
+// image.)
+
+use core;
+
+use elapsed::{elapsed_ms, has_elapsed};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// Scans `samples` for the first point where the signal crosses `level` in the direction of
+/// `edge`, after having been at least `hysteresis` counts on the other side of it. Returns the
+/// index of the first sample at or beyond `level`, or `None` if there is no such crossing.
+pub fn find_trigger(samples: &[u16], level: u16, edge: Edge, hysteresis: u16) -> Option<usize> {
+    let mut armed = false;
+    match edge {
+        Edge::Rising => {
+            let arm_level = level.saturating_sub(hysteresis);
+            for (i, &sample) in samples.iter().enumerate() {
+                if sample <= arm_level {
+                    armed = true;
+                } else if armed && sample >= level {
+                    return Some(i);
+                }
+            }
+        }
+        Edge::Falling => {
+            let arm_level = level.saturating_add(hysteresis);
+            for (i, &sample) in samples.iter().enumerate() {
+                if sample >= arm_level {
+                    armed = true;
+                } else if armed && sample <= level {
+                    return Some(i);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Which way a pulse goes from the signal's resting level.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Polarity {
+    High, // above the trigger level
+    Low,  // below it
+}
+
+/// How a pulse's width must compare with the pulse-width trigger's width for it to fire.
+#[derive(Clone, Copy, PartialEq)]
+pub enum WidthCompare {
+    Wider,
+    Narrower,
+}
+
+/// What the trigger looks for: an edge, or a pulse wider or narrower than `width_us`
+/// microseconds, e.g. to catch glitches and runt pulses.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TriggerType {
+    Edge,
+    PulseWidth { polarity: Polarity, compare: WidthCompare, width_us: u32 },
+}
+
+/// Scans `samples` for the first pulse of `polarity` across `level`, after the signal has been at
+/// least `hysteresis` counts on the other side of it, whose width compares with `width` samples
+/// as `compare` asks. A pulse ends when the signal is back `hysteresis` counts beyond `level`, so
+/// a narrower one is only found once it has ended, but a wider one is found as soon as it has
+/// lasted longer than `width`. Returns the index of the pulse's first sample at or beyond
+/// `level`, or `None` if there is no such pulse.
+pub fn find_pulse(samples: &[u16], level: u16, polarity: Polarity, compare: WidthCompare,
+                  width: usize, hysteresis: u16) -> Option<usize> {
+    let inside = |sample: u16| match polarity {
+        Polarity::High => sample >= level,
+        Polarity::Low => sample <= level,
+    };
+    let outside = |sample: u16| match polarity {
+        Polarity::High => sample <= level.saturating_sub(hysteresis),
+        Polarity::Low => sample >= level.saturating_add(hysteresis),
+    };
+    let mut armed = false;
+    let mut start = None; // of the pulse in progress
+    for (i, &sample) in samples.iter().enumerate() {
+        match start {
+            None if outside(sample) => armed = true,
+            None if armed && inside(sample) => start = Some(i),
+            Some(s) if outside(sample) => {
+                if compare == WidthCompare::Narrower && i - s < width {
+                    return Some(s);
+                }
+                start = None;
+            }
+            Some(s) if compare == WidthCompare::Wider && i - s + 1 > width => return Some(s),
+            _ => {}
+        }
+    }
+    None
+}
+
+// ======== trigger modes ========
+
+/// The time after the last trigger, in milliseconds, after which Auto mode displays untriggered
+/// sweeps.
+const AUTO_TIMEOUT_MS: u32 = 100;
+
+/// The time after the last trigger, in milliseconds, after which Normal mode is considered to be
+/// waiting for one.
+const NORMAL_WAITING_MS: u32 = 1000;
+
+/// Which signal the trigger looks for its edge in.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TriggerSource {
+    ChA,    // channel A's samples
+    ChB,    // channel B's samples, where they are captured
+    ExtPin, // the external trigger input, whose edges are timed against the samples
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TriggerMode {
+    Auto,   // display triggered sweeps, or untriggered ones if no trigger arrives for a while
+    Normal, // display only triggered sweeps
+    Single, // display one triggered sweep, then hold it until re-armed
+}
+
+/// The trigger settings, plus the state machine which decides which captured buffers get
+/// displayed.
+pub struct Trigger {
+    pub level: u16,
+    pub edge: Edge,
+    pub hysteresis: u16,
+    pub source: TriggerSource,
+    pub trigger_type: TriggerType, // (the external trigger input can only trigger on edges)
+    pub offset: i16, // the DC level the level is relative to, from mid-scale (AC coupling)
+    mode: TriggerMode,
+    armed: bool, // Single mode: waiting for a trigger
+    last_trigger_ms: u32,
+    holdoff_ms: u32, // triggers are ignored for this long after each one
+    triggered: bool, // whether the last buffer displayed was triggered, or free-run
+    position: u8, // percent of the screen width left of the trigger
+}
+
+impl Trigger {
+    pub fn new(level: u16, edge: Edge, hysteresis: u16) -> Trigger {
+        Trigger {
+            level,
+            edge,
+            hysteresis,
+            source: TriggerSource::ChA,
+            trigger_type: TriggerType::Edge,
+            offset: 0,
+            mode: TriggerMode::Auto,
+            armed: false,
+            last_trigger_ms: 0,
+            holdoff_ms: 0,
+            triggered: false,
+            position: 0,
+        }
+    }
+
+    pub fn mode(&self) -> TriggerMode {
+        self.mode
+    }
+
+    /// Changes the trigger mode. Entering Single mode arms it.
+    pub fn set_mode(&mut self, mode: TriggerMode) {
+        self.mode = mode;
+        self.armed = mode == TriggerMode::Single;
+    }
+
+    /// Re-arms Single mode, so the next triggered sweep will be captured.
+    pub fn arm(&mut self) {
+        if self.mode == TriggerMode::Single {
+            self.armed = true;
+        }
+    }
+
+    /// Returns true if Single mode is waiting for a trigger, false if it has captured its sweep.
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Returns true if Normal mode has gone more than a second without a trigger, as of `now_ms`,
+    /// so that nothing new is being displayed.
+    pub fn is_waiting(&self, now_ms: u32) -> bool {
+        self.mode == TriggerMode::Normal &&
+            elapsed_ms(self.last_trigger_ms, now_ms) > NORMAL_WAITING_MS
+    }
+
+    /// Returns true if the last buffer `check()` chose to display was triggered, or false if it
+    /// was free-run by Auto mode, so that it isn't lined up on the trigger point.
+    pub fn was_triggered(&self) -> bool {
+        self.triggered
+    }
+
+    /// Sets the trigger holdoff: after each trigger, further triggers are ignored, and nothing new
+    /// is displayed, for `ms` milliseconds, so that a waveform with several edges per period that
+    /// would trigger it can be made to trigger at the same place each time. In Auto mode, the time
+    /// before untriggered sweeps are displayed only starts once the holdoff has passed.
+    pub fn set_holdoff_ms(&mut self, ms: u32) {
+        self.holdoff_ms = ms;
+    }
+
+    /// Sets where the trigger point appears on the screen, as a percentage (at most 100) of the
+    /// screen width from its left edge, so that the display shows that many samples from before
+    /// the trigger.
+    pub fn set_position(&mut self, percent: u8) {
+        self.position = core::cmp::min(percent, 100);
+    }
+
+    /// Returns the number of pre-trigger samples to show, out of a screen `width` samples wide:
+    /// at 100%, all but the trigger's own sample, so that it is the last one shown.
+    pub fn pre_trigger_samples(&self, width: usize) -> usize {
+        core::cmp::min(width * self.position as usize / 100, width.saturating_sub(1))
+    }
+
+    /// Returns the level in the samples as captured: the level, moved by the offset, which AC
+    /// coupling sets to the signal's DC level, so that the level stays put on an AC-coupled
+    /// display as the DC level changes.
+    pub fn sample_level(&self) -> u16 {
+        core::cmp::max(0, core::cmp::min(self.level as i32 + self.offset as i32, 4095)) as u16
+    }
+
+    /// Returns the edge a trigger fires on: the trigger edge, or the pulse-width trigger's
+    /// pulses' leading edge.
+    pub fn leading_edge(&self) -> Edge {
+        match self.trigger_type {
+            TriggerType::Edge => self.edge,
+            TriggerType::PulseWidth { polarity: Polarity::High, .. } => Edge::Rising,
+            TriggerType::PulseWidth { polarity: Polarity::Low, .. } => Edge::Falling,
+        }
+    }
+
+    /// Returns true if `check()` would ignore a trigger at `now_ms`: Single mode is holding its
+    /// captured sweep, or the holdoff after the last trigger hasn't passed yet.
+    pub fn is_holding(&self, now_ms: u32) -> bool {
+        (self.mode == TriggerMode::Single && !self.armed) ||
+            !has_elapsed(self.last_trigger_ms, now_ms, self.holdoff_ms)
+    }
+
+    /// Looks for the trigger in `samples`, captured at `sample_rate` samples per second, as
+    /// `check()` does, but without changing any state: returns the index of the first trigger
+    /// found, or `None`, e.g. to draw a sweep before its buffer is complete.
+    pub fn find(&self, samples: &[u16], sample_rate: u32) -> Option<usize> {
+        find_in(samples, self.sample_level(), self.edge, self.hysteresis, self.trigger_type,
+                sample_rate)
+    }
+
+    /// Decides whether the newly captured `samples`, captured at `sample_rate` samples per second,
+    /// should be displayed, given the current time `now_ms`. Returns the index to begin displaying
+    /// from, or `None` to leave the display as it is.
+    pub fn check(&mut self, samples: &[u16], sample_rate: u32, now_ms: u32) -> Option<usize> {
+        let (level, edge, hysteresis, trigger_type) =
+            (self.sample_level(), self.edge, self.hysteresis, self.trigger_type);
+        self.check_with(now_ms, || {
+            find_in(samples, level, edge, hysteresis, trigger_type, sample_rate)
+        })
+    }
+
+    /// Like `check()`, but for a trigger found elsewhere than in the samples, e.g. on the external
+    /// trigger input: `found` is the index of the sample it came at, if there was one.
+    pub fn check_external(&mut self, found: Option<usize>, now_ms: u32) -> Option<usize> {
+        self.check_with(now_ms, || found)
+    }
+
+    // the trigger state machine, with `find` to look for the trigger once it is needed
+    fn check_with<F: FnOnce() -> Option<usize>>(&mut self, now_ms: u32, find: F)
+                                                -> Option<usize> {
+        if self.mode == TriggerMode::Single && !self.armed {
+            return None; // holding the captured sweep
+        }
+        let since_trigger = elapsed_ms(self.last_trigger_ms, now_ms);
+        if since_trigger < self.holdoff_ms {
+            return None; // holding off
+        }
+        match find() {
+            Some(i) => {
+                self.last_trigger_ms = now_ms;
+                self.armed = false;
+                self.triggered = true;
+                Some(i)
+            }
+            None => {
+                if self.mode == TriggerMode::Auto &&
+                   since_trigger >= self.holdoff_ms + AUTO_TIMEOUT_MS {
+                    self.triggered = false;
+                    Some(0) // free-run
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+// look for a trigger of `trigger_type` in `samples`, captured at `sample_rate` samples per second
+fn find_in(samples: &[u16], level: u16, edge: Edge, hysteresis: u16, trigger_type: TriggerType,
+           sample_rate: u32) -> Option<usize> {
+    match trigger_type {
+        TriggerType::Edge => find_trigger(samples, level, edge, hysteresis),
+        TriggerType::PulseWidth { polarity, compare, width_us } => {
+            let width = (width_us as u64 * sample_rate as u64 / 1_000_000) as usize;
+            find_pulse(samples, level, polarity, compare, width, hysteresis)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Polarity::{High, Low};
+    use super::WidthCompare::{Narrower, Wider};
+    use testgen::{TEST_RATE, add_noise, dc, sine, square};
+
+    #[test]
+    fn finds_each_edge_of_a_sine() {
+        // 1kHz: a period of 100 samples, starting at mid-scale on the way up
+        let s = sine(1_000.0, 1000.0, 2048.0, 300);
+        // the first rise doesn't count, since the signal hasn't been below the hysteresis yet
+        assert_eq!(find_trigger(&s, 2048, Edge::Rising, 100), Some(100));
+        assert_eq!(find_trigger(&s, 2048, Edge::Falling, 100), Some(50));
+    }
+
+    #[test]
+    fn hysteresis_ignores_noise_at_the_level() {
+        // a slow sine, so the noise crosses the level many times on each edge
+        let s = add_noise(&sine(100.0, 1000.0, 2048.0, 1900), 7, 40.0);
+        let mut crossings = 0;
+        let mut start = 0;
+        while let Some(i) = find_trigger(&s[start..], 2048, Edge::Rising, 100) {
+            crossings += 1;
+            start += i + 1;
+        }
+        assert_eq!(crossings, 1); // only the rise at sample 1000
+    }
+
+    #[test]
+    fn dc_never_triggers() {
+        let s = dc(2048, 300);
+        assert_eq!(find_trigger(&s, 2048, Edge::Rising, 10), None);
+        assert_eq!(find_trigger(&s, 2048, Edge::Falling, 10), None);
+    }
+
+    // a pulse train resting at 1000, with a pulse to 3000 (or, for low ones, a rest at 3000 and
+    // pulses to 1000) of each of `widths` samples, in turn, 20 samples apart
+    fn pulse_train(widths: &[usize], polarity: Polarity) -> Vec<u16> {
+        let (rest, pulse) = match polarity {
+            Polarity::High => (1000, 3000),
+            Polarity::Low => (3000, 1000),
+        };
+        let mut s = vec![rest; 20];
+        for &width in widths {
+            s.extend(vec![pulse; width]);
+            s.extend(vec![rest; 20]);
+        }
+        s
+    }
+
+    #[test]
+    fn pulse_width_trigger_picks_out_the_odd_pulse() {
+        // the pulses start at samples 20, 50, 80, 103, 133, and 178
+        for &polarity in [High, Low].iter() {
+            let s = pulse_train(&[10, 10, 3, 10, 25, 10], polarity);
+            assert_eq!(find_pulse(&s, 2048, polarity, Narrower, 5, 100), Some(80));
+            assert_eq!(find_pulse(&s, 2048, polarity, Wider, 20, 100), Some(133));
+            assert_eq!(find_pulse(&s, 2048, polarity, Narrower, 3, 100), None);
+            assert_eq!(find_pulse(&s, 2048, polarity, Wider, 25, 100), None);
+            // the opposite polarity's pulses are the gaps between these, all 20 samples wide
+            let opposite = if polarity == High { Low } else { High };
+            assert_eq!(find_pulse(&s, 2048, opposite, Narrower, 20, 100), None);
+            assert_eq!(find_pulse(&s, 2048, opposite, Wider, 19, 100), Some(30));
+        }
+    }
+
+    #[test]
+    fn pulse_width_boundaries() {
+        let s = pulse_train(&[10], High);
+        // a pulse exactly `width` wide is neither narrower nor wider
+        assert_eq!(find_pulse(&s, 2048, High, Narrower, 10, 100), None);
+        assert_eq!(find_pulse(&s, 2048, High, Narrower, 11, 100), Some(20));
+        assert_eq!(find_pulse(&s, 2048, High, Wider, 10, 100), None);
+        assert_eq!(find_pulse(&s, 2048, High, Wider, 9, 100), Some(20));
+        // a narrower pulse still going at the end of the buffer hasn't been measured yet
+        assert_eq!(find_pulse(&s[..25], 2048, High, Narrower, 11, 100), None);
+        // and one already in progress at the start isn't timed from partway through
+        assert_eq!(find_pulse(&s[25..], 2048, High, Narrower, 11, 100), None);
+    }
+
+    #[test]
+    fn pulse_width_trigger_converts_microseconds() {
+        // 10-sample pulses at 100ksps are 100us wide
+        let s = pulse_train(&[10, 4, 10], High);
+        let mut trigger = Trigger::new(2048, Edge::Rising, 100);
+        trigger.trigger_type =
+            TriggerType::PulseWidth { polarity: High, compare: Narrower, width_us: 50 };
+        assert_eq!(trigger.find(&s, TEST_RATE), Some(50));
+        assert!(trigger.leading_edge() == Edge::Rising);
+        trigger.trigger_type =
+            TriggerType::PulseWidth { polarity: Low, compare: Wider, width_us: 150 };
+        assert_eq!(trigger.find(&s, TEST_RATE), Some(30));
+        assert!(trigger.leading_edge() == Edge::Falling);
+    }
+
+    #[test]
+    fn pre_trigger_leaves_room_for_the_trigger() {
+        let mut trigger = Trigger::new(2048, Edge::Rising, 100);
+        for &(percent, pre) in [(0, 0), (25, 40), (50, 80), (100, 159), (200, 159)].iter() {
+            trigger.set_position(percent);
+            assert_eq!(trigger.pre_trigger_samples(160), pre);
+        }
+        assert_eq!(trigger.pre_trigger_samples(0), 0);
+    }
+
+    #[test]
+    fn trigger_finds_a_square_wave_edge_in_free_run() {
+        let s = square(1_000.0, 0.5, 1000.0, 2048.0, 300);
+        let mut trigger = Trigger::new(2048, Edge::Rising, 100);
+        assert_eq!(trigger.check(&s, TEST_RATE, 1_000), Some(100));
+        assert!(trigger.was_triggered());
+    }
+
+    #[test]
+    fn auto_free_runs_after_the_timeout_but_normal_waits() {
+        let s = dc(2048, 300);
+        let mut trigger = Trigger::new(2048, Edge::Rising, 100);
+        assert_eq!(trigger.check(&s, TEST_RATE, AUTO_TIMEOUT_MS - 1), None);
+        assert_eq!(trigger.check(&s, TEST_RATE, AUTO_TIMEOUT_MS), Some(0));
+        assert!(!trigger.was_triggered());
+        trigger.set_mode(TriggerMode::Normal);
+        assert_eq!(trigger.check(&s, TEST_RATE, 10 * AUTO_TIMEOUT_MS), None);
+        assert!(!trigger.is_waiting(NORMAL_WAITING_MS));
+        assert!(trigger.is_waiting(NORMAL_WAITING_MS + 1));
+    }
+
+    #[test]
+    fn single_disarms_after_one_capture_until_rearmed() {
+        let s = square(1_000.0, 0.5, 1000.0, 2048.0, 300);
+        let mut trigger = Trigger::new(2048, Edge::Rising, 100);
+        trigger.set_mode(TriggerMode::Single);
+        assert!(trigger.is_armed());
+        assert_eq!(trigger.check(&s, TEST_RATE, 1_000), Some(100));
+        assert!(!trigger.is_armed());
+        assert!(trigger.is_holding(2_000));
+        assert_eq!(trigger.check(&s, TEST_RATE, 2_000), None);
+        trigger.arm();
+        assert!(trigger.is_armed());
+        assert_eq!(trigger.check(&s, TEST_RATE, 3_000), Some(100));
+        assert!(!trigger.is_armed());
+    }
+
+    #[test]
+    fn holdoff_holds_back_a_retrigger_across_a_millis_wrap() {
+        let s = square(1_000.0, 0.5, 1000.0, 2048.0, 300);
+        let mut trigger = Trigger::new(2048, Edge::Rising, 100);
+        trigger.set_mode(TriggerMode::Normal);
+        trigger.set_holdoff_ms(50);
+        assert_eq!(trigger.check(&s, TEST_RATE, 0xffff_ffe0), Some(100));
+        // 32 ms later, just past the wrap
+        assert!(trigger.is_holding(0x0000_0000));
+        assert_eq!(trigger.check(&s, TEST_RATE, 0x0000_0000), None);
+        // 50 ms later
+        assert!(!trigger.is_holding(0x0000_0012));
+        assert_eq!(trigger.check(&s, TEST_RATE, 0x0000_0012), Some(100));
+    }
+}
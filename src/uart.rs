@@ -0,0 +1,449 @@
+// stm32f3-oscilloscope - src/vscale.rs
+// vertical scale (display gain) settings
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// USART1 is used for talking to a host PC, on PC4 (TX) and PC5 (RX), which are free on the
+// STM32F3 Discovery board's headers. Connect them to a 3.3V USB-serial adapter.
+//
+// Received bytes are taken from USART1 by its interrupt handler as they arrive, into a ring buffer
+// the main loop reads with uart_read_byte(), so that none are lost while it is busy drawing. If
+// the buffer fills up, or a byte arrives before the handler has taken the last (an overrun), the
+// byte is dropped, and counted.
+//
+// Sample buffers are sent by uart_send_samples() in frames of:
+//   2 bytes  sync header, 0xa5 0x5a
+//   2 bytes  sample count, little-endian
+//   2 bytes  per sample, little-endian
+//   1 byte   checksum: the two's complement of the 8-bit sum of the count and sample bytes, so
+//            that all of the bytes after the sync header sum to zero
+//
+// Screenshots are sent by uart_send_screenshot() in frames of:
+//   2 bytes  sync header, 0xa5 0x5b
+//   1 byte   width, in pixels
+//   1 byte   height, in pixels
+//   2 bytes  per pixel, an RGB565 color, little-endian, across each row from the top left
+//   1 byte   checksum, as for samples, of the width, height, and pixel bytes
+//
+// Sample buffers can be sent as text instead, by uart_send_csv(), for spreadsheets and plotting
+// tools: a comment line with the sample count, sample rate, and timebase, e.g.
+//   # 320 samples at 1.00MHz, 10us/div
+// then each sample, in millivolts at the probe tip (by the calibration), on a line of its own.
+//
+// The host can also control the scope with lines of text, ending in a newline (an optional
+// carriage return before it is ignored), which uart_poll_command() parses:
+//   TB <time/div>             set the timebase, e.g. "TB 1ms" or "TB .5s"
+//   VS <gain>                 set the vertical gain, e.g. "VS 2x"
+//   TRIG RISING|FALLING <n>   set the trigger edge and level, in ADC counts (0 to 4095)
+//   SINGLE                    arm a single sweep
+//   DUMP [BIN|CSV]            send the displayed sweep as a frame (BIN, the default), or as
+//                             text (CSV)
+//   SHOT                      send a screenshot (only if built with the 'lcd-framebuffer'
+//                             feature)
+//   CAL ZERO                  calibrate the input offset, with the input grounded
+//   CAL <mV>                  calibrate the input scale, with <mV> millivolts applied, e.g.
+//                             "CAL 1500" (calibrate the offset first)
+//   ROT <n>                   rotate the screen to orientation <n>, 0 to 3 quarter turns from
+//                             portrait, e.g. "ROT 1" to turn the default landscape upside down
+//   SPI <kHz>                 set the LCD's SPI clock to at most <kHz>, e.g. "SPI 2250" to slow
+//                             it down for long wires
+//   FPS <n>                   draw at most <n> sweeps per second (50 by default), or every
+//                             captured sweep the LCD can keep up with for "FPS 0"
+//   OS <k>                    oversample <k> times, 2 or 4, or 1 for plain sampling; anything
+//                             more is refused, as the capture buffers have no room for it
+//   STATUS                    send the error counts since power-on: ADC overruns, then received
+//                             bytes dropped, e.g. "0 0"
+// Commands are answered with "OK", or "ERR" and a reason, on a line of their own. For DUMP and
+// SHOT, the frame (or text) follows the "OK" line, for SPI, a line with the clock set, in kHz, and
+// for STATUS, a line with the counts.
+//
+// Without a display (headless), each triggered sweep's measurements are sent by
+// uart_send_measurements() on a line of their own:
+//   MEAS <Hz> <pp mV> <mean mV> <AC RMS mV>
+// with the frequency rounded to the nearest hertz, or "-" if there was none to measure.
+
+use core::ptr;
+
+use cortex_m;
+use stm32f30x::{GPIOC, RCC, USART1};
+use stm32f30x::interrupt::Interrupt;
+
+use calibration::counts_to_mv;
+use fmt::{fmt_eng_scaled, fmt_i32};
+use sysclk::SYSCLK_HZ;
+use timebase::TimeBase;
+use trigger::Edge;
+use vscale::VerticalScale;
+
+/// The default baud rate.
+pub const UART_BAUD: u32 = 115_200;
+
+const UART_SYNC: [u8; 2] = [0xa5, 0x5a];
+const UART_SCREENSHOT_SYNC: [u8; 2] = [0xa5, 0x5b];
+
+// the size of the receive ring buffer, which holds one byte fewer than this
+const RX_BUFFER_SIZE: usize = 64;
+
+// the receive ring buffer: the interrupt handler puts bytes in at RX_HEAD, the only one it
+// writes, and uart_read_byte() takes them out at RX_TAIL, the only one it writes; equal is empty
+static mut RX_BUFFER: [u8; RX_BUFFER_SIZE] = [0; RX_BUFFER_SIZE];
+static mut RX_HEAD: usize = 0;
+static mut RX_TAIL: usize = 0;
+static mut RX_DROPPED: u32 = 0; // bytes lost to a full buffer or an overrun
+
+/// Sets up USART1 on PC4 and PC5 for 8N1 at `baud` bits per second.
+pub fn uart_init(baud: u32) {
+    cortex_m::interrupt::free(|cs| {
+        let rcc = RCC.borrow(cs);
+        let gpioc = GPIOC.borrow(cs);
+        let usart1 = USART1.borrow(cs);
+
+        // enable clock to GPIOC and USART1
+        rcc.ahbenr.modify(|_, w| w.iopcen().enabled());
+        rcc.apb2enr.modify(|_, w| w.usart1en().enabled());
+
+        // set PC4 and PC5 to alternate function 7, USART1_TX and USART1_RX
+        gpioc.moder.modify(|_, w| w.moder4().alternate()
+                                   .moder5().alternate());
+        gpioc.pupdr.modify(|_, w| unsafe {
+            w.pupdr4().bits(0b00) // no pull
+             .pupdr5().bits(0b01) // pull up, so an unconnected RX idles
+        });
+        gpioc.afrl.modify(|_, w| unsafe {
+            w.afrl4().bits(0b0111) // PC4 AF7: USART1_TX
+             .afrl5().bits(0b0111) // PC5 AF7: USART1_RX
+        });
+
+        // configure USART1: it is clocked from PCLK2, which is SYSCLK_HZ
+        usart1.cr1.modify(|_, w| unsafe { w.ue().bits(0) }); // disable while configuring
+        usart1.brr.write(|w| unsafe { w.bits((SYSCLK_HZ + baud / 2) / baud) }); // 16x oversampling
+        usart1.cr2.write(|w| unsafe { w.stop().bits(0b00) }); // 1 stop bit
+        // no flow control, and no DMA (overrun errors stay enabled, for the interrupt handler to
+        // count and clear)
+        usart1.cr3.write(|w| unsafe { w.ovrdis().bits(0) });
+        usart1.cr1.write(|w| unsafe {
+            w.m().bits(0)      // 8 data bits
+             .pce().bits(0)    // no parity
+             .over8().bits(0)  // 16x oversampling
+             .rxneie().bits(1) // interrupt on a received byte, or an overrun
+             .te().bits(1)     // transmitter enable
+             .re().bits(1)     // receiver enable
+             .ue().bits(1)     // USART enable
+        });
+
+        // enable the USART1 interrupt, below the capture's priority
+        let nvic = cortex_m::peripheral::NVIC.borrow(cs);
+        unsafe { nvic.set_priority(Interrupt::Usart1Exti25, 0x80); }
+        nvic.enable(Interrupt::Usart1Exti25);
+    });
+}
+
+/// Handles the USART1 receive and overrun interrupts; called from the interrupt handler. This
+/// puts the received byte in the ring buffer, or counts it as dropped if the buffer is full, and
+/// clears an overrun, counting the byte lost to it.
+pub fn usart1_interrupt() {
+    let usart1 = USART1.get();
+    unsafe {
+        let isr = (*usart1).isr.read();
+        if isr.ore().bits() != 0 {
+            (*usart1).icr.write(|w| w.orecf().bits(1));
+            RX_DROPPED = RX_DROPPED.wrapping_add(1);
+        }
+        if isr.rxne().bits() != 0 {
+            // (reading the byte clears RXNE)
+            let byte = (*usart1).rdr.read().rdr().bits() as u8;
+            let head = RX_HEAD;
+            let next = (head + 1) % RX_BUFFER_SIZE;
+            if next == ptr::read_volatile(&RX_TAIL) {
+                RX_DROPPED = RX_DROPPED.wrapping_add(1);
+            } else {
+                ptr::write_volatile(&mut RX_BUFFER[head], byte);
+                ptr::write_volatile(&mut RX_HEAD, next);
+            }
+        }
+    }
+}
+
+/// Sends `byte` if the transmit data register is empty, without waiting. Returns true if it was
+/// sent, or false if the transmitter is still busy with a previous byte.
+pub fn uart_try_write_byte(byte: u8) -> bool {
+    let usart1 = USART1.get();
+    unsafe {
+        if (*usart1).isr.read().txe().bits() == 0 {
+            return false;
+        }
+        (*usart1).tdr.write(|w| w.tdr().bits(byte as u16));
+    }
+    true
+}
+
+/// Sends `byte`, waiting until the transmitter can take it.
+pub fn uart_write_byte(byte: u8) {
+    while !uart_try_write_byte(byte) {}
+}
+
+/// Sends all of `bytes`, waiting as necessary.
+pub fn uart_write(bytes: &[u8]) {
+    for &byte in bytes {
+        uart_write_byte(byte);
+    }
+}
+
+/// Sends `samples` to the host as one frame (see the top of this file for the format), waiting
+/// as necessary. At 115200 baud this takes about 87µs per byte, or 56ms for 320 samples.
+pub fn uart_send_samples(samples: &[u16]) {
+    uart_write(&UART_SYNC);
+    let count = samples.len() as u16;
+    let mut sum = 0u8;
+    for &byte in &[count as u8, (count >> 8) as u8] {
+        sum = sum.wrapping_add(byte);
+        uart_write_byte(byte);
+    }
+    for &sample in samples {
+        for &byte in &[sample as u8, (sample >> 8) as u8] {
+            sum = sum.wrapping_add(byte);
+            uart_write_byte(byte);
+        }
+    }
+    uart_write_byte(0u8.wrapping_sub(sum));
+}
+
+/// Sends `samples`, taken at `sample_rate` samples per second at `timebase`, to the host as text
+/// (see the top of this file for the format), waiting as necessary. At 115200 baud this takes
+/// about 0.5ms per digit, or around 130ms for 320 samples of a few hundred millivolts.
+pub fn uart_send_csv(samples: &[u16], sample_rate: u32, timebase: &TimeBase) {
+    let mut buf = [0u8; 11];
+    uart_write(b"# ");
+    uart_write(fmt_i32(samples.len() as i32, &mut buf));
+    uart_write(b" samples at ");
+    uart_write(fmt_eng_scaled(sample_rate as i32, 0, "Hz", &mut buf));
+    uart_write(b", ");
+    let label = timebase.label();
+    let start = label.iter().position(|&c| c != b' ').unwrap_or(label.len());
+    uart_write(&label[start..]);
+    uart_write(b"/div\r\n");
+    for &sample in samples {
+        uart_write(fmt_i32(counts_to_mv(sample), &mut buf));
+        uart_write(b"\r\n");
+    }
+}
+
+/// Sends a sweep's measurements to the host as a MEAS line (see the top of this file for the
+/// format), waiting as necessary: its frequency, `hz`, if it has one, and its peak-to-peak, mean,
+/// and AC RMS voltages, in millivolts.
+pub fn uart_send_measurements(hz: Option<u32>, pp_mv: i32, mean_mv: i32, ac_mv: i32) {
+    let mut buf = [0u8; 11];
+    uart_write(b"MEAS ");
+    match hz {
+        Some(hz) => uart_write(fmt_i32(hz as i32, &mut buf)),
+        None => uart_write(b"-"),
+    }
+    for &mv in &[pp_mv, mean_mv, ac_mv] {
+        uart_write_byte(b' ');
+        uart_write(fmt_i32(mv, &mut buf));
+    }
+    uart_write(b"\r\n");
+}
+
+/// Sends a `width` by `height` pixel screenshot to the host as one frame (see the top of this
+/// file for the format), waiting as necessary, with `read_row(y, row)` filling in the RGB565
+/// colors of each row `y` of pixels. At 115200 baud a 160 x 128 screenshot takes about 3.6s.
+pub fn uart_send_screenshot<F: FnMut(u8, &mut [u16])>(width: u8, height: u8, mut read_row: F) {
+    uart_write(&UART_SCREENSHOT_SYNC);
+    let mut sum = 0u8;
+    for &byte in &[width, height] {
+        sum = sum.wrapping_add(byte);
+        uart_write_byte(byte);
+    }
+    let mut row = [0u16; 255];
+    for y in 0..height {
+        read_row(y, &mut row[..width as usize]);
+        for &pixel in row[..width as usize].iter() {
+            for &byte in &[pixel as u8, (pixel >> 8) as u8] {
+                sum = sum.wrapping_add(byte);
+                uart_write_byte(byte);
+            }
+        }
+    }
+    uart_write_byte(0u8.wrapping_sub(sum));
+}
+
+// ======== commands ========
+
+#[derive(Clone, Copy)]
+pub enum Command {
+    TimeBase(TimeBase),
+    VerticalScale(VerticalScale),
+    Trigger(Edge, u16),
+    Single,
+    Dump(DumpFormat),
+    Screenshot,
+    CalibrateOffset,
+    CalibrateScale(u16), // millivolts
+    Rotate(u8),
+    SpiClock(u16), // kHz
+    DisplayRate(u16), // sweeps per second, or 0 for no limit
+    Oversample(u8), // samples averaged per sample displayed
+    Status,
+}
+
+/// How DUMP sends the displayed sweep: as a binary frame, or as text.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Binary,
+    Csv,
+}
+
+// the longest command line accepted, not counting its newline
+const LINE_MAX: usize = 32;
+
+static mut LINE: [u8; LINE_MAX] = [0; LINE_MAX];
+static mut LINE_LENGTH: usize = 0;
+static mut LINE_OVERLONG: bool = false; // discarding the rest of a too-long line
+
+/// Takes the oldest received byte from the receive buffer, if there is one, without waiting.
+pub fn uart_read_byte() -> Option<u8> {
+    unsafe {
+        let tail = RX_TAIL;
+        if tail == ptr::read_volatile(&RX_HEAD) {
+            None
+        } else {
+            let byte = ptr::read_volatile(&RX_BUFFER[tail]);
+            ptr::write_volatile(&mut RX_TAIL, (tail + 1) % RX_BUFFER_SIZE);
+            Some(byte)
+        }
+    }
+}
+
+/// Returns the number of received bytes dropped, because the receive buffer was full or USART1
+/// overran, since power-on (wrapping).
+pub fn uart_dropped_bytes() -> u32 {
+    unsafe { ptr::read_volatile(&RX_DROPPED) }
+}
+
+/// Collects any received bytes into the command line buffer, and once a whole line has arrived,
+/// parses it. Returns the command, or `None` if there isn't a whole line yet. Malformed or
+/// overlong lines are answered with an error and otherwise ignored.
+pub fn uart_poll_command() -> Option<Command> {
+    while let Some(byte) = uart_read_byte() {
+        unsafe {
+            if byte == b'\n' {
+                let length = LINE_LENGTH;
+                let overlong = LINE_OVERLONG;
+                LINE_LENGTH = 0;
+                LINE_OVERLONG = false;
+                if overlong {
+                    uart_write(b"ERR line too long\r\n");
+                } else if length > 0 {
+                    match parse_command(&LINE[..length]) {
+                        Some(command) => return Some(command),
+                        None => uart_write(b"ERR bad command\r\n"),
+                    }
+                }
+            } else if byte == b'\r' {
+                // ignore, for hosts which send CR LF
+            } else if LINE_LENGTH < LINE_MAX {
+                LINE[LINE_LENGTH] = byte;
+                LINE_LENGTH += 1;
+            } else {
+                LINE_OVERLONG = true;
+            }
+        }
+    }
+    None
+}
+
+// parse a command line (without its newline) into a Command
+fn parse_command(line: &[u8]) -> Option<Command> {
+    let mut words = line.split(|&c| c == b' ').filter(|word| !word.is_empty());
+    let command = match words.next() {
+        Some(b"TB") => match words.next() {
+            Some(label) => TimeBase::from_label(label).map(Command::TimeBase),
+            None => None,
+        },
+        Some(b"VS") => match words.next() {
+            Some(label) => VerticalScale::from_label(label).map(Command::VerticalScale),
+            None => None,
+        },
+        Some(b"TRIG") => {
+            let edge = match words.next() {
+                Some(b"RISING") => Edge::Rising,
+                Some(b"FALLING") => Edge::Falling,
+                _ => return None,
+            };
+            match words.next().and_then(parse_u16) {
+                Some(level) if level <= 4095 => Some(Command::Trigger(edge, level)),
+                _ => None,
+            }
+        }
+        Some(b"SINGLE") => Some(Command::Single),
+        Some(b"DUMP") => match words.next() {
+            None | Some(b"BIN") => Some(Command::Dump(DumpFormat::Binary)),
+            Some(b"CSV") => Some(Command::Dump(DumpFormat::Csv)),
+            _ => None,
+        },
+        Some(b"SHOT") => Some(Command::Screenshot),
+        Some(b"CAL") => match words.next() {
+            Some(b"ZERO") => Some(Command::CalibrateOffset),
+            Some(mv) => parse_u16(mv).map(Command::CalibrateScale),
+            None => None,
+        },
+        Some(b"ROT") => match words.next().and_then(parse_u16) {
+            Some(rotation) if rotation <= 3 => Some(Command::Rotate(rotation as u8)),
+            _ => None,
+        },
+        Some(b"SPI") => match words.next().and_then(parse_u16) {
+            Some(khz) if khz > 0 => Some(Command::SpiClock(khz)),
+            _ => None,
+        },
+        Some(b"FPS") => words.next().and_then(parse_u16).map(Command::DisplayRate),
+        Some(b"OS") => match words.next().and_then(parse_u16) {
+            Some(k) if k <= 255 => Some(Command::Oversample(k as u8)),
+            _ => None,
+        },
+        Some(b"STATUS") => Some(Command::Status),
+        _ => None,
+    };
+    if words.next().is_some() {
+        None // trailing junk
+    } else {
+        command
+    }
+}
+
+// parse a decimal number
+fn parse_u16(digits: &[u8]) -> Option<u16> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut n: u32 = 0;
+    for &c in digits {
+        if c < b'0' || c > b'9' {
+            return None;
+        }
+        n = n * 10 + (c - b'0') as u32;
+        if n > 0xffff {
+            return None;
+        }
+    }
+    Some(n as u16)
+}
@@ -0,0 +1,240 @@
+// stm32f3-oscilloscope - src/vscale.rs
+// vertical scale (display gain) settings
+
+// Copyright © 2017 Sean Bolton
+//
+// Permission is hereby granted, free of charge, to any person obtaining
+// a copy of this software and associated documentation files (the
+// "Software"), to deal in the Software without restriction, including
+// without limitation the rights to use, copy, modify, merge, publish,
+// distribute, sublicense, and/or sell copies of the Software, and to
+// permit persons to whom the Software is furnished to do so, subject to
+// the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE
+// LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION
+// OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION
+// WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.
+
+// At a gain of 1x, the full 12-bit ADC range fills the screen height. Higher gains magnify the
+// signal about the center reading (mid-scale, 2048, or a center-zero front end's zero offset; see
+// calibration.rs), so that it stays centered on the graticule. Gains are kept in Q16.16 fixed
+// point, so that fractional steps (e.g. 2.5x) can be represented exactly.
+//
+// The vertical position of a sample is worked out in Q16.16 fixed point too, in pixels per ADC
+// count: the gain times the screen height, over the 4096-count ADC range. The screen's center row
+// is the center reading, and rows count down from the top.
+
+use core;
+
+use calibration::center_counts;
+
+struct Gain {
+    gain: i32, // Q16.16
+    label: &'static [u8],
+}
+
+const GAINS: [Gain; 4] = [
+    Gain { gain:  1 << 16, label: b" 1x" },
+    Gain { gain:  2 << 16, label: b" 2x" },
+    Gain { gain:  5 << 16, label: b" 5x" },
+    Gain { gain: 10 << 16, label: b"10x" },
+];
+
+#[derive(Clone, Copy)]
+pub struct VerticalScale {
+    index: usize, // into GAINS
+}
+
+impl VerticalScale {
+    pub fn new() -> VerticalScale {
+        VerticalScale { index: 0 }
+    }
+
+    /// Returns the position of this setting in the table of steps, e.g. for saving it.
+    pub fn index(&self) -> u8 {
+        self.index as u8
+    }
+
+    /// Returns the setting at position `index` in the table of steps, or `None` if there isn't
+    /// one.
+    pub fn from_index(index: u8) -> Option<VerticalScale> {
+        if (index as usize) < GAINS.len() {
+            Some(VerticalScale { index: index as usize })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the setting whose label is `label`, e.g. b"2x", ignoring any leading spaces, or
+    /// `None` if there isn't one.
+    pub fn from_label(label: &[u8]) -> Option<VerticalScale> {
+        for (i, step) in GAINS.iter().enumerate() {
+            if trim_spaces(step.label) == trim_spaces(label) {
+                return Some(VerticalScale { index: i });
+            }
+        }
+        None
+    }
+
+    /// Returns the current gain, as a multiple of the full-ADC-range-fills-the-screen scale, in
+    /// Q16.16 fixed point (so 1x is 65536).
+    pub fn gain_q16(&self) -> i32 {
+        GAINS[self.index].gain
+    }
+
+    /// Returns the label for the current gain, e.g. b" 2x".
+    pub fn label(&self) -> &'static [u8] {
+        GAINS[self.index].label
+    }
+
+    /// Returns the row at which `sample` is drawn on a screen `height` rows high, moved up by
+    /// `offset` pixels. This isn't clipped to the screen, so it may be beyond the top (negative)
+    /// or bottom row.
+    pub fn sample_to_y(&self, sample: u16, height: u8, offset: i16) -> i16 {
+        let center = height as i32 / 2 - 1;
+        let counts = sample as i32 - center_counts() as i32;
+        let pixels = (counts * self.pixels_per_count_q16(height)) >> 16;
+        (center - pixels - offset as i32) as i16
+    }
+
+    /// The inverse of `sample_to_y`: returns the smallest sample drawn at row `y` of a screen
+    /// `height` rows high, at offset `offset`, clipped to the 12-bit ADC range for rows beyond it.
+    pub fn y_to_sample(&self, y: i16, height: u8, offset: i16) -> u16 {
+        let center = height as i32 / 2 - 1;
+        let pixels = (center - offset as i32 - y as i32) << 16;
+        let per_count = self.pixels_per_count_q16(height);
+        // round up (integer division truncates toward zero), so sample_to_y() gives back `y`
+        let counts = if pixels >= 0 {
+            (pixels + per_count - 1) / per_count
+        } else {
+            pixels / per_count
+        };
+        core::cmp::max(0, core::cmp::min(center_counts() as i32 + counts, 4095)) as u16
+    }
+
+    /// Returns the smallest sample drawn `percent` (at most 100) of the way up from the bottom row
+    /// to the top of a screen `height` rows high, at offset `offset`, clipped to the 12-bit ADC
+    /// range.
+    pub fn percent_to_sample(&self, percent: u8, height: u8, offset: i16) -> u16 {
+        let bottom = height as i32 - 1;
+        let y = bottom - (bottom * core::cmp::min(percent, 100) as i32 + 50) / 100;
+        self.y_to_sample(y as i16, height, offset)
+    }
+
+    /// The inverse of `percent_to_sample`: returns how far up a screen `height` rows high, as a
+    /// percentage from its bottom row, `sample` is drawn at offset `offset`, clipped to the screen.
+    pub fn sample_to_percent(&self, sample: u16, height: u8, offset: i16) -> u8 {
+        let bottom = height as i32 - 1;
+        let y = core::cmp::max(0, core::cmp::min(self.sample_to_y(sample, height, offset) as i32,
+                                                 bottom));
+        (((bottom - y) * 100 + bottom / 2) / bottom) as u8
+    }
+
+    // pixels per ADC count on a screen `height` rows high, in Q16.16 fixed point
+    fn pixels_per_count_q16(&self, height: u8) -> i32 {
+        (self.gain_q16() * height as i32) >> 12
+    }
+}
+
+impl Default for VerticalScale {
+    fn default() -> VerticalScale {
+        VerticalScale::new()
+    }
+}
+
+/// Steps `scale` up to the next higher gain, if there is one. Returns true if it changed.
+pub fn vscale_up(scale: &mut VerticalScale) -> bool {
+    if scale.index + 1 < GAINS.len() {
+        scale.index += 1;
+        true
+    } else {
+        false
+    }
+}
+
+/// Steps `scale` down to the next lower gain, if there is one. Returns true if it changed.
+pub fn vscale_down(scale: &mut VerticalScale) -> bool {
+    if scale.index > 0 {
+        scale.index -= 1;
+        true
+    } else {
+        false
+    }
+}
+
+// `label` without its leading spaces
+fn trim_spaces(label: &[u8]) -> &[u8] {
+    let start = label.iter().position(|&c| c != b' ').unwrap_or(label.len());
+    &label[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every gain, from the lowest up
+    fn scales() -> Vec<VerticalScale> {
+        (0..GAINS.len() as u8).map(|i| VerticalScale::from_index(i).unwrap()).collect()
+    }
+
+    #[test]
+    fn center_reading_is_drawn_on_the_center_row() {
+        for scale in scales() {
+            assert_eq!(scale.sample_to_y(2048, 128, 0), 63);
+            assert_eq!(scale.sample_to_y(2048, 160, 0), 79);
+            assert_eq!(scale.sample_to_y(2048, 128, 10), 53);
+        }
+    }
+
+    #[test]
+    fn rows_round_trip() {
+        for scale in scales() {
+            for &height in [128u8, 160].iter() {
+                for &offset in [0i16, 20, -20].iter() {
+                    for y in 0..height as i16 {
+                        let sample = scale.y_to_sample(y, height, offset);
+                        if sample == 0 || sample == 4095 {
+                            continue; // a row beyond the ADC range, clipped
+                        }
+                        assert_eq!(scale.sample_to_y(sample, height, offset), y);
+                        // and it's the smallest sample drawn there
+                        assert!(scale.sample_to_y(sample - 1, height, offset) > y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_range_fills_the_screen_at_1x() {
+        let scale = VerticalScale::new();
+        assert_eq!(scale.sample_to_y(0, 128, 0), 127);
+        assert_eq!(scale.sample_to_y(4095, 128, 0), 0);
+        assert_eq!(scale.y_to_sample(127, 128, 0), 0);
+        assert_eq!(scale.y_to_sample(0, 128, 0), 4064);
+    }
+
+    #[test]
+    fn rows_beyond_the_adc_range_clip() {
+        for scale in scales() {
+            assert_eq!(scale.y_to_sample(-3000, 128, 0), 4095);
+            assert_eq!(scale.y_to_sample(3000, 128, 0), 0);
+            assert_eq!(scale.sample_to_percent(4095, 128, 0), 100);
+            assert_eq!(scale.sample_to_percent(0, 128, 0), 0);
+        }
+        // at 10x, the samples drawn beyond the screen edges aren't clipped, only those beyond the
+        // ADC range
+        let scale = VerticalScale::from_label(b"10x").unwrap();
+        assert!(scale.sample_to_y(4095, 128, 0) < -500);
+        assert!(scale.sample_to_y(0, 128, 0) > 600);
+        // (the top row is 63 rows above the center, at 3.2 counts per row, rounded up)
+        assert_eq!(scale.percent_to_sample(100, 128, 0), 2250);
+    }
+}